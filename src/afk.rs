@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use serde::Deserialize;
+
+// Players count as AFK once they've gone timeout_mins without chatting, dying or making an
+// advancement. AFK plugins that log when players go AFK can be matched with afk_pattern and
+// back_pattern, which need a (?P<player>...) group.
+#[derive(Deserialize)]
+pub struct AfkToml {
+    #[serde(default = "default_timeout_mins")]
+    pub timeout_mins: u64,
+    // Leaves AFK time out of play time instead of only noting it
+    #[serde(default)]
+    pub exclude: bool,
+    pub afk_pattern: Option<PlayerPattern>,
+    pub back_pattern: Option<PlayerPattern>,
+}
+
+fn default_timeout_mins() -> u64 {
+    15
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+pub struct PlayerPattern(Regex);
+
+impl TryFrom<String> for PlayerPattern {
+    type Error = String;
+
+    fn try_from(pattern: String) -> Result<PlayerPattern, String> {
+        let regex = Regex::new(&pattern).map_err(|e| format!("Invalid AFK pattern \"{}\": {}", pattern, e))?;
+        if !regex.capture_names().any(|x| x == Some("player")) {
+            return Err(format!("AFK pattern \"{}\" needs a (?P<player>...) group", pattern));
+        }
+        Ok(PlayerPattern(regex))
+    }
+}
+
+impl PlayerPattern {
+    fn player<'a>(&self, line: &'a str) -> Option<&'a str> {
+        self.0.captures(line)?.name("player").map(|x| x.as_str())
+    }
+}
+
+struct Activity {
+    last_activity: Instant,
+    // Set when an AFK plugin says the player is AFK
+    afk_since: Option<Instant>,
+    afk: Duration,
+}
+
+pub struct AfkTracker {
+    timeout: Duration,
+    exclude: bool,
+    afk_pattern: Option<PlayerPattern>,
+    back_pattern: Option<PlayerPattern>,
+    players: HashMap<String, Activity>,
+}
+
+impl AfkTracker {
+    pub fn new(config: AfkToml) -> AfkTracker {
+        AfkTracker {
+            timeout: Duration::from_secs(config.timeout_mins * 60),
+            exclude: config.exclude,
+            afk_pattern: config.afk_pattern,
+            back_pattern: config.back_pattern,
+            players: HashMap::new(),
+        }
+    }
+
+    pub fn joined(&mut self, name: &str) {
+        self.joined_at(name, Instant::now());
+    }
+
+    fn joined_at(&mut self, name: &str, now: Instant) {
+        self.players.insert(name.to_string(), Activity { last_activity: now, afk_since: None, afk: Duration::ZERO });
+    }
+
+    // Adds up any AFK time since the player's last activity
+    pub fn activity(&mut self, name: &str) {
+        self.activity_at(name, Instant::now());
+    }
+
+    fn activity_at(&mut self, name: &str, now: Instant) {
+        let activity = match self.players.get_mut(name) {
+            Some(v) => v,
+            None => return,
+        };
+
+        match activity.afk_since.take() {
+            Some(afk_since) => activity.afk += now.saturating_duration_since(afk_since),
+            None => activity.afk += now.saturating_duration_since(activity.last_activity).saturating_sub(self.timeout),
+        }
+        activity.last_activity = now;
+    }
+
+    // Checks a log line for an AFK plugin's messages
+    pub fn check_line(&mut self, line: &str) {
+        self.check_line_at(line, Instant::now());
+    }
+
+    fn check_line_at(&mut self, line: &str, now: Instant) {
+        if let Some(player) = self.afk_pattern.as_ref().and_then(|x| x.player(line)).map(|x| x.to_string()) {
+            self.activity_at(&player, now);
+            if let Some(activity) = self.players.get_mut(&player) {
+                activity.afk_since = Some(now);
+            }
+        } else if let Some(player) = self.back_pattern.as_ref().and_then(|x| x.player(line)).map(|x| x.to_string()) {
+            self.activity_at(&player, now);
+        }
+    }
+
+    // Stops tracking a player, returning how long they were AFK in milliseconds
+    pub fn left(&mut self, name: &str) -> u128 {
+        self.left_at(name, Instant::now())
+    }
+
+    fn left_at(&mut self, name: &str, now: Instant) -> u128 {
+        self.activity_at(name, now);
+        self.players.remove(name).map(|x| x.afk.as_millis()).unwrap_or(0)
+    }
+}
+
+// Returns how long a player that left was AFK and whether that time should be left out of
+// their play time
+pub fn left(tracker: &mut Option<AfkTracker>, name: &str) -> (u128, bool) {
+    match tracker {
+        Some(tracker) => (tracker.left(name), tracker.exclude),
+        None => (0, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::afk::{AfkToml, AfkTracker};
+
+    #[test]
+    fn test_afk_tracker() {
+        let config: AfkToml = toml::from_str("timeout_mins = 10\nafk_pattern = '^(?P<player>\\w+) is now AFK$'\nback_pattern = '^(?P<player>\\w+) is no longer AFK$'").unwrap();
+        assert!(toml::from_str::<AfkToml>("afk_pattern = 'is now AFK'").is_err());
+
+        let mut tracker = AfkTracker::new(config);
+        let start = Instant::now();
+        let minutes = |x: u64| start + Duration::from_secs(x * 60);
+
+        // Idle for 25 minutes, the last 15 count as AFK
+        tracker.joined_at("Steve", start);
+        tracker.activity_at("Steve", minutes(25));
+
+        // The plugin marks them AFK for 5 minutes
+        tracker.check_line_at("Steve is now AFK", minutes(30));
+        tracker.check_line_at("Steve is no longer AFK", minutes(35));
+        assert_eq!(tracker.left_at("Steve", minutes(40)), 20 * 60 * 1000);
+        assert_eq!(tracker.left_at("Alex", minutes(40)), 0);
+    }
+}
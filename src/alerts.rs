@@ -0,0 +1,139 @@
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use serde::Deserialize;
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    fn label(&self) -> &str {
+        match self {
+            Severity::Info => "ℹ️ Info",
+            Severity::Warning => "⚠️ Warning",
+            Severity::Critical => "🚨 Critical",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AlertRuleToml {
+    pattern: String,
+    // Treats pattern as a regular expression instead of text to look for
+    #[serde(default)]
+    regex: bool,
+    #[serde(default)]
+    severity: Severity,
+    #[serde(default = "default_cooldown_secs")]
+    cooldown_secs: u64,
+}
+
+fn default_cooldown_secs() -> u64 {
+    300
+}
+
+// Log lines matching a rule are posted to the admin channel, at most once per cooldown.
+// Patterns are compiled when the config is loaded so mistakes are found on startup.
+#[derive(Deserialize)]
+#[serde(try_from = "AlertRuleToml")]
+pub struct AlertRule {
+    matcher: Regex,
+    severity: Severity,
+    cooldown: Duration,
+}
+
+impl TryFrom<AlertRuleToml> for AlertRule {
+    type Error = String;
+
+    fn try_from(rule: AlertRuleToml) -> Result<AlertRule, String> {
+        let pattern = match rule.regex {
+            true => rule.pattern.clone(),
+            false => regex::escape(&rule.pattern),
+        };
+        let matcher = Regex::new(&pattern).map_err(|e| format!("Invalid alert pattern \"{}\": {}", rule.pattern, e))?;
+        Ok(AlertRule { matcher, severity: rule.severity, cooldown: Duration::from_secs(rule.cooldown_secs) })
+    }
+}
+
+// Anchored to the start of the content so players can't raise them by saying the text in chat
+pub fn default_rules() -> Vec<AlertRule> {
+    let rule = |pattern: &str, severity, cooldown_secs| AlertRule::try_from(AlertRuleToml { pattern: pattern.to_string(), regex: true, severity, cooldown_secs }).unwrap();
+    vec![
+        rule(r"^Can't keep up!", Severity::Warning, 600),
+        rule(r#"^(?:Caused by: |Exception in thread "[^"]*" )?java\.lang\.OutOfMemoryError"#, Severity::Critical, 300),
+        rule(r"^(?:[\w.$]+: )?Exception in server tick loop", Severity::Critical, 300),
+    ]
+}
+
+pub struct Alerts {
+    rules: Vec<AlertRule>,
+    last_sent: Vec<Option<Instant>>,
+}
+
+impl Alerts {
+    pub fn new(rules: Vec<AlertRule>) -> Alerts {
+        let last_sent = vec![None; rules.len()];
+        Alerts { rules, last_sent }
+    }
+
+    // Returns the alert to post for a log line, if it matches a rule that isn't cooling down.
+    // Only the first matching rule is used.
+    pub fn check(&mut self, label: &str, content: &str) -> Option<String> {
+        let index = self.rules.iter().position(|x| x.matcher.is_match(content))?;
+        let rule = &self.rules[index];
+        if self.last_sent[index].map(|x| x.elapsed() < rule.cooldown).unwrap_or(false) {
+            return None;
+        }
+
+        self.last_sent[index] = Some(Instant::now());
+        match label.is_empty() {
+            true => Some(format!("**{}** {}", rule.severity.label(), content)),
+            false => Some(format!("**{}** `[{}]` {}", rule.severity.label(), label, content)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::alerts::{default_rules, AlertRule, Alerts, Severity};
+
+    #[derive(Deserialize)]
+    struct RulesToml {
+        alerts: Vec<AlertRule>,
+    }
+
+    #[test]
+    fn test_alerts() {
+        let config: RulesToml = toml::from_str("[[alerts]]\npattern = 'Player \\w+ moved too quickly'\nregex = true\nseverity = 'info'").unwrap();
+        assert_eq!(config.alerts[0].severity, Severity::Info);
+        assert!(toml::from_str::<RulesToml>("[[alerts]]\npattern = '('\nregex = true").is_err());
+
+        let mut alerts = Alerts::new(config.alerts.into_iter().chain(default_rules()).collect());
+        assert_eq!(alerts.check("WARN", "Player Steve moved too quickly! 3,0,0").as_deref(), Some("**ℹ️ Info** `[WARN]` Player Steve moved too quickly! 3,0,0"));
+        assert!(alerts.check("WARN", "Player Alex moved too quickly! 3,0,0").is_none());
+        assert!(alerts.check("WARN", "Can't keep up! Is the server overloaded?").is_some());
+        assert!(alerts.check("INFO", "Steve joined the game").is_none());
+        assert_eq!(alerts.check("", "java.lang.OutOfMemoryError: Java heap space").as_deref(), Some("**🚨 Critical** java.lang.OutOfMemoryError: Java heap space"));
+    }
+
+    #[test]
+    fn test_default_rules_ignore_chat() {
+        let mut alerts = Alerts::new(default_rules());
+        assert!(alerts.check("INFO", "<Steve> Can't keep up! Is the server overloaded?").is_none());
+        assert!(alerts.check("INFO", "<Steve> java.lang.OutOfMemoryError: Java heap space").is_none());
+        assert!(alerts.check("INFO", "<Steve> Exception in server tick loop").is_none());
+        assert!(alerts.check("INFO", "[Server] Can't keep up!").is_none());
+
+        assert!(alerts.check("ERROR", "net.minecraft.ReportedException: Exception in server tick loop").is_some());
+        assert!(alerts.check("", "Exception in thread \"Server thread\" java.lang.OutOfMemoryError: Java heap space").is_some());
+        assert!(alerts.check("WARN", "Can't keep up! Is the server overloaded? Running 2034ms or 40 ticks behind").is_some());
+    }
+}
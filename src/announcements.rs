@@ -0,0 +1,216 @@
+// Messages sent on a schedule, eg. server rules or backup warnings. Each runs every interval_mins
+// or on a cron expression, and goes to game chat, the chat channel or both.
+
+use std::time::Duration;
+
+use chrono::{Datelike, Local, NaiveDateTime, Timelike};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use crate::bot::Bot;
+use crate::{send_or_log, tellraw, Packet};
+
+// How far ahead to look for a cron expression's next time before giving up, eg. for Feb 30
+const CRON_SEARCH_DAYS: i64 = 366 * 4;
+
+#[derive(Deserialize)]
+pub struct AnnouncementToml {
+    pub message: String,
+    pub interval_mins: Option<u64>,
+    // Standard 5 field cron expression in local time, eg. "0 */2 * * *"
+    pub cron: Option<String>,
+    #[serde(default = "default_true")]
+    pub game: bool,
+    #[serde(default)]
+    pub discord: bool,
+    // Sent with `say` so it shows as coming from the server, tellraw otherwise
+    #[serde(default)]
+    pub say: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// The values a cron field matches
+#[derive(Debug, PartialEq, Eq)]
+struct CronField(Vec<bool>);
+
+impl CronField {
+    // Parses eg. "*", "*/15", "1-5", "0,30" or "8-18/2" for values from min to max
+    fn parse(text: &str, min: u32, max: u32) -> Option<CronField> {
+        let mut values = vec![false; max as usize + 1];
+        for part in text.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().ok().filter(|x| *x > 0)?),
+                None => (part, 1),
+            };
+            let (start, end) = match range {
+                "*" => (min, max),
+                range => match range.split_once('-') {
+                    Some((start, end)) => (start.parse().ok()?, end.parse().ok()?),
+                    None => {
+                        let value = range.parse().ok()?;
+                        // "5/10" means from 5 onwards
+                        (value, if part.contains('/') { max } else { value })
+                    },
+                },
+            };
+            if start < min || end > max || start > end {
+                return None;
+            }
+            for value in (start..=end).step_by(step as usize) {
+                values[value as usize] = true;
+            }
+        }
+        Some(CronField(values))
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.0.get(value as usize).copied().unwrap_or(false)
+    }
+
+    fn is_any(&self, min: u32) -> bool {
+        self.0.iter().skip(min as usize).all(|x| *x)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cron {
+    minute: CronField,
+    hour: CronField,
+    day: CronField,
+    month: CronField,
+    weekday: CronField,
+}
+
+impl Cron {
+    pub fn parse(text: &str) -> Result<Cron, String> {
+        let error = || format!("Invalid cron expression \"{}\", expected 5 fields, eg. \"0 */2 * * *\"", text);
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields.as_slice() else {
+            return Err(error());
+        };
+        let mut weekday = CronField::parse(weekday, 0, 7).ok_or_else(error)?;
+        // Both 0 and 7 are sunday
+        if weekday.0[7] {
+            weekday.0[0] = true;
+        }
+        Ok(Cron {
+            minute: CronField::parse(minute, 0, 59).ok_or_else(error)?,
+            hour: CronField::parse(hour, 0, 23).ok_or_else(error)?,
+            day: CronField::parse(day, 1, 31).ok_or_else(error)?,
+            month: CronField::parse(month, 1, 12).ok_or_else(error)?,
+            weekday,
+        })
+    }
+
+    // Like cron, when both the day of the month and the weekday are restricted either can match
+    fn matches_date(&self, time: NaiveDateTime) -> bool {
+        let day = self.day.matches(time.day());
+        let weekday = self.weekday.matches(time.weekday().num_days_from_sunday());
+        let day_matches = match (self.day.is_any(1), self.weekday.is_any(0)) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        day_matches && self.month.matches(time.month())
+    }
+
+    // Finds the first matching minute after now
+    pub fn next_after(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut time = now.with_second(0)?.with_nanosecond(0)? + chrono::Duration::minutes(1);
+        let last = now + chrono::Duration::days(CRON_SEARCH_DAYS);
+        while time < last {
+            if !self.matches_date(time) {
+                time = time.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if self.hour.matches(time.hour()) && self.minute.matches(time.minute()) {
+                return Some(time);
+            }
+            time += chrono::Duration::minutes(1);
+        }
+        None
+    }
+}
+
+pub enum Schedule {
+    Interval(Duration),
+    Cron(Cron),
+}
+
+pub fn parse_schedules(announcements: &[AnnouncementToml]) -> Result<Vec<Schedule>, String> {
+    announcements.iter()
+        .map(|x| match (x.interval_mins, &x.cron) {
+            (Some(mins), None) if mins > 0 => Ok(Schedule::Interval(Duration::from_secs(mins * 60))),
+            (None, Some(cron)) => Cron::parse(cron).map(Schedule::Cron),
+            _ => Err(format!("Announcement \"{}\" needs either interval_mins or cron", x.message)),
+        })
+        .collect()
+}
+
+// Sends an Announcement packet with the announcement's index each time it's due
+pub fn start_announcements(schedules: Vec<Schedule>, sender: &mpsc::UnboundedSender<Packet>) {
+    for (index, schedule) in schedules.into_iter().enumerate() {
+        let sender = sender.clone();
+        tokio::task::spawn(async move {
+            loop {
+                let wait = match &schedule {
+                    Schedule::Interval(interval) => *interval,
+                    Schedule::Cron(cron) => {
+                        let now = Local::now().naive_local();
+                        match cron.next_after(now).and_then(|x| (x - now).to_std().ok()) {
+                            Some(v) => v,
+                            None => return,
+                        }
+                    },
+                };
+                tokio::time::sleep(wait).await;
+                send_or_log(&sender, Packet::Announcement(index));
+            }
+        });
+    }
+}
+
+pub async fn announce(bot: &Bot, index: usize) {
+    let announcement = match bot.config.announcements.get(index) {
+        Some(v) => v,
+        None => return,
+    };
+
+    if announcement.game {
+        let line = match announcement.say {
+            true => format!("say {}", announcement.message),
+            false => tellraw::to_all(&announcement.message),
+        };
+        bot.write_console(&line).await;
+    }
+    if announcement.discord {
+        bot.say(bot.channel_id, &announcement.message).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::announcements::Cron;
+
+    #[test]
+    fn test_cron() {
+        let at = |day, hour, minute| NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(hour, minute, 0).unwrap();
+        let now = at(1, 10, 7);
+
+        assert_eq!(Cron::parse("*/15 * * * *").unwrap().next_after(now), Some(at(1, 10, 15)));
+        assert_eq!(Cron::parse("0 */2 * * *").unwrap().next_after(now), Some(at(1, 12, 0)));
+        assert_eq!(Cron::parse("30 3 * * *").unwrap().next_after(now), Some(at(2, 3, 30)));
+        // 2024-01-01 is a monday, so the next friday is the 5th
+        assert_eq!(Cron::parse("0 20 * * 5").unwrap().next_after(now), Some(at(5, 20, 0)));
+        assert_eq!(Cron::parse("0 9 * * 7").unwrap().next_after(now), Some(at(7, 9, 0)));
+        assert_eq!(Cron::parse("0 9 15 * 1-5").unwrap().next_after(now), Some(at(2, 9, 0)));
+        assert_eq!(Cron::parse("0 0 30 2 *").unwrap().next_after(now), None);
+        assert!(Cron::parse("0 9 * *").is_err());
+        assert!(Cron::parse("60 * * * *").is_err());
+        assert!(Cron::parse("*/0 * * * *").is_err());
+    }
+}
@@ -0,0 +1,371 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+pub use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tracing::{debug, error, info, warn};
+
+use crate::oauth::{self, OAuthToml, Sessions};
+use crate::{Packet, send_or_log};
+
+#[derive(Deserialize)]
+pub struct ApiToml {
+    pub port: u16,
+    // Needed in an Authorization: Bearer header for every endpoint but the dashboard and login,
+    // or as ?token= for /events since browsers can't set headers on websockets. Token access is
+    // disabled when not set.
+    #[serde(default)]
+    pub token: String,
+    // Lets anyone read /status, /players and /playtime, eg. for a server list website
+    #[serde(default)]
+    pub public: bool,
+    // Lets the dashboard log in with Discord, disabled when not set
+    pub oauth: Option<OAuthToml>,
+}
+
+// Asked of the packet handler, which owns the bot's state
+pub enum ApiQuery {
+    Status,
+    Players,
+    PlayTimes,
+    // A console line and who is running it
+    Command { user: String, line: String },
+    // Checks a Discord user could use the console from Discord
+    Authorize(u64),
+}
+
+pub type ApiResult = Result<Value, (StatusCode, String)>;
+
+pub struct ApiRequest {
+    pub query: ApiQuery,
+    pub reply: oneshot::Sender<ApiResult>,
+}
+
+// Events sent as JSON to /events clients, eg. {"type":"join","player":"Steve"}
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ApiEvent {
+    Log { label: String, line: String },
+    Join { player: String },
+    Leave { player: String },
+    Chat { source: &'static str, player: String, message: String },
+}
+
+// Events waiting to be sent to a client, slow clients skip the oldest
+const EVENT_CAPACITY: usize = 256;
+
+pub struct EventStream {
+    sender: broadcast::Sender<ApiEvent>,
+}
+
+impl EventStream {
+    pub fn new() -> EventStream {
+        EventStream { sender: broadcast::channel(EVENT_CAPACITY).0 }
+    }
+
+    // Sending only fails when nobody is listening
+    pub fn publish(&self, event: ApiEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ApiEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[derive(Serialize)]
+pub struct PlayerJson {
+    pub name: String,
+    pub online_secs: u64,
+}
+
+#[derive(Serialize)]
+pub struct PlayTimeJson {
+    pub name: String,
+    pub millis: u64,
+}
+
+#[derive(Clone)]
+struct ApiState {
+    sender: mpsc::UnboundedSender<Packet>,
+    token: String,
+    public: bool,
+    oauth: Option<OAuthToml>,
+    sessions: Arc<Mutex<Sessions>>,
+}
+
+#[derive(Deserialize)]
+struct CommandBody {
+    command: String,
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+    code: String,
+    state: String,
+}
+
+// Compares every byte so the time taken doesn't show how much of the token was right
+fn same_token(given: &str, token: &str) -> bool {
+    !token.is_empty() && given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn token_matches(headers: &HeaderMap, token: &str) -> bool {
+    let given = headers.get("authorization")
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+        .unwrap_or("");
+    same_token(given, token)
+}
+
+fn token_authorized(state: &ApiState, headers: &HeaderMap, query_token: Option<&str>) -> bool {
+    token_matches(headers, &state.token) || query_token.is_some_and(|x| same_token(x, &state.token))
+}
+
+// Who is making the request, from the token or a dashboard login
+fn authorized(state: &ApiState, headers: &HeaderMap, query_token: Option<&str>) -> Option<String> {
+    if token_authorized(state, headers, query_token) {
+        return Some("token".to_string());
+    }
+    state.sessions.lock().unwrap().user(headers).map(|x| x.name.clone())
+}
+
+// Browsers send the page's address with websocket requests. Logins are only accepted from the
+// dashboard itself so pages served from other ports on the same host can't use the cookie.
+fn same_origin(headers: &HeaderMap) -> bool {
+    let header = |name| headers.get(name).and_then(|x| x.to_str().ok());
+    let origin = header("origin").and_then(|x| x.split_once("://")).map(|(_, host)| host);
+    origin.is_some() && origin == header("host")
+}
+
+// Like authorized, but dashboard logins are checked for console access again since the user's
+// roles may have changed after they logged in
+async fn authorized_console(state: &ApiState, headers: &HeaderMap, query_token: Option<&str>) -> Result<String, (StatusCode, String)> {
+    if token_authorized(state, headers, query_token) {
+        return Ok("token".to_string());
+    }
+    let user = state.sessions.lock().unwrap().user(headers).map(|x| (x.id, x.name.clone()));
+    let (id, name) = user.ok_or((StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+    // Fails when the user doesn't have console access anymore
+    let _ = ask(state, ApiQuery::Authorize(id)).await?;
+    Ok(name)
+}
+
+async fn ask(state: &ApiState, query: ApiQuery) -> Result<Json<Value>, (StatusCode, String)> {
+    let (reply, response) = oneshot::channel();
+    send_or_log(&state.sender, Packet::Api(ApiRequest { query, reply }));
+    match response.await {
+        Ok(result) => result.map(Json),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "No response".to_string())),
+    }
+}
+
+// Reading the server's state needs the token or a login unless the API is public
+async fn ask_readable(state: &ApiState, headers: &HeaderMap, query: ApiQuery) -> Result<Json<Value>, (StatusCode, String)> {
+    if !state.public && authorized(state, headers, None).is_none() {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid token".to_string()));
+    }
+    ask(state, query).await
+}
+
+async fn status(State(state): State<ApiState>, headers: HeaderMap) -> Result<Json<Value>, (StatusCode, String)> {
+    ask_readable(&state, &headers, ApiQuery::Status).await
+}
+
+async fn players(State(state): State<ApiState>, headers: HeaderMap) -> Result<Json<Value>, (StatusCode, String)> {
+    ask_readable(&state, &headers, ApiQuery::Players).await
+}
+
+async fn play_times(State(state): State<ApiState>, headers: HeaderMap) -> Result<Json<Value>, (StatusCode, String)> {
+    ask_readable(&state, &headers, ApiQuery::PlayTimes).await
+}
+
+async fn dashboard() -> Html<&'static str> {
+    Html(include_str!("dashboard.html"))
+}
+
+// Tells the dashboard whether it needs to log in
+async fn session(State(state): State<ApiState>, headers: HeaderMap) -> Json<Value> {
+    let user = authorized(&state, &headers, None);
+    Json(json!({ "user": user, "oauth": state.oauth.is_some() }))
+}
+
+async fn login(State(state): State<ApiState>) -> Result<Redirect, (StatusCode, String)> {
+    let config = state.oauth.as_ref().ok_or((StatusCode::NOT_FOUND, "Discord login isn't enabled".to_string()))?;
+    let login_state = state.sessions.lock().unwrap().start_login();
+    let url = oauth::authorize_url(config, &login_state).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(Redirect::to(&url))
+}
+
+async fn callback(State(state): State<ApiState>, Query(query): Query<CallbackQuery>) -> Result<Response, (StatusCode, String)> {
+    let config = state.oauth.as_ref().ok_or((StatusCode::NOT_FOUND, "Discord login isn't enabled".to_string()))?;
+    if !state.sessions.lock().unwrap().finish_login(&query.state) {
+        return Err((StatusCode::BAD_REQUEST, "Login expired, try again".to_string()));
+    }
+
+    let user = oauth::fetch_user(config, &query.code).await.map_err(|e| {
+        warn!("Dashboard login failed: {}", e);
+        (StatusCode::BAD_GATEWAY, "Error logging in with Discord".to_string())
+    })?;
+    // Fails when the user doesn't have console access
+    let _ = ask(&state, ApiQuery::Authorize(user.id)).await?;
+
+    info!("{} logged in to the dashboard", user.name);
+    let length = Duration::from_secs(config.session_hours.max(1) * 3600);
+    let id = state.sessions.lock().unwrap().create(user, length);
+    let cookie = oauth::set_cookie(config, &id, length.as_secs());
+    Ok(([(header::SET_COOKIE, cookie)], Redirect::to("/")).into_response())
+}
+
+async fn logout(State(state): State<ApiState>, headers: HeaderMap) -> Response {
+    state.sessions.lock().unwrap().remove(&headers);
+    match &state.oauth {
+        Some(config) => ([(header::SET_COOKIE, oauth::set_cookie(config, "", 0))], Redirect::to("/")).into_response(),
+        None => Redirect::to("/").into_response(),
+    }
+}
+
+async fn command(State(state): State<ApiState>, headers: HeaderMap, Json(body): Json<CommandBody>) -> Result<Json<Value>, (StatusCode, String)> {
+    let user = authorized_console(&state, &headers, None).await?;
+
+    // Only one console line can be run at a time
+    let command = body.command.lines().next().unwrap_or("").trim().to_string();
+    if command.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "No command".to_string()));
+    }
+    info!("API ran command as {}: {}", user, command);
+    ask(&state, ApiQuery::Command { user, line: command }).await
+}
+
+async fn events(State(state): State<ApiState>, headers: HeaderMap, Query(query): Query<TokenQuery>, ws: WebSocketUpgrade) -> Result<Response, (StatusCode, String)> {
+    if !token_authorized(&state, &headers, query.token.as_deref()) && !same_origin(&headers) {
+        return Err((StatusCode::FORBIDDEN, "Wrong origin".to_string()));
+    }
+    authorized_console(&state, &headers, query.token.as_deref()).await?;
+
+    let (reply, response) = oneshot::channel();
+    send_or_log(&state.sender, Packet::ApiSubscribe(reply));
+    let receiver = response.await.map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "No response".to_string()))?;
+    Ok(ws.on_upgrade(|socket| stream_events(socket, receiver)))
+}
+
+async fn stream_events(mut socket: WebSocket, mut receiver: broadcast::Receiver<ApiEvent>) {
+    loop {
+        tokio::select! {
+            event = receiver.recv() => {
+                let event = match event {
+                    Ok(v) => v,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        debug!("Websocket client skipped {} events", skipped);
+                        continue;
+                    },
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let text = match serde_json::to_string(&event) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!("Error serializing event: {}", e);
+                        continue;
+                    },
+                };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            },
+            // Messages from the client are ignored, reading them answers pings and notices closes
+            message = socket.recv() => {
+                if !matches!(message, Some(Ok(_))) {
+                    break;
+                }
+            },
+        }
+    }
+}
+
+// Serves the dashboard, GET /status, GET /players, GET /playtime, POST /command, the /events
+// websocket and the dashboard's Discord login
+pub fn start_api_server(config: &ApiToml, sender: &mpsc::UnboundedSender<Packet>) {
+    let state = ApiState {
+        sender: sender.clone(),
+        token: config.token.clone(),
+        public: config.public,
+        oauth: config.oauth.clone(),
+        sessions: Arc::new(Mutex::new(Sessions::default())),
+    };
+    let app = Router::new()
+        .route("/", get(dashboard))
+        .route("/status", get(status))
+        .route("/players", get(players))
+        .route("/playtime", get(play_times))
+        .route("/command", post(command))
+        .route("/events", get(events))
+        .route("/session", get(session))
+        .route("/login", get(login))
+        .route("/auth/callback", get(callback))
+        .route("/logout", get(logout))
+        .with_state(state);
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+    tokio::task::spawn(async move {
+        let server = match axum::Server::try_bind(&addr) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Error binding API port {}: {}", addr.port(), e);
+                return;
+            },
+        };
+        info!("Serving API on port {}", addr.port());
+
+        if let Err(e) = server.serve(app.into_make_service()).await {
+            error!("API server error: {}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderMap;
+
+    use crate::api::{same_origin, token_matches, ApiEvent};
+
+    #[test]
+    fn test_token_matches() {
+        let mut headers = HeaderMap::new();
+        assert!(!token_matches(&headers, "secret"));
+        headers.insert("authorization", "Bearer secret".parse().unwrap());
+        assert!(token_matches(&headers, "secret"));
+        assert!(!token_matches(&headers, "secreT"));
+        assert!(!token_matches(&headers, ""));
+    }
+
+    #[test]
+    fn test_same_origin() {
+        let mut headers = HeaderMap::new();
+        headers.insert("host", "mc.example.com:8080".parse().unwrap());
+        assert!(!same_origin(&headers));
+        headers.insert("origin", "http://mc.example.com:8081".parse().unwrap());
+        assert!(!same_origin(&headers));
+        headers.insert("origin", "http://mc.example.com:8080".parse().unwrap());
+        assert!(same_origin(&headers));
+    }
+
+    #[test]
+    fn test_event_json() {
+        let event = ApiEvent::Chat { source: "game", player: "Steve".to_string(), message: "hi".to_string() };
+        assert_eq!(serde_json::to_string(&event).unwrap(), r#"{"type":"chat","source":"game","player":"Steve","message":"hi"}"#);
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::time::SystemTime;
+
+use chrono::{Local, TimeZone};
+use serde::{Serialize, Deserialize};
+
+use crate::playtime::unix_millis;
+
+// One json entry per line so recording an action is a cheap append
+pub const AUDIT_LOG_PATH: &str = "mc-discord-bot-audit.jsonl";
+
+// Number of entries shown by !audit
+const SHOWN_ENTRIES: usize = 20;
+
+// A moderation action taken from discord, times are unix timestamps in milliseconds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub time: u64,
+    pub user: String,
+    pub user_id: u64,
+    pub action: String,
+    pub target: String,
+    pub reason: Option<String>,
+    pub succeeded: bool,
+    #[serde(default)]
+    pub confirmed_by: Option<String>,
+}
+
+impl AuditEntry {
+    // Builds an entry from the console command that was run, eg. "ban Steve griefing"
+    pub fn new(user: String, user_id: u64, console_line: &str) -> AuditEntry {
+        let mut parts = console_line.splitn(3, ' ');
+        AuditEntry {
+            time: unix_millis(SystemTime::now()),
+            user,
+            user_id,
+            action: parts.next().unwrap_or_default().to_string(),
+            target: parts.next().unwrap_or_default().to_string(),
+            reason: parts.next().map(|x| x.to_string()),
+            succeeded: false,
+            confirmed_by: None,
+        }
+    }
+}
+
+pub fn record(entry: &AuditEntry) -> Result<(), String> {
+    let mut line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+    line.push('\n');
+
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(AUDIT_LOG_PATH).map_err(|e| e.to_string())?;
+    file.write_all(line.as_bytes()).map_err(|e| e.to_string())
+}
+
+// Reads the audit log oldest first, skipping any lines that can't be parsed
+pub fn read_entries() -> Vec<AuditEntry> {
+    std::fs::read_to_string(AUDIT_LOG_PATH).unwrap_or_default()
+        .lines()
+        .filter_map(|x| serde_json::from_str(x).ok())
+        .collect()
+}
+
+// Builds the !audit reply from the most recent entries, optionally only those about one player
+pub fn format_entries(entries: &[AuditEntry], player: Option<&str>) -> String {
+    let entries: Vec<&AuditEntry> = entries.iter()
+        .filter(|x| player.map(|player| x.target.eq_ignore_ascii_case(player)).unwrap_or(true))
+        .collect();
+
+    if entries.is_empty() {
+        return "No moderation actions recorded".to_string();
+    }
+
+    let mut text = String::new();
+    for entry in &entries[entries.len().saturating_sub(SHOWN_ENTRIES)..] {
+        let time = Local.timestamp_millis_opt(entry.time as i64).single()
+            .map(|x| x.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        let _ = write!(&mut text, "{} {} {} {} by {}", time, if entry.succeeded { "✔" } else { "✘" }, entry.action, entry.target, entry.user);
+        if let Some(confirmed_by) = &entry.confirmed_by {
+            let _ = write!(&mut text, " (confirmed by {})", confirmed_by);
+        }
+        if let Some(reason) = &entry.reason {
+            let _ = write!(&mut text, ": {}", reason);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audit::{format_entries, AuditEntry};
+
+    #[test]
+    fn test_entries() {
+        let mut ban = AuditEntry::new("alice".to_string(), 1, "ban Steve griefing the spawn");
+        assert_eq!((ban.action.as_str(), ban.target.as_str(), ban.reason.as_deref()), ("ban", "Steve", Some("griefing the spawn")));
+        ban.succeeded = true;
+
+        let pardon = AuditEntry::new("bob".to_string(), 2, "pardon Alex");
+        assert_eq!(pardon.reason, None);
+
+        let entries = vec![ban, pardon];
+        let text = format_entries(&entries, Some("steve"));
+        assert!(text.contains("✔ ban Steve by alice: griefing the spawn\n"));
+        assert!(!text.contains("Alex"));
+        assert_eq!(format_entries(&entries, Some("Notch")), "No moderation actions recorded");
+    }
+}
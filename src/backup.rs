@@ -0,0 +1,413 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use regex::Regex;
+use serde::Deserialize;
+use serenity::model::channel::AttachmentType;
+use serenity::model::id::ChannelId;
+use serenity::prelude::*;
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+use crate::{Packet, say_or_log, send_or_log, s3, update};
+use crate::properties::Properties;
+use crate::server_files::SERVER_PROPERTIES_PATH;
+
+pub const BACKUP_DIR: &str = "backups";
+
+// The server holds this open while it's running, and it's meaningless in a backup
+const SKIPPED_FILES: &[&str] = &["session.lock"];
+
+#[derive(Deserialize, Clone)]
+pub struct BackupToml {
+    // Backups larger than this are uploaded to the destination instead of attached
+    #[serde(default = "default_attachment_limit_mb")]
+    pub attachment_limit_mb: u64,
+    pub destination: Option<BackupDestination>,
+
+    // Number of backups kept in backups/ and at the destination, older ones are deleted
+    pub keep_local: Option<usize>,
+    pub keep_remote: Option<usize>,
+}
+
+impl Default for BackupToml {
+    fn default() -> BackupToml {
+        BackupToml {
+            attachment_limit_mb: default_attachment_limit_mb(),
+            destination: None,
+            keep_local: None,
+            keep_remote: None,
+        }
+    }
+}
+
+fn default_attachment_limit_mb() -> u64 {
+    8
+}
+
+// Where backups too large to attach, and scheduled backups, are copied to. url_prefix turns the
+// copied file's name into a link, eg. when the directory is served over http.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum BackupDestination {
+    Local { path: String, url_prefix: Option<String> },
+    // Copied with scp so the bot's user needs key based access, target is eg. user@host:/backups
+    Sftp { target: String, url_prefix: Option<String> },
+    S3 {
+        #[serde(flatten)]
+        s3: s3::S3Toml,
+        // Prepended to the backup's file name to make its key, eg. "backups/"
+        #[serde(default)]
+        prefix: String,
+        url_prefix: Option<String>,
+    },
+}
+
+// What asked for a backup. Scheduled backups always go to the destination, and are reported in
+// channel_id when there's an admin channel.
+#[derive(Clone, Copy)]
+pub struct BackupRequest {
+    pub channel_id: Option<ChannelId>,
+    pub scheduled: bool,
+}
+
+// The world directory is named by level-name in server.properties
+pub fn world_dir() -> String {
+    Properties::read(SERVER_PROPERTIES_PATH).ok()
+        .and_then(|x| x.get("level-name").map(|x| x.to_string()))
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| "world".to_string())
+}
+
+fn add_dir_to_zip(zip: &mut zip::ZipWriter<std::fs::File>, dir: &Path, prefix: &str) -> Result<(), String> {
+    let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("Error reading {}: {}", dir.display(), e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Error reading {}: {}", dir.display(), e))?;
+        let name = format!("{}/{}", prefix, entry.file_name().to_string_lossy());
+        let path = entry.path();
+
+        if path.is_dir() {
+            zip.add_directory(name.as_str(), options).map_err(|e| format!("Error writing backup: {}", e))?;
+            add_dir_to_zip(zip, &path, &name)?;
+        } else if !SKIPPED_FILES.contains(&entry.file_name().to_string_lossy().as_ref()) {
+            let data = std::fs::read(&path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+            zip.start_file(name.as_str(), options).map_err(|e| format!("Error writing backup: {}", e))?;
+            zip.write_all(&data).map_err(|e| format!("Error writing backup: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+// Compresses the world into backups/<world>-<timestamp>.zip, the world directory is stored at
+// the root of the archive
+pub async fn create_backup(world_dir: &str) -> Result<PathBuf, String> {
+    let world_dir = world_dir.to_string();
+
+    // The zip crate is synchronous so compression runs on the blocking thread pool
+    tokio::task::spawn_blocking(move || {
+        if !Path::new(&world_dir).is_dir() {
+            return Err(format!("World directory {} not found", world_dir));
+        }
+
+        std::fs::create_dir_all(BACKUP_DIR).map_err(|e| format!("Error creating {}: {}", BACKUP_DIR, e))?;
+        let name = format!("{}-{}.zip", backup_name_prefix(&world_dir), Local::now().format("%Y%m%d-%H%M%S"));
+        let path = Path::new(BACKUP_DIR).join(name);
+
+        let file = std::fs::File::create(&path).map_err(|e| format!("Error creating {}: {}", path.display(), e))?;
+        let mut zip = zip::ZipWriter::new(file);
+        let prefix = Path::new(&world_dir).file_name().map(|x| x.to_string_lossy().to_string()).unwrap_or(world_dir.clone());
+        if let Err(e) = add_dir_to_zip(&mut zip, Path::new(&world_dir), &prefix).and_then(|_| zip.finish().map(|_| ()).map_err(|e| format!("Error writing backup: {}", e))) {
+            let _ = std::fs::remove_file(&path);
+            return Err(e);
+        }
+        Ok(path)
+    }).await.map_err(|e| format!("Error creating backup: {}", e))?
+}
+
+// Copies a backup to the destination, returning a link or path to it
+pub async fn upload(destination: &BackupDestination, path: &Path) -> Result<String, String> {
+    let file_name = path.file_name().map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+    let (location, url_prefix) = match destination {
+        BackupDestination::Local { path: dir, url_prefix } => {
+            tokio::fs::create_dir_all(dir).await.map_err(|e| format!("Error creating {}: {}", dir, e))?;
+            let target = Path::new(dir).join(&file_name);
+            tokio::fs::copy(path, &target).await.map_err(|e| format!("Error copying backup to {}: {}", target.display(), e))?;
+            (target.display().to_string(), url_prefix)
+        },
+        BackupDestination::Sftp { target, url_prefix } => {
+            let status = tokio::process::Command::new("scp")
+                .args(["-q", "-o", "BatchMode=yes"])
+                .arg(path)
+                .arg(target)
+                .status().await
+                .map_err(|e| format!("Error running scp: {}", e))?;
+            if !status.success() {
+                return Err(format!("scp exited with {}", status));
+            }
+            (format!("{}/{}", target.trim_end_matches('/'), file_name), url_prefix)
+        },
+        BackupDestination::S3 { s3, prefix, url_prefix } => {
+            let key = format!("{}{}", prefix, file_name);
+            s3::put_object(s3, &key, path).await?;
+            (format!("s3://{}/{}", s3.bucket, key), url_prefix)
+        },
+    };
+
+    Ok(match url_prefix {
+        Some(url_prefix) => format!("{}/{}", url_prefix.trim_end_matches('/'), file_name),
+        None => location,
+    })
+}
+
+// Picks which of a list of backup names to delete so only the newest keep remain. Names contain
+// a timestamp so sorting them sorts by age.
+pub fn expired_backups(mut names: Vec<String>, keep: usize) -> Vec<String> {
+    names.sort();
+    let expired = names.len().saturating_sub(keep);
+    names.truncate(expired);
+    names
+}
+
+// What create_backup starts a backup's file name with, before the timestamp
+fn backup_name_prefix(world_dir: &str) -> String {
+    world_dir.replace(['/', '\\'], "_")
+}
+
+// Whether a file is one of world_dir's backups. Other archives next to them, including other
+// worlds' backups, are never deleted by retention.
+fn is_backup_name(name: &str, world_dir: &str) -> bool {
+    let pattern = format!(r"^{}-\d{{8}}-\d{{6}}\.zip$", regex::escape(&backup_name_prefix(world_dir)));
+    Regex::new(&pattern).unwrap().is_match(name)
+}
+
+// Ids of the backups in backups/, newest first. A backup's id is its file name without .zip.
+pub fn list_backups() -> Vec<String> {
+    let mut ids: Vec<String> = std::fs::read_dir(BACKUP_DIR).map(|entries| {
+        entries.filter_map(|x| x.ok())
+            .filter_map(|x| x.file_name().to_string_lossy().strip_suffix(".zip").map(|x| x.to_string()))
+            .collect()
+    }).unwrap_or_default();
+    ids.sort_by(|a, b| b.cmp(a));
+    ids
+}
+
+// Finds the archive for a backup id, ids can't contain paths so only backups/ can be restored from
+pub fn backup_path(id: &str) -> Option<PathBuf> {
+    if id.is_empty() || id.contains(['/', '\\']) || id.starts_with('.') {
+        return None;
+    }
+    let path = Path::new(BACKUP_DIR).join(format!("{}.zip", id));
+    path.is_file().then_some(path)
+}
+
+// Finds the world inside an extracted backup, the directory holding level.dat
+fn find_world(dir: &Path) -> Option<PathBuf> {
+    if dir.join("level.dat").is_file() {
+        return Some(dir.to_path_buf());
+    }
+    std::fs::read_dir(dir).ok()?
+        .filter_map(|x| x.ok())
+        .map(|x| x.path())
+        .find(|x| x.join("level.dat").is_file())
+}
+
+// Checks an extracted world looks complete before the server is allowed to boot it
+fn verify_world(world: &Path) -> Result<(), String> {
+    let level_dat = std::fs::metadata(world.join("level.dat")).map_err(|_| "The backup has no level.dat".to_string())?;
+    if level_dat.len() == 0 {
+        return Err("The backup's level.dat is empty".to_string());
+    }
+    if !world.join("region").is_dir() {
+        return Err("The backup has no region directory".to_string());
+    }
+    Ok(())
+}
+
+// Moves the world extracted into restore_dir into place, returning where the current world was
+// moved to
+async fn swap_world(restore_dir: &Path, report: &impl Fn(String)) -> Result<PathBuf, String> {
+    let world = find_world(restore_dir).ok_or("The backup doesn't contain a world".to_string())?;
+    verify_world(&world)?;
+    report("Backup verified, swapping worlds".to_string());
+
+    let world_dir = PathBuf::from(world_dir());
+    let previous = PathBuf::from(format!("{}-before-restore", world_dir.display()));
+    update::remove_path(&previous).await?;
+    if world_dir.exists() {
+        tokio::fs::rename(&world_dir, &previous).await.map_err(|e| format!("Error moving {}: {}", world_dir.display(), e))?;
+    }
+
+    if let Err(e) = tokio::fs::rename(&world, &world_dir).await {
+        let _ = tokio::fs::rename(&previous, &world_dir).await;
+        return Err(format!("Error moving the backup into place: {}", e));
+    }
+    Ok(previous)
+}
+
+// Replaces the world with a backup. The server must be stopped. The current world is kept as
+// <world>-before-restore in case the restore needs undoing.
+pub async fn run_restore(id: &str, report: impl Fn(String)) -> Result<(), String> {
+    let archive = backup_path(id).ok_or(format!("No backup with id {}", id))?;
+    let restore_dir = Path::new(BACKUP_DIR).join("restore-tmp");
+    update::remove_path(&restore_dir).await?;
+
+    // Extracting also checks each file's checksum
+    report(format!("Extracting backup {}", id));
+    let result = match update::extract(&archive.to_string_lossy(), &restore_dir.to_string_lossy()).await {
+        Ok(()) => swap_world(&restore_dir, &report).await,
+        Err(e) => Err(e),
+    };
+
+    // Whatever is left of the extracted backup is removed whether or not the swap worked
+    let _ = update::remove_path(&restore_dir).await;
+    let previous = result?;
+    report(format!("World restored, the previous world was kept in {}", previous.display()));
+    Ok(())
+}
+
+// Deletes old backups from backups/, returning how many were deleted
+async fn apply_local_retention(dir: &Path, world_dir: &str, keep: usize) -> Result<usize, String> {
+    let mut names = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await.map_err(|e| format!("Error reading {}: {}", dir.display(), e))?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if is_backup_name(&name, world_dir) {
+            names.push(name);
+        }
+    }
+
+    let expired = expired_backups(names, keep);
+    for name in &expired {
+        tokio::fs::remove_file(dir.join(name)).await.map_err(|e| format!("Error deleting {}: {}", name, e))?;
+    }
+    Ok(expired.len())
+}
+
+// Deletes old backups from the destination. scp destinations can't be listed so they're skipped.
+async fn apply_remote_retention(destination: &BackupDestination, world_dir: &str, keep: usize) -> Result<usize, String> {
+    match destination {
+        BackupDestination::Local { path, .. } => apply_local_retention(Path::new(path), world_dir, keep).await,
+        BackupDestination::Sftp { .. } => Ok(0),
+        BackupDestination::S3 { s3, prefix, .. } => {
+            let keys: Vec<String> = s3::list_objects(s3, prefix).await?.into_iter()
+                .map(|x| x.key)
+                .filter(|x| x.strip_prefix(prefix.as_str()).is_some_and(|name| is_backup_name(name, world_dir)))
+                .collect();
+
+            let expired = expired_backups(keys, keep);
+            for key in &expired {
+                s3::delete_object(s3, key).await?;
+            }
+            Ok(expired.len())
+        },
+    }
+}
+
+async fn report(channel_id: Option<ChannelId>, ctx: &Context, text: &str) {
+    match channel_id {
+        Some(channel_id) => say_or_log(channel_id, ctx, text).await,
+        None => info!("{}", text),
+    }
+}
+
+// Uploads or attaches a finished backup and applies retention
+async fn deliver_backup(ctx: &Context, request: BackupRequest, config: &BackupToml, world_dir: &str, path: &Path) {
+    let size = tokio::fs::metadata(path).await.map(|x| x.len()).unwrap_or(u64::MAX);
+    let size_mb = size as f64 / (1024.0 * 1024.0);
+
+    if let (Some(channel_id), false) = (request.channel_id, request.scheduled) {
+        if size <= config.attachment_limit_mb * 1024 * 1024 {
+            let content = format!("World backup ({:.1} MB)", size_mb);
+            if let Err(e) = channel_id.send_files(&ctx.http, [AttachmentType::Path(path)], |m| m.content(content)).await {
+                error!("Error sending attachment: {:?}", e);
+                say_or_log(channel_id, ctx, &format!("Error attaching backup, it was saved to {}", path.display())).await;
+            }
+            return;
+        }
+    }
+
+    let reply = match &config.destination {
+        Some(destination) => match upload(destination, path).await {
+            Ok(location) => format!("World backup ({:.1} MB): {}", size_mb, location),
+            Err(e) => format!("{}, the backup was saved to {}", e, path.display()),
+        },
+        None if request.scheduled => format!("World backup ({:.1} MB) saved to {}", size_mb, path.display()),
+        None => format!("World backup ({:.1} MB) is too large to attach, it was saved to {}", size_mb, path.display()),
+    };
+    report(request.channel_id, ctx, &reply).await;
+
+    if let (Some(destination), Some(keep)) = (&config.destination, config.keep_remote) {
+        if let Err(e) = apply_remote_retention(destination, world_dir, keep).await {
+            report(request.channel_id, ctx, &format!("Error deleting old backups from the destination: {}", e)).await;
+        }
+    }
+}
+
+// Backs up the world in the background, then attaches or uploads it and replies in the
+// request's channel. BackupFinished is sent once the world has been copied so saving can be
+// turned back on.
+pub fn start_backup(ctx: Context, request: BackupRequest, config: &BackupToml, sender: &mpsc::UnboundedSender<Packet>) {
+    let config = config.clone();
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        let world_dir = world_dir();
+        let result = create_backup(&world_dir).await;
+        send_or_log(&sender, Packet::BackupFinished(result.as_ref().ok().cloned()));
+
+        match result {
+            Ok(path) => deliver_backup(&ctx, request, &config, &world_dir, &path).await,
+            Err(e) => report(request.channel_id, &ctx, &e).await,
+        }
+
+        if let Some(keep) = config.keep_local {
+            if let Err(e) = apply_local_retention(Path::new(BACKUP_DIR), &world_dir, keep).await {
+                report(request.channel_id, &ctx, &e).await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backup::{apply_local_retention, expired_backups, is_backup_name, BackupDestination, BackupToml};
+
+    #[test]
+    fn test_expired_backups() {
+        let names = vec!["world-20240103-030000.zip".to_string(), "world-20240101-030000.zip".to_string(), "world-20240102-030000.zip".to_string()];
+        assert_eq!(expired_backups(names.clone(), 2), vec!["world-20240101-030000.zip".to_string()]);
+        assert!(expired_backups(names, 5).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_local_retention() {
+        let dir = std::env::temp_dir().join(format!("mc-discord-bot-backup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let names = ["world-20240101-030000.zip", "world-20240102-030000.zip", "world-20240103-030000.zip", "photos.zip", "world_nether-20230101-030000.zip", "world-old.zip"];
+        for name in names {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        assert_eq!(apply_local_retention(&dir, "world", 1).await, Ok(2));
+        let mut left: Vec<String> = std::fs::read_dir(&dir).unwrap().map(|x| x.unwrap().file_name().to_string_lossy().to_string()).collect();
+        left.sort();
+        assert_eq!(left, vec!["photos.zip", "world-20240103-030000.zip", "world-old.zip", "world_nether-20230101-030000.zip"]);
+
+        assert!(is_backup_name("worlds_survival-20240101-030000.zip", "worlds/survival"));
+        assert!(!is_backup_name("world-20240101-030000.zip.part", "world"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_parse_destination() {
+        let config: BackupToml = toml::from_str("keep_remote = 7\n[destination]\ntype = \"s3\"\nendpoint = \"https://s3.example.com\"\nbucket = \"mc\"\naccess_key = \"a\"\nsecret_key = \"b\"\nprefix = \"backups/\"").unwrap();
+        match config.destination {
+            Some(BackupDestination::S3 { s3, prefix, .. }) => {
+                assert_eq!((s3.bucket.as_str(), s3.region.as_str(), prefix.as_str()), ("mc", "us-east-1", "backups/"));
+            },
+            _ => panic!("Expected an S3 destination"),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use serde::Deserialize;
+
+// Floodgate prefixes Bedrock players' names so they can't clash with Java players, eg. ".Steve".
+// The prefix is left off their names in discord and stats.
+#[derive(Deserialize)]
+pub struct BedrockToml {
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+}
+
+fn default_prefix() -> String {
+    ".".to_string()
+}
+
+#[derive(Default)]
+pub struct BedrockNames {
+    prefix: Option<String>,
+}
+
+impl BedrockNames {
+    pub fn new(config: Option<&BedrockToml>) -> BedrockNames {
+        BedrockNames { prefix: config.map(|x| x.prefix.clone()).filter(|x| !x.is_empty()) }
+    }
+
+    // The name to show for a player, names that are only the prefix are left alone
+    pub fn display<'a>(&self, name: &'a str) -> &'a str {
+        match &self.prefix {
+            Some(prefix) => name.strip_prefix(prefix.as_str()).filter(|x| !x.is_empty()).unwrap_or(name),
+            None => name,
+        }
+    }
+
+    // Fixes the player's name at the start of a death or advancement message
+    pub fn message(&self, player: &str, message: &str) -> String {
+        match message.strip_prefix(player) {
+            Some(rest) => format!("{}{}", self.display(player), rest),
+            None => message.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bedrock::{BedrockNames, BedrockToml};
+
+    #[test]
+    fn test_bedrock_names() {
+        let config: BedrockToml = toml::from_str("").unwrap();
+        let names = BedrockNames::new(Some(&config));
+        assert_eq!(names.display(".Steve"), "Steve");
+        assert_eq!(names.display("Alex"), "Alex");
+        assert_eq!(names.display("."), ".");
+        assert_eq!(names.message(".Steve", ".Steve fell out of the world"), "Steve fell out of the world");
+
+        assert_eq!(BedrockNames::default().display(".Steve"), ".Steve");
+    }
+}
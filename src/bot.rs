@@ -0,0 +1,201 @@
+// State shared by the subsystems that handle packets: the discord connection, the config and the
+// server's console, along with the places every subsystem reports to. Each subsystem keeps its
+// own state and gets this passed to its handle method.
+
+use std::sync::{Arc, Mutex};
+
+use serenity::model::gateway::Activity;
+use serenity::model::id::{ChannelId, UserId};
+use serenity::prelude::*;
+use tokio::sync::mpsc;
+
+use crate::api::EventStream;
+use crate::bedrock::BedrockNames;
+use crate::calendar::Calendar;
+use crate::chat_filter::ChatFilter;
+use crate::command_queue::CommandQueue;
+use crate::console::Console;
+use crate::cross_server::CrossServer;
+use crate::hooks::Hooks;
+use crate::locale::Locale;
+use crate::log_buffer::LogBuffer;
+use crate::log_mirror::LogMirror;
+use crate::maintenance::MaintenanceState;
+use crate::metrics::SharedMetrics;
+use crate::outbound::OutboundQueue;
+use crate::permissions::{self, Permissions};
+use crate::player_reports::Reports;
+use crate::plugins::Plugins;
+use crate::polls::Poll;
+use crate::pregen::PregenTask;
+use crate::presence::{Presence, PresenceValues};
+use crate::redaction::Redactor;
+use crate::tempbans::TempBans;
+use crate::vote_actions::CommunityVotes;
+use crate::warnings::Warnings;
+use crate::{say_or_log, write_console, ConfigToml, Packet};
+
+pub struct Bot {
+    // Set once discord is ready
+    pub ctx: Option<Context>,
+    pub config: ConfigToml,
+    pub locale: Locale,
+    pub sender: mpsc::UnboundedSender<Packet>,
+    pub metrics: SharedMetrics,
+    pub channel_id: ChannelId,
+    pub admin_channel_id: Option<ChannelId>,
+    pub permissions: Permissions,
+    pub admin_user_ids: Vec<UserId>,
+    pub bedrock: BedrockNames,
+    // Set while the server is running
+    pub console: Option<Console>,
+    // Console commands waiting for their response
+    pub command_queue: CommandQueue,
+    pub plugins: Plugins,
+    pub events: EventStream,
+    pub hooks: Hooks,
+    // Applied to log output before it's posted to discord
+    pub redactor: Redactor,
+    pub chat_filter: Option<ChatFilter>,
+    // Sends game chat to the other servers' bots, when set up
+    pub cross_server: Option<CrossServer>,
+    // Game chat, deaths and advancements, started once discord is ready
+    pub outbound: Option<OutboundQueue>,
+    pub presence: Presence,
+    // Recent server log lines for !logs
+    pub log_buffer: LogBuffer,
+    pub log_mirror: Option<LogMirror>,
+    // The running !vote poll, voted on from discord and in game
+    pub poll: Option<Poll>,
+    // Open !voteday and !voterestart votes, when they're enabled
+    pub votes: Option<CommunityVotes>,
+    // Upcoming !event events
+    pub calendar: Calendar,
+    // Set while maintenance mode is on
+    pub maintenance: Option<MaintenanceState>,
+    // Written to server.properties once the server stops, since it would overwrite it before then
+    pub pending_motd: Option<String>,
+    // The running !pregen, whose progress is read from the log
+    pub pregen: Option<PregenTask>,
+    // In game !report reports waiting in the admin channel
+    pub player_reports: Reports,
+    // !tempban bans waiting to be pardoned
+    pub tempbans: TempBans,
+    // Strikes from !warn
+    pub warnings: Warnings,
+    // Stands in for discord when testing without a connection
+    pub recorder: Option<DiscordRecorder>,
+}
+
+// Keeps the messages the bot would have sent to discord
+#[derive(Clone, Default)]
+pub struct DiscordRecorder(Arc<Mutex<Vec<(ChannelId, String)>>>);
+
+impl DiscordRecorder {
+    pub fn push(&self, channel_id: ChannelId, text: &str) {
+        self.0.lock().unwrap().push((channel_id, text.to_string()));
+    }
+
+    // Returns the messages recorded since the last call
+    #[cfg(test)]
+    pub fn take(&self) -> Vec<(ChannelId, String)> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl Bot {
+    pub fn new(config: ConfigToml, sender: mpsc::UnboundedSender<Packet>, metrics: SharedMetrics, channel_id: ChannelId, admin_channel_id: Option<ChannelId>, log_mirror: Option<LogMirror>) -> Bot {
+        let mut bot = Bot {
+            ctx: None,
+            locale: Locale::new(&config.language).with_templates(&config.templates.messages),
+            sender,
+            metrics,
+            channel_id,
+            admin_channel_id,
+            permissions: Permissions::new(&config.permissions),
+            admin_user_ids: permissions::parse_user_ids(&config.admin_user_ids),
+            bedrock: BedrockNames::new(config.bedrock.as_ref()),
+            console: None,
+            command_queue: CommandQueue::default(),
+            plugins: Plugins::load(&config.plugins_dir),
+            events: EventStream::new(),
+            hooks: Hooks::new(&config.hooks),
+            redactor: Redactor::new(&config.redaction),
+            chat_filter: config.chat_filter.as_ref().map(ChatFilter::new),
+            cross_server: config.cross_server.as_ref().map(CrossServer::new),
+            outbound: None,
+            presence: Presence::new(&config.presence),
+            log_buffer: LogBuffer::new(config.log_buffer_lines),
+            log_mirror,
+            poll: None,
+            votes: config.votes.as_ref().map(CommunityVotes::new),
+            calendar: Calendar::read(),
+            maintenance: None,
+            pending_motd: None,
+            pregen: None,
+            player_reports: Reports::default(),
+            tempbans: TempBans::read(),
+            warnings: Warnings::read(),
+            recorder: None,
+            config,
+        };
+        if let Some(state) = MaintenanceState::read() {
+            bot.presence.fixed = Some(bot.config.maintenance.motd.clone());
+            bot.maintenance = Some(state);
+        }
+        bot
+    }
+
+    // Whether discord is ready or being recorded, server events are only handled once it is
+    pub fn is_connected(&self) -> bool {
+        self.ctx.is_some() || self.recorder.is_some()
+    }
+
+    // Does nothing until discord is ready
+    pub async fn say(&self, channel_id: ChannelId, text: &str) {
+        match (&self.ctx, &self.recorder) {
+            (Some(ctx), _) => say_or_log(channel_id, ctx, text).await,
+            (None, Some(recorder)) => recorder.push(channel_id, text),
+            (None, None) => {},
+        }
+    }
+
+    // Game chat, deaths and advancements, these are batched by the outbound queue
+    pub fn say_game(&self, text: &str) {
+        match (&self.outbound, &self.recorder) {
+            (Some(outbound), _) => outbound.say(self.channel_id, text),
+            (None, Some(recorder)) => recorder.push(self.channel_id, text),
+            (None, None) => {},
+        }
+    }
+
+    // Does nothing without an admin channel
+    pub async fn say_admin(&self, text: &str) {
+        if let Some(admin_channel_id) = self.admin_channel_id {
+            self.say(admin_channel_id, text).await;
+        }
+    }
+
+    // The bot exits when the server crashes so both are restarted, tests carry on instead
+    pub fn exit(&self) {
+        if self.recorder.is_none() {
+            std::process::exit(0);
+        }
+    }
+
+    pub async fn write_console(&self, line: &str) {
+        write_console(&self.console, line).await;
+    }
+
+    // Shows the new player count in the bot's status
+    pub async fn players_changed(&mut self, players_online: usize) {
+        let values = {
+            let mut metrics = self.metrics.lock().unwrap();
+            metrics.players_online = players_online;
+            PresenceValues::new(players_online, &metrics)
+        };
+        if let Some(ctx) = &self.ctx {
+            ctx.set_activity(Activity::playing(self.presence.current(&values))).await;
+        }
+    }
+}
@@ -0,0 +1,205 @@
+// Community events added with !event. Reminders are sent in discord and in game reminder_mins
+// before each event and again when it starts, events are kept in a file so they survive restarts.
+
+use std::time::Duration;
+
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::error;
+
+use crate::bot::Bot;
+use crate::scheduler::{next_occurrence, next_weekly_occurrence};
+use crate::{send_or_log, tellraw, Packet};
+
+pub const CALENDAR_PATH: &str = "mc-discord-bot-events.json";
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// Starts aren't announced if the bot was down for longer than this past them
+const LATE_START_SECS: i64 = 10 * 60;
+
+pub const USAGE: &str = "Usage: `!event add <when> <description>`, when is `YYYY-MM-DD HH:MM`, `sat 20:00` or `20:00`";
+
+#[derive(Deserialize)]
+pub struct CalendarToml {
+    #[serde(default = "default_reminder_mins")]
+    pub reminder_mins: Vec<u64>,
+}
+
+impl Default for CalendarToml {
+    fn default() -> CalendarToml {
+        CalendarToml { reminder_mins: default_reminder_mins() }
+    }
+}
+
+fn default_reminder_mins() -> Vec<u64> {
+    vec![60, 10]
+}
+
+// Parses the start of !event add's arguments as a local time after now, returning it and the
+// rest of the text
+pub fn parse_when(text: &str, now: NaiveDateTime) -> Option<(NaiveDateTime, &str)> {
+    let mut words = text.splitn(3, ' ');
+    let first = words.next()?;
+    let second = words.next().unwrap_or("");
+    let rest = words.next().unwrap_or("").trim();
+
+    let time = |x: &str| NaiveTime::parse_from_str(x, "%H:%M").ok();
+    let when = if let (Ok(date), Some(time)) = (NaiveDate::parse_from_str(first, "%Y-%m-%d"), time(second)) {
+        (date.and_time(time), rest)
+    } else if let (Ok(day), Some(time)) = (first.parse::<Weekday>(), time(second)) {
+        (next_weekly_occurrence(day, time, now), rest)
+    } else {
+        let time = time(first)?;
+        (next_occurrence(&[time], now)?, text[first.len()..].trim())
+    };
+    (when.0 > now).then_some(when)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub id: u64,
+    // Unix timestamp in seconds
+    pub time: i64,
+    pub description: String,
+    pub added_by: u64,
+    // Reminders already sent, in minutes before the event
+    #[serde(default)]
+    pub reminded: Vec<u64>,
+}
+
+impl Event {
+    // eg. "#3 Build contest <t:1717264800:f> (<t:1717264800:R>)", shown in the reader's time zone
+    pub fn describe(&self) -> String {
+        format!("#{} {} <t:{}:f> (<t:{}:R>)", self.id, self.description, self.time, self.time)
+    }
+}
+
+#[derive(Default)]
+pub struct Calendar {
+    events: Vec<Event>,
+}
+
+impl Calendar {
+    pub fn read() -> Calendar {
+        let events = std::fs::read_to_string(CALENDAR_PATH).ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default();
+        Calendar { events }
+    }
+
+    pub fn write(&self) {
+        let json_str = serde_json::to_string_pretty(&self.events).unwrap();
+        if let Err(e) = std::fs::write(CALENDAR_PATH, json_str) {
+            error!("Error writing {}: {}", CALENDAR_PATH, e);
+        }
+    }
+
+    pub fn add(&mut self, time: i64, description: &str, added_by: u64) -> &Event {
+        let id = self.events.iter().map(|x| x.id).max().unwrap_or(0) + 1;
+        self.events.push(Event { id, time, description: description.to_string(), added_by, reminded: Vec::new() });
+        self.events.sort_by_key(|x| x.time);
+        self.events.iter().find(|x| x.id == id).unwrap()
+    }
+
+    pub fn upcoming(&self) -> &[Event] {
+        &self.events
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Event> {
+        self.events.iter().find(|x| x.id == id)
+    }
+
+    pub fn delete(&mut self, id: u64) -> bool {
+        let count = self.events.len();
+        self.events.retain(|x| x.id != id);
+        self.events.len() != count
+    }
+
+    // Returns the reminders due at now, removing events once they've started. When several of an
+    // event's reminders are due at once, eg. after the bot was down, only the latest is sent.
+    pub fn due(&mut self, reminder_mins: &[u64], now: i64) -> Vec<String> {
+        let mut reminders = Vec::new();
+        for event in &mut self.events {
+            if now >= event.time {
+                if now - event.time < LATE_START_SECS {
+                    reminders.push(format!("Event starting now: {}", event.description));
+                }
+                continue;
+            }
+
+            let due: Vec<u64> = reminder_mins.iter().copied()
+                .filter(|x| !event.reminded.contains(x) && now >= event.time - *x as i64 * 60)
+                .collect();
+            if let Some(mins) = due.iter().min() {
+                let plural = if *mins == 1 { "" } else { "s" };
+                reminders.push(format!("Event in {} minute{}: {}", mins, plural, event.description));
+                event.reminded.extend(due);
+            }
+        }
+        self.events.retain(|x| x.time > now);
+        reminders
+    }
+}
+
+// Converts a local time to a unix timestamp
+pub fn timestamp(time: NaiveDateTime) -> Option<i64> {
+    Local.from_local_datetime(&time).earliest().map(|x| x.timestamp())
+}
+
+pub fn start_calendar_timer(sender: &mpsc::UnboundedSender<Packet>) {
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            send_or_log(&sender, Packet::CalendarTick());
+        }
+    });
+}
+
+// Sends any reminders that are due in game and to the chat channel
+pub async fn remind(bot: &mut Bot) {
+    let reminders = bot.calendar.due(&bot.config.calendar.reminder_mins, Local::now().timestamp());
+    if reminders.is_empty() {
+        return;
+    }
+    bot.calendar.write();
+
+    for reminder in reminders {
+        bot.write_console(&tellraw::to_all(&reminder)).await;
+        bot.say(bot.channel_id, &format!("📅 {}", reminder)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::calendar::{parse_when, Calendar};
+
+    #[test]
+    fn test_calendar() {
+        // 2024-01-01 is a monday
+        let at = |day, hour, minute| NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(hour, minute, 0).unwrap();
+        let now = at(1, 12, 0);
+        assert_eq!(parse_when("2024-01-06 20:00 Build contest", now), Some((at(6, 20, 0), "Build contest")));
+        assert_eq!(parse_when("sat 20:00 Build contest", now), Some((at(6, 20, 0), "Build contest")));
+        assert_eq!(parse_when("09:30 Mining trip", now), Some((at(2, 9, 30), "Mining trip")));
+        assert_eq!(parse_when("2023-12-31 20:00 Too late", now), None);
+        assert_eq!(parse_when("soon Party", now), None);
+
+        let mut calendar = Calendar::default();
+        calendar.add(10_000, "Build contest", 1);
+        assert_eq!(calendar.add(5_000, "Mining trip", 1).id, 2);
+        assert_eq!(calendar.upcoming()[0].description, "Mining trip");
+
+        // The bot was down for the hour reminder, so only the 10 minute one is sent
+        assert_eq!(calendar.due(&[60, 10], 4_500), vec!["Event in 10 minutes: Mining trip".to_string()]);
+        assert!(calendar.due(&[60, 10], 4_600).is_empty());
+        assert_eq!(calendar.due(&[60, 10], 5_000), vec!["Event starting now: Mining trip".to_string()]);
+        assert_eq!(calendar.upcoming().len(), 1);
+        assert!(calendar.delete(1));
+        assert!(calendar.get(1).is_none());
+    }
+}
@@ -0,0 +1,83 @@
+// Announces when the server is full and pings everyone who asked with !notify once a slot frees
+// up. The player limit comes from the query or status ping when they're set up, since a proxy's
+// limit can differ from server.properties.
+
+use crate::properties::Properties;
+use crate::server_files::SERVER_PROPERTIES_PATH;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CapacityChange {
+    Full(u32),
+    // The discord users waiting for a slot
+    SlotFree(Vec<u64>),
+}
+
+#[derive(Default)]
+pub struct Capacity {
+    // The limit the status ping last reported
+    pub ping_max: Option<u32>,
+    full: bool,
+    waiting: Vec<u64>,
+}
+
+// max-players from server.properties
+pub fn properties_max() -> Option<u32> {
+    Properties::read(SERVER_PROPERTIES_PATH).ok()?.get("max-players")?.parse().ok()
+}
+
+impl Capacity {
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+
+    // Adds or removes the user from those waiting for a slot, returning whether they're now waiting
+    pub fn toggle_notify(&mut self, user_id: u64) -> bool {
+        match self.waiting.iter().position(|x| *x == user_id) {
+            Some(i) => {
+                self.waiting.remove(i);
+                false
+            },
+            None => {
+                self.waiting.push(user_id);
+                true
+            },
+        }
+    }
+
+    // Called whenever the player count changes
+    pub fn players_changed(&mut self, online: usize, max: u32) -> Option<CapacityChange> {
+        let full = max > 0 && online >= max as usize;
+        if full == self.full {
+            return None;
+        }
+
+        self.full = full;
+        match full {
+            true => Some(CapacityChange::Full(max)),
+            false if self.waiting.is_empty() => None,
+            false => Some(CapacityChange::SlotFree(std::mem::take(&mut self.waiting))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capacity::{Capacity, CapacityChange};
+
+    #[test]
+    fn test_capacity() {
+        let mut capacity = Capacity::default();
+        assert_eq!(capacity.players_changed(1, 2), None);
+        assert_eq!(capacity.players_changed(2, 2), Some(CapacityChange::Full(2)));
+        assert_eq!(capacity.players_changed(2, 2), None);
+        assert!(capacity.toggle_notify(1));
+        assert!(capacity.toggle_notify(2));
+        assert!(!capacity.toggle_notify(1));
+        assert!(capacity.toggle_notify(3));
+
+        assert_eq!(capacity.players_changed(1, 2), Some(CapacityChange::SlotFree(vec![2, 3])));
+        assert_eq!(capacity.players_changed(2, 2), Some(CapacityChange::Full(2)));
+        assert_eq!(capacity.players_changed(1, 2), None);
+        assert_eq!(capacity.players_changed(5, 0), None);
+    }
+}
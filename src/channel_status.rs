@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use serenity::model::id::ChannelId;
+use serenity::prelude::*;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+use crate::{Packet, send_or_log};
+use crate::presence::{render, PresenceValues};
+
+// Discord only allows a channel's name or topic to be changed twice every 10 minutes
+const MIN_INTERVAL_SECS: u64 = 300;
+
+// Shows the player count in a voice channel's name and/or the chat channel's topic. The
+// templates can use the same values as the presence.
+#[derive(Deserialize)]
+pub struct ChannelStatusToml {
+    // Voice channel that is renamed
+    pub channel_id: Option<String>,
+    #[serde(default = "default_name")]
+    pub name: String,
+    // Topic for the chat channel, it's left alone when not set
+    pub topic: Option<String>,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_name() -> String {
+    "🟢 Online: {players}".to_string()
+}
+
+fn default_interval_secs() -> u64 {
+    600
+}
+
+pub struct ChannelStatus {
+    channel_id: Option<ChannelId>,
+    name: String,
+    topic: Option<String>,
+    // What was last set, so unchanged values aren't sent again
+    last_name: Option<String>,
+    last_topic: Option<String>,
+}
+
+impl ChannelStatus {
+    pub fn new(config: &ChannelStatusToml) -> ChannelStatus {
+        let channel_id = config.channel_id.as_ref().and_then(|x| match x.parse() {
+            Ok(v) => Some(ChannelId(v)),
+            Err(_) => {
+                warn!("Invalid status channel id \"{}\"", x);
+                None
+            },
+        });
+
+        ChannelStatus { channel_id, name: config.name.clone(), topic: config.topic.clone(), last_name: None, last_topic: None }
+    }
+
+    // Returns the channel name and topic that need updating, templates that use a value that
+    // isn't known are skipped
+    pub fn changes(&mut self, values: &PresenceValues) -> (Option<(ChannelId, String)>, Option<String>) {
+        let name = self.channel_id
+            .and_then(|channel_id| Some((channel_id, render(&self.name, values)?)))
+            .filter(|(_, name)| self.last_name.as_ref() != Some(name));
+        if let Some((_, name)) = &name {
+            self.last_name = Some(name.clone());
+        }
+
+        let topic = self.topic.as_ref()
+            .and_then(|x| render(x, values))
+            .filter(|topic| self.last_topic.as_ref() != Some(topic));
+        if let Some(topic) = &topic {
+            self.last_topic = Some(topic.clone());
+        }
+
+        (name, topic)
+    }
+}
+
+// Edits the channels from another task since rate limited edits can take a while
+pub fn apply_changes(ctx: &Context, chat_channel_id: ChannelId, name: Option<(ChannelId, String)>, topic: Option<String>) {
+    let ctx = ctx.clone();
+    tokio::task::spawn(async move {
+        if let Some((channel_id, name)) = name {
+            if let Err(e) = channel_id.edit(&ctx.http, |c| c.name(name)).await {
+                error!("Error renaming status channel: {:?}", e);
+            }
+        }
+
+        if let Some(topic) = topic {
+            if let Err(e) = chat_channel_id.edit(&ctx.http, |c| c.topic(topic)).await {
+                error!("Error setting channel topic: {:?}", e);
+            }
+        }
+    });
+}
+
+// Sends a ChannelStatusTick packet every interval, the first one is sent when discord is ready
+pub fn start_channel_status_timer(config: &ChannelStatusToml, sender: &mpsc::UnboundedSender<Packet>) {
+    let interval = Duration::from_secs(config.interval_secs.max(MIN_INTERVAL_SECS));
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            send_or_log(&sender, Packet::ChannelStatusTick());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::id::ChannelId;
+
+    use crate::channel_status::{ChannelStatus, ChannelStatusToml};
+    use crate::presence::PresenceValues;
+
+    #[test]
+    fn test_channel_status() {
+        let config: ChannelStatusToml = toml::from_str("channel_id = '123'\ntopic = '{players} online, up {uptime}'").unwrap();
+        let mut status = ChannelStatus::new(&config);
+
+        let mut values = PresenceValues { players: 2, tps: None, uptime: None, motd: None };
+        // The topic needs the uptime so it waits until it's known
+        assert_eq!(status.changes(&values), (Some((ChannelId(123), "🟢 Online: 2".to_string())), None));
+
+        values.uptime = Some("1h 00m".to_string());
+        assert_eq!(status.changes(&values), (None, Some("2 online, up 1h 00m".to_string())));
+        assert_eq!(status.changes(&values), (None, None));
+    }
+}
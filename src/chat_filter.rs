@@ -0,0 +1,226 @@
+// Filters chat crossing the bridge in either direction. Blocked words and patterns are masked or
+// the message isn't relayed, players flooding game chat stop being relayed for a while, and the
+// admin channel is told about both. Players who keep tripping the filter are warned.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::bot::Bot;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    // Replaces the matched text with asterisks
+    #[default]
+    Mask,
+    // Doesn't relay the message at all
+    Drop,
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+pub struct FilterPattern(Regex);
+
+impl TryFrom<String> for FilterPattern {
+    type Error = String;
+
+    fn try_from(pattern: String) -> Result<FilterPattern, String> {
+        Regex::new(&pattern).map(FilterPattern).map_err(|e| format!("Invalid chat filter pattern \"{}\": {}", pattern, e))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ChatFilterToml {
+    // Matched as whole words, ignoring case
+    #[serde(default)]
+    pub words: Vec<String>,
+    #[serde(default)]
+    pub patterns: Vec<FilterPattern>,
+    #[serde(default)]
+    pub action: FilterAction,
+    // A player or discord user is warned each time they reach this many filtered messages
+    #[serde(default = "default_warn_after")]
+    pub warn_after: u32,
+    // More than spam_messages game chat messages in spam_secs counts as spam
+    #[serde(default = "default_spam_messages")]
+    pub spam_messages: usize,
+    #[serde(default = "default_spam_secs")]
+    pub spam_secs: u64,
+}
+
+fn default_warn_after() -> u32 {
+    3
+}
+
+fn default_spam_messages() -> usize {
+    5
+}
+
+fn default_spam_secs() -> u64 {
+    10
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FilterResult {
+    Clean,
+    Masked(String),
+    Dropped,
+}
+
+// What to do about a message, the text to relay if any plus what to tell the sender and admins
+#[derive(Debug, PartialEq, Eq, Default)]
+pub struct Verdict {
+    pub relay: Option<String>,
+    pub warning: Option<String>,
+    pub admin_notice: Option<String>,
+}
+
+pub struct ChatFilter {
+    words: Option<Regex>,
+    patterns: Vec<Regex>,
+    action: FilterAction,
+    warn_after: u32,
+    spam_messages: usize,
+    spam_window: Duration,
+    // Filtered messages per sender
+    offences: HashMap<String, u32>,
+    // Recent game chat times per player
+    recent: HashMap<String, VecDeque<Instant>>,
+    // Players whose flood has already been reported
+    flooding: HashSet<String>,
+}
+
+impl ChatFilter {
+    pub fn new(config: &ChatFilterToml) -> ChatFilter {
+        let words: Vec<String> = config.words.iter().filter(|x| !x.is_empty()).map(|x| regex::escape(x)).collect();
+        ChatFilter {
+            words: (!words.is_empty()).then(|| Regex::new(&format!(r"(?i)\b(?:{})\b", words.join("|"))).unwrap()),
+            patterns: config.patterns.iter().map(|x| x.0.clone()).collect(),
+            action: config.action,
+            warn_after: config.warn_after.max(1),
+            spam_messages: config.spam_messages.max(1),
+            spam_window: Duration::from_secs(config.spam_secs),
+            offences: HashMap::new(),
+            recent: HashMap::new(),
+            flooding: HashSet::new(),
+        }
+    }
+
+    pub fn filter(&self, message: &str) -> FilterResult {
+        let matchers = self.words.iter().chain(&self.patterns);
+        let mut masked = message.to_string();
+        for matcher in matchers {
+            masked = matcher.replace_all(&masked, |x: &regex::Captures| "*".repeat(x[0].chars().count())).to_string();
+        }
+        match (masked == message, self.action) {
+            (true, _) => FilterResult::Clean,
+            (false, FilterAction::Mask) => FilterResult::Masked(masked),
+            (false, FilterAction::Drop) => FilterResult::Dropped,
+        }
+    }
+
+    // Counts more than spam_messages messages inside the window as spam
+    fn is_spam(&mut self, player: &str, now: Instant) -> bool {
+        let times = self.recent.entry(player.to_string()).or_default();
+        times.push_back(now);
+        while times.front().is_some_and(|x| now.duration_since(*x) > self.spam_window) {
+            times.pop_front();
+        }
+        times.len() > self.spam_messages
+    }
+
+    fn offence(&mut self, sender: &str) -> Option<String> {
+        let count = self.offences.entry(sender.to_string()).or_insert(0);
+        *count += 1;
+        count.is_multiple_of(self.warn_after).then(|| format!("Please keep chat friendly, {} of your messages have been filtered", count))
+    }
+
+    fn check(&mut self, sender: &str, message: &str, source: &str) -> Verdict {
+        let relay = match self.filter(message) {
+            FilterResult::Clean => return Verdict { relay: Some(message.to_string()), ..Verdict::default() },
+            FilterResult::Masked(masked) => Some(masked),
+            FilterResult::Dropped => None,
+        };
+        let action = if relay.is_some() { "Masked" } else { "Blocked" };
+        Verdict {
+            relay,
+            warning: self.offence(sender),
+            admin_notice: Some(format!("{} {} chat from {}: {}", action, source, sender, message)),
+        }
+    }
+
+    // Game chat on its way to discord
+    pub fn game_message(&mut self, player: &str, message: &str) -> Verdict {
+        self.game_message_at(player, message, Instant::now())
+    }
+
+    fn game_message_at(&mut self, player: &str, message: &str, now: Instant) -> Verdict {
+        if self.is_spam(player, now) {
+            // Reported once per flood, a flood ends once the player slows down
+            let reported = !self.flooding.insert(player.to_string());
+            return Verdict {
+                relay: None,
+                warning: (!reported).then(|| "Slow down, your messages aren't being sent to discord".to_string()),
+                admin_notice: (!reported).then(|| format!("{} is flooding game chat, their messages aren't being relayed", player)),
+            };
+        }
+        self.flooding.remove(player);
+        self.check(player, message, "game")
+    }
+
+    // Discord chat on its way into the game
+    pub fn discord_message(&mut self, author: &str, message: &str) -> Verdict {
+        self.check(author, message, "discord")
+    }
+}
+
+// Checks a message when the filter is on, telling the admin channel about anything filtered.
+// game is whether it's game chat going to discord, otherwise it's discord chat going into the game.
+pub async fn check(bot: &mut Bot, game: bool, sender: &str, message: &str) -> Verdict {
+    let verdict = match (&mut bot.chat_filter, game) {
+        (Some(filter), true) => filter.game_message(sender, message),
+        (Some(filter), false) => filter.discord_message(sender, message),
+        (None, _) => return Verdict { relay: Some(message.to_string()), ..Verdict::default() },
+    };
+    if let Some(notice) = &verdict.admin_notice {
+        bot.say_admin(notice).await;
+    }
+    verdict
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::chat_filter::{ChatFilter, ChatFilterToml, FilterResult, Verdict};
+
+    #[test]
+    fn test_chat_filter() {
+        let config: ChatFilterToml = toml::from_str("words = ['heck']\npatterns = ['discord\\.gg/\\w+']\nwarn_after = 2\nspam_messages = 3\nspam_secs = 10").unwrap();
+        let mut filter = ChatFilter::new(&config);
+        assert_eq!(filter.filter("what the HECK"), FilterResult::Masked("what the ****".to_string()));
+        assert_eq!(filter.filter("check this"), FilterResult::Clean);
+        assert_eq!(filter.filter("join discord.gg/abc"), FilterResult::Masked("join **************".to_string()));
+
+        assert_eq!(filter.discord_message("bob", "hi"), Verdict { relay: Some("hi".to_string()), ..Verdict::default() });
+        let verdict = filter.discord_message("bob", "heck");
+        assert_eq!((verdict.relay.as_deref(), verdict.warning.is_some()), (Some("****"), false));
+        assert_eq!(verdict.admin_notice.as_deref(), Some("Masked discord chat from bob: heck"));
+        assert!(filter.discord_message("bob", "heck").warning.is_some());
+
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        for secs in 0..3 {
+            assert!(filter.game_message_at("Steve", "hello", at(secs)).relay.is_some());
+        }
+        let flood = filter.game_message_at("Steve", "hello", at(3));
+        assert!(flood.relay.is_none() && flood.warning.is_some() && flood.admin_notice.is_some());
+        assert_eq!(filter.game_message_at("Steve", "hello", at(4)), Verdict::default());
+        assert!(filter.game_message_at("Steve", "hello", at(30)).relay.is_some());
+
+        assert!(toml::from_str::<ChatFilterToml>("patterns = ['(']").is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::time::SystemTime;
+
+use chrono::{Local, TimeZone};
+use serde::{Serialize, Deserialize};
+use tracing::error;
+
+use crate::playtime::unix_millis;
+
+// One json entry per line, like the audit log
+pub const CHAT_LOG_PATH: &str = "mc-discord-bot-chat.jsonl";
+
+// Messages shown by !chatlog without a count
+const DEFAULT_MESSAGES: usize = 20;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatSource {
+    Game,
+    Discord,
+}
+
+// A bridged chat message, time is a unix timestamp in milliseconds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChatEntry {
+    pub time: u64,
+    pub source: ChatSource,
+    pub user: String,
+    pub message: String,
+}
+
+impl ChatEntry {
+    pub fn new(source: ChatSource, user: &str, message: &str) -> ChatEntry {
+        ChatEntry { time: unix_millis(SystemTime::now()), source, user: user.to_string(), message: message.to_string() }
+    }
+}
+
+pub fn record(entry: &ChatEntry) {
+    let result = serde_json::to_string(entry).map_err(|e| e.to_string()).and_then(|mut line| {
+        line.push('\n');
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(CHAT_LOG_PATH).map_err(|e| e.to_string())?;
+        file.write_all(line.as_bytes()).map_err(|e| e.to_string())
+    });
+
+    if let Err(e) = result {
+        error!("Error writing {} {}", CHAT_LOG_PATH, e);
+    }
+}
+
+// Reads the chat log oldest first, skipping any lines that can't be parsed
+pub fn read_entries() -> Vec<ChatEntry> {
+    std::fs::read_to_string(CHAT_LOG_PATH).unwrap_or_default()
+        .lines()
+        .filter_map(|x| serde_json::from_str(x).ok())
+        .collect()
+}
+
+pub enum ChatQuery {
+    Player(String, usize),
+    Search(String),
+}
+
+// Parses `!chatlog <player> [n]` or `!chatlog search <term>`
+pub fn parse_query(content: &str) -> Result<ChatQuery, &'static str> {
+    let usage = "Usage: `!chatlog <player> [messages]` or `!chatlog search <text>`";
+    let args = content.strip_prefix("!chatlog").unwrap_or_default().trim();
+    let (first, rest) = args.split_once(' ').unwrap_or((args, ""));
+    let rest = rest.trim();
+
+    match first {
+        "" => Err(usage),
+        "search" if !rest.is_empty() => Ok(ChatQuery::Search(rest.to_string())),
+        player if rest.is_empty() => Ok(ChatQuery::Player(player.to_string(), DEFAULT_MESSAGES)),
+        player => match rest.parse() {
+            Ok(count) => Ok(ChatQuery::Player(player.to_string(), count)),
+            Err(_) => Err(usage),
+        },
+    }
+}
+
+// Builds the !chatlog reply, searches show the most recent matches
+pub fn format_entries(entries: &[ChatEntry], query: &ChatQuery) -> String {
+    let (entries, count): (Vec<&ChatEntry>, usize) = match query {
+        ChatQuery::Player(player, count) => (entries.iter().filter(|x| x.user.eq_ignore_ascii_case(player)).collect(), *count),
+        ChatQuery::Search(term) => {
+            let term = term.to_lowercase();
+            (entries.iter().filter(|x| x.message.to_lowercase().contains(&term)).collect(), DEFAULT_MESSAGES)
+        },
+    };
+
+    if entries.is_empty() {
+        return "No matching chat messages".to_string();
+    }
+
+    let mut text = String::new();
+    for entry in &entries[entries.len().saturating_sub(count)..] {
+        let time = Local.timestamp_millis_opt(entry.time as i64).single()
+            .map(|x| x.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        let source = match entry.source {
+            ChatSource::Game => "game",
+            ChatSource::Discord => "discord",
+        };
+        let _ = writeln!(&mut text, "{} [{}] {}: {}", time, source, entry.user, entry.message);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chat_log::{format_entries, parse_query, ChatEntry, ChatQuery, ChatSource};
+
+    #[test]
+    fn test_chat_log() {
+        let entries = vec![
+            ChatEntry::new(ChatSource::Game, "Steve", "anyone got diamonds?"),
+            ChatEntry::new(ChatSource::Discord, "alice", "Check the chest at spawn"),
+            ChatEntry::new(ChatSource::Game, "Steve", "thanks"),
+        ];
+
+        let query = parse_query("!chatlog steve 1").unwrap();
+        let text = format_entries(&entries, &query);
+        assert!(text.ends_with("[game] Steve: thanks\n"));
+        assert_eq!(text.lines().count(), 1);
+
+        let text = format_entries(&entries, &parse_query("!chatlog search DIAMONDS").unwrap());
+        assert!(text.ends_with("[game] Steve: anyone got diamonds?\n"));
+
+        assert!(matches!(parse_query("!chatlog Alex"), Ok(ChatQuery::Player(x, 20)) if x == "Alex"));
+        assert!(parse_query("!chatlog").is_err());
+        assert!(parse_query("!chatlog Alex lots").is_err());
+        assert_eq!(format_entries(&entries, &parse_query("!chatlog Notch").unwrap()), "No matching chat messages");
+    }
+}
@@ -0,0 +1,209 @@
+// Runs console commands one at a time and works out which log lines belong to which command.
+// The server doesn't echo a prompt, so a command's output starts at the first line that looks
+// like its response and ends once the log goes quiet. Anything logged before that is left out.
+// On a busy server the log may never go quiet, so responses are also cut off after
+// MAX_RESPONSE_LINES lines or RESPONSE_DEADLINE.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serenity::model::id::ChannelId;
+
+use crate::audit::AuditEntry;
+use crate::console_commands::{self, ConsoleCommand};
+
+// How long the log has to be quiet after a response before the command is done
+pub const QUIET_PERIOD: Duration = Duration::from_millis(250);
+
+// How long to wait for a command's first response line
+pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// How long a command can run for, even if the log never goes quiet
+pub const RESPONSE_DEADLINE: Duration = Duration::from_secs(10);
+
+// Lines kept for a command's response, later ones are left out
+const MAX_RESPONSE_LINES: usize = 100;
+
+// Commands that can be queued, counting the running one
+const MAX_QUEUED: usize = 8;
+
+pub struct ConsoleTask {
+    pub channel_id: ChannelId,
+    pub command: ConsoleCommand,
+    pub console_line: String,
+    pub audit_entry: Option<AuditEntry>,
+    pub lines: Vec<String>,
+    id: u64,
+    sent: Option<Instant>,
+    last_line: Option<Instant>,
+}
+
+impl ConsoleTask {
+    pub fn new(channel_id: ChannelId, command: ConsoleCommand, console_line: String, audit_entry: Option<AuditEntry>) -> ConsoleTask {
+        ConsoleTask { channel_id, command, console_line, audit_entry, lines: Vec::new(), id: 0, sent: None, last_line: None }
+    }
+}
+
+#[derive(Default)]
+pub struct CommandQueue {
+    tasks: VecDeque<ConsoleTask>,
+    next_id: u64,
+}
+
+impl CommandQueue {
+    pub fn push(&mut self, mut task: ConsoleTask) -> Result<(), &'static str> {
+        if self.tasks.len() >= MAX_QUEUED {
+            return Err("Too many commands are waiting, try again in a moment");
+        }
+
+        self.next_id += 1;
+        task.id = self.next_id;
+        self.tasks.push_back(task);
+        Ok(())
+    }
+
+    // Marks the next command as sent, returning its id and console line. Returns None while a
+    // command is already running.
+    pub fn start_next(&mut self) -> Option<(u64, String)> {
+        let task = self.tasks.front_mut()?;
+        if task.sent.is_some() {
+            return None;
+        }
+
+        task.sent = Some(Instant::now());
+        Some((task.id, task.console_line.clone()))
+    }
+
+    // Captures a log line for the running command, returning its id if the line was captured
+    pub fn push_line(&mut self, line: &str) -> Option<u64> {
+        let task = match self.tasks.front_mut() {
+            Some(v) if v.sent.is_some() => v,
+            _ => return None,
+        };
+
+        // Once the response has started every line is kept until the log goes quiet, so
+        // responses that span several lines stay together. Players are never part of it.
+        if is_player_activity(line) || task.lines.len() >= MAX_RESPONSE_LINES {
+            return None;
+        }
+        if task.lines.is_empty() && !console_commands::is_response(task.command, line) {
+            return None;
+        }
+
+        task.lines.push(line.to_string());
+        task.last_line = Some(Instant::now());
+        Some(task.id)
+    }
+
+    // Removes the running command if it has the given id and is done
+    pub fn finish(&mut self, id: u64) -> Option<ConsoleTask> {
+        let task = self.tasks.front()?;
+        let done = match (task.sent, task.last_line) {
+            (Some(sent), _) if sent.elapsed() >= RESPONSE_DEADLINE => true,
+            (_, Some(last_line)) => last_line.elapsed() >= QUIET_PERIOD,
+            (Some(sent), None) => sent.elapsed() >= RESPONSE_TIMEOUT,
+            (None, None) => false,
+        };
+
+        match task.id == id && done {
+            true => self.tasks.pop_front(),
+            false => None,
+        }
+    }
+
+    // Forgets every command, eg. when there's no discord to reply to them in
+    pub fn clear(&mut self) {
+        self.tasks.clear();
+    }
+
+    // Removes the running command straight away, eg. when it couldn't be sent
+    pub fn cancel(&mut self, id: u64) -> Option<ConsoleTask> {
+        match self.tasks.front() {
+            Some(task) if task.id == id => self.tasks.pop_front(),
+            _ => None,
+        }
+    }
+}
+
+// <Steve> hello
+// Steve joined the game
+fn is_player_activity(line: &str) -> bool {
+    line.starts_with('<') || line.ends_with(" joined the game") || line.ends_with(" left the game")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use serenity::model::id::ChannelId;
+
+    use crate::command_queue::{CommandQueue, ConsoleTask, MAX_QUEUED, MAX_RESPONSE_LINES, QUIET_PERIOD, RESPONSE_DEADLINE};
+    use crate::console_commands::ConsoleCommand;
+
+    #[test]
+    fn test_capture() {
+        let mut queue = CommandQueue::default();
+        queue.push(ConsoleTask::new(ChannelId(1), ConsoleCommand::Seed, "seed".to_string(), None)).unwrap();
+        queue.push(ConsoleTask::new(ChannelId(1), ConsoleCommand::Weather, "weather rain".to_string(), None)).unwrap();
+
+        // Lines before the command is sent aren't captured
+        assert_eq!(queue.push_line("Seed: [1]"), None);
+
+        let (id, line) = queue.start_next().unwrap();
+        assert_eq!(line, "seed");
+        assert_eq!(queue.start_next(), None);
+
+        // Unrelated lines before the response are left out, the response and anything
+        // straight after it are kept except for players' chat, joins and leaves
+        assert_eq!(queue.push_line("Steve joined the game"), None);
+        assert_eq!(queue.push_line("Seed: [-42]"), Some(id));
+        assert_eq!(queue.push_line("<Steve> hi"), None);
+        assert_eq!(queue.push_line("Steve left the game"), None);
+        assert_eq!(queue.push_line("Saved the game"), Some(id));
+
+        // Not done until the log has been quiet
+        assert!(queue.finish(id).is_none());
+        std::thread::sleep(crate::command_queue::QUIET_PERIOD);
+        assert!(queue.finish(id + 1).is_none());
+        let task = queue.finish(id).unwrap();
+        assert_eq!(task.lines, vec!["Seed: [-42]", "Saved the game"]);
+
+        let (_, line) = queue.start_next().unwrap();
+        assert_eq!(line, "weather rain");
+
+        for _ in 1..MAX_QUEUED {
+            queue.push(ConsoleTask::new(ChannelId(1), ConsoleCommand::Seed, "seed".to_string(), None)).unwrap();
+        }
+        assert!(queue.push(ConsoleTask::new(ChannelId(1), ConsoleCommand::Seed, "seed".to_string(), None)).is_err());
+        queue.clear();
+        assert_eq!(queue.start_next(), None);
+    }
+
+    #[test]
+    fn test_busy_log() {
+        let mut queue = CommandQueue::default();
+        queue.push(ConsoleTask::new(ChannelId(1), ConsoleCommand::Raw, "list".to_string(), None)).unwrap();
+        let (id, _) = queue.start_next().unwrap();
+
+        // Chat alone doesn't start a raw command's response
+        assert_eq!(queue.push_line("<Steve> hi"), None);
+
+        // The log never goes quiet, but the response stops growing and the command finishes
+        let started = Instant::now();
+        let mut finished = None;
+        while finished.is_none() && started.elapsed() < QUIET_PERIOD * 4 {
+            queue.push_line("[Plugin] Autosaving");
+            std::thread::sleep(Duration::from_millis(1));
+            finished = queue.finish(id);
+        }
+        assert_eq!(finished.unwrap().lines.len(), MAX_RESPONSE_LINES);
+
+        // Or when it runs past the deadline
+        queue.push(ConsoleTask::new(ChannelId(1), ConsoleCommand::Raw, "list".to_string(), None)).unwrap();
+        let (id, _) = queue.start_next().unwrap();
+        queue.push_line("There are 0 of a max of 20 players online");
+        assert!(queue.finish(id).is_none());
+        queue.tasks.front_mut().unwrap().sent = Some(Instant::now() - RESPONSE_DEADLINE);
+        assert!(queue.finish(id).is_some());
+    }
+}
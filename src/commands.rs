@@ -0,0 +1,938 @@
+// Every built in discord command. !help, the [permissions] names, cooldowns and which channel
+// a command works in all come from here, so a new command only has to be added once.
+// Messages are parsed into an Invocation and dispatched to the command's handler with a
+// CommandContext holding everything commands can use.
+
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{Local, Utc};
+use serenity::model::channel::Message;
+use serenity::model::id::RoleId;
+use serenity::prelude::*;
+use tracing::info;
+
+use crate::bedrock::BedrockNames;
+use crate::bot::Bot;
+use crate::command_queue::ConsoleTask;
+use crate::confirm::{self, ConfirmAction, Confirmation, Confirmations};
+use crate::console_commands::ConsoleCommand;
+use crate::locale::Locale;
+use crate::maintenance::{self, MaintenanceState};
+use crate::polls::{self, Poll, Voter};
+use crate::pregen::{self, PregenTask};
+use crate::log_buffer;
+use crate::properties::Properties;
+use crate::server_admin::ServerAdmin;
+use crate::stats_tracker::StatsTracker;
+use crate::update::{self, UpdateKind};
+use crate::uptime::RestartReason;
+use crate::vote_actions::{self, VoteAction};
+use crate::{audit, backup, calendar, chat_log, console_commands, logging, modpack_source, player_stats, playtime, response, scheduler, server_files, stats_export, status, tellraw, tempbans, usage, uptime, warnings, waypoints, world_info};
+use crate::{run_console_task, say_or_log, send_or_log, start_server, Packet};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CommandChannel {
+    // The game chat channel and the admin channel. These have cooldowns in the chat channel.
+    Any,
+    Admin,
+}
+
+pub struct Command {
+    pub name: &'static str,
+    // Each way the command can be used, with the help.* message describing it
+    pub usages: &'static [(&'static str, &'static str)],
+    pub channel: CommandChannel,
+    // The [permissions] entry that limits who can use the command
+    pub permission: &'static str,
+    // Other names the command can be used by
+    pub aliases: &'static [&'static str],
+}
+
+const fn command(name: &'static str, usages: &'static [(&'static str, &'static str)], channel: CommandChannel) -> Command {
+    Command { name, usages, channel, permission: name, aliases: &[] }
+}
+
+impl Command {
+    const fn aliases(mut self, aliases: &'static [&'static str]) -> Command {
+        self.aliases = aliases;
+        self
+    }
+
+    pub fn usage(&self) -> String {
+        let usages: Vec<String> = self.usages.iter().map(|(usage, _)| format!("`{}`", usage)).collect();
+        format!("Usage: {}", usages.join(" or "))
+    }
+}
+
+// In the order they're listed in !help
+pub const COMMANDS: &[Command] = &[
+    command("help", &[("!help", "help")], CommandChannel::Any),
+    command("online", &[("!online", "online")], CommandChannel::Any).aliases(&["players", "list"]),
+    command("time", &[("!time [week|month|all]", "time")], CommandChannel::Any).aliases(&["playtime"]),
+    command("perms", &[("!perms", "perms")], CommandChannel::Any),
+    command("uptime", &[("!uptime", "uptime")], CommandChannel::Any),
+    command("status", &[("!status", "status")], CommandChannel::Any),
+    command("stats", &[("!stats <player>", "stats")], CommandChannel::Any),
+    command("deaths", &[("!deaths", "deaths")], CommandChannel::Any),
+    command("map", &[("!map [player]", "map")], CommandChannel::Any),
+    command("vote", &[("!vote \"question\" option1 option2 ...", "vote")], CommandChannel::Any),
+    command("voteday", &[("!voteday", "voteday")], CommandChannel::Any),
+    command("voterestart", &[("!voterestart", "voterestart")], CommandChannel::Any),
+    command("notify", &[("!notify", "notify.slot"), ("!notify when <player> online", "notify.player")], CommandChannel::Any),
+    command("event", &[("!event add <when> <description>", "event.add"), ("!event list", "event.list"), ("!event delete <id>", "event.delete")], CommandChannel::Any).aliases(&["events"]),
+    command("coords", &[("!coords save <player> <name>", "coords.save"), ("!coords get <name>", "coords.get"), ("!coords list [player]", "coords.list")], CommandChannel::Any),
+    command("seed", &[("!seed", "seed")], CommandChannel::Admin),
+    command("weather", &[("!weather clear|rain|thunder", "weather")], CommandChannel::Admin),
+    command("difficulty", &[("!difficulty [level]", "difficulty")], CommandChannel::Admin),
+    command("ban", &[("!ban <player> [reason]", "ban")], CommandChannel::Admin),
+    command("pardon", &[("!pardon <player>", "pardon")], CommandChannel::Admin),
+    command("kick", &[("!kick <player> [reason]", "kick")], CommandChannel::Admin),
+    command("warn", &[("!warn <player> <reason>", "warn")], CommandChannel::Admin),
+    command("warnings", &[("!warnings <player>", "warnings")], CommandChannel::Admin),
+    command("tempban", &[("!tempban <player> <duration> [reason]", "tempban.ban"), ("!tempban list", "tempban.list")], CommandChannel::Admin),
+    command("audit", &[("!audit [player]", "audit")], CommandChannel::Admin),
+    command("op", &[("!op <player>", "op")], CommandChannel::Admin),
+    command("deop", &[("!deop <player>", "deop")], CommandChannel::Admin),
+    command("motd", &[("!motd <text>", "motd")], CommandChannel::Admin),
+    command("icon", &[("!icon", "icon")], CommandChannel::Admin),
+    command("props", &[("!props get <key>", "props.get"), ("!props set <key> <value>", "props.set")], CommandChannel::Admin),
+    command("restart", &[("!restart [minutes]", "restart")], CommandChannel::Admin),
+    command("usage", &[("!usage", "usage")], CommandChannel::Admin),
+    command("logs", &[("!logs [lines]", "logs.recent"), ("!logs grep <text>", "logs.grep"), ("!logs since <minutes>", "logs.since")], CommandChannel::Admin),
+    command("chatlog", &[("!chatlog <player> [messages]", "chatlog.player"), ("!chatlog search <text>", "chatlog.search")], CommandChannel::Admin),
+    command("loglevel", &[("!loglevel [module] [level]", "loglevel")], CommandChannel::Admin),
+    command("export", &[("!export stats [csv|json]", "export")], CommandChannel::Admin),
+    command("import", &[("!import stats [path]", "import")], CommandChannel::Admin),
+    command("update", &[("!update [version]", "update.install"), ("!update check", "update.check")], CommandChannel::Admin),
+    command("rollback", &[("!rollback", "rollback")], CommandChannel::Admin),
+    command("modpack", &[("!modpack", "modpack")], CommandChannel::Admin),
+    command("maintenance", &[("!maintenance on|off", "maintenance")], CommandChannel::Admin),
+    command("start", &[("!start", "start")], CommandChannel::Admin),
+    command("stop", &[("!stop", "stop")], CommandChannel::Admin),
+    command("kill", &[("!kill", "kill")], CommandChannel::Admin),
+    command("pregen", &[("!pregen <radius>", "pregen")], CommandChannel::Admin),
+    command("world", &[("!world backup", "world.backup"), ("!world info", "world.info")], CommandChannel::Admin),
+    command("restore", &[("!restore [backup-id]", "restore")], CommandChannel::Admin),
+];
+
+// Notes shown after the admin commands, each is a help.* message
+const ADMIN_NOTES: &[&str] = &["confirm", "console"];
+
+// Looks a command up by its name or one of its aliases
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|x| x.name == name || x.aliases.contains(&name))
+}
+
+// The name a message's command was used by, without the prefix. None if it isn't a command.
+pub fn command_name(content: &str) -> Option<&str> {
+    content.strip_prefix('!').map(|x| x.split_whitespace().next().unwrap_or(""))
+}
+
+// A built in command and the arguments it was given
+pub struct Invocation<'a> {
+    pub command: &'static Command,
+    pub args: &'a str,
+}
+
+impl<'a> Invocation<'a> {
+    // None if the message isn't a built in command, eg. chat or a plugin command
+    pub fn parse(content: &'a str) -> Option<Invocation<'a>> {
+        let rest = content.strip_prefix('!')?;
+        let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        Some(Invocation { command: find(name)?, args: args.trim() })
+    }
+
+    // The nth whitespace separated argument
+    pub fn arg(&self, n: usize) -> Option<&'a str> {
+        self.args.split_whitespace().nth(n)
+    }
+
+    // The message with the command's own name in place of any alias, for parsers that take the
+    // whole message
+    pub fn content(&self) -> String {
+        match self.args.is_empty() {
+            true => format!("!{}", self.command.name),
+            false => format!("!{} {}", self.command.name, self.args),
+        }
+    }
+}
+
+// Everything a command can see or change while it runs
+pub struct CommandContext<'a> {
+    pub ctx: Context,
+    pub msg: &'a Message,
+    pub roles: &'a [RoleId],
+    pub is_admin_channel: bool,
+    pub bot: &'a mut Bot,
+    pub stats: &'a mut StatsTracker,
+    pub admin: &'a ServerAdmin,
+    pub confirmations: &'a mut Confirmations,
+}
+
+impl CommandContext<'_> {
+    async fn reply(&self, text: &str) {
+        say_or_log(self.msg.channel_id, &self.ctx, text).await;
+    }
+
+    async fn reply_code_block(&self, title: &str, text: &str) {
+        response::send_code_block(self.msg.channel_id, &self.ctx, title, text, self.bot.config.response_attachment_threshold).await;
+    }
+
+    // Asks an admin to confirm the action before it runs
+    async fn confirm(&mut self, prompt: &str, action: ConfirmAction) {
+        if let Some(prompt_id) = confirm::send_prompt(self.msg.channel_id, &self.ctx, prompt).await {
+            self.confirmations.add(prompt_id, Confirmation::new(self.msg.channel_id, self.msg.author.tag(), self.msg.author.id.0, action));
+        }
+    }
+
+    fn display_name(&self, id: &str) -> String {
+        self.bot.bedrock.display(self.stats.state.players.display_name(id)).to_string()
+    }
+
+    // Finds an online player by the name they're shown with, returning the server's name for them
+    fn online_player(&self, name: &str) -> Option<String> {
+        self.stats.online.keys().find(|x| self.bot.bedrock.display(x).eq_ignore_ascii_case(name)).cloned()
+    }
+}
+
+// Runs a built in command, permissions and channels have already been checked
+pub async fn dispatch(cx: &mut CommandContext<'_>, invocation: &Invocation<'_>) {
+    match invocation.command.name {
+        "help" => help_command(cx).await,
+        "perms" => cx.reply(&cx.bot.permissions.describe(cx.roles)).await,
+        "online" => cx.reply(&online_players(&cx.stats.online, &cx.bot.bedrock, &cx.bot.locale)).await,
+        "time" => time(cx, invocation).await,
+        "seed" | "weather" | "difficulty" | "ban" | "pardon" | "kick" | "op" | "deop" => console_command(cx, invocation).await,
+        "warn" => match warnings::parse_command(invocation.args) {
+            Some((player, reason)) => {
+                let reply = warnings::warn(cx.bot, cx.msg.channel_id, player, &reason, cx.msg.author.tag(), cx.msg.author.id.0).await;
+                cx.reply(&reply).await;
+            },
+            None => cx.reply(&invocation.command.usage()).await,
+        },
+        "warnings" => match invocation.arg(0) {
+            Some(player) => cx.reply(&cx.bot.warnings.describe(player)).await,
+            None => cx.reply(&invocation.command.usage()).await,
+        },
+        "tempban" if invocation.args == "list" => cx.reply(&cx.bot.tempbans.describe()).await,
+        "tempban" => tempban(cx, invocation).await,
+        "logs" => match log_buffer::parse_query(&invocation.content()) {
+            Ok(query) => {
+                let text = log_buffer::format_entries(&cx.bot.log_buffer.query(&query));
+                cx.reply_code_block("", &cx.bot.redactor.redact(&text, true)).await;
+            },
+            Err(usage) => cx.reply(usage).await,
+        },
+        "chatlog" => match chat_log::parse_query(&invocation.content()) {
+            Ok(query) => cx.reply_code_block("", &chat_log::format_entries(&chat_log::read_entries(), &query)).await,
+            Err(usage) => cx.reply(usage).await,
+        },
+        "loglevel" => loglevel(cx, invocation).await,
+        "audit" => cx.reply_code_block("Moderation actions", &audit::format_entries(&audit::read_entries(), invocation.arg(0))).await,
+        "motd" => motd(cx, invocation).await,
+        "props" => props(cx, invocation).await,
+        "icon" => icon(cx).await,
+        "restart" => restart(cx, invocation).await,
+        "update" if invocation.args == "check" => update_check(cx).await,
+        "update" | "rollback" => update(cx, invocation).await,
+        "stop" => stop(cx).await,
+        "kill" => kill(cx).await,
+        "modpack" => cx.reply(&update::VersionHistory::read().await.describe()).await,
+        "start" => start(cx).await,
+        "maintenance" => maintenance(cx, invocation).await,
+        "pregen" => pregen(cx, invocation).await,
+        "world" if invocation.args == "backup" => world_backup(cx).await,
+        "world" if invocation.args == "info" => world_info::send_world_info(&cx.ctx, cx.msg.channel_id, cx.bot.config.response_attachment_threshold),
+        "world" => cx.reply(&invocation.command.usage()).await,
+        "restore" if invocation.args.is_empty() => list_backups(cx).await,
+        "restore" => restore(cx, invocation.args).await,
+        "uptime" => {
+            let uptime = uptime::format_uptime(&cx.bot.metrics.lock().unwrap(), cx.stats.state.last_restart.as_ref());
+            cx.reply(&uptime).await;
+        },
+        "status" => server_status(cx),
+        "stats" => stats(cx, invocation).await,
+        "deaths" => deaths(cx).await,
+        "map" => map(cx, invocation).await,
+        "coords" => coords(cx, invocation).await,
+        "vote" => vote(cx, invocation).await,
+        "voteday" => vote_action(cx, VoteAction::Day).await,
+        "event" => event(cx, invocation).await,
+        "notify" => notify(cx, invocation).await,
+        "voterestart" => vote_action(cx, VoteAction::Restart).await,
+        "export" => export(cx, invocation).await,
+        "import" => import(cx, invocation).await,
+        "usage" => {
+            let usage = usage::format_usage(&cx.bot.metrics.lock().unwrap());
+            cx.reply(&usage).await;
+        },
+        name => cx.reply(&format!("Unknown command: !{}", name)).await,
+    }
+}
+
+async fn help_command(cx: &CommandContext<'_>) {
+    let mut help = help(&cx.bot.locale, cx.is_admin_channel);
+    let plugin_commands = cx.bot.plugins.command_names();
+    if !plugin_commands.is_empty() {
+        let commands = plugin_commands.iter().map(|x| format!("`!{}`", x)).collect::<Vec<String>>().join(", ");
+        help.push('\n');
+        help.push_str(&cx.bot.locale.format("commands.plugin_commands", &[("commands", &commands)]));
+    }
+    cx.reply(&help).await;
+}
+
+fn online_players(players_online: &HashMap<String, Instant>, bedrock: &BedrockNames, locale: &Locale) -> String {
+    if players_online.is_empty() {
+        return locale.get("commands.no_players_online").to_string();
+    }
+
+    let mut sorted_players: Vec<&str> = players_online.keys().map(|x| bedrock.display(x)).collect();
+    sorted_players.sort();
+    locale.format("commands.online_players", &[("players", &sorted_players.join(", "))])
+}
+
+async fn time(cx: &mut CommandContext<'_>, invocation: &Invocation<'_>) {
+    let window = match playtime::Window::parse(invocation.arg(0)) {
+        Some(v) => v,
+        None => return cx.reply(&invocation.command.usage()).await,
+    };
+
+    // Work out when currently online players logged in so their current session is counted
+    let now_unix = playtime::unix_millis(SystemTime::now());
+    let online_since = cx.stats.online_since(now_unix);
+
+    let play_times: Vec<(String, u128)> = playtime::window_play_times(&cx.stats.state.play_times, &cx.stats.state.sessions, &online_since, window, now_unix).into_iter()
+        .map(|(id, millis)| (cx.display_name(&id), millis))
+        .collect();
+    let afk_times: HashMap<String, u128> = playtime::window_afk_times(&cx.stats.state.sessions, window, now_unix).into_iter()
+        .map(|(id, millis)| (cx.display_name(&id), millis))
+        .collect();
+    let leaderboard = playtime::format_leaderboard(window, &play_times, &afk_times);
+    cx.reply_code_block("", &leaderboard).await;
+}
+
+async fn console_command(cx: &mut CommandContext<'_>, invocation: &Invocation<'_>) {
+    let (command, console_line) = match console_commands::parse_command(&invocation.content()) {
+        Some(Ok(v)) => v,
+        Some(Err(usage)) => return cx.reply(usage).await,
+        None => return,
+    };
+
+    if command.needs_confirmation() {
+        let action = ConfirmAction::Console(command, console_line);
+        let prompt = format!("{} wants to run {}, an admin needs to confirm it within a minute", cx.msg.author.tag(), action.describe());
+        return cx.confirm(&prompt, action).await;
+    }
+
+    let audit_entry = match command.is_moderation() {
+        true => Some(audit::AuditEntry::new(cx.msg.author.tag(), cx.msg.author.id.0, &console_line)),
+        false => None,
+    };
+    let new_task = ConsoleTask::new(cx.msg.channel_id, command, console_line, audit_entry);
+    if let Err(e) = run_console_task(&cx.bot.console, &mut cx.bot.command_queue, &cx.bot.sender, &cx.ctx, new_task).await {
+        return cx.reply(e).await;
+    }
+
+    // A tempbanned player pardoned early doesn't need pardoning again when the ban ends
+    if command == ConsoleCommand::Pardon && cx.bot.tempbans.remove(invocation.arg(0).unwrap_or_default()) {
+        cx.bot.tempbans.write();
+    }
+}
+
+async fn tempban(cx: &mut CommandContext<'_>, invocation: &Invocation<'_>) {
+    let (ban, console_line) = match tempbans::parse_command(invocation.args, &cx.msg.author.tag(), Local::now().timestamp()) {
+        Some(v) => v,
+        None => return cx.reply(&invocation.command.usage()).await,
+    };
+    let reply = format!("{} will be pardoned <t:{}:R>", ban.player, ban.expires);
+    let audit_entry = audit::AuditEntry::new(cx.msg.author.tag(), cx.msg.author.id.0, &console_line);
+    match tempbans::start(cx.bot, cx.msg.channel_id, ban, console_line, audit_entry).await {
+        Ok(()) => cx.reply(&reply).await,
+        Err(e) => cx.reply(e).await,
+    }
+}
+
+async fn loglevel(cx: &CommandContext<'_>, invocation: &Invocation<'_>) {
+    if invocation.args.is_empty() {
+        return cx.reply(&logging::describe()).await;
+    }
+
+    match logging::parse_command(&invocation.content()) {
+        Ok((module, level)) => {
+            logging::set_level(module.as_deref(), level);
+            cx.reply(&format!("{} log level set to {}", module.as_deref().unwrap_or("Default"), level)).await;
+        },
+        Err(e) => cx.reply(&e).await,
+    }
+}
+
+async fn motd(cx: &mut CommandContext<'_>, invocation: &Invocation<'_>) {
+    if invocation.args.is_empty() {
+        return cx.reply(&invocation.command.usage()).await;
+    }
+
+    // A running server overwrites server.properties, eg. on whitelist changes, so it's written
+    // once the server stops like maintenance mode's MOTD
+    if cx.bot.console.is_some() {
+        cx.bot.pending_motd = Some(invocation.args.to_string());
+        return cx.reply("MOTD updated, use `!restart` to apply it").await;
+    }
+    match maintenance::write_properties(invocation.args, None) {
+        Ok(_) => cx.reply("MOTD updated, it's used when the server next starts").await,
+        Err(e) => cx.reply(&e).await,
+    }
+}
+
+async fn props(cx: &CommandContext<'_>, invocation: &Invocation<'_>) {
+    let mut args = invocation.args.splitn(3, ' ');
+    let (action, key, value) = (args.next(), args.next(), args.next());
+    let key = match key {
+        Some(key) if matches!((action, value), (Some("get"), None) | (Some("set"), Some(_))) => key,
+        _ => return cx.reply(&invocation.command.usage()).await,
+    };
+
+    let mut properties = match Properties::read(server_files::SERVER_PROPERTIES_PATH) {
+        Ok(v) => v,
+        Err(e) => return cx.reply(&format!("Error reading {}: {}", server_files::SERVER_PROPERTIES_PATH, e)).await,
+    };
+
+    match value {
+        None => match properties.get(key) {
+            Some(value) => cx.reply(&format!("`{}` = `{}`", key, value)).await,
+            None => cx.reply(&format!("`{}` isn't set", key)).await,
+        },
+        Some(value) => {
+            let is_new = properties.get(key).is_none();
+            properties.set(key, value);
+            match properties.write(server_files::SERVER_PROPERTIES_PATH) {
+                Ok(_) => {
+                    let mut reply = format!("`{}` set to `{}`, {}", key, value, server_files::property_restart_note(key));
+                    if is_new {
+                        reply.push_str(&format!("\n`{}` wasn't in server.properties before, check it's spelled correctly", key));
+                    }
+                    cx.reply(&reply).await;
+                },
+                Err(e) => cx.reply(&format!("Error writing {}: {}", server_files::SERVER_PROPERTIES_PATH, e)).await,
+            }
+        },
+    }
+}
+
+async fn icon(cx: &CommandContext<'_>) {
+    let attachment = match cx.msg.attachments.first() {
+        Some(v) => v,
+        None => return cx.reply("Attach a 64x64 PNG to set it as the server icon").await,
+    };
+
+    let data = match attachment.download().await {
+        Ok(v) => v,
+        Err(e) => return cx.reply(&format!("Error downloading attachment: {}", e)).await,
+    };
+
+    if let Err(e) = server_files::validate_icon(&data) {
+        return cx.reply(&e).await;
+    }
+
+    match fs::write(server_files::SERVER_ICON_PATH, data) {
+        Ok(_) => cx.reply("Server icon updated, use `!restart` to apply it").await,
+        Err(e) => cx.reply(&format!("Error writing {}: {}", server_files::SERVER_ICON_PATH, e)).await,
+    }
+}
+
+async fn restart(cx: &CommandContext<'_>, invocation: &Invocation<'_>) {
+    let minutes = match invocation.arg(0).map(|x| x.parse::<u64>()) {
+        Some(Ok(v)) => v,
+        Some(Err(_)) => return cx.reply(&invocation.command.usage()).await,
+        None => 0,
+    };
+
+    if cx.bot.console.is_none() {
+        return cx.reply("Server is not running").await;
+    }
+
+    if minutes == 0 {
+        send_or_log(&cx.bot.sender, Packet::ScheduledRestart(RestartReason::Manual));
+    } else {
+        scheduler::start_restart_countdown(minutes, &cx.bot.sender, RestartReason::Manual);
+        cx.reply(&format!("Server will restart in {} minutes", minutes)).await;
+    }
+}
+
+async fn update_check(cx: &CommandContext<'_>) {
+    let modpack = match &cx.bot.config.modpack {
+        Some(v) => v.clone(),
+        None => return cx.reply("Checking for updates requires a [modpack] source to be configured").await,
+    };
+
+    // Query the API in the background so the event loop isn't held up
+    let ctx = cx.ctx.clone();
+    let reply_channel_id = cx.msg.channel_id;
+    tokio::task::spawn(async move {
+        let installed = update::VersionHistory::read().await.current.and_then(|x| x.version);
+        let reply = match modpack_source::list_versions(&modpack).await {
+            Ok(versions) if versions.is_empty() => "No server installable versions found".to_string(),
+            Ok(versions) => {
+                let newest = &versions[0].name;
+                let mut reply = match &installed {
+                    Some(installed) if installed == newest => format!("Up to date, version {} is installed", installed),
+                    Some(installed) => format!("Version {} is available, version {} is installed. Use `!update` to install it", newest, installed),
+                    None => format!("Version {} is available. Use `!update` to install it", newest),
+                };
+                let recent: Vec<&str> = versions.iter().take(10).map(|x| x.name.as_str()).collect();
+                reply.push_str(&format!("\nRecent versions: {}", recent.join(", ")));
+                reply
+            },
+            Err(e) => e,
+        };
+        say_or_log(reply_channel_id, &ctx, &reply).await;
+    });
+}
+
+async fn update(cx: &mut CommandContext<'_>, invocation: &Invocation<'_>) {
+    if cx.admin.is_updating() {
+        return cx.reply("An update is already running").await;
+    }
+
+    let kind = match invocation.command.name {
+        "update" => UpdateKind::Update(Some(invocation.args).filter(|x| !x.is_empty()).map(|x| x.to_string())),
+        _ => UpdateKind::Rollback,
+    };
+
+    let action = ConfirmAction::Update(kind.clone());
+    let mut prompt = format!("{} wants to run {}, this stops the server. An admin needs to confirm it within a minute", cx.msg.author.tag(), action.describe());
+    if kind == UpdateKind::Rollback {
+        prompt.push_str(&format!("\n{}", update::VersionHistory::read().await.describe()));
+    }
+    cx.confirm(&prompt, action).await;
+}
+
+async fn stop(cx: &mut CommandContext<'_>) {
+    if cx.bot.console.is_none() {
+        return cx.reply("Server is not running").await;
+    }
+
+    let prompt = format!("{} wants to stop the server, an admin needs to confirm it within a minute", cx.msg.author.tag());
+    cx.confirm(&prompt, ConfirmAction::Stop).await;
+}
+
+async fn kill(cx: &mut CommandContext<'_>) {
+    if !cx.admin.is_killable() {
+        return cx.reply("Server is not running").await;
+    }
+
+    let prompt = format!("{} wants to kill the server without saving, an admin needs to confirm it within a minute", cx.msg.author.tag());
+    cx.confirm(&prompt, ConfirmAction::Kill).await;
+}
+
+async fn start(cx: &CommandContext<'_>) {
+    if cx.bot.console.is_some() || cx.admin.is_updating() || cx.admin.is_restarting() {
+        return cx.reply("Server is already running or about to start").await;
+    }
+    if cx.bot.maintenance.is_some() && !cx.bot.admin_user_ids.contains(&cx.msg.author.id) {
+        return cx.reply("The server is in maintenance, only admin users can start it until `!maintenance off`").await;
+    }
+
+    cx.reply("Starting server").await;
+    start_server(&cx.bot.sender, &cx.bot.config);
+}
+
+async fn maintenance(cx: &mut CommandContext<'_>, invocation: &Invocation<'_>) {
+    let running = cx.bot.console.is_some();
+    match (invocation.args, &cx.bot.maintenance) {
+        ("on", Some(_)) => cx.reply("Maintenance mode is already on").await,
+        ("off", None) => cx.reply("Maintenance mode is already off").await,
+        ("on", None) => {
+            let properties = Properties::read(server_files::SERVER_PROPERTIES_PATH).unwrap_or_else(|_| Properties::parse(""));
+            let state = MaintenanceState::new(&properties);
+            let motd = cx.bot.config.maintenance.motd.clone();
+            if running {
+                cx.bot.write_console("whitelist on").await;
+                if cx.bot.config.maintenance.kick_non_ops {
+                    for player in maintenance::non_ops(cx.stats.online.keys()) {
+                        cx.bot.write_console(&format!("kick {} {}", player, motd)).await;
+                    }
+                }
+                cx.bot.pending_motd = Some(motd.clone());
+            } else if let Err(e) = maintenance::write_properties(&motd, Some(true)) {
+                return cx.reply(&e).await;
+            }
+
+            state.write();
+            cx.bot.maintenance = Some(state);
+            cx.bot.presence.fixed = Some(motd);
+            cx.bot.players_changed(cx.stats.online.len()).await;
+            cx.bot.say(cx.bot.channel_id, "The server is going into maintenance").await;
+            match running {
+                true => cx.reply("Maintenance mode is on, the server is whitelist only and the MOTD changes once it restarts").await,
+                false => cx.reply("Maintenance mode is on").await,
+            }
+        },
+        ("off", Some(state)) => {
+            let state = state.clone();
+            let motd = state.restored_motd().to_string();
+            if running {
+                if !state.whitelist_was_on {
+                    cx.bot.write_console("whitelist off").await;
+                }
+                cx.bot.pending_motd = Some(motd);
+            } else if let Err(e) = maintenance::write_properties(&motd, Some(state.whitelist_was_on)) {
+                return cx.reply(&e).await;
+            }
+
+            MaintenanceState::remove();
+            cx.bot.maintenance = None;
+            cx.bot.presence.fixed = None;
+            cx.bot.players_changed(cx.stats.online.len()).await;
+            cx.bot.say(cx.bot.channel_id, "Maintenance is over").await;
+            cx.reply("Maintenance mode is off").await;
+        },
+        _ => cx.reply(&invocation.command.usage()).await,
+    }
+}
+
+async fn pregen(cx: &mut CommandContext<'_>, invocation: &Invocation<'_>) {
+    let radius = match invocation.args.parse::<u32>() {
+        Ok(v) if v > 0 => v,
+        _ => return cx.reply(&invocation.command.usage()).await,
+    };
+    if cx.bot.console.is_none() {
+        return cx.reply("The server isn't running").await;
+    }
+    if let Some(task) = &cx.bot.pregen {
+        return cx.reply(&format!("A {} block pregeneration is already running", task.radius)).await;
+    }
+
+    for command in pregen::commands(cx.bot.config.pregen.tool, radius) {
+        cx.bot.write_console(&command).await;
+    }
+    cx.bot.pregen = Some(PregenTask::new(radius, cx.msg.channel_id));
+    cx.reply(&format!("Pregenerating a {} block radius around 0, 0, progress is posted once the server reports it", radius)).await;
+}
+
+async fn world_backup(cx: &mut CommandContext<'_>) {
+    send_or_log(&cx.bot.sender, Packet::BackupRequested(backup::BackupRequest { channel_id: Some(cx.msg.channel_id), scheduled: false }));
+}
+
+async fn list_backups(cx: &CommandContext<'_>) {
+    let backups = backup::list_backups();
+    let text = match backups.is_empty() {
+        true => "No backups found".to_string(),
+        false => backups.iter().take(10).map(|x| x.as_str()).collect::<Vec<&str>>().join("\n"),
+    };
+    cx.reply_code_block("Backups, use `!restore <backup-id>` to restore one", &text).await;
+}
+
+async fn restore(cx: &mut CommandContext<'_>, id: &str) {
+    if backup::backup_path(id).is_none() {
+        return cx.reply(&format!("No backup with id {}, use `!restore` to list them", id)).await;
+    }
+
+    if cx.admin.is_updating() {
+        return cx.reply("An update is already running").await;
+    }
+
+    let action = ConfirmAction::Update(UpdateKind::Restore(id.to_string()));
+    let prompt = format!("{} wants to run {}, this stops the server and replaces the world. An admin needs to confirm it within a minute", cx.msg.author.tag(), action.describe());
+    cx.confirm(&prompt, action).await;
+}
+
+fn server_status(cx: &CommandContext<'_>) {
+    let state = status::ServerState::new(cx.admin.is_updating(), cx.admin.is_restarting(), cx.bot.console.is_some());
+    let mut players: Vec<String> = cx.stats.online.keys().map(|x| cx.bot.bedrock.display(x).to_string()).collect();
+    players.sort();
+    let mut server_status = status::Status::new(state, players, &cx.bot.metrics.lock().unwrap(), cx.stats.query.clone(), cx.admin.latest_ping());
+    server_status.map_url = cx.bot.config.map.as_ref().map(|x| x.url.clone());
+    status::send_status(&cx.ctx, cx.msg.channel_id, server_status, cx.bot.locale.clone());
+}
+
+async fn map(cx: &mut CommandContext<'_>, invocation: &Invocation<'_>) {
+    let url = match &cx.bot.config.map {
+        Some(v) => v.url.clone(),
+        None => return cx.reply("No map has been set up").await,
+    };
+    let name = match invocation.arg(0) {
+        Some(v) => v,
+        None => return cx.reply(&url).await,
+    };
+
+    let player = match cx.online_player(name) {
+        Some(v) => v,
+        None => return cx.reply(&format!("{} isn't online", name)).await,
+    };
+    let new_task = ConsoleTask::new(cx.msg.channel_id, ConsoleCommand::Position, format!("data get entity {} Pos", player), None);
+    if let Err(e) = run_console_task(&cx.bot.console, &mut cx.bot.command_queue, &cx.bot.sender, &cx.ctx, new_task).await {
+        cx.reply(e).await;
+    }
+}
+
+// Starts a poll in the chat channel, which is announced in game
+async fn vote(cx: &mut CommandContext<'_>, invocation: &Invocation<'_>) {
+    if cx.bot.poll.is_some() {
+        return cx.reply("A poll is already running").await;
+    }
+    let (question, options) = match polls::parse_poll(invocation.args) {
+        Ok(v) => v,
+        Err(e) => return cx.reply(&e).await,
+    };
+
+    let duration = Duration::from_secs(cx.bot.config.polls.duration_mins.max(1) * 60);
+    let mut poll = Poll::new(rand::random(), cx.bot.channel_id, question, options);
+    poll.message_id = polls::send_poll(&cx.ctx, &poll, Utc::now().timestamp() + duration.as_secs() as i64).await;
+    if poll.message_id.is_none() {
+        return;
+    }
+
+    cx.bot.write_console(&tellraw::to_all(&poll.game_instructions())).await;
+    polls::start_poll_timer(&cx.bot.sender, poll.id, duration);
+    cx.bot.poll = Some(poll);
+}
+
+// Counts towards a !voteday or !voterestart, which is announced in the chat channel and in game
+async fn vote_action(cx: &mut CommandContext<'_>, action: VoteAction) {
+    let voter = Voter::Discord(cx.msg.author.id.0);
+    if let Err(e) = vote_actions::cast_vote(cx.bot, action, voter, &cx.msg.author.name, cx.stats.online.len()).await {
+        cx.reply(&e).await;
+    }
+}
+
+// Pings the user once there's a free slot on a full server, or the next time a player joins.
+// Using it again cancels that.
+async fn notify(cx: &mut CommandContext<'_>, invocation: &Invocation<'_>) {
+    match (invocation.arg(0), invocation.arg(1), invocation.arg(2), invocation.arg(3)) {
+        (None, ..) => {
+            let waiting = cx.stats.capacity.is_full() && cx.stats.capacity.toggle_notify(cx.msg.author.id.0);
+            match (cx.stats.capacity.is_full(), waiting) {
+                (false, _) => cx.reply("The server isn't full, there's room to join now").await,
+                (true, true) => cx.reply("You'll be pinged when a slot frees up").await,
+                (true, false) => cx.reply("You won't be pinged anymore").await,
+            }
+        },
+        (Some("when"), Some(player), Some("online"), None) => {
+            let subscribed = cx.stats.subscriptions.toggle(player, cx.msg.author.id.0);
+            cx.stats.subscriptions.write();
+            match subscribed {
+                true => cx.reply(&format!("You'll be messaged the next time {} joins", player)).await,
+                false => cx.reply(&format!("You won't be messaged when {} joins", player)).await,
+            }
+        },
+        _ => cx.reply(&invocation.command.usage()).await,
+    }
+}
+
+// Events are shown in discord's timestamp format so everyone sees them in their own time zone
+async fn event(cx: &mut CommandContext<'_>, invocation: &Invocation<'_>) {
+    match (invocation.arg(0), invocation.arg(1)) {
+        (Some("add"), Some(_)) => {
+            let args = invocation.args["add".len()..].trim();
+            let (time, description) = match calendar::parse_when(args, Local::now().naive_local()) {
+                Some((time, description)) if !description.is_empty() => (time, description),
+                _ => return cx.reply(calendar::USAGE).await,
+            };
+            let timestamp = match calendar::timestamp(time) {
+                Some(v) => v,
+                None => return cx.reply(calendar::USAGE).await,
+            };
+            let reply = format!("Added event {}", cx.bot.calendar.add(timestamp, description, cx.msg.author.id.0).describe());
+            cx.bot.calendar.write();
+            cx.reply(&reply).await;
+        },
+        (Some("list"), None) | (None, None) => {
+            let events: Vec<String> = cx.bot.calendar.upcoming().iter().map(|x| x.describe()).collect();
+            match events.is_empty() {
+                true => cx.reply("No events are coming up").await,
+                false => cx.reply(&events.join("\n")).await,
+            }
+        },
+        (Some("delete"), Some(id)) => {
+            let event = match id.trim_start_matches('#').parse().ok().and_then(|x| cx.bot.calendar.get(x)) {
+                Some(v) => v.clone(),
+                None => return cx.reply(&format!("No event {}", id)).await,
+            };
+            // Anyone can remove their own events, admins can remove any
+            if event.added_by != cx.msg.author.id.0 && !cx.is_admin_channel {
+                return cx.reply("Only whoever added an event or an admin can delete it").await;
+            }
+            cx.bot.calendar.delete(event.id);
+            cx.bot.calendar.write();
+            cx.reply(&format!("Deleted event {}", event.describe())).await;
+        },
+        _ => cx.reply(&invocation.command.usage()).await,
+    }
+}
+
+async fn coords(cx: &mut CommandContext<'_>, invocation: &Invocation<'_>) {
+    match (invocation.arg(0), invocation.arg(1), invocation.arg(2)) {
+        (Some("save"), Some(name), Some(waypoint)) => {
+            if !waypoints::is_valid_name(waypoint) {
+                return cx.reply("Waypoint names can only use letters, numbers, `-` and `_`").await;
+            }
+            let player = match cx.online_player(name) {
+                Some(v) => v,
+                None => return cx.reply(&format!("{} isn't online", name)).await,
+            };
+            for line in cx.stats.waypoints.request_save(&player, waypoint, Some(cx.msg.channel_id)) {
+                cx.bot.write_console(&line).await;
+            }
+        },
+        (Some("get"), Some(waypoint), None) => {
+            let found = cx.stats.waypoints.find(waypoint);
+            if found.is_empty() {
+                return cx.reply(&format!("No waypoint called {}", waypoint)).await;
+            }
+            let lines: Vec<String> = found.iter().map(|(id, x)| format!("{}'s {}", cx.display_name(id), x.describe())).collect();
+            cx.reply(&lines.join("\n")).await;
+        },
+        (Some("list"), Some(name), None) => {
+            let id = match cx.stats.state.find_player(name, &cx.bot.bedrock) {
+                Some(v) => v,
+                None => return cx.reply(&format!("{} hasn't played here", name)).await,
+            };
+            let waypoints: Vec<String> = cx.stats.waypoints.list(&id).iter().map(|x| x.describe()).collect();
+            match waypoints.is_empty() {
+                true => cx.reply(&format!("{} hasn't saved any waypoints", cx.display_name(&id))).await,
+                false => cx.reply(&waypoints.join("\n")).await,
+            }
+        },
+        (Some("list"), None, None) => {
+            let lines: Vec<String> = cx.stats.waypoints.players().iter()
+                .map(|id| {
+                    let names: Vec<&str> = cx.stats.waypoints.list(id).iter().map(|x| x.name.as_str()).collect();
+                    format!("{}: {}", cx.display_name(id), names.join(", "))
+                })
+                .collect();
+            match lines.is_empty() {
+                true => cx.reply("No waypoints have been saved").await,
+                false => cx.reply(&lines.join("\n")).await,
+            }
+        },
+        _ => cx.reply(&invocation.command.usage()).await,
+    }
+}
+
+async fn stats(cx: &CommandContext<'_>, invocation: &Invocation<'_>) {
+    let name = match invocation.arg(0) {
+        Some(v) => v,
+        None => return cx.reply(&invocation.command.usage()).await,
+    };
+    let id = match cx.stats.state.find_player(name, &cx.bot.bedrock) {
+        Some(v) => v,
+        None => return cx.reply(&format!("{} hasn't played here", name)).await,
+    };
+
+    let name = cx.display_name(&id);
+    let login_time = cx.stats.online.iter().find(|(x, _)| cx.stats.state.players.id(x) == id).map(|(_, x)| *x);
+    let profile = cx.stats.state.profile(&id, &name, login_time);
+    player_stats::send_profile(&cx.ctx, cx.msg.channel_id, &profile, playtime::unix_millis(SystemTime::now())).await;
+}
+
+async fn deaths(cx: &CommandContext<'_>) {
+    let mut deaths: Vec<(String, &player_stats::PlayerStats)> = cx.stats.state.stats.iter()
+        .filter(|(_, stats)| stats.deaths > 0)
+        .map(|(id, stats)| (cx.display_name(id), stats))
+        .collect();
+    deaths.sort_by(|a, b| b.1.deaths.cmp(&a.1.deaths).then_with(|| a.0.cmp(&b.0)));
+    cx.reply_code_block("", &player_stats::format_deaths(&deaths)).await;
+}
+
+async fn export(cx: &CommandContext<'_>, invocation: &Invocation<'_>) {
+    let format = match (invocation.arg(0), stats_export::ExportFormat::parse(invocation.arg(1))) {
+        (Some("stats"), Some(v)) => v,
+        _ => return cx.reply(&invocation.command.usage()).await,
+    };
+
+    // Current sessions are counted in play time but not listed as sessions
+    let mut play_times = cx.stats.state.play_times.clone();
+    for (name, login_time) in &cx.stats.online {
+        *play_times.entry(cx.stats.state.players.id(name)).or_insert(0) += login_time.elapsed().as_millis();
+    }
+    let export = stats_export::StatsExport::new(&play_times, &cx.stats.state.sessions, &cx.stats.state.stats, |id| cx.display_name(id));
+    match export.file(format) {
+        Ok((filename, data)) => stats_export::send_file(&cx.ctx, cx.msg.channel_id, filename, data).await,
+        Err(e) => cx.reply(&e).await,
+    }
+}
+
+async fn import(cx: &mut CommandContext<'_>, invocation: &Invocation<'_>) {
+    if invocation.arg(0) != Some("stats") {
+        return cx.reply("Usage: `!import stats [path]` with a JSON export attached or saved at path").await;
+    }
+
+    let text = match (invocation.arg(1), cx.msg.attachments.first()) {
+        (Some(path), _) => fs::read_to_string(path).map_err(|e| format!("Error reading {}: {}", path, e)),
+        (None, Some(attachment)) => attachment.download().await
+            .map(|x| String::from_utf8_lossy(&x).to_string())
+            .map_err(|e| format!("Error downloading attachment: {}", e)),
+        (None, None) => Err("Attach a JSON export from `!export stats json` or give the path to one".to_string()),
+    };
+    let export = match text.and_then(|x| stats_export::parse_import(&x)) {
+        Ok(v) => v,
+        Err(e) => return cx.reply(&e).await,
+    };
+
+    let state = &mut cx.stats.state;
+    let imported = stats_export::merge(export, &mut state.play_times, &mut state.sessions, &mut state.stats, &mut state.players);
+    state.write();
+    info!("{} imported stats for {} players", cx.msg.author.name, imported);
+    cx.reply(&format!("Imported stats for {} players", imported)).await;
+}
+
+// Lists the commands that can be used in the channel, plugin commands are added separately
+pub fn help(locale: &Locale, is_admin_channel: bool) -> String {
+    let mut help = locale.get("help.title").to_string();
+    for command in COMMANDS.iter().filter(|x| is_admin_channel || x.channel == CommandChannel::Any) {
+        for (usage, key) in command.usages {
+            help.push_str(&format!("\n`{}` - {}", usage, locale.get(&format!("help.{}", key))));
+        }
+    }
+    if is_admin_channel {
+        for key in ADMIN_NOTES {
+            help.push('\n');
+            help.push_str(locale.get(&format!("help.{}", key)));
+        }
+    }
+    help
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Instant;
+
+    use crate::bedrock::BedrockNames;
+    use crate::commands::{help, online_players, Invocation, COMMANDS};
+    use crate::locale::Locale;
+
+    #[test]
+    fn test_invocation() {
+        let invocation = Invocation::parse("!playtime  week").unwrap();
+        assert_eq!(invocation.command.name, "time");
+        assert_eq!(invocation.arg(0), Some("week"));
+        assert_eq!(invocation.content(), "!time week");
+        assert_eq!(Invocation::parse("!props set motd A  server").unwrap().args, "set motd A  server");
+        assert!(Invocation::parse("!plugincommand").is_none());
+        assert!(Invocation::parse("hello").is_none());
+        assert_eq!(COMMANDS[2].usage(), "Usage: `!time [week|month|all]`");
+
+        let locale = Locale::new("en");
+        let bedrock = BedrockNames::new(None);
+        assert_eq!(online_players(&HashMap::new(), &bedrock, &locale), locale.get("commands.no_players_online"));
+        let players = HashMap::from([("Steve".to_string(), Instant::now()), ("Alex".to_string(), Instant::now())]);
+        assert_eq!(online_players(&players, &bedrock, &locale), locale.format("commands.online_players", &[("players", "Alex, Steve")]));
+    }
+
+    #[test]
+    fn test_help() {
+        let locale = Locale::new("en");
+        let public = help(&locale, false);
+        assert!(public.contains("\n`!time [week|month|all]` - lists hours played"));
+        assert!(!public.contains("!props"));
+
+        // Every usage needs a description, missing ones would show their key instead
+        let admin = help(&locale, true);
+        assert_eq!(admin.lines().count(), 1 + COMMANDS.iter().map(|x| x.usages.len()).sum::<usize>() + 2);
+        assert!(!admin.contains("help."));
+        assert!(admin.contains("\n`!props set <key> <value>` - changes a server.properties value\n"));
+    }
+}
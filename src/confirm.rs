@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serenity::builder::CreateComponents;
+use serenity::model::id::{ChannelId, MessageId};
+use serenity::model::interactions::InteractionResponseType;
+use serenity::model::interactions::message_component::{ButtonStyle, MessageComponentInteraction};
+use serenity::prelude::*;
+use tracing::error;
+
+use crate::console_commands::ConsoleCommand;
+use crate::update::UpdateKind;
+
+// custom_id of the prompt buttons
+pub const CONFIRM_BUTTON: &str = "confirm";
+pub const CANCEL_BUTTON: &str = "cancel";
+
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Destructive actions that only run once an admin presses Confirm
+pub enum ConfirmAction {
+    Console(ConsoleCommand, String),
+    Update(UpdateKind),
+    Stop,
+    Kill,
+}
+
+impl ConfirmAction {
+    // Confirming needs the same permission as the command itself
+    pub fn permission(&self) -> &str {
+        match self {
+            ConfirmAction::Console(_, console_line) => console_line.split(' ').next().unwrap_or_default(),
+            ConfirmAction::Update(UpdateKind::Update(_)) => "update",
+            ConfirmAction::Update(UpdateKind::Rollback) => "rollback",
+            ConfirmAction::Update(UpdateKind::Restore(_)) => "restore",
+            ConfirmAction::Stop => "stop",
+            ConfirmAction::Kill => "kill",
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            ConfirmAction::Console(_, console_line) => format!("`{}`", console_line),
+            ConfirmAction::Update(UpdateKind::Update(Some(version))) => format!("`!update {}`", version),
+            ConfirmAction::Update(UpdateKind::Update(None)) => "`!update`".to_string(),
+            ConfirmAction::Update(UpdateKind::Rollback) => "`!rollback`".to_string(),
+            ConfirmAction::Update(UpdateKind::Restore(id)) => format!("`!restore {}`", id),
+            ConfirmAction::Stop => "`!stop`".to_string(),
+            ConfirmAction::Kill => "`!kill`".to_string(),
+        }
+    }
+}
+
+pub struct Confirmation {
+    pub channel_id: ChannelId,
+    pub requested_by: String,
+    pub requested_by_id: u64,
+    pub action: ConfirmAction,
+    created: Instant,
+}
+
+impl Confirmation {
+    pub fn new(channel_id: ChannelId, requested_by: String, requested_by_id: u64, action: ConfirmAction) -> Confirmation {
+        Confirmation { channel_id, requested_by, requested_by_id, action, created: Instant::now() }
+    }
+}
+
+// Confirmations waiting for a button press, keyed by the prompt message
+#[derive(Default)]
+pub struct Confirmations {
+    pending: HashMap<MessageId, Confirmation>,
+}
+
+impl Confirmations {
+    pub fn add(&mut self, prompt_id: MessageId, confirmation: Confirmation) {
+        self.pending.retain(|_, x| x.created.elapsed() < CONFIRMATION_TIMEOUT);
+        self.pending.insert(prompt_id, confirmation);
+    }
+
+    // Returns None if there's no confirmation for the prompt or it has expired
+    pub fn get(&self, prompt_id: MessageId) -> Option<&Confirmation> {
+        self.pending.get(&prompt_id).filter(|x| x.created.elapsed() < CONFIRMATION_TIMEOUT)
+    }
+
+    pub fn remove(&mut self, prompt_id: MessageId) -> Option<Confirmation> {
+        self.pending.remove(&prompt_id)
+    }
+}
+
+// Posts a prompt with Confirm and Cancel buttons, returning its id
+pub async fn send_prompt(channel_id: ChannelId, ctx: &Context, text: &str) -> Option<MessageId> {
+    let result = channel_id.send_message(&ctx.http, |m| {
+        m.content(text).components(|c| c.create_action_row(|row| {
+            row.create_button(|b| b.custom_id(CONFIRM_BUTTON).label("Confirm").style(ButtonStyle::Danger))
+                .create_button(|b| b.custom_id(CANCEL_BUTTON).label("Cancel").style(ButtonStyle::Secondary))
+        }))
+    }).await;
+
+    match result {
+        Ok(v) => Some(v.id),
+        Err(e) => {
+            error!("Error sending message: {:?}", e);
+            None
+        },
+    }
+}
+
+// Answers a button press. Resolved prompts are edited to remove the buttons, otherwise only the
+// person who pressed the button sees the reply.
+pub async fn respond(component: &MessageComponentInteraction, ctx: &Context, text: &str, resolved: bool) {
+    let result = component.create_interaction_response(&ctx.http, |r| {
+        if resolved {
+            r.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|d| d.content(text).set_components(CreateComponents::default()))
+        } else {
+            r.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|d| d.content(text).ephemeral(true))
+        }
+    }).await;
+
+    if let Err(e) = result {
+        error!("Error responding to interaction: {:?}", e);
+    }
+}
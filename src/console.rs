@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tracing::error;
+
+// Lines waiting to be written before senders have to wait
+const QUEUE_SIZE: usize = 64;
+
+// How long a sender waits for space in the queue before giving up, so a server that has
+// stopped reading its console can't hold up the bot forever
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[cfg(windows)]
+const LINE_ENDING: &str = "\r\n";
+#[cfg(not(windows))]
+const LINE_ENDING: &str = "\n";
+
+// Queues lines for the server's stdin. A single writer task owns stdin so lines from different
+// handlers can't interleave, and each line is flushed as soon as it's written.
+#[derive(Clone)]
+pub struct Console {
+    sender: mpsc::Sender<String>,
+}
+
+// Turns text into a single console line, a command can never span several lines
+pub fn normalize_line(text: &str) -> String {
+    text.trim_end_matches(['\r', '\n']).replace(['\r', '\n'], " ")
+}
+
+impl Console {
+    // Spawns the writer task, it exits when stdin closes or every Console is dropped. stdin is
+    // usually the child process' but can be anything the server reads its console from.
+    pub fn start(mut stdin: impl AsyncWrite + Unpin + Send + 'static) -> Console {
+        let (sender, mut receiver) = mpsc::channel::<String>(QUEUE_SIZE);
+        tokio::task::spawn(async move {
+            while let Some(line) = receiver.recv().await {
+                let result = async {
+                    stdin.write_all(line.as_bytes()).await?;
+                    stdin.write_all(LINE_ENDING.as_bytes()).await?;
+                    stdin.flush().await
+                }.await;
+
+                if let Err(e) = result {
+                    error!("Error writing to stdin {}", e);
+                    return;
+                }
+            }
+        });
+        Console { sender }
+    }
+
+    pub async fn send(&self, line: &str) -> Result<(), &'static str> {
+        let line = normalize_line(line);
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+
+        match tokio::time::timeout(SEND_TIMEOUT, self.sender.send(line)).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(_)) => Err("The server console is closed"),
+            Err(_) => Err("The server console isn't accepting input"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::console::normalize_line;
+
+    #[test]
+    fn test_normalize_line() {
+        assert_eq!(normalize_line("say hi\r\n"), "say hi");
+        assert_eq!(normalize_line("say hi\nop Steve"), "say hi op Steve");
+    }
+}
@@ -0,0 +1,246 @@
+// Thin wrappers around common console commands. The bot runs the console command, captures
+// the server's response, then turns it into a clean reply.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConsoleCommand {
+    Seed,
+    Weather,
+    Difficulty,
+    Ban,
+    Pardon,
+    Kick,
+    Op,
+    Deop,
+    // data get entity <player> Pos, for !map
+    Position,
+    // Any line run from the API, everything logged after it is its response
+    Raw,
+}
+
+impl ConsoleCommand {
+    // Moderation commands are recorded in the audit log
+    pub fn is_moderation(&self) -> bool {
+        matches!(self, ConsoleCommand::Ban | ConsoleCommand::Pardon | ConsoleCommand::Kick | ConsoleCommand::Op | ConsoleCommand::Deop)
+    }
+
+    // Commands that are hard to undo have to be confirmed first
+    pub fn needs_confirmation(&self) -> bool {
+        matches!(self, ConsoleCommand::Ban | ConsoleCommand::Op | ConsoleCommand::Deop)
+    }
+}
+
+const WEATHER_TYPES: &[&str] = &["clear", "rain", "thunder"];
+const DIFFICULTIES: &[&str] = &["peaceful", "easy", "normal", "hard"];
+
+// Responses to moderation commands that didn't change anything
+const MODERATION_ERRORS: &[&str] = &["Nothing changed", "That player does not exist", "No player was found", "Unknown or incomplete command"];
+
+// Minecraft usernames are 3-16 letters, numbers and underscores. Checking them also stops
+// anything else being smuggled into the console command.
+pub fn is_valid_player(name: &str) -> bool {
+    (3..=16).contains(&name.len()) && name.chars().all(|x| x.is_ascii_alphanumeric() || x == '_')
+}
+
+// Splits a command's arguments into a valid player name and the reason after it, if any
+pub fn parse_player_reason(args: &str) -> Option<(&str, Option<String>)> {
+    let mut words = args.split_whitespace();
+    let player = words.next().filter(|x| is_valid_player(x))?;
+    // Rejoining the words also removes any newlines from the reason
+    let reason = Some(words.collect::<Vec<&str>>().join(" ")).filter(|x| !x.is_empty());
+    Some((player, reason))
+}
+
+// Parses a discord command into the console command to run. Returns None if the message isn't
+// one of these commands, or an error with usage information if the arguments are invalid.
+pub fn parse_command(content: &str) -> Option<Result<(ConsoleCommand, String), &'static str>> {
+    let mut args = content.split_whitespace();
+    let command = args.next()?;
+    let arg = args.next();
+
+    match command {
+        "!seed" => Some(Ok((ConsoleCommand::Seed, "seed".to_string()))),
+        "!weather" => match arg {
+            Some(weather) if WEATHER_TYPES.contains(&weather) => Some(Ok((ConsoleCommand::Weather, format!("weather {}", weather)))),
+            _ => Some(Err("Usage: `!weather clear|rain|thunder`")),
+        },
+        "!difficulty" => match arg {
+            Some(difficulty) if DIFFICULTIES.contains(&difficulty) => Some(Ok((ConsoleCommand::Difficulty, format!("difficulty {}", difficulty)))),
+            None => Some(Ok((ConsoleCommand::Difficulty, "difficulty".to_string()))),
+            _ => Some(Err("Usage: `!difficulty [peaceful|easy|normal|hard]`")),
+        },
+        "!ban" | "!kick" => {
+            let (command, name, usage) = match command {
+                "!ban" => (ConsoleCommand::Ban, "ban", "Usage: `!ban <player> [reason]`"),
+                _ => (ConsoleCommand::Kick, "kick", "Usage: `!kick <player> [reason]`"),
+            };
+            let rest = content.trim_start().split_once(char::is_whitespace).map_or("", |(_, rest)| rest);
+            match parse_player_reason(rest) {
+                Some((player, Some(reason))) => Some(Ok((command, format!("{} {} {}", name, player, reason)))),
+                Some((player, None)) => Some(Ok((command, format!("{} {}", name, player)))),
+                None => Some(Err(usage)),
+            }
+        },
+        "!pardon" => match arg {
+            Some(player) if is_valid_player(player) => Some(Ok((ConsoleCommand::Pardon, format!("pardon {}", player)))),
+            _ => Some(Err("Usage: `!pardon <player>`")),
+        },
+        "!op" => match arg {
+            Some(player) if is_valid_player(player) => Some(Ok((ConsoleCommand::Op, format!("op {}", player)))),
+            _ => Some(Err("Usage: `!op <player>`")),
+        },
+        "!deop" => match arg {
+            Some(player) if is_valid_player(player) => Some(Ok((ConsoleCommand::Deop, format!("deop {}", player)))),
+            _ => Some(Err("Usage: `!deop <player>`")),
+        },
+        _ => None,
+    }
+}
+
+// Checks whether a line is the response to a moderation command succeeding
+// Banned Steve: Banned by an operator.
+// Unbanned Steve
+// Kicked Steve: Kicked by an operator.
+// Made Steve a server operator
+// Made Steve no longer a server operator
+fn is_moderation_success(command: ConsoleCommand, line: &str) -> bool {
+    match command {
+        ConsoleCommand::Ban => line.starts_with("Banned "),
+        ConsoleCommand::Pardon => line.starts_with("Unbanned "),
+        ConsoleCommand::Kick => line.starts_with("Kicked "),
+        ConsoleCommand::Op => line.starts_with("Made ") && line.ends_with(" a server operator") && !line.contains(" no longer "),
+        ConsoleCommand::Deop => line.starts_with("Made ") && line.ends_with(" no longer a server operator"),
+        _ => false,
+    }
+}
+
+// Steve has the following entity data: [12.5d, 64.0d, -30.25d]
+pub fn parse_position(line: &str) -> Option<(&str, [f64; 3])> {
+    let (player, data) = line.split_once(" has the following entity data: ")?;
+    let mut values = data.strip_prefix('[')?.strip_suffix(']')?.split(", ").map(|x| x.trim_end_matches('d').parse().ok());
+    let position = [values.next()??, values.next()??, values.next()??];
+    Some((player, position))
+}
+
+// Errors the server gives for any command it couldn't run
+const COMMAND_ERRORS: &[&str] = &["Unknown or incomplete command", "Incorrect argument for command", "Unknown command"];
+
+// Checks whether a log line is the start of the server's response to command
+pub fn is_response(command: ConsoleCommand, line: &str) -> bool {
+    if COMMAND_ERRORS.iter().any(|x| line.starts_with(x)) {
+        return true;
+    }
+
+    match command {
+        ConsoleCommand::Seed => line.starts_with("Seed: ["),
+        ConsoleCommand::Weather => line.starts_with("Set the weather to ") || line.starts_with("Changing to "),
+        ConsoleCommand::Difficulty => line.starts_with("The difficulty "),
+        ConsoleCommand::Ban | ConsoleCommand::Pardon | ConsoleCommand::Kick | ConsoleCommand::Op | ConsoleCommand::Deop => {
+            is_moderation_success(command, line) || MODERATION_ERRORS.iter().any(|x| line.starts_with(x))
+        },
+        ConsoleCommand::Position => parse_position(line).is_some() || line.starts_with("No entity was found"),
+        ConsoleCommand::Raw => true,
+    }
+}
+
+// Whether the captured log lines show that a moderation command worked, or for a raw command
+// that the server answered without an error
+pub fn succeeded(command: ConsoleCommand, lines: &[String]) -> bool {
+    match command {
+        ConsoleCommand::Raw => !lines.is_empty() && !lines.iter().any(|x| COMMAND_ERRORS.iter().any(|error| x.starts_with(error))),
+        _ => lines.iter().any(|x| is_moderation_success(command, x)),
+    }
+}
+
+// Finds the server's response to command in the captured log lines
+pub fn format_response(command: ConsoleCommand, lines: &[String]) -> String {
+    for line in lines {
+        match command {
+            // Seed: [-1234567890]
+            ConsoleCommand::Seed => {
+                if let Some(seed) = line.strip_prefix("Seed: [").and_then(|x| x.strip_suffix(']')) {
+                    return format!("World seed: `{}`", seed);
+                }
+            },
+            // Set the weather to clear
+            ConsoleCommand::Weather => {
+                if let Some(weather) = line.strip_prefix("Set the weather to ") {
+                    return format!("Weather set to {}", weather);
+                }
+            },
+            // The difficulty has been set to Hard
+            // The difficulty did not change; it is already set to hard
+            // The difficulty is Normal
+            ConsoleCommand::Difficulty => {
+                if line.starts_with("The difficulty ") {
+                    return line.clone();
+                }
+            },
+            ConsoleCommand::Ban | ConsoleCommand::Pardon | ConsoleCommand::Kick | ConsoleCommand::Op | ConsoleCommand::Deop => {
+                if is_moderation_success(command, line) || MODERATION_ERRORS.iter().any(|x| line.starts_with(x)) {
+                    return line.clone();
+                }
+            },
+            ConsoleCommand::Position => {
+                if let Some((player, [x, y, z])) = parse_position(line) {
+                    return format!("{} is at {}, {}, {}", player, x.floor(), y.floor(), z.floor());
+                }
+                if line.starts_with("No entity was found") {
+                    return "That player isn't online".to_string();
+                }
+            },
+            ConsoleCommand::Raw => return format!("```\n{}```", lines.join("\n")),
+        }
+    }
+
+    if lines.is_empty() {
+        "No response from server".to_string()
+    } else {
+        format!("Unexpected response from server:\n```\n{}```", lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::console_commands::{parse_command, format_response, is_response, succeeded, ConsoleCommand};
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("!seed"), Some(Ok((ConsoleCommand::Seed, "seed".to_string()))));
+        assert_eq!(parse_command("!weather rain"), Some(Ok((ConsoleCommand::Weather, "weather rain".to_string()))));
+        assert!(parse_command("!weather snow").unwrap().is_err());
+        assert_eq!(parse_command("!difficulty"), Some(Ok((ConsoleCommand::Difficulty, "difficulty".to_string()))));
+        assert!(parse_command("!difficulty impossible").unwrap().is_err());
+        assert_eq!(parse_command("!online"), None);
+        assert_eq!(parse_command("!ban Steve griefing\nop Alex"), Some(Ok((ConsoleCommand::Ban, "ban Steve griefing op Alex".to_string()))));
+        assert_eq!(parse_command("!kick Steve"), Some(Ok((ConsoleCommand::Kick, "kick Steve".to_string()))));
+        assert!(parse_command("!pardon @a").unwrap().is_err());
+        assert!(parse_command("!ban").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_format_response() {
+        let lines = vec!["Steve joined the game".to_string(), "Seed: [-42]".to_string()];
+        assert_eq!(format_response(ConsoleCommand::Seed, &lines), "World seed: `-42`");
+        assert_eq!(format_response(ConsoleCommand::Weather, &["Set the weather to rain".to_string()]), "Weather set to rain");
+        assert_eq!(format_response(ConsoleCommand::Weather, &[]), "No response from server");
+
+        let lines = vec!["Nothing changed. The player is already banned".to_string()];
+        assert_eq!(format_response(ConsoleCommand::Ban, &lines), "Nothing changed. The player is already banned");
+        assert!(!succeeded(ConsoleCommand::Ban, &lines));
+        assert!(succeeded(ConsoleCommand::Kick, &["Kicked Steve: Kicked by an operator.".to_string()]));
+        assert!(succeeded(ConsoleCommand::Deop, &["Made Steve no longer a server operator".to_string()]));
+        assert!(!succeeded(ConsoleCommand::Op, &["Made Steve no longer a server operator".to_string()]));
+        assert!(succeeded(ConsoleCommand::Raw, &["Set the time to 1000".to_string()]));
+        assert!(!succeeded(ConsoleCommand::Raw, &["Unknown or incomplete command, see below for error".to_string()]));
+        assert!(!succeeded(ConsoleCommand::Raw, &[]));
+        assert_eq!(format_response(ConsoleCommand::Position, &["Steve has the following entity data: [12.5d, 64.0d, -30.25d]".to_string()]), "Steve is at 12, 64, -31");
+    }
+
+    #[test]
+    fn test_is_response() {
+        assert!(is_response(ConsoleCommand::Seed, "Seed: [-42]"));
+        assert!(!is_response(ConsoleCommand::Seed, "Steve joined the game"));
+        assert!(is_response(ConsoleCommand::Ban, "Unknown or incomplete command, see below for error"));
+        assert!(!is_response(ConsoleCommand::Op, "Banned Steve: Banned by an operator."));
+    }
+}
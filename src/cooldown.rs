@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serenity::model::channel::Message;
+use serenity::prelude::*;
+use tracing::error;
+
+// Rate limit notices are deleted after this long so they don't clutter the channel
+const NOTICE_LIFETIME: Duration = Duration::from_secs(5);
+
+// How long a user has to wait before using the same command again, and how long anyone in a
+// channel has to wait after it has been used there
+#[derive(Deserialize)]
+pub struct CooldownToml {
+    #[serde(default = "default_user_secs")]
+    pub user_secs: u64,
+    #[serde(default = "default_channel_secs")]
+    pub channel_secs: u64,
+}
+
+impl Default for CooldownToml {
+    fn default() -> CooldownToml {
+        CooldownToml { user_secs: default_user_secs(), channel_secs: default_channel_secs() }
+    }
+}
+
+fn default_user_secs() -> u64 {
+    10
+}
+
+fn default_channel_secs() -> u64 {
+    3
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum CooldownCheck {
+    Allowed,
+    // The command is on cooldown for this many more seconds, the user should be told
+    Limited(u64),
+    // Still on cooldown and the user has already been told
+    Ignored,
+}
+
+pub struct Cooldowns {
+    user_cooldown: Duration,
+    channel_cooldown: Duration,
+    // Keyed by user or channel id and the command
+    users: HashMap<(u64, String), Instant>,
+    channels: HashMap<(u64, String), Instant>,
+    // Users that have been told they're rate limited, so they're only told once
+    notified: HashMap<(u64, String), Instant>,
+}
+
+impl Cooldowns {
+    pub fn new(config: &CooldownToml) -> Cooldowns {
+        Cooldowns {
+            user_cooldown: Duration::from_secs(config.user_secs),
+            channel_cooldown: Duration::from_secs(config.channel_secs),
+            users: HashMap::new(),
+            channels: HashMap::new(),
+            notified: HashMap::new(),
+        }
+    }
+
+    pub fn check(&mut self, user_id: u64, channel_id: u64, command: &str) -> CooldownCheck {
+        self.check_at(user_id, channel_id, command, Instant::now())
+    }
+
+    fn check_at(&mut self, user_id: u64, channel_id: u64, command: &str, now: Instant) -> CooldownCheck {
+        let user_key = (user_id, command.to_string());
+        let channel_key = (channel_id, command.to_string());
+        let remaining = |last: Option<&Instant>, cooldown: Duration| last.map(|x| cooldown.saturating_sub(now.saturating_duration_since(*x))).unwrap_or_default();
+
+        let wait = remaining(self.users.get(&user_key), self.user_cooldown).max(remaining(self.channels.get(&channel_key), self.channel_cooldown));
+        if wait.is_zero() {
+            self.users.insert(user_key.clone(), now);
+            self.channels.insert(channel_key, now);
+            self.notified.remove(&user_key);
+
+            // Old entries are dropped so the maps don't grow forever
+            let longest = self.user_cooldown.max(self.channel_cooldown);
+            self.users.retain(|_, x| now.saturating_duration_since(*x) < longest);
+            self.channels.retain(|_, x| now.saturating_duration_since(*x) < longest);
+            self.notified.retain(|_, x| now.saturating_duration_since(*x) < longest);
+            return CooldownCheck::Allowed;
+        }
+
+        if self.notified.insert(user_key, now).is_some() {
+            return CooldownCheck::Ignored;
+        }
+        CooldownCheck::Limited(wait.as_millis().div_ceil(1000) as u64)
+    }
+}
+
+// Replies to the command and deletes the reply shortly after, discord doesn't support ephemeral
+// replies to normal messages
+pub async fn send_notice(msg: &Message, ctx: &Context, text: &str) {
+    let notice = match msg.reply(&ctx.http, text).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Error sending message: {:?}", e);
+            return;
+        },
+    };
+
+    let ctx = ctx.clone();
+    tokio::task::spawn(async move {
+        tokio::time::sleep(NOTICE_LIFETIME).await;
+        if let Err(e) = notice.delete(&ctx.http).await {
+            error!("Error deleting message: {:?}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::cooldown::{CooldownCheck, CooldownToml, Cooldowns};
+
+    #[test]
+    fn test_cooldowns() {
+        let mut cooldowns = Cooldowns::new(&CooldownToml { user_secs: 10, channel_secs: 3 });
+        let start = Instant::now();
+        let seconds = |x: u64| start + Duration::from_secs(x);
+
+        assert_eq!(cooldowns.check_at(1, 100, "online", start), CooldownCheck::Allowed);
+        // Another user in the same channel waits for the channel cooldown
+        assert_eq!(cooldowns.check_at(2, 100, "online", seconds(1)), CooldownCheck::Limited(2));
+        assert_eq!(cooldowns.check_at(2, 100, "online", seconds(2)), CooldownCheck::Ignored);
+        assert_eq!(cooldowns.check_at(2, 100, "online", seconds(3)), CooldownCheck::Allowed);
+        assert_eq!(cooldowns.check_at(2, 100, "time", seconds(3)), CooldownCheck::Allowed);
+
+        // The first user waits for their own cooldown
+        assert_eq!(cooldowns.check_at(1, 200, "online", seconds(5)), CooldownCheck::Limited(5));
+        assert_eq!(cooldowns.check_at(1, 200, "online", seconds(10)), CooldownCheck::Allowed);
+    }
+}
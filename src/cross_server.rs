@@ -0,0 +1,242 @@
+// Bridges game chat between servers run by different instances of the bot. Each instance posts
+// its players' chat to its peers over HTTP, signed with a shared secret, and shows chat from
+// its peers in game with the other server's name.
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::Router;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::bot::Bot;
+use crate::{send_or_log, tellraw, Packet};
+
+const SIGNATURE_HEADER: &str = "x-signature";
+
+// Messages older than this are refused, so a captured request can't be replayed later
+const MAX_AGE_SECS: i64 = 60;
+
+// How far ahead a peer's clock may be
+const MAX_SKEW_SECS: i64 = 5;
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Longer names and messages are cut short
+const MAX_NAME_CHARS: usize = 32;
+const MAX_MESSAGE_CHARS: usize = 256;
+
+#[derive(Deserialize)]
+pub struct CrossServerToml {
+    // Shown before this server's chat on the other servers
+    pub name: String,
+    // Every instance has to use the same secret
+    pub secret: Secret,
+    // Port peers post chat to
+    pub port: u16,
+    // The other instances, eg. "http://10.0.0.2:8126"
+    #[serde(default)]
+    pub peers: Vec<String>,
+    // A Minecraft color name for the server name in game
+    #[serde(default = "default_color")]
+    pub color: String,
+}
+
+fn default_color() -> String {
+    "dark_green".to_string()
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+pub struct Secret(String);
+
+impl TryFrom<String> for Secret {
+    type Error = String;
+
+    fn try_from(secret: String) -> Result<Secret, String> {
+        match secret.trim().is_empty() {
+            true => Err("The cross server secret can't be empty".to_string()),
+            false => Ok(Secret(secret)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChatMessage {
+    pub server: String,
+    pub player: String,
+    pub message: String,
+    // Unix timestamp in seconds
+    pub time: i64,
+    // Random, so the same message can't be accepted twice
+    pub id: u64,
+}
+
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// Checks the signature in constant time
+pub fn verify(secret: &str, body: &str, signature: &str) -> bool {
+    let signature = match hex::decode(signature) {
+        Ok(v) => v,
+        Err(_) => return false,
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    text.chars().take(max_chars).collect()
+}
+
+pub struct CrossServer {
+    name: String,
+    secret: String,
+    color: String,
+    peers: Vec<String>,
+    client: reqwest::Client,
+    // Ids of recently accepted messages and when they'd be too old to accept anyway
+    seen: VecDeque<(i64, u64)>,
+}
+
+impl CrossServer {
+    pub fn new(config: &CrossServerToml) -> CrossServer {
+        let client = reqwest::Client::builder().timeout(SEND_TIMEOUT).build().unwrap_or_default();
+        let peers = config.peers.iter().map(|x| x.trim_end_matches('/').to_string()).collect();
+        CrossServer { name: config.name.clone(), secret: config.secret.0.clone(), color: config.color.clone(), peers, client, seen: VecDeque::new() }
+    }
+
+    // Posts a player's chat to every peer from another task
+    pub fn forward(&self, player: &str, message: &str) {
+        let chat = ChatMessage { server: self.name.clone(), player: player.to_string(), message: message.to_string(), time: Utc::now().timestamp(), id: rand::random() };
+        let body = serde_json::to_string(&chat).unwrap();
+        let signature = sign(&self.secret, &body);
+        for peer in &self.peers {
+            let request = self.client.post(format!("{}/chat", peer))
+                .header("content-type", "application/json")
+                .header(SIGNATURE_HEADER, &signature)
+                .body(body.clone());
+            let peer = peer.clone();
+            tokio::task::spawn(async move {
+                match request.send().await.and_then(|x| x.error_for_status()) {
+                    Ok(_) => {},
+                    Err(e) => warn!("Error relaying chat to {}: {}", peer, e),
+                }
+            });
+        }
+    }
+
+    // Refuses messages that are too old, from the future or already accepted
+    pub fn accept(&mut self, chat: &ChatMessage, now: i64) -> bool {
+        self.seen.retain(|(expires, _)| *expires >= now);
+        if now - chat.time > MAX_AGE_SECS || chat.time - now > MAX_SKEW_SECS || self.seen.iter().any(|(_, id)| *id == chat.id) {
+            return false;
+        }
+        self.seen.push_back((chat.time + MAX_AGE_SECS, chat.id));
+        true
+    }
+
+    // The tellraw command that shows a peer's chat in game
+    pub fn game_line(&self, chat: &ChatMessage) -> String {
+        let prefix = format!("[{}]", truncate(&chat.server, MAX_NAME_CHARS));
+        tellraw::relay_message(&prefix, &self.color, Some(&truncate(&chat.player, MAX_NAME_CHARS)), &truncate(&chat.message, MAX_MESSAGE_CHARS), &[])
+    }
+}
+
+#[derive(Clone)]
+struct PeerState {
+    sender: mpsc::UnboundedSender<Packet>,
+    secret: String,
+}
+
+async fn receive_chat(State(state): State<PeerState>, headers: HeaderMap, body: String) -> StatusCode {
+    let signature = headers.get(SIGNATURE_HEADER).and_then(|x| x.to_str().ok()).unwrap_or("");
+    if !verify(&state.secret, &body, signature) {
+        return StatusCode::UNAUTHORIZED;
+    }
+    match serde_json::from_str(&body) {
+        Ok(chat) => {
+            send_or_log(&state.sender, Packet::CrossServerChat(chat));
+            StatusCode::NO_CONTENT
+        },
+        Err(_) => StatusCode::BAD_REQUEST,
+    }
+}
+
+// Serves POST /chat for peers
+pub fn start_peer_server(config: &CrossServerToml, sender: &mpsc::UnboundedSender<Packet>) {
+    let state = PeerState { sender: sender.clone(), secret: config.secret.0.clone() };
+    let app = Router::new()
+        .route("/chat", post(receive_chat))
+        .with_state(state);
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+    tokio::task::spawn(async move {
+        let server = match axum::Server::try_bind(&addr) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Error binding cross server chat port {}: {}", addr.port(), e);
+                return;
+            },
+        };
+        info!("Receiving cross server chat on port {}", addr.port());
+
+        if let Err(e) = server.serve(app.into_make_service()).await {
+            error!("Cross server chat error: {}", e);
+        }
+    });
+}
+
+// Shows chat from a peer in game
+pub async fn receive(bot: &mut Bot, chat: ChatMessage) {
+    let cross_server = match &mut bot.cross_server {
+        Some(v) => v,
+        None => return,
+    };
+    if !cross_server.accept(&chat, Utc::now().timestamp()) {
+        warn!("Ignored a stale or repeated chat message from {}", chat.server);
+        return;
+    }
+    let line = cross_server.game_line(&chat);
+    bot.write_console(&line).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cross_server::{sign, verify, ChatMessage, CrossServer, CrossServerToml};
+
+    #[test]
+    fn test_cross_server() {
+        let signature = sign("secret", r#"{"message":"hi"}"#);
+        assert!(verify("secret", r#"{"message":"hi"}"#, &signature));
+        assert!(!verify("secret", r#"{"message":"bye"}"#, &signature));
+        assert!(!verify("other", r#"{"message":"hi"}"#, &signature));
+        assert!(!verify("secret", r#"{"message":"hi"}"#, "not hex"));
+
+        let config: CrossServerToml = toml::from_str("name = 'Survival'\nsecret = 'secret'\nport = 8126\npeers = ['http://10.0.0.2:8126/']").unwrap();
+        let mut cross_server = CrossServer::new(&config);
+        assert_eq!(cross_server.peers, vec!["http://10.0.0.2:8126"]);
+
+        let chat = ChatMessage { server: "Creative".to_string(), player: "Steve".to_string(), message: "hi".to_string(), time: 1_000, id: 7 };
+        assert!(cross_server.accept(&chat, 1_010));
+        assert!(!cross_server.accept(&chat, 1_020));
+        assert!(!cross_server.accept(&chat, 1_060));
+        assert!(!cross_server.accept(&ChatMessage { id: 8, ..chat.clone() }, 1_100));
+        assert!(cross_server.accept(&ChatMessage { id: 9, time: 1_105, ..chat.clone() }, 1_100));
+        assert!(!cross_server.accept(&ChatMessage { id: 10, time: 1_106, ..chat.clone() }, 1_100));
+        assert!(toml::from_str::<CrossServerToml>("name = 'Survival'\nsecret = ' '\nport = 8126").is_err());
+        assert_eq!(cross_server.game_line(&chat), r#"tellraw @a ["",{"color":"dark_green","text":"[Creative] "},{"color":"aqua","text":"Steve: "},{"text":"hi"}]"#);
+    }
+}
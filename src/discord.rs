@@ -1,9 +1,13 @@
 use serenity::async_trait;
 use serenity::model::channel::Message;
+use serenity::model::event::MessageUpdateEvent;
+use serenity::model::id::{ChannelId, GuildId, MessageId};
+use serenity::model::interactions::Interaction;
 use serenity::model::gateway::Ready;
 use serenity::prelude::*;
 
 use tokio::sync::mpsc;
+use tracing::{error, info};
 
 use crate::{Packet, send_or_log};
 
@@ -12,11 +16,27 @@ struct Handler(mpsc::UnboundedSender<Packet>);
 #[async_trait]
 impl EventHandler for Handler {
     async fn message(&self, _: Context, msg: Message) {
-        send_or_log(&self.0, Packet::DiscordMessage(msg));
+        send_or_log(&self.0, Packet::DiscordMessage(Box::new(msg)));
+    }
+
+    // Messages aren't cached so only the event is passed on
+    async fn message_update(&self, _: Context, _old: Option<Message>, _new: Option<Message>, event: MessageUpdateEvent) {
+        send_or_log(&self.0, Packet::DiscordMessageUpdate(Box::new(event)));
+    }
+
+    async fn message_delete(&self, _: Context, channel_id: ChannelId, message_id: MessageId, _guild_id: Option<GuildId>) {
+        send_or_log(&self.0, Packet::DiscordMessageDelete(channel_id, message_id));
+    }
+
+    // Button presses on confirmation prompts
+    async fn interaction_create(&self, _: Context, interaction: Interaction) {
+        if let Interaction::MessageComponent(component) = interaction {
+            send_or_log(&self.0, Packet::DiscordComponent(Box::new(component)));
+        }
     }
 
     async fn ready(&self, ctx: Context, ready: Ready) {
-        send_or_log(&self.0, Packet::DiscordReady(ctx, ready));
+        send_or_log(&self.0, Packet::DiscordReady(ctx, Box::new(ready)));
     }
 }
 
@@ -34,14 +54,14 @@ pub async fn start_discord_integration(token: &str, sender: &mpsc::UnboundedSend
         .await
         .expect("Err creating client");
 
-    println!("Starting discord integration");
+    info!("Starting discord integration");
 
     // Finally, start a single shard, and start listening to events.
     //
     // Shards will automatically attempt to reconnect, and will perform
     // exponential backoff until it reconnects.
     if let Err(why) = client.start().await {
-        println!("Client error: {:?}", why);
+        error!("Client error: {:?}", why);
     }
 
     send_or_log(sender, Packet::StopServer());
@@ -0,0 +1,387 @@
+// Messages, edits, deletes and button presses from discord. Commands are checked against
+// permissions, channels and cooldowns before being dispatched, other messages are relayed into
+// the game as chat or passed to the console from the admin channel.
+
+use serenity::model::channel::Message;
+use serenity::model::event::MessageUpdateEvent;
+use serenity::model::gateway::Activity;
+use serenity::model::id::{ChannelId, MessageId};
+use serenity::model::interactions::message_component::MessageComponentInteraction;
+use serenity::utils::ContentSafeOptions;
+use tracing::{error, info};
+
+use crate::bot::Bot;
+use crate::channel_status::{self, ChannelStatus};
+use crate::command_queue::ConsoleTask;
+use crate::confirm::{self, ConfirmAction, Confirmations};
+use crate::console_commands::ConsoleCommand;
+use crate::cooldown::{self, Cooldowns};
+use crate::outbound::OutboundQueue;
+use crate::polls::Voter;
+use crate::relay_channels::{self, RelayChannel, RelayChannels};
+use crate::relayed_messages::RelayedMessages;
+use crate::server_admin::ServerAdmin;
+use crate::stats_tracker::StatsTracker;
+use crate::{api, audit, chat_filter, chat_log, commands, console_commands, permissions, player_reports, polls, presence, tellraw};
+use crate::{plugin_stats, run_console_task, run_plugin_actions, say_or_log, send_or_log, start_next_command, ConfigToml, Packet};
+
+pub struct DiscordEvents {
+    my_id: u64,
+    cooldowns: Cooldowns,
+    channel_status: Option<ChannelStatus>,
+
+    // Chat messages sent into the game, so edits and deletes can follow them
+    relayed_messages: RelayedMessages,
+
+    // Destructive commands waiting for an admin to press Confirm
+    confirmations: Confirmations,
+
+    // Channels whose messages are shown in game, besides the chat channel
+    relay_channels: RelayChannels,
+}
+
+impl DiscordEvents {
+    pub fn new(config: &ConfigToml) -> DiscordEvents {
+        DiscordEvents {
+            my_id: 0,
+            cooldowns: Cooldowns::new(&config.cooldowns),
+            channel_status: config.channel_status.as_ref().map(ChannelStatus::new),
+            relayed_messages: RelayedMessages::default(),
+            confirmations: Confirmations::default(),
+            relay_channels: RelayChannels::new(&config.relay_channels),
+        }
+    }
+
+    pub async fn handle(&mut self, bot: &mut Bot, stats: &mut StatsTracker, admin: &ServerAdmin, packet: Packet) {
+        match packet {
+            Packet::DiscordReady(new_ctx, ready) => {
+                let values = presence::PresenceValues::new(stats.online.len(), &bot.metrics.lock().unwrap());
+                new_ctx.set_activity(Activity::playing(bot.presence.current(&values))).await;
+
+                if bot.outbound.is_none() {
+                    bot.outbound = Some(OutboundQueue::start(new_ctx.clone()));
+                }
+                bot.ctx = Some(new_ctx);
+                self.my_id = ready.user.id.0;
+                if self.channel_status.is_some() {
+                    send_or_log(&bot.sender, Packet::ChannelStatusTick());
+                }
+                info!("Discord ready");
+            },
+            Packet::DiscordMessage(msg) => self.message(bot, stats, admin, &msg).await,
+            Packet::DiscordMessageUpdate(event) => self.message_update(bot, &event).await,
+            Packet::DiscordMessageDelete(channel_id, message_id) => self.message_delete(bot, channel_id, message_id).await,
+            Packet::DiscordComponent(component) => self.component(bot, &component).await,
+            Packet::CommandTimerElapsed(id) => {
+                let task = match bot.command_queue.finish(id) {
+                    Some(v) => v,
+                    None => return,
+                };
+
+                if let Some(mut entry) = task.audit_entry {
+                    entry.succeeded = console_commands::succeeded(task.command, &task.lines);
+                    if let Err(e) = audit::record(&entry) {
+                        error!("Error writing {} {}", audit::AUDIT_LOG_PATH, e);
+                    }
+                }
+
+                let ctx = match &bot.ctx {
+                    Some(v) => v,
+                    // The waiting commands would never be started, so they're dropped
+                    None => return bot.command_queue.clear(),
+                };
+
+                let mut response = console_commands::format_response(task.command, &task.lines);
+                if let (ConsoleCommand::Position, Some(map)) = (task.command, &bot.config.map) {
+                    if let Some(link) = map.player_link(&task.lines) {
+                        response = format!("{}\n{}", response, link);
+                    }
+                }
+                say_or_log(task.channel_id, ctx, &response).await;
+                start_next_command(&bot.console, &mut bot.command_queue, &bot.sender, ctx).await;
+            },
+            Packet::PollEnded(id) => {
+                let poll = match bot.poll.take_if(|x| x.id == id) {
+                    Some(v) => v,
+                    None => return,
+                };
+
+                let results = poll.results();
+                bot.write_console(&tellraw::to_all(&results)).await;
+                bot.say(poll.channel_id, &results).await;
+                if let (Some(ctx), Some(message_id)) = (&bot.ctx, poll.message_id) {
+                    polls::close_poll(ctx, poll.channel_id, message_id).await;
+                }
+            },
+            Packet::ChannelStatusTick() => {
+                let (ctx, channel_status) = match (&bot.ctx, &mut self.channel_status) {
+                    (Some(ctx), Some(channel_status)) => (ctx, channel_status),
+                    _ => return,
+                };
+
+                let values = presence::PresenceValues::new(stats.online.len(), &bot.metrics.lock().unwrap());
+                let (name, topic) = channel_status.changes(&values);
+                channel_status::apply_changes(ctx, bot.channel_id, name, topic);
+            },
+            Packet::PresenceTick() => {
+                if let Some(ctx) = &bot.ctx {
+                    let values = presence::PresenceValues::new(stats.online.len(), &bot.metrics.lock().unwrap());
+                    ctx.set_activity(Activity::playing(bot.presence.next(&values))).await;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    async fn message(&mut self, bot: &mut Bot, stats: &mut StatsTracker, admin: &ServerAdmin, msg: &Message) {
+        if msg.author.id == self.my_id {
+            return;
+        }
+
+        // Direct messages from admin users are treated like the admin channel
+        let is_admin_dm = msg.guild_id.is_none() && bot.admin_user_ids.contains(&msg.author.id);
+        let is_admin_channel = Some(msg.channel_id) == bot.admin_channel_id || is_admin_dm;
+        if msg.channel_id != bot.channel_id && !is_admin_channel {
+            if let Some(channel) = self.relay_channels.get(msg.channel_id) {
+                relay(bot, channel, msg).await;
+            }
+            return;
+        }
+
+        let ctx = match bot.ctx.clone() {
+            Some(v) => v,
+            None => return,
+        };
+
+        // Commands are checked against their own name, plain messages are either relayed
+        // as chat or passed through to the server console in the admin channel
+        let dm_roles;
+        let roles = if is_admin_dm {
+            dm_roles = permissions::guild_member_roles(&ctx, bot.channel_id, msg.author.id).await;
+            &dm_roles
+        } else {
+            permissions::member_roles(msg)
+        };
+        let name = commands::command_name(&msg.content);
+        let command = name.and_then(commands::find);
+        let permission = match (name, command) {
+            (_, Some(command)) => command.permission,
+            (Some(name), None) => name,
+            (None, _) if is_admin_channel => "console",
+            (None, _) => "chat",
+        };
+
+        if !bot.permissions.is_allowed(permission, roles) {
+            if permission != "chat" {
+                say_or_log(msg.channel_id, &ctx, &bot.locale.format("commands.no_permission", &[("command", permission)])).await;
+            }
+            return;
+        }
+
+        let channel = command.map(|x| x.channel);
+        if channel == Some(commands::CommandChannel::Admin) && !is_admin_channel {
+            say_or_log(msg.channel_id, &ctx, bot.locale.get("commands.admin_only")).await;
+            return;
+        }
+
+        if channel == Some(commands::CommandChannel::Any) && !is_admin_channel {
+            match self.cooldowns.check(msg.author.id.0, msg.channel_id.0, permission) {
+                cooldown::CooldownCheck::Allowed => {},
+                cooldown::CooldownCheck::Limited(secs) => {
+                    cooldown::send_notice(msg, &ctx, &bot.locale.format("commands.cooldown", &[("secs", &secs.to_string()), ("command", permission)])).await;
+                    return;
+                },
+                cooldown::CooldownCheck::Ignored => return,
+            }
+        }
+
+        if let Some(invocation) = commands::Invocation::parse(&msg.content) {
+            let mut command_context = commands::CommandContext {
+                ctx: ctx.clone(),
+                msg,
+                roles,
+                is_admin_channel,
+                bot,
+                stats,
+                admin,
+                confirmations: &mut self.confirmations,
+            };
+            commands::dispatch(&mut command_context, &invocation).await;
+        } else if msg.content.starts_with('!') && bot.plugins.has_command(permission) {
+            let args = msg.content.split_once(' ').map(|x| x.1.trim()).unwrap_or("");
+            let plugin_stats = plugin_stats(&stats.state, &stats.online, &bot.bedrock);
+            let actions = bot.plugins.run_command(permission, args, &msg.author.name, is_admin_channel, plugin_stats);
+            run_plugin_actions(actions, &bot.console, &ctx, msg.channel_id).await;
+        } else if msg.content.starts_with('!') {
+            say_or_log(msg.channel_id, &ctx, &format!("Unknown command: {}", msg.content)).await;
+        } else if is_admin_channel {
+            // Each line of an admin channel message is its own console command, chat
+            // is always kept to a single line so it can't run anything
+            for line in msg.content.lines() {
+                bot.write_console(line).await;
+            }
+        } else {
+            let verdict = chat_filter::check(bot, false, &msg.author.name, &msg.content_safe(&ctx)).await;
+            if let Some(warning) = &verdict.warning {
+                say_or_log(msg.channel_id, &ctx, &format!("<@{}> {}", msg.author.id, warning)).await;
+            }
+            let message = match verdict.relay {
+                Some(v) => v,
+                None => return,
+            };
+            let reply = msg.referenced_message.as_ref().map(|x| tellraw::reply_preview(&x.author.name, &x.content_safe(&ctx)));
+
+            // Link previews are skipped since their url is already in the message
+            let mut links: Vec<(String, String)> = msg.attachments.iter()
+                .map(|x| (tellraw::attachment_label(&x.filename, x.content_type.as_deref()), x.url.clone()))
+                .collect();
+            for embed in &msg.embeds {
+                if let Some(url) = embed.url.as_ref().filter(|x| !message.contains(x.as_str())) {
+                    links.push((format!("link: {}", embed.title.as_deref().unwrap_or(url)), url.clone()));
+                }
+            }
+            bot.write_console(&tellraw::discord_chat(&msg.author.name, &message, reply.as_deref(), &links)).await;
+            if bot.console.is_some() {
+                self.relayed_messages.push(msg.id, &msg.author.name);
+                chat_log::record(&chat_log::ChatEntry::new(chat_log::ChatSource::Discord, &msg.author.name, &message));
+                bot.events.publish(api::ApiEvent::Chat { source: "discord", player: msg.author.name.clone(), message: message.clone() });
+            }
+        }
+    }
+
+    async fn message_update(&mut self, bot: &mut Bot, event: &MessageUpdateEvent) {
+        // Updates without an edit time are discord adding link previews
+        let (ctx, content) = match (&bot.ctx, &event.content) {
+            (Some(ctx), Some(content)) if event.channel_id == bot.channel_id && event.edited_timestamp.is_some() => (ctx, content),
+            _ => return,
+        };
+        let author = match self.relayed_messages.author(event.id) {
+            Some(v) => v.to_string(),
+            None => return,
+        };
+
+        let message = serenity::utils::content_safe(ctx, content, &ContentSafeOptions::default(), event.mentions.as_deref().unwrap_or_default());
+        let message = match chat_filter::check(bot, false, &author, &message).await.relay {
+            Some(v) => v,
+            None => return,
+        };
+        let message = format!("{} (edited)", message);
+        bot.write_console(&tellraw::discord_chat(&author, &message, None, &[])).await;
+        chat_log::record(&chat_log::ChatEntry::new(chat_log::ChatSource::Discord, &author, &message));
+    }
+
+    async fn message_delete(&mut self, bot: &Bot, channel_id: ChannelId, message_id: MessageId) {
+        if channel_id != bot.channel_id {
+            return;
+        }
+
+        if let Some(author) = self.relayed_messages.remove(message_id) {
+            if bot.config.mirror_deletes {
+                bot.write_console(&tellraw::retraction(&author)).await;
+            }
+        }
+    }
+
+    // Confirm and Cancel presses on prompts for destructive commands
+    async fn component(&mut self, bot: &mut Bot, component: &MessageComponentInteraction) {
+        let ctx = match &bot.ctx {
+            Some(v) => v,
+            None => return,
+        };
+
+        if let Some(option) = polls::button_option(&component.data.custom_id) {
+            let text = match &mut bot.poll {
+                Some(poll) if poll.message_id == Some(component.message.id) => match poll.vote(Voter::Discord(component.user.id.0), option) {
+                    Some(name) => format!("You voted for {}", name),
+                    None => return,
+                },
+                _ => "This poll has ended".to_string(),
+            };
+            confirm::respond(component, ctx, &text, false).await;
+            return;
+        }
+
+        let dm_roles;
+        let roles = match &component.member {
+            Some(member) => member.roles.as_slice(),
+            None if bot.admin_user_ids.contains(&component.user.id) => {
+                dm_roles = permissions::guild_member_roles(ctx, bot.channel_id, component.user.id).await;
+                &dm_roles
+            },
+            None => &[],
+        };
+
+        if let Some(action) = player_reports::button_action(&component.data.custom_id) {
+            let allowed = bot.permissions.is_allowed(action.permission(), roles);
+            player_reports::button_pressed(bot, component, action, allowed).await;
+            return;
+        }
+
+        let confirmation = match self.confirmations.get(component.message.id) {
+            Some(v) => v,
+            None => {
+                confirm::respond(component, ctx, "This prompt has expired, run the command again", false).await;
+                return;
+            },
+        };
+
+        if !bot.permissions.is_allowed(confirmation.action.permission(), roles) {
+            confirm::respond(component, ctx, &format!("You don't have permission to use `{}`", confirmation.action.permission()), false).await;
+            return;
+        }
+
+        let confirmation = match self.confirmations.remove(component.message.id) {
+            Some(v) => v,
+            None => return,
+        };
+
+        if component.data.custom_id != confirm::CONFIRM_BUTTON {
+            let text = format!("{} requested by {} was cancelled by {}", confirmation.action.describe(), confirmation.requested_by, component.user.tag());
+            confirm::respond(component, ctx, &text, true).await;
+            return;
+        }
+
+        // The prompt is kept as a record of who requested and confirmed the action
+        let text = format!("{} requested by {} and confirmed by {}", confirmation.action.describe(), confirmation.requested_by, component.user.tag());
+        confirm::respond(component, ctx, &text, true).await;
+
+        match confirmation.action {
+            ConfirmAction::Console(command, console_line) => {
+                let mut audit_entry = audit::AuditEntry::new(confirmation.requested_by.clone(), confirmation.requested_by_id, &console_line);
+                audit_entry.confirmed_by = Some(component.user.tag());
+                let player = console_line.split(' ').nth(1).unwrap_or_default().to_string();
+                let new_task = ConsoleTask::new(confirmation.channel_id, command, console_line, Some(audit_entry));
+                match run_console_task(&bot.console, &mut bot.command_queue, &bot.sender, ctx, new_task).await {
+                    Err(e) => say_or_log(confirmation.channel_id, ctx, e).await,
+                    // A permanent ban replaces a tempban, so the player isn't pardoned when it ends
+                    Ok(()) if command == ConsoleCommand::Ban && bot.tempbans.remove(&player) => bot.tempbans.write(),
+                    Ok(()) => {},
+                }
+            },
+            // Stopping and updating the server are left to the server admin
+            action => send_or_log(&bot.sender, Packet::Confirmed(confirmation.channel_id, action)),
+        }
+    }
+}
+
+// Shows a relay channel's message in game, messages that look like commands are left alone
+async fn relay(bot: &Bot, channel: &RelayChannel, msg: &Message) {
+    let ctx = match &bot.ctx {
+        Some(v) => v,
+        None => return,
+    };
+    let content = msg.content_safe(ctx);
+    if content.starts_with('!') {
+        return;
+    }
+    let embed = msg.embeds.first();
+    let text = match relay_channels::message_text(&content, embed.and_then(|x| x.title.as_deref()), embed.and_then(|x| x.description.as_deref())) {
+        Some(v) => v,
+        None => return,
+    };
+    let links: Vec<(String, String)> = msg.attachments.iter()
+        .map(|x| (tellraw::attachment_label(&x.filename, x.content_type.as_deref()), x.url.clone()))
+        .collect();
+    let author = channel.show_author.then_some(msg.author.name.as_str());
+    bot.write_console(&tellraw::relay_message(&channel.prefix, &channel.color, author, &text, &links)).await;
+}
@@ -0,0 +1,179 @@
+// Watches free space on the disks holding the server and its backups, telling the admin channel
+// once when one runs low and again when it recovers. Backups that likely won't fit are refused
+// before saving is paused.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use crate::backup::{self, BackupDestination, BACKUP_DIR};
+use crate::preflight::free_space;
+use crate::{send_or_log, status, ConfigToml, Packet};
+
+const GB: u64 = 1024 * 1024 * 1024;
+
+#[derive(Deserialize)]
+pub struct DiskToml {
+    #[serde(default = "default_interval_mins")]
+    pub interval_mins: u64,
+    #[serde(default = "default_min_free_gb")]
+    pub min_free_gb: u64,
+}
+
+impl Default for DiskToml {
+    fn default() -> DiskToml {
+        DiskToml { interval_mins: default_interval_mins(), min_free_gb: default_min_free_gb() }
+    }
+}
+
+fn default_interval_mins() -> u64 {
+    10
+}
+
+fn default_min_free_gb() -> u64 {
+    5
+}
+
+fn gb(bytes: u64) -> String {
+    format!("{:.1} GB", bytes as f64 / GB as f64)
+}
+
+// Directories that don't exist yet, eg. backups/ before the first backup, are measured by the
+// closest parent that does
+fn existing(path: &Path) -> PathBuf {
+    path.ancestors().find(|x| x.exists() && !x.as_os_str().is_empty())
+        .map(|x| x.to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+// Identifies the disk a path is on so one disk isn't reported once per directory
+#[cfg(unix)]
+fn device(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).ok().map(|x| x.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_path: &Path) -> Option<u64> {
+    None
+}
+
+// The directories backups are written to, backups/ and a local destination
+fn backup_dirs(config: &ConfigToml) -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from(BACKUP_DIR)];
+    if let Some(BackupDestination::Local { path, .. }) = &config.backup.destination {
+        dirs.push(PathBuf::from(path));
+    }
+    dirs
+}
+
+// The server's directory and the backup directories with the free space on each disk
+fn readings(config: &ConfigToml) -> Vec<(String, u64)> {
+    let mut dirs = vec![PathBuf::from(config.launch.working_dir.as_deref().unwrap_or("."))];
+    dirs.extend(backup_dirs(config));
+
+    let mut devices = HashSet::new();
+    let mut readings = Vec::new();
+    for dir in dirs {
+        let path = existing(&dir);
+        if device(&path).is_some_and(|x| !devices.insert(x)) {
+            continue;
+        }
+        if let Some(free) = free_space(&path) {
+            readings.push((dir.display().to_string(), free));
+        }
+    }
+    readings
+}
+
+#[derive(Default)]
+pub struct DiskMonitor {
+    min_free: u64,
+    // Directories that have been reported as low
+    low: HashSet<String>,
+}
+
+impl DiskMonitor {
+    pub fn new(config: &DiskToml) -> DiskMonitor {
+        DiskMonitor { min_free: config.min_free_gb * GB, low: HashSet::new() }
+    }
+
+    // Returns messages for the admin channel for directories that ran low or recovered
+    pub fn check(&mut self, readings: &[(String, u64)]) -> Vec<String> {
+        let mut messages = Vec::new();
+        for (dir, free) in readings {
+            if *free < self.min_free && self.low.insert(dir.clone()) {
+                messages.push(format!("Only {} of disk space is free for {}, remove old backups or logs before it runs out", gb(*free), dir));
+            } else if *free >= self.min_free && self.low.remove(dir) {
+                messages.push(format!("{} of disk space is free for {} again", gb(*free), dir));
+            }
+        }
+        messages
+    }
+
+    pub fn tick(&mut self, config: &ConfigToml) -> Vec<String> {
+        self.check(&readings(config))
+    }
+}
+
+// The last backup's size is the best guess at the next one's, the world's own size is used
+// before there is one since compression only makes it smaller
+fn estimate_backup_size() -> u64 {
+    backup::list_backups().first()
+        .and_then(|id| backup::backup_path(id))
+        .and_then(|x| std::fs::metadata(x).ok())
+        .map(|x| x.len())
+        .unwrap_or_else(|| status::dir_size(Path::new(&backup::world_dir())))
+}
+
+// Whether a backup of estimate bytes fits, leaving the disk with min_free to spare
+pub fn fits(estimate: u64, free: u64, min_free: u64) -> bool {
+    free >= estimate.saturating_add(min_free)
+}
+
+// Why a backup can't be made, None if there's room for it. The backup is written to backups/ and
+// then copied to a local destination so both need room.
+pub fn check_backup(config: &ConfigToml) -> Option<String> {
+    let estimate = estimate_backup_size();
+    let min_free = config.disk.min_free_gb * GB;
+    backup_dirs(config).into_iter().find_map(|dir| {
+        let free = free_space(&existing(&dir))?;
+        (!fits(estimate, free, min_free)).then(|| {
+            format!("Not backing up, the backup needs about {} but only {} is free for {} and {} GB should be left free", gb(estimate), gb(free), dir.display(), config.disk.min_free_gb)
+        })
+    })
+}
+
+// Sends a DiskTick packet every interval_mins
+pub fn start_disk_timer(config: &DiskToml, sender: &mpsc::UnboundedSender<Packet>) {
+    let interval = Duration::from_secs(config.interval_mins.max(1) * 60);
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            send_or_log(&sender, Packet::DiskTick());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disk_monitor::{fits, DiskMonitor, DiskToml, GB};
+
+    #[test]
+    fn test_disk_monitor() {
+        let mut monitor = DiskMonitor::new(&DiskToml { interval_mins: 10, min_free_gb: 5 });
+        assert!(monitor.check(&[(".".to_string(), 20 * GB), ("backups".to_string(), 20 * GB)]).is_empty());
+        assert_eq!(monitor.check(&[(".".to_string(), 4 * GB), ("backups".to_string(), 20 * GB)]), vec![
+            "Only 4.0 GB of disk space is free for ., remove old backups or logs before it runs out".to_string(),
+        ]);
+        assert!(monitor.check(&[(".".to_string(), 3 * GB)]).is_empty());
+        assert_eq!(monitor.check(&[(".".to_string(), 6 * GB)]), vec!["6.0 GB of disk space is free for . again".to_string()]);
+
+        assert!(fits(GB, 10 * GB, 5 * GB));
+        assert!(!fits(6 * GB, 10 * GB, 5 * GB));
+    }
+}
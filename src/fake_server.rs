@@ -0,0 +1,141 @@
+// A stand in for a Minecraft server that logs like a vanilla one, so the bot can be tried out and
+// tested without a real server. Besides the usual console commands it takes a few of its own to
+// act out what players do: join <player>, leave <player>, chat <player> <message> and crash.
+
+use std::io::{BufRead, Write};
+
+use chrono::Local;
+
+const MAX_PLAYERS: usize = 20;
+
+#[derive(Default)]
+pub struct FakeServer {
+    players: Vec<String>,
+}
+
+// Lines the server logged in response to a command and its exit code if it stopped
+#[derive(Debug, PartialEq, Eq)]
+pub struct Output {
+    pub lines: Vec<String>,
+    pub exit_code: Option<i32>,
+}
+
+fn log(thread: &str, level: &str, content: &str) -> String {
+    format!("[{}] [{}/{}]: {}", Local::now().format("%H:%M:%S"), thread, level, content)
+}
+
+fn info(content: &str) -> String {
+    log("Server thread", "INFO", content)
+}
+
+// Made up but stable for each name so a player keeps their uuid across joins
+pub fn uuid(player: &str) -> String {
+    let hash = player.bytes().fold(0u64, |hash, x| hash.wrapping_mul(31).wrapping_add(x as u64));
+    let hex = format!("{:016x}{:016x}", hash, hash.rotate_left(17));
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+impl FakeServer {
+    pub fn startup(&self) -> Vec<String> {
+        vec![
+            log("main", "INFO", "Environment: authHost='https://authserver.mojang.com'"),
+            info("Starting minecraft server version 1.20.1"),
+            info("Loading properties"),
+            info("Preparing level \"world\""),
+            info("Done (2.481s)! For help, type \"help\""),
+        ]
+    }
+
+    pub fn command(&mut self, line: &str) -> Output {
+        let (command, args) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        let mut exit_code = None;
+        let lines = match command {
+            "list" => vec![info(&format!("There are {} of a max of {} players online: {}", self.players.len(), MAX_PLAYERS, self.players.join(", ")))],
+            "say" => vec![info(&format!("[Server] {}", args))],
+            "tellraw" | "save-on" | "save-off" => Vec::new(),
+            "save-all" => vec![info("Saving the game (this may take a moment!)"), info("Saved the game")],
+            "stop" => {
+                exit_code = Some(0);
+                let mut lines = vec![info("Stopping the server"), info("Stopping server")];
+                lines.extend(self.players.drain(..).map(|x| info(&format!("{} left the game", x))));
+                lines.push(info("Saving worlds"));
+                lines
+            },
+            "join" if !args.is_empty() && !self.players.iter().any(|x| x == args) => {
+                self.players.push(args.to_string());
+                vec![
+                    log("User Authenticator #1", "INFO", &format!("UUID of player {} is {}", args, uuid(args))),
+                    info(&format!("{} joined the game", args)),
+                ]
+            },
+            "leave" if self.players.iter().any(|x| x == args) => {
+                self.players.retain(|x| x != args);
+                vec![info(&format!("{} lost connection: Disconnected", args)), info(&format!("{} left the game", args))]
+            },
+            "chat" if args.contains(' ') => {
+                let (player, message) = args.split_once(' ').unwrap();
+                vec![info(&format!("<{}> {}", player, message))]
+            },
+            // Crashes log a stack trace, the lines after the first aren't in the log format
+            "crash" => {
+                exit_code = Some(1);
+                vec![
+                    log("Server thread", "ERROR", "Encountered an unexpected exception"),
+                    "java.lang.RuntimeException: Fake crash".to_string(),
+                    "\tat net.minecraft.server.MinecraftServer.runServer(MinecraftServer.java:709)".to_string(),
+                    "\tat java.lang.Thread.run(Thread.java:833)".to_string(),
+                ]
+            },
+            _ => vec![info("Unknown or incomplete command, see below for error")],
+        };
+
+        Output { lines, exit_code }
+    }
+}
+
+// Runs the fake server on stdin and stdout, this is what `mc-discord-bot fake-server` does
+pub fn run() {
+    let mut server = FakeServer::default();
+    let mut stdout = std::io::stdout();
+    for line in server.startup() {
+        let _ = writeln!(stdout, "{}", line);
+    }
+
+    for command in std::io::stdin().lock().lines() {
+        let command = match command {
+            Ok(v) => v,
+            Err(_) => break,
+        };
+
+        let output = server.command(&command);
+        for line in output.lines {
+            let _ = writeln!(stdout, "{}", line);
+        }
+        if let Some(exit_code) = output.exit_code {
+            std::process::exit(exit_code);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_parser::{parse_event, parse_line, LogEvent};
+
+    #[test]
+    fn test_fake_server() {
+        let mut server = FakeServer::default();
+        let events = |lines: Vec<String>| lines.iter().filter_map(|x| parse_line(x).ok()).filter_map(|x| parse_event(&x, std::iter::empty())).collect::<Vec<_>>();
+
+        assert_eq!(events(server.startup()), vec![LogEvent::ServerDone]);
+        assert_eq!(events(server.command("join Steve").lines)[1], LogEvent::PlayerJoined("Steve".to_string()));
+        assert!(server.command("join Steve").lines[0].ends_with("Unknown or incomplete command, see below for error"));
+        assert_eq!(events(server.command("list").lines), vec![LogEvent::PlayerList(vec!["Steve".to_string()])]);
+        assert_eq!(events(server.command("chat Steve hello there").lines), vec![LogEvent::Chat { user: "Steve".to_string(), message: "hello there".to_string() }]);
+
+        let stop = server.command("stop");
+        assert_eq!(stop.exit_code, Some(0));
+        assert!(events(stop.lines).contains(&LogEvent::PlayerLeft("Steve".to_string())));
+        assert_eq!(server.command("crash").exit_code, Some(1));
+    }
+}
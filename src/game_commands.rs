@@ -0,0 +1,70 @@
+use crate::player_reports;
+use crate::waypoints::{self, CoordsCommand};
+
+// Commands players can type in game chat, the bot replies to them with tellraw
+#[derive(Debug, PartialEq, Eq)]
+pub enum GameCommand {
+    Help,
+    Online,
+    Time,
+    Discord,
+    // None when the arguments weren't valid, so the usage is shown
+    Coords(Option<CoordsCommand>),
+    // The option voted for in the running poll, empty to show how to vote
+    Vote(String),
+    VoteDay,
+    VoteRestart,
+    // The reported player and reason, None when the arguments weren't valid
+    Report(Option<(String, String)>),
+}
+
+pub fn default_enabled() -> bool {
+    true
+}
+
+// Other ! messages are left alone since plugins or players may use them for something else
+pub fn parse(message: &str) -> Option<GameCommand> {
+    match message.trim() {
+        "!help" => Some(GameCommand::Help),
+        "!online" => Some(GameCommand::Online),
+        "!time" => Some(GameCommand::Time),
+        "!discord" => Some(GameCommand::Discord),
+        "!coords" => Some(GameCommand::Coords(None)),
+        "!vote" => Some(GameCommand::Vote(String::new())),
+        "!voteday" => Some(GameCommand::VoteDay),
+        "!voterestart" => Some(GameCommand::VoteRestart),
+        "!report" => Some(GameCommand::Report(None)),
+        message => {
+            if let Some(args) = message.strip_prefix("!coords ") {
+                return Some(GameCommand::Coords(waypoints::parse_command(args)));
+            }
+            if let Some(args) = message.strip_prefix("!report ") {
+                return Some(GameCommand::Report(player_reports::parse(args)));
+            }
+            message.strip_prefix("!vote ").map(|x| GameCommand::Vote(x.trim().to_string()))
+        },
+    }
+}
+
+pub const HELP: &str = "Commands: !online, !time, !discord, !coords, !vote, !voteday, !voterestart, !report";
+
+pub const COORDS_USAGE: &str = "Usage: !coords save|get|delete <name> or !coords list";
+
+#[cfg(test)]
+mod tests {
+    use crate::game_commands::{parse, GameCommand};
+    use crate::waypoints::CoordsCommand;
+
+    #[test]
+    fn test_game_commands() {
+        assert_eq!(parse(" !online "), Some(GameCommand::Online));
+        assert_eq!(parse("!online please"), None);
+        assert_eq!(parse("online"), None);
+        assert_eq!(parse("!coords save base"), Some(GameCommand::Coords(Some(CoordsCommand::Save("base".to_string())))));
+        assert_eq!(parse("!coords save"), Some(GameCommand::Coords(None)));
+        assert_eq!(parse("!vote  2"), Some(GameCommand::Vote("2".to_string())));
+        assert_eq!(parse("!voteday"), Some(GameCommand::VoteDay));
+        assert_eq!(parse("!report Steve stole my diamonds"), Some(GameCommand::Report(Some(("Steve".to_string(), "stole my diamonds".to_string())))));
+        assert_eq!(parse("!report Steve"), Some(GameCommand::Report(None)));
+    }
+}
@@ -0,0 +1,109 @@
+// End to end tests of the packet loop. The fake server's log lines go through the same parsing
+// as a real server's and discord is recorded so tests run without a server or a bot token.
+
+use serenity::model::id::ChannelId;
+use tokio::sync::mpsc;
+
+use crate::bot::{Bot, DiscordRecorder};
+use crate::fake_server::{self, FakeServer};
+use crate::log_events::LogEvents;
+use crate::metrics::Metrics;
+use crate::server_admin::ServerAdmin;
+use crate::stats_tracker::StatsTracker;
+use crate::{process, ConfigToml, Packet};
+
+const CHANNEL_ID: ChannelId = ChannelId(1);
+
+const CONFIG: &str = r#"
+discord_token = ""
+discord_channel_id = "1"
+server_setup_url = ""
+plain_join_messages = true
+client_mods = []
+"#;
+
+struct Harness {
+    server: FakeServer,
+    sender: mpsc::UnboundedSender<Packet>,
+    receiver: mpsc::UnboundedReceiver<Packet>,
+    bot: Bot,
+    stats: StatsTracker,
+    logs: LogEvents,
+    admin: ServerAdmin,
+    recorder: DiscordRecorder,
+}
+
+impl Harness {
+    async fn start(config: &str) -> Harness {
+        let mut config: ConfigToml = toml::from_str(config).unwrap();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let stats = StatsTracker::new(&mut config);
+        let logs = LogEvents::new(&mut config);
+        let admin = ServerAdmin::new(&config);
+        let mut bot = Bot::new(config, sender.clone(), Metrics::new(), CHANNEL_ID, None, None);
+        let recorder = DiscordRecorder::default();
+        bot.recorder = Some(recorder.clone());
+
+        let mut harness = Harness { server: FakeServer::default(), sender, receiver, bot, stats, logs, admin, recorder };
+        let startup = harness.server.startup();
+        harness.output(startup).await;
+        harness
+    }
+
+    // Runs a command on the fake server, returning what the bot sent to discord
+    async fn command(&mut self, command: &str) -> Vec<String> {
+        let output = self.server.command(command);
+        self.output(output.lines).await;
+        if output.exit_code.is_some() {
+            self.sender.send(Packet::ProcessExited()).unwrap();
+            self.run().await;
+        }
+        self.recorder.take().into_iter().map(|(_, text)| text).collect()
+    }
+
+    async fn output(&mut self, lines: Vec<String>) {
+        for line in lines {
+            process::process_line(&line, &self.sender);
+        }
+        self.run().await;
+    }
+
+    // Handles the packets sent so far the way the packet loop does
+    async fn run(&mut self) {
+        while let Ok(packet) = self.receiver.try_recv() {
+            match packet {
+                Packet::LogLine(log_line) => self.logs.handle(&mut self.bot, &mut self.stats, &mut self.admin, log_line).await,
+                packet => self.admin.handle(&mut self.bot, &mut self.stats, packet).await,
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_server_flows() {
+    // The bot keeps its state in the working directory
+    let dir = std::env::temp_dir().join(format!("mc-discord-bot-harness-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::env::set_current_dir(&dir).unwrap();
+
+    let mut harness = Harness::start(CONFIG).await;
+    assert_eq!(harness.recorder.take(), vec![(CHANNEL_ID, "Server Started".to_string())]);
+
+    assert_eq!(harness.command("join Steve").await, vec!["Steve joined for the first time!"]);
+    assert!(harness.stats.online.contains_key("Steve"));
+    assert_eq!(harness.command("chat Steve hello").await, vec!["Steve: hello"]);
+    assert_eq!(harness.stats.state.stats_mut("Steve").messages, 1);
+
+    assert_eq!(harness.command("leave Steve").await, vec!["Steve left the server"]);
+    assert!(harness.stats.state.play_times.contains_key(&harness.stats.state.players.id("Steve")));
+
+    // A real server saves the player's data, after which they're no longer new
+    std::fs::create_dir_all("world/playerdata").unwrap();
+    std::fs::write(format!("world/playerdata/{}.dat", fake_server::uuid("Steve")), "").unwrap();
+    assert_eq!(harness.command("join Steve").await, vec!["Steve joined the server"]);
+    assert_eq!(harness.command("crash").await, vec!["Server Shutdown"]);
+    assert!(harness.stats.online.is_empty());
+    assert!(harness.stats.state.last_restart.is_some());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,177 @@
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tokio::io::AsyncWriteExt;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::platform;
+
+// Commands run when something happens, each is run with sh -c (cmd /C on windows). The event's
+// data is passed as MC_ environment variables, eg. MC_PLAYER, and as JSON on stdin.
+#[derive(Deserialize, Default, Clone)]
+pub struct HooksToml {
+    #[serde(default)]
+    pub server_started: Vec<String>,
+    #[serde(default)]
+    pub player_joined: Vec<String>,
+    #[serde(default)]
+    pub player_left: Vec<String>,
+    #[serde(default)]
+    pub backup_complete: Vec<String>,
+    #[serde(default)]
+    pub crash: Vec<String>,
+    // Hooks still running after this long are killed
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+pub enum HookEvent {
+    ServerStarted,
+    PlayerJoined { player: String, first_join: bool },
+    PlayerLeft { player: String, session_millis: Option<u128> },
+    BackupComplete { path: String },
+    Crash,
+}
+
+impl HookEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            HookEvent::ServerStarted => "server_started",
+            HookEvent::PlayerJoined { .. } => "player_joined",
+            HookEvent::PlayerLeft { .. } => "player_left",
+            HookEvent::BackupComplete { .. } => "backup_complete",
+            HookEvent::Crash => "crash",
+        }
+    }
+
+    fn data(&self) -> Map<String, Value> {
+        let data = match self {
+            HookEvent::ServerStarted | HookEvent::Crash => json!({}),
+            HookEvent::PlayerJoined { player, first_join } => json!({ "player": player, "first_join": first_join }),
+            HookEvent::PlayerLeft { player, session_millis } => json!({ "player": player, "session_millis": session_millis.map(|x| x as u64) }),
+            HookEvent::BackupComplete { path } => json!({ "path": path }),
+        };
+
+        let mut data = match data {
+            Value::Object(v) => v,
+            _ => Map::new(),
+        };
+        data.insert("event".to_string(), json!(self.name()));
+        data
+    }
+}
+
+// Environment variables for an event's data, eg. player becomes MC_PLAYER. Missing values
+// are left out.
+fn env_vars(data: &Map<String, Value>) -> Vec<(String, String)> {
+    data.iter()
+        .filter_map(|(key, value)| {
+            let value = match value {
+                Value::String(v) => v.clone(),
+                Value::Null => return None,
+                v => v.to_string(),
+            };
+            Some((format!("MC_{}", key.to_uppercase()), value))
+        })
+        .collect()
+}
+
+pub struct Hooks {
+    config: HooksToml,
+}
+
+impl Hooks {
+    pub fn new(config: &HooksToml) -> Hooks {
+        Hooks { config: config.clone() }
+    }
+
+    fn commands(&self, event: &HookEvent) -> &[String] {
+        match event {
+            HookEvent::ServerStarted => &self.config.server_started,
+            HookEvent::PlayerJoined { .. } => &self.config.player_joined,
+            HookEvent::PlayerLeft { .. } => &self.config.player_left,
+            HookEvent::BackupComplete { .. } => &self.config.backup_complete,
+            HookEvent::Crash => &self.config.crash,
+        }
+    }
+
+    fn start(&self, event: HookEvent) -> Vec<JoinHandle<()>> {
+        let data = event.data();
+        let timeout = Duration::from_secs(self.config.timeout_secs.max(1));
+        self.commands(&event).iter().map(|command| {
+            let command = command.clone();
+            let data = data.clone();
+            tokio::task::spawn(async move {
+                match tokio::time::timeout(timeout, run_hook(&command, &data)).await {
+                    Ok(Ok(())) => {},
+                    Ok(Err(e)) => warn!("Hook \"{}\" failed: {}", command, e),
+                    Err(_) => warn!("Hook \"{}\" timed out", command),
+                }
+            })
+        }).collect()
+    }
+
+    // Starts the event's hooks in the background
+    pub fn run(&self, event: HookEvent) {
+        self.start(event);
+    }
+
+    // Runs the event's hooks and waits for them, for when the bot is about to exit
+    pub async fn run_and_wait(&self, event: HookEvent) {
+        for handle in self.start(event) {
+            let _ = handle.await;
+        }
+    }
+}
+
+async fn run_hook(command: &str, data: &Map<String, Value>) -> Result<(), String> {
+    let mut child = platform::shell(command)
+        .envs(env_vars(data))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Error starting hook: {}", e))?;
+
+    // Hooks that don't read stdin close it early, that isn't an error
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Err(e) = stdin.write_all(Value::Object(data.clone()).to_string().as_bytes()).await {
+            if e.kind() != std::io::ErrorKind::BrokenPipe {
+                error!("Error writing hook stdin: {}", e);
+            }
+        }
+    }
+
+    let output = child.wait_with_output().await.map_err(|e| format!("Error waiting for hook: {}", e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !stdout.trim().is_empty() {
+        info!("Hook \"{}\": {}", command, stdout.trim());
+    }
+    if !output.status.success() {
+        return Err(format!("exited with {}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hooks::{env_vars, HookEvent};
+
+    #[test]
+    fn test_hook_data() {
+        let data = HookEvent::PlayerLeft { player: "Steve".to_string(), session_millis: None }.data();
+        assert_eq!(serde_json::Value::Object(data.clone()).to_string(), r#"{"event":"player_left","player":"Steve","session_millis":null}"#);
+
+        let mut vars = env_vars(&data);
+        vars.sort();
+        assert_eq!(vars, vec![("MC_EVENT".to_string(), "player_left".to_string()), ("MC_PLAYER".to_string(), "Steve".to_string())]);
+    }
+}
@@ -0,0 +1,41 @@
+use chrono::Local;
+use serenity::model::id::ChannelId;
+use serenity::prelude::*;
+use tracing::error;
+
+// Log lines posted in a crash thread
+pub const CRASH_LOG_LINES: usize = 50;
+
+// Threads are archived after a day without messages
+const AUTO_ARCHIVE_MINUTES: u16 = 1440;
+
+pub fn default_enabled() -> bool {
+    true
+}
+
+// Names a thread after what happened and when, eg. "Update 2024-01-01 12:00"
+pub fn thread_name(title: &str) -> String {
+    format!("{} {}", title, Local::now().format("%Y-%m-%d %H:%M"))
+}
+
+// Posts title in channel_id and starts a thread from it for the incident's messages, so long
+// running tasks don't fill up the admin channel. Returns channel_id itself if a thread can't be
+// made, eg. in a DM or without the Create Public Threads permission.
+pub async fn start_thread(channel_id: ChannelId, ctx: &Context, title: &str) -> ChannelId {
+    let name = thread_name(title);
+    let message = match channel_id.say(&ctx.http, &name).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Error sending message: {:?}", e);
+            return channel_id;
+        },
+    };
+
+    match channel_id.create_public_thread(&ctx.http, message.id, |t| t.name(&name).auto_archive_duration(AUTO_ARCHIVE_MINUTES)).await {
+        Ok(thread) => thread.id,
+        Err(e) => {
+            error!("Error creating thread: {:?}", e);
+            channel_id
+        },
+    }
+}
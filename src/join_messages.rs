@@ -0,0 +1,85 @@
+// Join and leave announcements for the game chat channel, sent as embeds unless
+// plain_join_messages is set in the config
+
+use serenity::model::id::ChannelId;
+use serenity::prelude::*;
+use tracing::error;
+
+use crate::locale::Locale;
+use crate::playtime::format_duration;
+use crate::say_or_log;
+
+const JOIN_COLOUR: u32 = 0x55FF55;
+const LEAVE_COLOUR: u32 = 0xFF5555;
+const FIRST_JOIN_COLOUR: u32 = 0xFFAA00;
+
+// Player heads are looked up by name so no uuid is needed
+pub fn avatar_url(name: &str) -> String {
+    format!("https://mc-heads.net/avatar/{}/64", name)
+}
+
+async fn send_embed(channel_id: ChannelId, ctx: &Context, name: &str, title: String, colour: u32, fields: Vec<(&str, String)>) {
+    let result = channel_id.send_message(&ctx.http, |m| m.embed(|e| {
+        e.author(|a| a.name(name).icon_url(avatar_url(name)))
+            .title(title)
+            .colour(colour)
+            .fields(fields.into_iter().map(|(name, value)| (name, value, true)))
+    })).await;
+
+    if let Err(e) = result {
+        error!("Error sending message: {:?}", e);
+    }
+}
+
+// The message for a join, this is all that's sent with plain_join_messages
+pub fn join_title(name: &str, online: usize, first_join: bool, locale: &Locale) -> String {
+    let key = if first_join { "join.first_join" } else { "join.joined" };
+    locale.format(key, &[("player", name), ("online", &online.to_string())])
+}
+
+pub fn leave_title(name: &str, online: usize, session: Option<(u128, u128, u128)>, locale: &Locale) -> String {
+    let (session_length, total) = match session {
+        Some((session_length, total, _)) => (format_duration(session_length), format_duration(total)),
+        None => (String::new(), String::new()),
+    };
+    locale.format("join.left", &[("player", name), ("online", &online.to_string()), ("session", &session_length), ("total_play_time", &total)])
+}
+
+pub async fn send_join(channel_id: ChannelId, ctx: &Context, name: &str, online: usize, plain: bool, locale: &Locale) {
+    let title = join_title(name, online, false, locale);
+    if plain {
+        say_or_log(channel_id, ctx, &title).await;
+        return;
+    }
+
+    send_embed(channel_id, ctx, name, title, JOIN_COLOUR, vec![(locale.get("join.online"), online.to_string())]).await;
+}
+
+pub async fn send_first_join(channel_id: ChannelId, ctx: &Context, name: &str, online: usize, plain: bool, locale: &Locale) {
+    let title = join_title(name, online, true, locale);
+    if plain {
+        say_or_log(channel_id, ctx, &title).await;
+        return;
+    }
+
+    send_embed(channel_id, ctx, name, title, FIRST_JOIN_COLOUR, vec![(locale.get("join.online"), online.to_string())]).await;
+}
+
+// session is the session length, total play time and AFK time in milliseconds
+pub async fn send_leave(channel_id: ChannelId, ctx: &Context, name: &str, online: usize, session: Option<(u128, u128, u128)>, plain: bool, locale: &Locale) {
+    let title = leave_title(name, online, session, locale);
+    if plain {
+        say_or_log(channel_id, ctx, &title).await;
+        return;
+    }
+
+    let mut fields = vec![(locale.get("join.online"), online.to_string())];
+    if let Some((session_length, total, afk)) = session {
+        fields.push((locale.get("join.session"), format_duration(session_length)));
+        fields.push((locale.get("join.total_play_time"), format_duration(total)));
+        if afk >= 60000 {
+            fields.push((locale.get("join.afk"), format_duration(afk)));
+        }
+    }
+    send_embed(channel_id, ctx, name, title, LEAVE_COLOUR, fields).await;
+}
@@ -0,0 +1,90 @@
+use serde::Deserialize;
+
+pub const JVM_ARGS_PATH: &str = "user_jvm_args.txt";
+pub const EULA_PATH: &str = "eula.txt";
+
+// Aikar's G1 flags, a good default for most modded servers
+const DEFAULT_GC_FLAGS: &[&str] = &[
+    "-XX:+UseG1GC", "-XX:+ParallelRefProcEnabled", "-XX:MaxGCPauseMillis=200",
+    "-XX:+UnlockExperimentalVMOptions", "-XX:+DisableExplicitGC", "-XX:+AlwaysPreTouch", "-XX:G1NewSizePercent=30",
+    "-XX:G1MaxNewSizePercent=40", "-XX:G1HeapRegionSize=8M", "-XX:G1ReservePercent=20", "-XX:G1HeapWastePercent=5",
+    "-XX:G1MixedGCCountTarget=4", "-XX:InitiatingHeapOccupancyPercent=15", "-XX:G1MixedGCLiveThresholdPercent=90",
+    "-XX:G1RSetUpdatingPauseTimePercent=5", "-XX:SurvivorRatio=32", "-XX:+PerfDisableSharedMem",
+    "-XX:MaxTenuringThreshold=1",
+];
+
+// Used to generate user_jvm_args.txt and eula.txt
+#[derive(Deserialize)]
+pub struct JvmToml {
+    // Used for both -Xms and -Xmx, eg. "12G"
+    #[serde(default = "default_heap_size")]
+    pub heap_size: String,
+    #[serde(default = "default_gc_flags")]
+    pub gc_flags: Vec<String>,
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+    #[serde(default = "default_accept_eula")]
+    pub accept_eula: bool,
+}
+
+impl Default for JvmToml {
+    fn default() -> JvmToml {
+        JvmToml {
+            heap_size: default_heap_size(),
+            gc_flags: default_gc_flags(),
+            extra_args: Vec::new(),
+            accept_eula: default_accept_eula(),
+        }
+    }
+}
+
+fn default_heap_size() -> String {
+    "12G".to_string()
+}
+
+fn default_gc_flags() -> Vec<String> {
+    DEFAULT_GC_FLAGS.iter().map(|x| x.to_string()).collect()
+}
+
+fn default_accept_eula() -> bool {
+    true
+}
+
+impl JvmToml {
+    // Renders the contents of user_jvm_args.txt, one argument per line
+    pub fn render(&self) -> String {
+        let mut args = vec![format!("-Xms{}", self.heap_size), format!("-Xmx{}", self.heap_size)];
+        args.extend(self.gc_flags.iter().cloned());
+        args.extend(self.extra_args.iter().cloned());
+
+        let mut contents = args.join("\n");
+        contents.push('\n');
+        contents
+    }
+
+    // Writes user_jvm_args.txt, and eula.txt if the eula has been accepted
+    pub fn write_files(&self) -> std::io::Result<()> {
+        std::fs::write(JVM_ARGS_PATH, self.render())?;
+        if self.accept_eula {
+            std::fs::write(EULA_PATH, "eula=true\n")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::jvm_args::JvmToml;
+
+    #[test]
+    fn test_render() {
+        let jvm = JvmToml {
+            heap_size: "4G".to_string(),
+            gc_flags: vec!["-XX:+UseZGC".to_string()],
+            extra_args: vec!["-Dfml.queryResult=confirm".to_string()],
+            accept_eula: true,
+        };
+        assert_eq!(jvm.render(), "-Xms4G\n-Xmx4G\n-XX:+UseZGC\n-Dfml.queryResult=confirm\n");
+        assert!(JvmToml::default().render().starts_with("-Xms12G\n-Xmx12G\n-XX:+UseG1GC\n"));
+    }
+}
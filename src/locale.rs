@@ -0,0 +1,104 @@
+// Messages shown in discord, looked up by key (eg. "join.joined") in the configured
+// language's catalog. Messages missing from a catalog fall back to English.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tracing::warn;
+
+use crate::templates::render;
+
+const LANGUAGES: &[(&str, &str)] = &[
+    ("en", include_str!("locales/en.toml")),
+    ("de", include_str!("locales/de.toml")),
+];
+
+pub fn default_language() -> String {
+    "en".to_string()
+}
+
+// Flattens nested tables into dotted keys
+fn flatten(prefix: &str, table: &toml::Table, messages: &mut HashMap<String, String>) {
+    for (key, value) in table {
+        let key = match prefix.is_empty() {
+            true => key.clone(),
+            false => format!("{}.{}", prefix, key),
+        };
+        match value {
+            toml::Value::Table(v) => flatten(&key, v, messages),
+            toml::Value::String(v) => {
+                messages.insert(key, v.clone());
+            },
+            _ => warn!("Message {} isn't text", key),
+        }
+    }
+}
+
+fn parse_catalog(text: &str) -> HashMap<String, String> {
+    let mut messages = HashMap::new();
+    match text.parse::<toml::Table>() {
+        Ok(v) => flatten("", &v, &mut messages),
+        Err(e) => warn!("Error parsing messages: {}", e),
+    }
+    messages
+}
+
+#[derive(Clone)]
+pub struct Locale {
+    messages: Arc<HashMap<String, String>>,
+}
+
+impl Locale {
+    pub fn new(language: &str) -> Locale {
+        let mut messages = parse_catalog(LANGUAGES[0].1);
+        match LANGUAGES.iter().find(|x| x.0 == language) {
+            Some((_, text)) => messages.extend(parse_catalog(text)),
+            None => warn!("Unknown language \"{}\", using English. Available languages are {}", language, LANGUAGES.iter().map(|x| x.0).collect::<Vec<&str>>().join(", ")),
+        }
+        Locale { messages: Arc::new(messages) }
+    }
+
+    // Replaces messages with the templates from the config
+    pub fn with_templates(mut self, templates: &HashMap<String, String>) -> Locale {
+        Arc::make_mut(&mut self.messages).extend(templates.iter().map(|(k, v)| (k.clone(), v.clone())));
+        self
+    }
+
+    // Unknown keys are shown as is so they're easy to spot
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.messages.get(key).map(|x| x.as_str()).unwrap_or(key)
+    }
+
+    // Fills in the message's {name} placeholders
+    pub fn format(&self, key: &str, args: &[(&str, &str)]) -> String {
+        render(self.get(key), args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::locale::{parse_catalog, Locale, LANGUAGES};
+
+    #[test]
+    fn test_locale() {
+        let english = parse_catalog(LANGUAGES[0].1);
+        for (language, text) in LANGUAGES {
+            let mut keys: Vec<String> = parse_catalog(text).into_keys().collect();
+            keys.sort();
+            let mut expected: Vec<String> = english.keys().cloned().collect();
+            expected.sort();
+            assert_eq!(keys, expected, "{} is missing messages", language);
+        }
+
+        let locale = Locale::new("de");
+        assert_eq!(locale.format("join.joined", &[("player", "Steve")]), "Steve hat den Server betreten");
+        assert_eq!(Locale::new("xx").get("status.title"), "Server status");
+        assert_eq!(locale.get("missing.key"), "missing.key");
+
+        let templates = HashMap::from([("join.joined".to_string(), "➡️ {player} hopped on ({online} online)".to_string())]);
+        let locale = locale.with_templates(&templates);
+        assert_eq!(locale.format("join.joined", &[("player", "Steve"), ("online", "3")]), "➡️ Steve hopped on (3 online)");
+    }
+}
@@ -0,0 +1,135 @@
+// Turns server log lines into events. The vanilla adapter always runs, mod adapters are chosen
+// in the config and get the first look at each line so they can handle lines vanilla would
+// otherwise take as a death.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use serde::Deserialize;
+
+use crate::log_parser::{parse_event, LogEvent, LogLine};
+
+pub trait LogAdapter: Send {
+    // players_online is used to spot lines that start with a player's name
+    fn parse(&self, line: &LogLine, players_online: &HashMap<String, Instant>) -> Option<LogEvent>;
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AdapterKind {
+    TickLag,
+    FtbBackups,
+    Dynmap,
+}
+
+pub fn default_adapters() -> Vec<AdapterKind> {
+    vec![AdapterKind::TickLag]
+}
+
+struct Vanilla;
+
+impl LogAdapter for Vanilla {
+    fn parse(&self, line: &LogLine, players_online: &HashMap<String, Instant>) -> Option<LogEvent> {
+        parse_event(line, players_online.keys())
+    }
+}
+
+// Can't keep up! Is the server overloaded? Running 2034ms or 40 ticks behind
+// Only the server's own warning counts, not chat quoting it
+struct TickLag;
+
+impl LogAdapter for TickLag {
+    fn parse(&self, line: &LogLine, _: &HashMap<String, Instant>) -> Option<LogEvent> {
+        if line.level != "WARN" || !line.is_from("MinecraftServer") {
+            return None;
+        }
+        let rest = line.content.strip_prefix("Can't keep up!")?;
+        let behind = rest.split_once("Running ")?.1;
+        let (millis, rest) = behind.split_once("ms or ")?;
+        let ticks = rest.split_whitespace().next()?;
+        Some(LogEvent::TickLag { millis: millis.parse().ok()?, ticks: ticks.parse().ok()? })
+    }
+}
+
+// [ftbbackups2/]: Backup finished in 12s
+struct FtbBackups;
+
+impl LogAdapter for FtbBackups {
+    fn parse(&self, line: &LogLine, _: &HashMap<String, Instant>) -> Option<LogEvent> {
+        let is_ftb = line.logger.as_deref().is_some_and(|x| x.to_lowercase().starts_with("ftbbackups"));
+        let content = line.content.as_str();
+        let is_finished = ["finished", "done", "complete"].iter().any(|x| content.contains(x));
+        if !is_ftb || !content.starts_with("Backup") || !is_finished {
+            return None;
+        }
+        Some(LogEvent::ModNotice { source: "FTB Backups", message: content.to_string() })
+    }
+}
+
+// [dynmap] Full render of map 'flat' of world 'world' completed - 1234 tiles rendered
+// Forge logs the plugin name as the logger instead of in the line
+struct Dynmap;
+
+impl LogAdapter for Dynmap {
+    fn parse(&self, line: &LogLine, _: &HashMap<String, Instant>) -> Option<LogEvent> {
+        let content = match line.content.strip_prefix("[dynmap] ") {
+            Some(v) => v,
+            None if line.logger.as_deref() == Some("dynmap") => line.content.as_str(),
+            None => return None,
+        };
+        if !content.starts_with("Full render of map") || !content.contains("completed") {
+            return None;
+        }
+        Some(LogEvent::ModNotice { source: "Dynmap", message: content.to_string() })
+    }
+}
+
+pub struct LogPipeline {
+    adapters: Vec<Box<dyn LogAdapter>>,
+}
+
+impl LogPipeline {
+    pub fn new(kinds: &[AdapterKind]) -> LogPipeline {
+        let mut adapters: Vec<Box<dyn LogAdapter>> = kinds.iter().map(|kind| -> Box<dyn LogAdapter> {
+            match kind {
+                AdapterKind::TickLag => Box::new(TickLag),
+                AdapterKind::FtbBackups => Box::new(FtbBackups),
+                AdapterKind::Dynmap => Box::new(Dynmap),
+            }
+        }).collect();
+        adapters.push(Box::new(Vanilla));
+        LogPipeline { adapters }
+    }
+
+    // The event from the first adapter that recognises the line
+    pub fn parse(&self, line: &LogLine, players_online: &HashMap<String, Instant>) -> Option<LogEvent> {
+        self.adapters.iter().find_map(|x| x.parse(line, players_online))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Instant;
+
+    use crate::log_adapters::{default_adapters, AdapterKind, LogPipeline};
+    use crate::log_parser::{parse_line, LogEvent};
+
+    #[test]
+    fn test_log_pipeline() {
+        let players = HashMap::from([("Steve".to_string(), Instant::now())]);
+        let pipeline = LogPipeline::new(&[AdapterKind::TickLag, AdapterKind::FtbBackups, AdapterKind::Dynmap]);
+        let event = |text: &str| pipeline.parse(&parse_line(text).unwrap(), &players);
+
+        assert_eq!(event("[12:00:00] [Server thread/WARN]: Can't keep up! Is the server overloaded? Running 2034ms or 40 ticks behind"), Some(LogEvent::TickLag { millis: 2034, ticks: 40 }));
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: <Steve> Can't keep up! Is the server overloaded? Running 9999ms or 199 ticks behind"), Some(LogEvent::Chat { user: "Steve".to_string(), message: "Can't keep up! Is the server overloaded? Running 9999ms or 199 ticks behind".to_string() }));
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: Can't keep up! Is the server overloaded? Running 2034ms or 40 ticks behind"), None);
+        assert_eq!(event("[12:00:00] [Backups/INFO] [ftbbackups2/]: Backup finished in 12s"), Some(LogEvent::ModNotice { source: "FTB Backups", message: "Backup finished in 12s".to_string() }));
+        assert_eq!(event("[12:00:00 INFO]: [dynmap] Full render of map 'flat' of world 'world' completed - 1234 tiles rendered"), Some(LogEvent::ModNotice { source: "Dynmap", message: "Full render of map 'flat' of world 'world' completed - 1234 tiles rendered".to_string() }));
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: Steve joined the game"), Some(LogEvent::PlayerJoined("Steve".to_string())));
+
+        // Adapters that aren't chosen leave lines to vanilla
+        let pipeline = LogPipeline::new(&default_adapters());
+        assert_eq!(pipeline.parse(&parse_line("[12:00:00 INFO]: [dynmap] Full render of map 'flat' of world 'world' completed").unwrap(), &players), None);
+    }
+}
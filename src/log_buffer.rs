@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Duration, Local};
+
+// Lines shown by `!logs` without a count
+const DEFAULT_LINES: usize = 20;
+
+pub fn default_capacity() -> usize {
+    1000
+}
+
+pub struct LogEntry {
+    pub time: DateTime<Local>,
+    pub label: String,
+    pub content: String,
+}
+
+// Keeps the most recent server log lines so they can be searched from discord
+pub struct LogBuffer {
+    entries: VecDeque<LogEntry>,
+    capacity: usize,
+}
+
+pub enum LogQuery {
+    Last(usize),
+    Grep(String),
+    Since(i64),
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> LogBuffer {
+        LogBuffer { entries: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    pub fn push(&mut self, label: &str, content: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(LogEntry { time: Local::now(), label: label.to_string(), content: content.to_string() });
+    }
+
+    // Returns the matching lines, oldest first
+    pub fn query(&self, query: &LogQuery) -> Vec<&LogEntry> {
+        match query {
+            LogQuery::Last(count) => self.entries.iter().skip(self.entries.len().saturating_sub(*count)).collect(),
+            LogQuery::Grep(pattern) => {
+                let pattern = pattern.to_lowercase();
+                self.entries.iter().filter(|x| x.content.to_lowercase().contains(&pattern)).collect()
+            },
+            LogQuery::Since(minutes) => {
+                let cutoff = Local::now() - Duration::minutes(*minutes);
+                self.entries.iter().filter(|x| x.time >= cutoff).collect()
+            },
+        }
+    }
+}
+
+// Parses `!logs [n]`, `!logs grep <pattern>` or `!logs since <minutes>`
+pub fn parse_query(content: &str) -> Result<LogQuery, &'static str> {
+    let args = content.strip_prefix("!logs").unwrap_or_default().trim();
+    let (subcommand, rest) = args.split_once(' ').unwrap_or((args, ""));
+    let rest = rest.trim();
+
+    match subcommand {
+        "" => Ok(LogQuery::Last(DEFAULT_LINES)),
+        "grep" if !rest.is_empty() => Ok(LogQuery::Grep(rest.to_string())),
+        "since" => match rest.parse() {
+            Ok(minutes) if minutes > 0 => Ok(LogQuery::Since(minutes)),
+            _ => Err("Usage: `!logs since <minutes>`"),
+        },
+        count => match count.parse() {
+            Ok(count) if rest.is_empty() => Ok(LogQuery::Last(count)),
+            _ => Err("Usage: `!logs [lines]`, `!logs grep <text>` or `!logs since <minutes>`"),
+        },
+    }
+}
+
+pub fn format_entries(entries: &[&LogEntry]) -> String {
+    if entries.is_empty() {
+        return "No matching log lines".to_string();
+    }
+
+    let lines: Vec<String> = entries.iter().map(|x| match x.label.is_empty() {
+        true => format!("{} {}", x.time.format("%H:%M:%S"), x.content),
+        false => format!("{} [{}] {}", x.time.format("%H:%M:%S"), x.label, x.content),
+    }).collect();
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::log_buffer::{parse_query, LogBuffer, LogQuery};
+
+    #[test]
+    fn test_log_buffer() {
+        let mut buffer = LogBuffer::new(3);
+        for content in ["Starting server", "Steve joined the game", "Can't keep up!", "Steve left the game"] {
+            buffer.push("INFO", content);
+        }
+
+        let contents = |query| buffer.query(&query).iter().map(|x| x.content.clone()).collect::<Vec<_>>();
+        assert_eq!(contents(LogQuery::Last(2)), vec!["Can't keep up!", "Steve left the game"]);
+        assert_eq!(contents(LogQuery::Grep("steve".to_string())), vec!["Steve joined the game", "Steve left the game"]);
+        assert_eq!(contents(LogQuery::Since(5)).len(), 3);
+
+        assert!(matches!(parse_query("!logs"), Ok(LogQuery::Last(20))));
+        assert!(matches!(parse_query("!logs 50"), Ok(LogQuery::Last(50))));
+        assert!(matches!(parse_query("!logs grep keep up"), Ok(LogQuery::Grep(x)) if x == "keep up"));
+        assert!(matches!(parse_query("!logs since 10"), Ok(LogQuery::Since(10))));
+        assert!(parse_query("!logs since soon").is_err());
+        assert!(parse_query("!logs grep").is_err());
+    }
+}
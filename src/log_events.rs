@@ -0,0 +1,114 @@
+// Lines the server logs. Every line is kept for !logs and the log mirror and checked against
+// alerts and plugins, then parsed into an event. Player events are passed on to the stats
+// tracker.
+
+use tracing::debug;
+
+use crate::alerts::Alerts;
+use crate::bot::Bot;
+use crate::log_adapters::LogPipeline;
+use crate::log_parser::{LogEvent, LogLine};
+use crate::server_admin::ServerAdmin;
+use crate::stats_tracker::StatsTracker;
+use crate::{api, command_queue, log_mirror, metrics, oom, plugin_stats, pregen, run_plugin_actions, start_command_timer, version_check, watchdog, ConfigToml};
+
+pub struct LogEvents {
+    log_pipeline: LogPipeline,
+    alerts: Alerts,
+}
+
+impl LogEvents {
+    pub fn new(config: &mut ConfigToml) -> LogEvents {
+        LogEvents {
+            log_pipeline: LogPipeline::new(&config.log_adapters),
+            alerts: Alerts::new(std::mem::take(&mut config.alerts)),
+        }
+    }
+
+    // Sent from another task so a large batch doesn't hold up other packets
+    pub fn flush_mirror(&self, bot: &mut Bot) {
+        let (ctx, log_mirror) = match (&bot.ctx, &mut bot.log_mirror) {
+            (Some(ctx), Some(log_mirror)) => (ctx.clone(), log_mirror),
+            _ => return,
+        };
+
+        let messages = log_mirror.take_messages();
+        let mirror_channel_id = log_mirror.channel_id;
+        if !messages.is_empty() {
+            tokio::task::spawn(async move { log_mirror::send_messages(mirror_channel_id, &ctx, messages).await });
+        }
+    }
+
+    pub async fn handle(&mut self, bot: &mut Bot, stats: &mut StatsTracker, admin: &mut ServerAdmin, log_line: LogLine) {
+        let label = log_line.label();
+        let content = log_line.content.as_str();
+        bot.log_buffer.push(&label, content);
+        bot.events.publish(api::ApiEvent::Log { label: label.clone(), line: content.to_string() });
+        if let Some(log_mirror) = &mut bot.log_mirror {
+            let admin = Some(log_mirror.channel_id) == bot.admin_channel_id;
+            log_mirror.push(&label, &bot.redactor.redact(content, admin));
+        }
+
+        if let Some(id) = bot.command_queue.push_line(content) {
+            start_command_timer(&bot.sender, id, command_queue::QUIET_PERIOD);
+        }
+
+        admin.server_output(bot).await;
+
+        if let Some(tps) = metrics::parse_tps(content) {
+            bot.metrics.lock().unwrap().tps = Some(tps);
+        }
+        if let Some(version) = version_check::parse_server_version(content) {
+            bot.metrics.lock().unwrap().server_version = Some(version.to_string());
+        }
+
+        // Restarting and raising -Xmx don't depend on discord so these come before the
+        // connection check
+        if let Some(stall) = watchdog::parse_tick_stall(content) {
+            admin.tick_stall(bot, stats, stall).await;
+        }
+        if oom::is_oom(content) {
+            admin.out_of_memory(bot).await;
+        }
+
+        if !bot.is_connected() {
+            return;
+        }
+
+        stats.check_line(content);
+        pregen::log_line(bot, content).await;
+        stats.position_line(bot, content).await;
+
+        if let Some(alert) = self.alerts.check(&label, content) {
+            bot.say_admin(&bot.redactor.redact(&alert, true)).await;
+        }
+
+        if let (true, Some(ctx)) = (bot.plugins.watches_log(), &bot.ctx) {
+            let actions = bot.plugins.log_line(content, plugin_stats(&stats.state, &stats.online, &bot.bedrock));
+            run_plugin_actions(actions, &bot.console, ctx, bot.channel_id).await;
+        }
+
+        // Saved the game
+        if content.starts_with("Saved the ") {
+            admin.world_saved(bot);
+        }
+
+        match self.log_pipeline.parse(&log_line, &stats.online) {
+            // Server startup
+            Some(LogEvent::ServerDone) => admin.server_done(bot).await,
+
+            // Lag is already covered by the default alert, it's only counted here
+            Some(LogEvent::TickLag { millis, ticks }) => {
+                debug!("Server is {millis}ms or {ticks} ticks behind");
+                bot.metrics.lock().unwrap().skipped_ticks += ticks;
+            },
+            Some(LogEvent::ModNotice { source, message }) => {
+                bot.say_admin(&bot.redactor.redact(&format!("{}: {}", source, message), true)).await;
+            },
+
+            // Joins, leaves, chat, deaths and advancements
+            Some(event) => stats.handle(bot, event).await,
+            None => {},
+        }
+    }
+}
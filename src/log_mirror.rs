@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use serenity::model::id::ChannelId;
+use serenity::prelude::*;
+use tokio::sync::mpsc;
+
+use crate::{Packet, say_or_log, send_or_log};
+use crate::response::split_into_code_blocks;
+
+// Copies the server log into a discord channel, batched so it doesn't hit rate limits
+#[derive(Deserialize)]
+pub struct LogMirrorToml {
+    pub channel_id: String,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    // Most messages sent per batch, older output is skipped when there's more
+    #[serde(default = "default_max_messages")]
+    pub max_messages: usize,
+}
+
+fn default_interval_secs() -> u64 {
+    5
+}
+
+fn default_max_messages() -> usize {
+    3
+}
+
+pub struct LogMirror {
+    pub channel_id: ChannelId,
+    max_messages: usize,
+    lines: Vec<String>,
+}
+
+impl LogMirror {
+    pub fn new(config: &LogMirrorToml) -> Result<LogMirror, String> {
+        let channel_id = config.channel_id.parse().map_err(|_| format!("Invalid log mirror channel id \"{}\"", config.channel_id))?;
+        Ok(LogMirror { channel_id, max_messages: config.max_messages.max(2), lines: Vec::new() })
+    }
+
+    pub fn push(&mut self, label: &str, content: &str) {
+        // A ``` in the log would end the code block early
+        let content = content.replace("```", "`\u{200b}``");
+        match label.is_empty() {
+            true => self.lines.push(content),
+            false => self.lines.push(format!("[{}] {}", label, content)),
+        }
+    }
+
+    // Takes the buffered lines as messages to send, when there are too many the oldest
+    // messages are replaced by a note saying how many were skipped
+    pub fn take_messages(&mut self) -> Vec<String> {
+        if self.lines.is_empty() {
+            return Vec::new();
+        }
+
+        let mut messages = split_into_code_blocks(&self.lines.join("\n"));
+        self.lines.clear();
+
+        if messages.len() > self.max_messages {
+            let skipped = messages.len() - (self.max_messages - 1);
+            messages.drain(..skipped);
+            messages.insert(0, format!("*{} messages of log output skipped*", skipped));
+        }
+        messages
+    }
+}
+
+pub async fn send_messages(channel_id: ChannelId, ctx: &Context, messages: Vec<String>) {
+    for message in messages {
+        say_or_log(channel_id, ctx, &message).await;
+    }
+}
+
+// Sends a LogMirrorTick packet every interval so buffered lines are posted
+pub fn start_log_mirror_timer(config: &LogMirrorToml, sender: &mpsc::UnboundedSender<Packet>) {
+    let interval = Duration::from_secs(config.interval_secs.max(1));
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            send_or_log(&sender, Packet::LogMirrorTick());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::log_mirror::{LogMirror, LogMirrorToml};
+
+    #[test]
+    fn test_take_messages() {
+        let config = LogMirrorToml { channel_id: "1".to_string(), interval_secs: 5, max_messages: 2 };
+        let mut mirror = LogMirror::new(&config).unwrap();
+        assert!(mirror.take_messages().is_empty());
+
+        mirror.push("INFO", "Done (3.2s)! For help, type \"help\"");
+        assert_eq!(mirror.take_messages(), vec!["```\n[INFO] Done (3.2s)! For help, type \"help\"```"]);
+
+        for _ in 0..3 {
+            mirror.push("INFO", &"x".repeat(1500));
+        }
+        let messages = mirror.take_messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0], "*2 messages of log output skipped*");
+    }
+}
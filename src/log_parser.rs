@@ -0,0 +1,278 @@
+// Parses server log lines into their parts. The known formats are:
+// Forge:   [12:00:00] [Server thread/INFO] [minecraft/DedicatedServer]: content
+// Vanilla: [12:00:00] [Server thread/INFO]: content
+// Fabric:  [12:00:00] [Server thread/INFO] (Minecraft) content
+// Paper:   [12:00:00 INFO]: content
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct LogLine {
+    pub thread: String,
+    pub level: String,
+    // Only Forge and Fabric log the logger's name
+    pub logger: Option<String>,
+    pub content: String,
+}
+
+impl LogLine {
+    // A line that isn't in a known format, eg. part of a stack trace
+    pub fn unparsed(line: &str) -> LogLine {
+        LogLine { content: line.to_string(), ..LogLine::default() }
+    }
+
+    // Short label for showing the line in discord
+    pub fn label(&self) -> String {
+        match (&self.logger, self.thread.is_empty(), self.level.is_empty()) {
+            (Some(logger), _, _) => logger.clone(),
+            (None, true, true) => String::new(),
+            (None, true, false) => self.level.clone(),
+            (None, false, _) => format!("{}/{}", self.thread, self.level),
+        }
+    }
+
+    // Checks whether the line was logged by the server class, eg. "MinecraftServer". Vanilla
+    // doesn't log the logger so any line from the server thread counts, Paper doesn't log the
+    // thread either and old Fabric versions log every vanilla line as "Minecraft".
+    pub fn is_from(&self, class: &str) -> bool {
+        match &self.logger {
+            Some(logger) => logger == "Minecraft" || logger.rsplit(['/', '.']).next() == Some(class),
+            None => self.thread == "Server thread" || (self.thread.is_empty() && !self.level.is_empty()),
+        }
+    }
+}
+
+// Things that happened on the server, parsed from log lines
+#[derive(PartialEq, Eq, Debug)]
+pub enum LogEvent {
+    ServerDone,
+    PlayerJoined(String),
+    // Logged while a player is logging in, before they join
+    PlayerAuthenticated { player: String, uuid: String },
+    PlayerLeft(String),
+    Chat { user: String, message: String },
+    Advancement { player: String, message: String },
+    Death { player: String, message: String },
+    // Response to the list command
+    PlayerList(Vec<String>),
+    // Events from log adapters
+    TickLag { millis: u64, ticks: u64 },
+    ModNotice { source: &'static str, message: String },
+}
+
+// Advancement messages, eg. "Steve has made the advancement [Stone Age]"
+const ADVANCEMENT_MESSAGES: &[&str] = &[" has made the advancement ", " has completed the challenge ", " has reached the goal "];
+
+// What vanilla death messages say after the player's name, eg. "Steve fell from a high place".
+// Other lines starting with a player's name, like "Steve moved too quickly!", aren't deaths.
+const DEATH_MESSAGES: &[&str] = &[
+    " was slain", " was shot", " was killed", " was blown up", " was fireballed", " was pummeled", " was squashed",
+    " was squished", " was pricked", " was poked", " was impaled", " was skewered", " was stung", " was struck by lightning",
+    " was frozen", " was obliterated", " was roasted", " was burnt", " was burned", " was doomed", " was stomped",
+    " was speared", " fell ", " drowned", " died", " blew up", " burned to death", " hit the ground too hard",
+    " tried to swim in lava", " starved to death", " suffocated", " withered away", " experienced kinetic energy",
+    " went up in flames", " went off with a bang", " walked into", " froze to death", " discovered the floor was lava",
+    " didn't want to live", " left the confines of this world",
+];
+
+// Parses a log line into an event. Deaths are INFO lines from the server that start with the
+// name of an online player followed by a known death message.
+pub fn parse_event<'a>(line: &LogLine, mut players_online: impl Iterator<Item = &'a String>) -> Option<LogEvent> {
+    let content = line.content.as_str();
+
+    // Done (3.2s)! For help, type "help"
+    if line.is_from("DedicatedServer") && content.starts_with("Done") {
+        return Some(LogEvent::ServerDone);
+    }
+
+    // UUID of player Steve is 069a79f4-44e9-4726-a5be-fca90e38aaf5
+    // This is logged from the authenticator thread rather than the server thread
+    if let Some((player, uuid)) = content.strip_prefix("UUID of player ").and_then(|x| x.split_once(" is ")) {
+        if is_uuid(uuid) {
+            return Some(LogEvent::PlayerAuthenticated { player: player.to_string(), uuid: uuid.to_string() });
+        }
+    }
+
+    if !line.is_from("MinecraftServer") {
+        return None;
+    }
+
+    // <Steve> hello
+    // Checked first so players can't fake a join or leave by typing one in chat
+    if let Some(rest) = content.strip_prefix('<') {
+        let (user, message) = rest.split_once("> ")?;
+        if user == "Server" {
+            return None;
+        }
+        return Some(LogEvent::Chat { user: user.to_string(), message: message.to_string() });
+    }
+
+    if let Some(player) = content.strip_suffix(" joined the game") {
+        return Some(LogEvent::PlayerJoined(player.to_string()));
+    }
+
+    if let Some(player) = content.strip_suffix(" left the game") {
+        return Some(LogEvent::PlayerLeft(player.to_string()));
+    }
+
+    // There are 2 of a max of 20 players online: Steve, Alex
+    if content.starts_with("There are ") {
+        if let Some((_, names)) = content.split_once(" players online:") {
+            let names = names.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()).map(|x| x.to_string()).collect();
+            return Some(LogEvent::PlayerList(names));
+        }
+    }
+
+    let player = players_online.find(|x| content.strip_prefix(x.as_str()).map(|x| x.starts_with(' ')).unwrap_or(false))?;
+    let rest = &content[player.len()..];
+    let (player, message) = (player.to_string(), content.to_string());
+    if ADVANCEMENT_MESSAGES.iter().any(|x| rest.starts_with(x)) {
+        Some(LogEvent::Advancement { player, message })
+    } else if line.level == "INFO" && DEATH_MESSAGES.iter().any(|x| rest.starts_with(x)) {
+        Some(LogEvent::Death { player, message })
+    } else {
+        None
+    }
+}
+
+pub fn is_uuid(text: &str) -> bool {
+    text.len() == 36 && text.chars().enumerate().all(|(i, c)| match i {
+        8 | 13 | 18 | 23 => c == '-',
+        _ => c.is_ascii_hexdigit(),
+    })
+}
+
+// Returns the text between open and close at the start of line, and the rest of the line
+fn take_delimited(line: &str, open: char, close: char) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix(open)?;
+    let end = rest.find(close)?;
+    Some((&rest[..end], &rest[end + close.len_utf8()..]))
+}
+
+// Checks for a HH:MM:SS timestamp
+fn is_time(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() == 8 && bytes[2] == b':' && bytes[5] == b':'
+}
+
+pub fn parse_line(line: &str) -> Result<LogLine, &'static str> {
+    let (time, rest) = take_delimited(line, '[', ']').ok_or("invalid format")?;
+
+    // Paper puts the level next to the time
+    if let Some((time, level)) = time.split_once(' ') {
+        if !is_time(time) {
+            return Err("invalid format");
+        }
+        let content = rest.strip_prefix(": ").ok_or("invalid format")?;
+        return finish(String::new(), level.to_string(), None, content);
+    }
+
+    if !is_time(time) {
+        return Err("invalid format");
+    }
+
+    let (source, rest) = take_delimited(rest.strip_prefix(' ').ok_or("invalid format")?, '[', ']').ok_or("no source segment found")?;
+    let (thread, level) = source.rsplit_once('/').unwrap_or((source, ""));
+    let (thread, level) = (thread.to_string(), level.to_string());
+
+    if let Some(content) = rest.strip_prefix(": ") {
+        finish(thread, level, None, content)
+    } else if let Some(rest) = rest.strip_prefix(' ') {
+        let (logger, content) = match rest.chars().next() {
+            Some('[') => {
+                let (logger, rest) = take_delimited(rest, '[', ']').ok_or("error finding logger end")?;
+                (logger, rest.strip_prefix(": ").ok_or("invalid content")?)
+            },
+            Some('(') => {
+                let (logger, rest) = take_delimited(rest, '(', ')').ok_or("error finding logger end")?;
+                (logger, rest.strip_prefix(' ').ok_or("invalid content")?)
+            },
+            _ => return Err("invalid format"),
+        };
+        finish(thread, level, Some(logger.to_string()), content)
+    } else {
+        Err("invalid format")
+    }
+}
+
+fn finish(thread: String, level: String, logger: Option<String>, content: &str) -> Result<LogLine, &'static str> {
+    if content.is_empty() {
+        return Err("invalid content");
+    }
+    Ok(LogLine { thread, level, logger, content: content.to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::log_parser::{parse_event, parse_line, LogEvent, LogLine};
+
+    fn line(thread: &str, level: &str, logger: Option<&str>, content: &str) -> LogLine {
+        LogLine { thread: thread.to_string(), level: level.to_string(), logger: logger.map(|x| x.to_string()), content: content.to_string() }
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(parse_line("[__:__:__] [A] [TEST1]: content").unwrap(), line("A", "", Some("TEST1"), "content"));
+        assert_eq!(parse_line("[__:__:__] [B] [TEST2]: A").unwrap(), line("B", "", Some("TEST2"), "A"));
+        assert_eq!(parse_line("[__:__:__] [] [TEST2]: A").unwrap(), line("", "", Some("TEST2"), "A"));
+        assert_eq!(parse_line("[__:__:__] [] [TEST3]: ").unwrap_err(), "invalid content");
+        assert_eq!(parse_line("[__:__:__] [] [").unwrap_err(), "error finding logger end");
+        assert_eq!(parse_line("[__:__:__] [] [abcdefg").unwrap_err(), "error finding logger end");
+        assert_eq!(parse_line("[__:__:__] ").unwrap_err(), "no source segment found");
+        assert_eq!(parse_line("A__:__:__] [] [").unwrap_err(), "invalid format");
+        assert_eq!(parse_line("[__:__:__] [A] [INFO]: Jöhn_ñ joined the game ✨").unwrap().content, "Jöhn_ñ joined the game ✨");
+    }
+
+    #[test]
+    fn test_formats() {
+        let forge = parse_line("[12:00:00] [Server thread/INFO] [minecraft/DedicatedServer]: Done (3.2s)! For help, type \"help\"").unwrap();
+        assert_eq!(forge, line("Server thread", "INFO", Some("minecraft/DedicatedServer"), "Done (3.2s)! For help, type \"help\""));
+        assert!(forge.is_from("DedicatedServer"));
+        assert!(!forge.is_from("MinecraftServer"));
+        assert_eq!(forge.label(), "minecraft/DedicatedServer");
+
+        let vanilla = parse_line("[12:00:00] [Server thread/INFO]: Steve joined the game").unwrap();
+        assert_eq!(vanilla, line("Server thread", "INFO", None, "Steve joined the game"));
+        assert!(vanilla.is_from("MinecraftServer"));
+        assert!(!parse_line("[12:00:00] [User Authenticator #1/INFO]: UUID of player Steve is 069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap().is_from("MinecraftServer"));
+        assert_eq!(vanilla.label(), "Server thread/INFO");
+
+        let fabric = parse_line("[12:00:00] [Server thread/INFO] (Minecraft) <Steve> hello").unwrap();
+        assert_eq!(fabric, line("Server thread", "INFO", Some("Minecraft"), "<Steve> hello"));
+        assert!(fabric.is_from("MinecraftServer"));
+        assert!(parse_line("[12:00:00] [Server thread/WARN] (MinecraftServer) Can't keep up!").unwrap().is_from("MinecraftServer"));
+        assert!(!parse_line("[12:00:00] [main/INFO] (FabricLoader) Loading 42 mods").unwrap().is_from("MinecraftServer"));
+
+        let paper = parse_line("[12:00:00 INFO]: Steve left the game").unwrap();
+        assert_eq!(paper, line("", "INFO", None, "Steve left the game"));
+        assert!(paper.is_from("MinecraftServer"));
+        assert_eq!(paper.label(), "INFO");
+
+        assert!(!LogLine::unparsed("\tat java.lang.Thread.run(Thread.java:833)").is_from("MinecraftServer"));
+    }
+
+    #[test]
+    fn test_parse_event() {
+        let players = ["Steve".to_string(), "Alex".to_string()];
+        let event = |text: &str| parse_event(&parse_line(text).unwrap(), players.iter());
+
+        assert_eq!(event("[12:00:00] [Server thread/INFO] [minecraft/DedicatedServer]: Done (3.2s)! For help, type \"help\""), Some(LogEvent::ServerDone));
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: Jöhn joined the game"), Some(LogEvent::PlayerJoined("Jöhn".to_string())));
+        assert_eq!(event("[12:00:00] [Server thread/INFO] [minecraft/MinecraftServer]: Steve left the game"), Some(LogEvent::PlayerLeft("Steve".to_string())));
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: <Steve> hi > there"), Some(LogEvent::Chat { user: "Steve".to_string(), message: "hi > there".to_string() }));
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: <Server> hi"), None);
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: <Steve> Alex joined the game"), Some(LogEvent::Chat { user: "Steve".to_string(), message: "Alex joined the game".to_string() }));
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: <Steve> Alex left the game"), Some(LogEvent::Chat { user: "Steve".to_string(), message: "Alex left the game".to_string() }));
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: Alex fell out of the world"), Some(LogEvent::Death { player: "Alex".to_string(), message: "Alex fell out of the world".to_string() }));
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: Steve has made the advancement [Stone Age]"), Some(LogEvent::Advancement { player: "Steve".to_string(), message: "Steve has made the advancement [Stone Age]".to_string() }));
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: Steven fell out of the world"), None);
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: Steve lost connection: Disconnected"), None);
+        assert_eq!(event("[12:00:00] [Server thread/WARN]: Steve moved too quickly! 1.5,2.0,3.0"), None);
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: Steve issued server command: /home"), None);
+        assert_eq!(event("[12:00:00] [Server thread/WARN]: Alex fell out of the world"), None);
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: Steve was slain by Zombie"), Some(LogEvent::Death { player: "Steve".to_string(), message: "Steve was slain by Zombie".to_string() }));
+        assert_eq!(event("[12:00:00] [User Authenticator #1/INFO]: Steve joined the game"), None);
+        assert_eq!(event("[12:00:00] [User Authenticator #1/INFO]: UUID of player Steve is 069a79f4-44e9-4726-a5be-fca90e38aaf5"), Some(LogEvent::PlayerAuthenticated { player: "Steve".to_string(), uuid: "069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string() }));
+        assert_eq!(event("[12:00:00] [User Authenticator #1/INFO]: UUID of player Steve is not-a-uuid"), None);
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: There are 2 of a max of 20 players online: Steve, Jöhn"), Some(LogEvent::PlayerList(vec!["Steve".to_string(), "Jöhn".to_string()])));
+        assert_eq!(event("[12:00:00] [Server thread/INFO]: There are 0 of a max of 20 players online: "), Some(LogEvent::PlayerList(Vec::new())));
+    }
+}
@@ -0,0 +1,122 @@
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+use crate::Packet;
+use crate::process::{complete_lines, process_line};
+
+// Where log lines are read from
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogSource {
+    // The child process' stdout and stderr
+    #[default]
+    Stdout,
+    // The server's log file, for launchers that don't forward the JVM's output
+    File,
+}
+
+pub fn default_log_file_path() -> String {
+    "logs/latest.log".to_string()
+}
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Identifies a specific log file so we can tell when the server has rotated it
+#[derive(PartialEq, Eq)]
+struct FileIdentity {
+    created: Option<SystemTime>,
+    #[cfg(unix)]
+    inode: u64,
+}
+
+impl FileIdentity {
+    fn new(metadata: &std::fs::Metadata) -> FileIdentity {
+        FileIdentity {
+            created: metadata.created().ok(),
+            #[cfg(unix)]
+            inode: std::os::unix::fs::MetadataExt::ino(metadata),
+        }
+    }
+}
+
+// Follows the log file at path, sending each complete line through the same parsing as
+// stdout. After a rotation the new file is read from the start.
+pub fn start_log_tailing(path: &str, sender: &mpsc::UnboundedSender<Packet>) {
+    let path = PathBuf::from(path);
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        let mut identity: Option<FileIdentity> = None;
+        let mut position: u64 = 0;
+        let mut pending: Vec<u8> = Vec::new();
+
+        // Skip whatever is already in the file, it's left over from a previous run
+        if let Ok(metadata) = tokio::fs::metadata(&path).await {
+            identity = Some(FileIdentity::new(&metadata));
+            position = metadata.len();
+        }
+
+        info!("Tailing log file {}", path.display());
+
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+
+            let metadata = match tokio::fs::metadata(&path).await {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            let new_identity = FileIdentity::new(&metadata);
+            let rotated = match &identity {
+                Some(old_identity) => (*old_identity != new_identity) || (metadata.len() < position),
+                None => true,
+            };
+
+            if rotated {
+                info!("Log file rotated");
+                position = 0;
+                pending.clear();
+                identity = Some(new_identity);
+            }
+
+            if metadata.len() == position {
+                continue;
+            }
+
+            let mut file = match File::open(&path).await {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("Error opening log file {}", e);
+                    continue;
+                },
+            };
+
+            if let Err(e) = file.seek(SeekFrom::Start(position)).await {
+                error!("Error seeking log file {}", e);
+                continue;
+            }
+
+            let bytes_read = match file.read_to_end(&mut pending).await {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("Error reading log file {}", e);
+                    continue;
+                },
+            };
+            position += bytes_read as u64;
+
+            // process completed lines, keeping any partial line for the next read
+            let (lines, line_start) = complete_lines(&pending);
+            for line in lines {
+                process_line(&line, &sender);
+            }
+            pending.drain(..line_start);
+        }
+    });
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io::Write as _;
+use std::str::FromStr;
+use std::sync::{Mutex, RwLock};
+
+use serde::Deserialize;
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span;
+use tracing::subscriber::Interest;
+use tracing::{Event, Metadata, Subscriber};
+
+pub const DEBUG_LOG_PATH: &str = "mc-discord-bot-debug.log";
+
+// Levels for the bot's own logging. modules maps targets, eg. "serenity" or
+// "mc_discord_bot::process", to their own level, server output uses the "server" target.
+#[derive(Deserialize)]
+pub struct LoggingToml {
+    #[serde(default)]
+    pub level: LogLevel,
+    #[serde(default)]
+    pub modules: HashMap<String, LogLevel>,
+    // The debug log is rotated once it reaches this size, keeping keep_files old logs
+    #[serde(default = "default_max_file_mb")]
+    pub max_file_mb: u64,
+    #[serde(default = "default_keep_files")]
+    pub keep_files: usize,
+}
+
+impl Default for LoggingToml {
+    fn default() -> LoggingToml {
+        LoggingToml { level: LogLevel::default(), modules: HashMap::new(), max_file_mb: default_max_file_mb(), keep_files: default_keep_files() }
+    }
+}
+
+fn default_max_file_mb() -> u64 {
+    10
+}
+
+fn default_keep_files() -> usize {
+    3
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(try_from = "String")]
+pub struct LogLevel(pub LevelFilter);
+
+impl Default for LogLevel {
+    fn default() -> LogLevel {
+        LogLevel(LevelFilter::INFO)
+    }
+}
+
+impl TryFrom<String> for LogLevel {
+    type Error = String;
+
+    fn try_from(level: String) -> Result<LogLevel, String> {
+        LevelFilter::from_str(&level)
+            .map(LogLevel)
+            .map_err(|_| format!("Invalid log level \"{}\", expected off, error, warn, info, debug or trace", level))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Filter {
+    default: LevelFilter,
+    modules: HashMap<String, LevelFilter>,
+}
+
+impl Filter {
+    // The most specific module that the target is in decides its level
+    fn level(&self, target: &str) -> LevelFilter {
+        self.modules.iter()
+            .filter(|(module, _)| target == module.as_str() || target.strip_prefix(module.as_str()).is_some_and(|x| x.starts_with("::")))
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+}
+
+struct LogFile {
+    path: String,
+    file: File,
+    size: u64,
+    max_size: u64,
+    keep_files: usize,
+}
+
+impl LogFile {
+    fn open(path: &str, max_size: u64, keep_files: usize) -> std::io::Result<LogFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(LogFile { path: path.to_string(), file, size, max_size, keep_files })
+    }
+
+    fn write_line(&mut self, line: &str) {
+        if self.max_size > 0 && self.size + line.len() as u64 > self.max_size {
+            if let Err(e) = self.rotate() {
+                println!("Error rotating {} {}", self.path, e);
+            }
+        }
+
+        if self.file.write_all(line.as_bytes()).is_ok() {
+            self.size += line.len() as u64;
+        }
+    }
+
+    // Renames the log to path.1, moving older logs up and deleting the oldest
+    fn rotate(&mut self) -> std::io::Result<()> {
+        if self.keep_files > 0 {
+            for i in (1..self.keep_files).rev() {
+                let _ = std::fs::rename(format!("{}.{}", self.path, i), format!("{}.{}", self.path, i + 1));
+            }
+            std::fs::rename(&self.path, format!("{}.1", self.path))?;
+        }
+
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+// Kept outside the subscriber so !loglevel can change it
+static FILTER: RwLock<Option<Filter>> = RwLock::new(None);
+static LOG_FILE: Mutex<Option<LogFile>> = Mutex::new(None);
+
+struct MessageVisitor<'a>(&'a mut String);
+
+impl Visit for MessageVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+// Prints events and writes them to the debug log, spans aren't used
+struct Logger;
+
+impl Subscriber for Logger {
+    // Always asks enabled so changes to the filter take effect straight away
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.is_event() && match FILTER.read().unwrap().as_ref() {
+            Some(filter) => *metadata.level() <= filter.level(metadata.target()),
+            None => false,
+        }
+    }
+
+    fn new_span(&self, _span: &span::Attributes<'_>) -> span::Id {
+        span::Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let metadata = event.metadata();
+        let mut line = format!("{} {:5} {}: ", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), metadata.level(), metadata.target());
+        event.record(&mut MessageVisitor(&mut line));
+        println!("{}", line);
+
+        line.push('\n');
+        if let Some(log_file) = LOG_FILE.lock().unwrap().as_mut() {
+            log_file.write_line(&line);
+        }
+    }
+
+    fn enter(&self, _span: &span::Id) {}
+
+    fn exit(&self, _span: &span::Id) {}
+}
+
+pub fn init(config: &LoggingToml) {
+    *FILTER.write().unwrap() = Some(Filter {
+        default: config.level.0,
+        modules: config.modules.iter().map(|(module, level)| (module.clone(), level.0)).collect(),
+    });
+
+    match LogFile::open(DEBUG_LOG_PATH, config.max_file_mb * 1024 * 1024, config.keep_files) {
+        Ok(v) => *LOG_FILE.lock().unwrap() = Some(v),
+        Err(e) => println!("Error opening {} {}", DEBUG_LOG_PATH, e),
+    }
+
+    if tracing::subscriber::set_global_default(Logger).is_err() {
+        println!("Logging was already initialised");
+    }
+}
+
+// Sets the level of a module, or the default level when module is None
+pub fn set_level(module: Option<&str>, level: LevelFilter) {
+    if let Some(filter) = FILTER.write().unwrap().as_mut() {
+        match module {
+            Some(module) => { filter.modules.insert(module.to_string(), level); },
+            None => filter.default = level,
+        }
+    }
+}
+
+// Lists the current levels for !loglevel
+pub fn describe() -> String {
+    let filter = match FILTER.read().unwrap().clone() {
+        Some(v) => v,
+        None => return "Logging isn't set up".to_string(),
+    };
+
+    let mut text = format!("Default: {}", filter.default);
+    let mut modules: Vec<_> = filter.modules.into_iter().collect();
+    modules.sort();
+    for (module, level) in modules {
+        let _ = write!(text, "\n{}: {}", module, level);
+    }
+    text
+}
+
+// Parses `!loglevel <level>` or `!loglevel <module> <level>`
+pub fn parse_command(content: &str) -> Result<(Option<String>, LevelFilter), String> {
+    let args: Vec<&str> = content.split_whitespace().skip(1).collect();
+    let (module, level) = match args[..] {
+        [level] => (None, level),
+        [module, level] => (Some(module.to_string()), level),
+        _ => return Err("Usage: `!loglevel [module] <level>`".to_string()),
+    };
+    Ok((module, LogLevel::try_from(level.to_string())?.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tracing::level_filters::LevelFilter;
+
+    use crate::logging::{parse_command, Filter, LogFile, LoggingToml};
+
+    #[test]
+    fn test_filter() {
+        let config: LoggingToml = toml::from_str("level = 'warn'\nmodules = { serenity = 'error', 'mc_discord_bot::process' = 'debug' }").unwrap();
+        assert!(toml::from_str::<LoggingToml>("level = 'loud'").is_err());
+
+        let filter = Filter {
+            default: config.level.0,
+            modules: config.modules.iter().map(|(module, level)| (module.clone(), level.0)).collect::<HashMap<_, _>>(),
+        };
+        assert_eq!(filter.level("serenity::gateway::shard"), LevelFilter::ERROR);
+        assert_eq!(filter.level("mc_discord_bot::process"), LevelFilter::DEBUG);
+        assert_eq!(filter.level("mc_discord_bot::processing"), LevelFilter::WARN);
+        assert_eq!(filter.level("mc_discord_bot"), LevelFilter::WARN);
+
+        assert_eq!(parse_command("!loglevel debug"), Ok((None, LevelFilter::DEBUG)));
+        assert_eq!(parse_command("!loglevel serenity off"), Ok((Some("serenity".to_string()), LevelFilter::OFF)));
+        assert!(parse_command("!loglevel").is_err());
+        assert!(parse_command("!loglevel serenity loud").is_err());
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("mc-discord-bot-logging-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("debug.log").to_string_lossy().to_string();
+
+        let mut log_file = LogFile::open(&path, 10, 2).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            log_file.write_line(line);
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(std::fs::read_to_string(format!("{}.1", path)).unwrap(), "third\n");
+        assert_eq!(std::fs::read_to_string(format!("{}.2", path)).unwrap(), "second\n");
+        assert!(std::fs::metadata(format!("{}.3", path)).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
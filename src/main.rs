@@ -1,42 +1,188 @@
 use std::collections::HashMap;
 use std::process::Command;
 use std::{fs, env};
-use std::time::Instant;
-use std::fmt::Write;
-use std::io::Write as _;
+use std::time::{Duration, Instant, SystemTime};
 
 use serde::{Serialize, Deserialize};
 use serenity::model::channel::Message;
-use serenity::model::gateway::{Ready, Activity};
+use serenity::model::interactions::message_component::MessageComponentInteraction;
+use serenity::model::gateway::Ready;
+use serenity::model::user::OnlineStatus;
 use serenity::prelude::*;
-use serenity::model::id::ChannelId;
+use serenity::model::event::MessageUpdateEvent;
+use serenity::model::id::{ChannelId, MessageId};
 
-use std::fs::OpenOptions;
 use tokio::sync::mpsc;
-use tokio::io::AsyncWriteExt;
-
+use tracing::{error, info, warn};
+
+mod afk;
+mod alerts;
+mod announcements;
+mod api;
+mod audit;
+mod backup;
+mod bot;
+mod bedrock;
+mod calendar;
+mod capacity;
+mod channel_status;
+mod chat_filter;
+mod chat_log;
+mod command_queue;
+mod commands;
+mod confirm;
+mod console;
+mod console_commands;
+mod cooldown;
+mod cross_server;
+mod disk_monitor;
 mod discord;
+mod discord_events;
+mod fake_server;
+mod game_commands;
+#[cfg(test)]
+mod harness;
+mod hooks;
+mod join_messages;
+mod incident;
+mod jvm_args;
+mod locale;
+mod log_adapters;
+mod log_buffer;
+mod log_events;
+mod logging;
+mod log_mirror;
+mod log_parser;
+mod log_tailer;
+mod maintenance;
+mod map;
+mod metrics;
+mod milestones;
+mod oauth;
+mod oom;
+mod outbound;
+mod modpack_source;
+mod permissions;
+mod platform;
+mod player_ids;
+mod player_reports;
+mod player_stats;
+mod playtime;
+mod plugins;
+mod polls;
+mod preflight;
+mod pregen;
+mod presence;
 mod process;
+mod process_stats;
+mod properties;
+mod query;
+mod pterodactyl;
+mod redaction;
+mod relay_channels;
+mod relayed_messages;
+mod report;
+mod response;
+mod s3;
+mod scheduler;
+mod server_files;
+mod server_admin;
+mod server_backend;
+mod shutdown;
+mod stats_export;
+mod stats_tracker;
+mod status;
+mod status_ping;
 mod stdin_forward;
+mod subscriptions;
+mod systemd;
+mod tellraw;
+mod tempbans;
+mod templates;
+mod update;
+mod uptime;
+mod version_check;
+mod vote_actions;
+mod warnings;
+mod watchdog;
+mod world_info;
+mod waypoints;
+mod welcome;
+mod usage;
+
+use command_queue::{CommandQueue, ConsoleTask};
+use console::Console;
+use log_tailer::LogSource;
+use metrics::{Metrics, SharedMetrics};
+use update::UpdateKind;
+use uptime::RestartReason;
 
 pub enum Packet {
-    DiscordReady(Context, Ready),
-    DiscordMessage(Message),
-    ProcessStarted(tokio::process::ChildStdin),
-    LogLine(String, String),
+    DiscordReady(Context, Box<Ready>),
+    DiscordMessage(Box<Message>),
+    DiscordComponent(Box<MessageComponentInteraction>),
+    DiscordMessageUpdate(Box<MessageUpdateEvent>),
+    DiscordMessageDelete(ChannelId, MessageId),
+    ProcessStarted(Console, Option<u32>, process::KillHandle),
+    LogLine(log_parser::LogLine),
     StdinLine(String),
+    ProcessExited(),
     StopServer(),
+    Shutdown(),
+    ShutdownTimedOut(),
+    StallTimedOut(),
+    CommandTimerElapsed(u64),
+    PollEnded(u64),
+    Announcement(usize),
+    CalendarTick(),
+    TempBanTick(),
+    CrossServerChat(cross_server::ChatMessage),
+    NewVersion(String),
+    PreflightFailed(Vec<String>),
+    DiskTick(),
+    RestartWarning(u64),
+    ScheduledRestart(RestartReason),
+    PresenceTick(),
+    MilestoneTick(),
+    ChannelStatusTick(),
+    LogMirrorTick(),
+    ListPlayers(),
+    QueryResult(Result<query::QueryInfo, String>),
+    StatusPing(Result<status_ping::PingInfo, String>),
+    WatchdogTick(),
+    ScheduledBackup(),
+    SummaryReport(report::ReportPeriod),
+    BackupFinished(Option<std::path::PathBuf>),
+    MemoryWarning(u64, u64),
+    UpdateProgress(String),
+    UpdateFinished(Result<(), String>),
+    // An admin confirmed stopping, killing or updating the server
+    Confirmed(ChannelId, confirm::ConfirmAction),
+    BackupRequested(backup::BackupRequest),
+    Api(api::ApiRequest),
+    ApiSubscribe(tokio::sync::oneshot::Sender<tokio::sync::broadcast::Receiver<api::ApiEvent>>),
 }
 
 pub fn send_or_log(sender: &mpsc::UnboundedSender<Packet>, packet: Packet) {
-    if let Err(_) = sender.send(packet) {
-        println!("Error sending internal packet");
+    if sender.send(packet).is_err() {
+        error!("Error sending internal packet");
+    }
+}
+
+pub async fn write_console(console: &Option<Console>, line: &str) {
+    let console = match console {
+        Some(v) => v,
+        None => return,
+    };
+
+    if let Err(e) = console.send(line).await {
+        error!("Error writing to stdin {}", e);
     }
 }
 
 pub async fn say_or_log(channel_id: ChannelId, ctx: &Context, msg: &str) {
     if let Err(e) = channel_id.say(&ctx.http, msg).await {
-        println!("Error sending message: {:?}", e);
+        error!("Error sending message: {:?}", e);
     }
 }
 
@@ -45,24 +191,216 @@ pub struct ConfigToml {
     // Used for discord integration
     pub discord_token: String,
     pub discord_channel_id: String,
+    pub discord_admin_channel_id: Option<String>,
+
+    // Language for messages in discord, "en" or "de"
+    #[serde(default = "locale::default_language")]
+    pub language: String,
+    // Replaces announcements with custom messages
+    #[serde(default)]
+    pub templates: templates::TemplatesToml,
+
+    // Maps permission names (eg. "console") to the role ids allowed to use them
+    #[serde(default)]
+    pub permissions: HashMap<String, Vec<String>>,
+    // Users that can use admin commands by messaging the bot directly, their roles are still
+    // checked against the permissions
+    #[serde(default)]
+    pub admin_user_ids: Vec<String>,
+
+    // Responses longer than this many characters are sent as a .txt attachment
+    #[serde(default = "response::default_attachment_threshold")]
+    pub response_attachment_threshold: usize,
+
+    // Announces joins and leaves as plain text instead of embeds
+    #[serde(default)]
+    pub plain_join_messages: bool,
+
+    // The server's start command, working directory and environment
+    #[serde(default)]
+    pub launch: process::LaunchToml,
+    // Used instead of launch when the server is a systemd unit or hosted on a panel
+    pub systemd: Option<systemd::SystemdToml>,
+    pub pterodactyl: Option<pterodactyl::PterodactylToml>,
+    // Used to choose where server log lines are read from
+    #[serde(default)]
+    pub log_source: LogSource,
+    #[serde(default = "log_tailer::default_log_file_path")]
+    pub log_file_path: String,
+    // Mod specific log formats to turn into events
+    #[serde(default = "log_adapters::default_adapters")]
+    pub log_adapters: Vec<log_adapters::AdapterKind>,
+    // Number of recent log lines kept for !logs
+    #[serde(default = "log_buffer::default_capacity")]
+    pub log_buffer_lines: usize,
+    // Lets players use !online, !time and !discord in game chat
+    #[serde(default = "game_commands::default_enabled")]
+    pub game_commands: bool,
+    // Shows the player count in a channel name or topic, disabled when not set
+    pub channel_status: Option<channel_status::ChannelStatusToml>,
+
+    // Posts update progress and crash logs in their own threads in the admin channel
+    #[serde(default = "incident::default_enabled")]
+    pub incident_threads: bool,
+
+    // Tells players in game when a relayed discord message is deleted
+    #[serde(default)]
+    pub mirror_deletes: bool,
+    // Shown to players that use !discord in game
+    pub discord_invite: Option<String>,
+    // Announces players joining for the first time
+    #[serde(default)]
+    pub welcome: welcome::WelcomeToml,
+    // Announces play time and login milestones
+    #[serde(default)]
+    pub milestones: milestones::MilestonesToml,
+    // WebAssembly plugins are loaded from here when it exists
+    #[serde(default = "plugins::default_dir")]
+    pub plugins_dir: String,
+    // Scripts run when things happen on the server
+    #[serde(default)]
+    pub hooks: hooks::HooksToml,
+    // Strips Floodgate's prefix from Bedrock players' names, disabled when not set
+    pub bedrock: Option<bedrock::BedrockToml>,
+
+    // Rate limits for public commands in the chat channel
+    #[serde(default)]
+    pub cooldowns: cooldown::CooldownToml,
+
+    // Levels and rotation for the bot's own log
+    #[serde(default)]
+    pub logging: logging::LoggingToml,
+    // Tracks AFK players, disabled when not set
+    pub afk: Option<afk::AfkToml>,
+    // Detects a server that has stopped responding, disabled when not set
+    pub watchdog: Option<watchdog::WatchdogToml>,
+
+    // Stops the server when the bot is shut down with ctrl-c or SIGTERM
+    #[serde(default = "shutdown::default_stop_server")]
+    pub stop_server_on_shutdown: bool,
+
+    // Log lines that are posted to the admin channel
+    #[serde(default = "alerts::default_rules")]
+    pub alerts: Vec<alerts::AlertRule>,
+
+    // Posts the server log to a channel as it's written, disabled when not set
+    pub log_mirror: Option<log_mirror::LogMirrorToml>,
+
+    // Used for scheduled restarts
+    #[serde(default)]
+    pub schedule: scheduler::ScheduleToml,
+
+    // Used to serve prometheus metrics, disabled when not set
+    pub metrics: Option<metrics::MetricsToml>,
+    // Checks the online players with the query protocol, disabled when not set
+    pub query: Option<query::QueryToml>,
+    // Pings the server like the multiplayer screen does, disabled when not set
+    pub status_ping: Option<status_ping::StatusPingToml>,
+    // Links to the server's web map for !map and !status, disabled when not set
+    pub map: Option<map::MapToml>,
+    // Used for polls started with !vote
+    #[serde(default)]
+    pub polls: polls::PollsToml,
+    // !voteday and !voterestart, disabled when not set
+    pub votes: Option<vote_actions::VotesToml>,
+    // Messages sent in game or to the chat channel on a schedule
+    #[serde(default)]
+    pub announcements: Vec<announcements::AnnouncementToml>,
+    // Reminders for events added with !event
+    #[serde(default)]
+    pub calendar: calendar::CalendarToml,
+    // Used by !maintenance
+    #[serde(default)]
+    pub maintenance: maintenance::MaintenanceToml,
+    // Posts new Minecraft and modpack releases to the admin channel, disabled when not set
+    pub version_check: Option<version_check::VersionCheckToml>,
+    // Checked before the server is started or updated
+    #[serde(default)]
+    pub preflight: preflight::PreflightToml,
+    // Other channels whose messages are shown in game, eg. announcements
+    #[serde(default)]
+    pub relay_channels: Vec<relay_channels::RelayChannelToml>,
+    // Bridges game chat with other instances of the bot, disabled when not set
+    pub cross_server: Option<cross_server::CrossServerToml>,
+    // Filters bridged chat and stops game chat floods, disabled when not set
+    pub chat_filter: Option<chat_filter::ChatFilterToml>,
+    // IPs and coordinates are removed from log output posted to discord
+    #[serde(default)]
+    pub redaction: redaction::RedactionToml,
+    // What's done after an OutOfMemoryError
+    #[serde(default)]
+    pub oom: oom::OomToml,
+    // Used by !pregen
+    #[serde(default)]
+    pub pregen: pregen::PregenToml,
+    // Automatic kicks and tempbans for !warn
+    #[serde(default)]
+    pub warnings: warnings::WarningsToml,
+    // Free space alerts and the check before each backup
+    #[serde(default)]
+    pub disk: disk_monitor::DiskToml,
+
+    // Serves a REST API for other tools, disabled when not set
+    pub api: Option<api::ApiToml>,
+
+    // Used for sampling the server's cpu and memory usage
+    #[serde(default)]
+    pub usage: usage::UsageToml,
+
+    #[serde(default)]
+    pub presence: presence::PresenceToml,
+
+    #[serde(default)]
+    pub backup: backup::BackupToml,
     
     // Used for server setup
     pub server_setup_url: String,
+    #[serde(default)]
+    pub jvm: jvm_args::JvmToml,
     
     // Used for server update (mod/config setup)
+    #[serde(default)]
     pub modpack_path: String,
+    // Used instead of modpack_path to install versions from CurseForge or Modrinth
+    pub modpack: Option<modpack_source::ModpackToml>,
     pub client_mods: Vec<String>,
 }
 
 #[tokio::main]
 async fn main() {
+    // Doesn't need a config since the bot runs it in place of the server
+    if env::args().nth(1).as_deref() == Some("fake-server") {
+        fake_server::run();
+        return;
+    }
+
     let config_toml_string = fs::read_to_string("mc-discord-bot.toml").unwrap();
     let config_toml: ConfigToml = toml::from_str(&config_toml_string).unwrap();
+    logging::init(&config_toml.logging);
     
     let channel_id: ChannelId = match config_toml.discord_channel_id.parse() {
         Ok(v) => v,
         Err(_) => {
-            println!("Invalid channel id \"{}\"", config_toml.discord_channel_id);
+            warn!("Invalid channel id \"{}\"", config_toml.discord_channel_id);
+            return;
+        },
+    };
+
+    let admin_channel_id: Option<ChannelId> = match &config_toml.discord_admin_channel_id {
+        Some(id) => match id.parse() {
+            Ok(v) => Some(v),
+            Err(_) => {
+                warn!("Invalid admin channel id \"{}\"", id);
+                return;
+            },
+        },
+        None => None,
+    };
+
+    let log_mirror = match config_toml.log_mirror.as_ref().map(log_mirror::LogMirror::new).transpose() {
+        Ok(v) => v,
+        Err(e) => {
+            error!("{}", e);
             return;
         },
     };
@@ -73,48 +411,275 @@ async fn main() {
             // NOTE(Jon): The only files we need to manually copy over are:
             // banned-ips.json, banned-players.json, mc-discord-bot, mc-discord-bot.toml, ops.json, server.properties & whitelist.json
 
-            println!("Setting up server");
-            let _ = Command::new("wget").args(&["-O", "installer.jar", &config_toml.server_setup_url]).status();
-            let _ = Command::new("java").args(&["-jar", "installer.jar", "--installServer"]).status();
-            let _ = Command::new("rm").args(&["installer.jar", "installer.jar.log"]).status();
-            let _ = fs::write("eula.txt", "eula=true");
-            let _ = fs::write("user_jvm_args.txt", include_str!("user_jvm_args.txt"));
+            info!("Setting up server");
+            if let Err(e) = update::download(&config_toml.server_setup_url, "installer.jar", &|msg| info!("{}", msg)).await {
+                error!("{}", e);
+                return;
+            }
+            let _ = Command::new("java").args(["-jar", "installer.jar", "--installServer"]).status();
+            let _ = fs::remove_file("installer.jar");
+            let _ = fs::remove_file("installer.jar.log");
+            if let Err(e) = config_toml.jvm.write_files() {
+                error!("Error writing JVM args and eula {}", e);
+            }
+            return;
+        } else if args[1] == "jvm-args" {
+            info!("Writing {}", jvm_args::JVM_ARGS_PATH);
+            if let Err(e) = config_toml.jvm.write_files() {
+                error!("Error writing JVM args and eula {}", e);
+            }
             return;
         } else if args[1] == "update" {
-            println!("Updating server");
-            let _ = Command::new("wget").args(&["-O", "pack.zip", &config_toml.modpack_path]).status();
-            let _ = Command::new("unzip").args(&["pack.zip", "-d", "temp-pack"]).status();
-            let _ = Command::new("rm").args(&["pack.zip"]).status();
-            let _ = Command::new("rm").args(&["-rf", "mods", "config", "defaultconfigs"]).status();
-            let _ = Command::new("cp").args(&["-r", "temp-pack/.minecraft/mods", "temp-pack/.minecraft/config", "temp-pack/.minecraft/defaultconfigs", "."]).status();
-            let _ = Command::new("rm").args(&["-rf", "temp-pack"]).status();
-
-            for client_mod in &config_toml.client_mods {
-                println!("Removing client mod {client_mod}");
-                let _ = Command::new("rm").args(&[format!("mods/{client_mod}")]).status();
+            info!("Updating server");
+            let version = args.get(2).map(|x| x.as_str());
+            let (modpack_url, version) = match modpack_source::resolve(&config_toml.modpack_path, config_toml.modpack.as_ref(), version).await {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("{}", e);
+                    return;
+                },
+            };
+
+            if let Err(e) = update::run_update(&modpack_url, version, &config_toml.client_mods, |msg| info!("{}", msg)).await {
+                error!("{}", e);
             }
-
             return;
         } else {
-            println!("Invalid command \"{}\"", args[1]);
+            warn!("Invalid command \"{}\"", args[1]);
             return;
         }
     }
 
+    let restart_times = match scheduler::parse_times(&config_toml.schedule.daily_restarts) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        },
+    };
+    let backup_times = match scheduler::parse_times(&config_toml.schedule.daily_backups) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        },
+    };
+
+    let daily_report = match config_toml.schedule.daily_report.as_ref().map(|x| scheduler::parse_times(std::slice::from_ref(x))).transpose() {
+        Ok(v) => v.and_then(|x| x.first().copied()),
+        Err(e) => {
+            error!("{}", e);
+            return;
+        },
+    };
+    let weekly_report = match config_toml.schedule.weekly_report.as_deref().map(scheduler::parse_weekly_time).transpose() {
+        Ok(v) => v,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        },
+    };
+
+    let announcement_schedules = match announcements::parse_schedules(&config_toml.announcements) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        },
+    };
+
     let (sender, receiver) = mpsc::unbounded_channel::<Packet>(); 
-    tokio::task::spawn(async move { handle_packets(receiver, channel_id).await });
+    let discord_token = config_toml.discord_token.clone();
+    if config_toml.log_source == LogSource::File {
+        log_tailer::start_log_tailing(&config_toml.log_file_path, &sender);
+    }
+
+    let metrics = Metrics::new();
+    if let Some(metrics_toml) = &config_toml.metrics {
+        metrics::start_metrics_server(metrics_toml, metrics.clone(), &sender);
+    }
+    if let Some(api_toml) = &config_toml.api {
+        api::start_api_server(api_toml, &sender);
+    }
+    if let Some(cross_server_toml) = &config_toml.cross_server {
+        cross_server::start_peer_server(cross_server_toml, &sender);
+    }
+    usage::start_usage_sampler(&config_toml.usage, metrics.clone(), &sender);
+    presence::start_presence_rotation(&config_toml.presence, &sender);
+    if !config_toml.milestones.play_hours.is_empty() {
+        milestones::start_milestone_timer(&sender);
+    }
+    if let Some(channel_status_config) = &config_toml.channel_status {
+        channel_status::start_channel_status_timer(channel_status_config, &sender);
+    }
+    playtime::start_list_timer(&sender);
+    calendar::start_calendar_timer(&sender);
+    tempbans::start_tempban_timer(&sender);
+    disk_monitor::start_disk_timer(&config_toml.disk, &sender);
+    if let Some(version_check_toml) = &config_toml.version_check {
+        version_check::start_version_checker(version_check_toml, config_toml.modpack.clone(), metrics.clone(), &sender);
+    }
+    if let Some(query_toml) = &config_toml.query {
+        query::start_query_timer(query_toml, &sender);
+    }
+    if let Some(status_ping_toml) = &config_toml.status_ping {
+        status_ping::start_ping_timer(status_ping_toml, &sender);
+    }
+    if config_toml.watchdog.is_some() {
+        watchdog::start_watchdog_timer(&sender);
+    }
+    shutdown::start_signal_handler(&sender);
+    if let Some(log_mirror_config) = &config_toml.log_mirror {
+        log_mirror::start_log_mirror_timer(log_mirror_config, &sender);
+    }
 
-    let discord_integration = discord::start_discord_integration(&config_toml.discord_token, &sender);
-    let process_wrapper = process::start_process_wrapper("./run.sh", &[], &sender);
+    start_server(&sender, &config_toml);
+    let packet_sender = sender.clone();
+    tokio::task::spawn(async move { handle_packets(receiver, packet_sender, config_toml, metrics, channel_id, admin_channel_id, log_mirror).await });
+
+    scheduler::start_scheduler(restart_times, &sender);
+    scheduler::start_backup_scheduler(backup_times, &sender);
+    scheduler::start_report_scheduler(daily_report, weekly_report, &sender);
+    announcements::start_announcements(announcement_schedules, &sender);
     stdin_forward::start_stdin_forwarding(&sender);
 
-    // TODO(Jon): Remove this so we can remove "futures" as a dependency 
-    futures::join!(discord_integration, process_wrapper);
+    discord::start_discord_integration(&discord_token, &sender).await;
+}
+
+// Runs the modpack update or rollback in the background, reporting progress with UpdateProgress packets
+fn start_update(config: &ConfigToml, sender: &mpsc::UnboundedSender<Packet>, kind: UpdateKind) {
+    let modpack_path = config.modpack_path.clone();
+    let modpack = config.modpack.clone();
+    let client_mods = config.client_mods.clone();
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        let progress_sender = sender.clone();
+        let report = move |msg| send_or_log(&progress_sender, Packet::UpdateProgress(msg));
+        let result = match kind {
+            UpdateKind::Update(version) => match modpack_source::resolve(&modpack_path, modpack.as_ref(), version.as_deref()).await {
+                Ok((modpack_url, version)) => update::run_update(&modpack_url, version, &client_mods, report).await,
+                Err(e) => Err(e),
+            },
+            UpdateKind::Rollback => update::run_rollback(report).await,
+            UpdateKind::Restore(id) => backup::run_restore(&id, report).await,
+        };
+        send_or_log(&sender, Packet::UpdateFinished(result));
+    });
+}
+
+// Starts the server with its backend, a ProcessExited packet is sent once it exits. PreflightFailed
+// is sent instead if it can't be started.
+fn start_server(sender: &mpsc::UnboundedSender<Packet>, config: &ConfigToml) {
+    let sender = sender.clone();
+    let backend = server_backend::ServerBackend::from_config(config);
+    let forward_lines = config.log_source == LogSource::Stdout;
+    tokio::task::spawn(async move {
+        backend.run(&sender, forward_lines).await;
+    });
+}
+
+// Saving is paused while the world is copied so the files don't change underneath it, the
+// backup starts once the server logs that it has saved
+async fn start_save_for_backup(console: &Option<Console>) {
+    write_console(console, "save-off").await;
+    write_console(console, "save-all flush").await;
+}
+
+// Sends a CommandTimerElapsed packet for the command after delay
+fn start_command_timer(sender: &mpsc::UnboundedSender<Packet>, id: u64, delay: Duration) {
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        tokio::time::sleep(delay).await;
+        send_or_log(&sender, Packet::CommandTimerElapsed(id));
+    });
+}
+
+// Sends the next queued console command if none is running, replying with the error for any
+// that can't be sent
+async fn start_next_command(console: &Option<Console>, queue: &mut CommandQueue, sender: &mpsc::UnboundedSender<Packet>, ctx: &Context) {
+    while let Some((id, console_line)) = queue.start_next() {
+        let result = match console {
+            Some(console) => console.send(&console_line).await,
+            None => Err("Server is not running"),
+        };
+
+        match result {
+            Ok(_) => {
+                start_command_timer(sender, id, command_queue::RESPONSE_TIMEOUT);
+                return;
+            },
+            Err(e) => {
+                if let Some(task) = queue.cancel(id) {
+                    say_or_log(task.channel_id, ctx, e).await;
+                }
+            },
+        }
+    }
+}
+
+// Queues a console command, its response is sent to the task's channel once it has finished
+async fn run_console_task(console: &Option<Console>, queue: &mut CommandQueue, sender: &mpsc::UnboundedSender<Packet>, ctx: &Context, task: ConsoleTask) -> Result<(), &'static str> {
+    if console.is_none() {
+        return Err("Server is not running");
+    }
+
+    queue.push(task)?;
+    start_next_command(console, queue, sender, ctx).await;
+    Ok(())
+}
+
+// What plugins can see of the server, players are listed by the name they're shown with
+fn plugin_stats(state: &BotState, players_online: &HashMap<String, Instant>, bedrock: &bedrock::BedrockNames) -> plugins::PluginStats {
+    let mut play_times: HashMap<String, u128> = state.play_times.iter()
+        .map(|(id, millis)| (bedrock.display(state.players.display_name(id)).to_string(), *millis))
+        .collect();
+    for (name, login_time) in players_online {
+        *play_times.entry(bedrock.display(name).to_string()).or_insert(0) += login_time.elapsed().as_millis();
+    }
+    plugins::PluginStats { players_online: players_online.len(), play_times }
+}
+
+async fn run_plugin_actions(actions: Vec<plugins::PluginAction>, console: &Option<Console>, ctx: &Context, channel_id: ChannelId) {
+    for action in actions {
+        match action {
+            plugins::PluginAction::SayInGame(text) => write_console(console, &tellraw::to_all(&text)).await,
+            plugins::PluginAction::PostDiscord(text) => say_or_log(channel_id, ctx, &text).await,
+        }
+    }
+}
+
+// Posts any remaining mirrored log output, shows the bot as offline and exits
+async fn finish_shutdown(ctx: &Option<Context>, log_mirror: &mut Option<log_mirror::LogMirror>) -> ! {
+    if let Some(ctx) = ctx {
+        if let Some(log_mirror) = log_mirror {
+            log_mirror::send_messages(log_mirror.channel_id, ctx, log_mirror.take_messages()).await;
+        }
+        ctx.set_presence(None, OnlineStatus::Offline).await;
+    }
+
+    info!("Shutdown complete");
+    std::process::exit(0);
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct BotState {
     pub play_times: HashMap<String, u128>,
+    #[serde(default)]
+    pub sessions: Vec<playtime::Session>,
+    #[serde(default)]
+    pub last_restart: Option<uptime::LastRestart>,
+    // Unix time in milliseconds that each online player logged in, so sessions survive a bot
+    // restart
+    #[serde(default)]
+    pub online: HashMap<String, u64>,
+    // Recent deaths for summary reports
+    #[serde(default)]
+    pub deaths: Vec<report::Death>,
+    #[serde(default)]
+    pub stats: HashMap<String, player_stats::PlayerStats>,
+    // Stats above are kept by UUID once it's known, this maps them to names
+    #[serde(default)]
+    pub players: player_ids::PlayerIds,
 }
 
 impl BotState {
@@ -122,216 +687,175 @@ impl BotState {
         let json_str = serde_json::to_string_pretty(self).unwrap();
         let _ = std::fs::write("mc-discord-bot.json", json_str);
     }
-}
 
-async fn handle_packets(mut receiver: mpsc::UnboundedReceiver<Packet>, channel_id: ChannelId) {
-    let mut ctx: Option<Context> = None;
-    let mut stdin: Option<tokio::process::ChildStdin> = None;
-    let mut my_id: u64 = 0;
-    let mut players_online: HashMap<String, Instant> = HashMap::new();
-    
-    let mut state: BotState = match fs::read_to_string("mc-discord-bot.json") {
-        Ok(v) => serde_json::from_str(&v).unwrap(),
-        Err(_) => BotState{
-            play_times: HashMap::new(),
-        },
-    };
+    // Records a finished session, returning its length and the player's total play time in
+    // milliseconds. When AFK time is excluded it's left out of both, and the session's start is
+    // moved later so it only covers the time the player was active.
+    pub fn end_session(&mut self, name: &str, login_time: Instant, afk_millis: u128, exclude_afk: bool) -> (u128, u128) {
+        let mut dt = login_time.elapsed().as_millis();
+        if exclude_afk {
+            dt = dt.saturating_sub(afk_millis);
+        }
+        let id = self.players.id(name);
+        let play_time = self.play_times.get(&id).cloned().unwrap_or(0) + dt;
+        self.play_times.insert(id.clone(), play_time);
+
+        let end = playtime::unix_millis(SystemTime::now());
+        self.online.remove(&id);
+        self.sessions.push(playtime::Session {
+            player: id,
+            start: end.saturating_sub(dt as u64),
+            end,
+            afk: afk_millis as u64,
+        });
+        (dt, play_time)
+    }
 
-    let mut debug_log = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("mc-discord-bot-debug.log")
-        .expect("Error opening mc-discord-bot-debug.log");
+    // Records a player's UUID, moving any stats that were kept under their name before it was
+    // known. Returns the player's previous name if they've changed it.
+    pub fn identify(&mut self, name: &str, uuid: &str) -> Option<String> {
+        let previous = self.players.record(uuid, name);
+        if let Some(play_time) = self.play_times.remove(name) {
+            *self.play_times.entry(uuid.to_string()).or_insert(0) += play_time;
+        }
+        if let Some(login_time) = self.online.remove(name) {
+            self.online.insert(uuid.to_string(), login_time);
+        }
+        for session in self.sessions.iter_mut().filter(|x| x.player == name) {
+            session.player = uuid.to_string();
+        }
+        for death in self.deaths.iter_mut().filter(|x| x.player == name) {
+            death.player = uuid.to_string();
+        }
+        if let Some(stats) = self.stats.remove(name) {
+            self.stats.entry(uuid.to_string()).or_default().merge(&stats);
+        }
+        previous
+    }
 
-    while let Some(packet) = receiver.recv().await {
-        match packet {
-            Packet::DiscordReady(new_ctx, ready) => {
-                new_ctx.set_activity(Activity::playing(
-                    format!("{} Online", players_online.len())
-                )).await;
-
-                ctx = Some(new_ctx);
-                my_id = ready.user.id.0;
-                println!("Discord ready");
-            },
-            Packet::DiscordMessage(msg) => {
-                if msg.author.id == my_id {
-                    continue;
-                }
+    pub fn stats_mut(&mut self, name: &str) -> &mut player_stats::PlayerStats {
+        let id = self.players.id(name);
+        self.stats.entry(id).or_default()
+    }
 
-                if msg.channel_id != channel_id {
-                    continue;
-                }
+    // Finds a player's stats key from their name, ignoring case and Floodgate's prefix
+    pub fn find_player(&self, name: &str, bedrock: &bedrock::BedrockNames) -> Option<String> {
+        self.play_times.keys()
+            .find(|id| {
+                let display_name = self.players.display_name(id);
+                display_name.eq_ignore_ascii_case(name) || bedrock.display(display_name).eq_ignore_ascii_case(name)
+            })
+            .cloned()
+    }
 
-                let ctx = match &ctx {
-                    Some(v) => v,
-                    None => continue,
-                };
-
-                if msg.content == "!help" {
-                    say_or_log(channel_id, ctx, "**mc-discord-bot Commands**\n`!help` - lists commands\n`!online` - lists online players\n`!time` - lists hours played").await;
-                } else if msg.content == "!online" {
-                    if players_online.len() == 0 {
-                        say_or_log(channel_id, ctx, "No players online").await;
-                        continue;
-                    }
-
-                    let mut sorted_players: Vec<&String> = players_online.keys().collect();
-                    sorted_players.sort();
-
-                    let mut player_list = "Online players: ".to_string();
-                    for (i, player) in sorted_players.iter().enumerate() {
-                        if i > 0 {
-                            player_list.push_str(", ");
-                        }
-                        player_list.push_str(player);
-                    }
-                    say_or_log(channel_id, ctx, &player_list).await;
-                } else if msg.content == "!time" {
-                    let now = Instant::now();
-                    
-                    // Calculate current play times, taking currently logged in time into account.
-                    // Also compute the longest player name
-                    let mut curr_play_times = Vec::with_capacity(state.play_times.len());
-                    let mut max_player_name = 0;
-                    for (player, play_time) in &state.play_times {
-                        let curr_play_time = match players_online.get(player) {
-                            Some(login_time) => *play_time + (now - *login_time).as_millis(),
-                            None => *play_time,
-                        };
-                        
-                        max_player_name = max_player_name.max(player.len()); 
-                        curr_play_times.push((player, curr_play_time));
-                    }
-
-                    curr_play_times.sort_by_key(|x| x.1);
-                    
-                    let mut player_list = "```Total play time:\n".to_string();
-                    for (player, play_time) in curr_play_times.iter().rev() {
-                        let total_hours = (*play_time as f64) / 3600000.0;
-                        // let days = play_time / ;
-                        let _ = writeln!(&mut player_list, "{player: <max_player_name$} | {total_hours: <6.2} hr");
-                    }
-                    let _ = write!(&mut player_list, "```");
-                    say_or_log(channel_id, ctx, &player_list).await;
-                } else if msg.content.starts_with("!") {
-                    say_or_log(channel_id, ctx, &format!("Unknown command: {}", msg.content)).await;
-                } else {
-                    let stdin = match &mut stdin {
-                        Some(v) => v,
-                        None => continue,
-                    };
-
-                    if let Err(e) = stdin.write(format!("/say {}: {}\r\n", msg.author.name, msg.content_safe(ctx)).as_bytes()).await {
-                        println!("Error writing to stdin {}", e);
-                    }
-                }
+    pub fn profile(&self, id: &str, name: &str, login_time: Option<Instant>) -> player_stats::Profile {
+        let sessions: Vec<&playtime::Session> = self.sessions.iter().filter(|x| x.player == id).collect();
+        let session = login_time.map(|x| x.elapsed().as_millis()).unwrap_or(0);
+        let stats = self.stats.get(id).cloned().unwrap_or_default();
+        player_stats::Profile {
+            name: name.to_string(),
+            play_time: self.play_times.get(id).copied().unwrap_or(0) + session,
+            first_seen: stats.first_seen.or_else(|| sessions.iter().map(|x| x.start).min()),
+            last_seen: match login_time {
+                Some(_) => None,
+                None => sessions.iter().map(|x| x.end).max(),
             },
-            Packet::ProcessStarted(new_stdin) => {
-                stdin = Some(new_stdin);
-                println!("Process started");
+            sessions: sessions.len() + login_time.is_some() as usize,
+            stats,
+        }
+    }
+}
+
+// Answers a query from the REST API
+async fn answer_api(bot: &mut bot::Bot, stats: &stats_tracker::StatsTracker, admin: &server_admin::ServerAdmin, request: api::ApiRequest) {
+    let result = match request.query {
+        api::ApiQuery::Status => {
+            let state = status::ServerState::new(admin.is_updating(), admin.is_restarting(), bot.console.is_some());
+            let metrics = bot.metrics.lock().unwrap();
+            let mut players: Vec<String> = stats.online.keys().map(|x| bot.bedrock.display(x).to_string()).collect();
+            players.sort();
+            Ok(serde_json::json!({
+                "state": state.name(),
+                "uptime_secs": metrics.server_started.map(|x| x.elapsed().as_secs()),
+                "players": players,
+                "tps": metrics.tps,
+                "memory": metrics.memory,
+            }))
+        },
+        api::ApiQuery::Players => {
+            let mut players: Vec<api::PlayerJson> = stats.online.iter()
+                .map(|(name, login_time)| api::PlayerJson { name: bot.bedrock.display(name).to_string(), online_secs: login_time.elapsed().as_secs() })
+                .collect();
+            players.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok(serde_json::json!(players))
+        },
+        // Most played first, including the current sessions
+        api::ApiQuery::PlayTimes => {
+            let mut play_times: Vec<api::PlayTimeJson> = plugin_stats(&stats.state, &stats.online, &bot.bedrock).play_times.into_iter()
+                .map(|(name, millis)| api::PlayTimeJson { name, millis: millis as u64 })
+                .collect();
+            play_times.sort_by(|a, b| b.millis.cmp(&a.millis).then_with(|| a.name.cmp(&b.name)));
+            Ok(serde_json::json!(play_times))
+        },
+        // Queued like commands from Discord, the response is posted in the admin channel
+        api::ApiQuery::Command { user, line } => match bot.ctx.clone() {
+            Some(ctx) => {
+                let audit_entry = audit::AuditEntry::new(format!("{} (API)", user), 0, &line);
+                let task = ConsoleTask::new(bot.admin_channel_id.unwrap_or(bot.channel_id), console_commands::ConsoleCommand::Raw, line, Some(audit_entry));
+                run_console_task(&bot.console, &mut bot.command_queue, &bot.sender, &ctx, task).await
+                    .map(|_| serde_json::json!({ "ok": true }))
+                    .map_err(|e| (api::StatusCode::SERVICE_UNAVAILABLE, e.to_string()))
             },
-            Packet::LogLine(label, content) => {
-                let ctx = match &ctx {
-                    Some(v) => v,
-                    None => continue,
-                };
-
-                match label.as_str() {
-                    // Server startup
-                    "minecraft/DedicatedServer" if content.starts_with("Done") => {
-                        say_or_log(channel_id, ctx, "Server Started").await;
-                    },
-
-                    // Player login
-                    "minecraft/MinecraftServer" if content.ends_with(" joined the game") => {
-                        let name = &content[0..(content.len() - 16)];
-                        let now = Instant::now();
-                        players_online.insert(name.to_string(), now);
-                        let _ = writeln!(&mut debug_log, "{name} Joined: {now:?}");
-
-                        if !state.play_times.contains_key(name) {
-                            state.play_times.insert(name.to_string(), 0);
-                        }
-                        
-                        ctx.set_activity(Activity::playing(
-                            format!("{} Online", players_online.len())
-                        )).await;
-
-                        say_or_log(channel_id, ctx, &format!("{} joined the server", name)).await;
-                    },
-
-                    // Player logout
-                    "minecraft/MinecraftServer" if content.ends_with(" left the game") => {
-                        let name = &content[0..(content.len() - 14)];
-                        if let Some(login_time) = players_online.remove(name) {
-                            // Update play time
-                            let mut play_time = state.play_times.get(name).cloned().unwrap_or(0);
-                            let now = Instant::now();
-                            let dt = now - login_time;
-                            play_time += dt.as_millis();
-                            let _ = writeln!(&mut debug_log, "{name} Left: login time {login_time:?}, logout time {now:?}, dt millis {}, play time {play_time}", dt.as_millis());
-
-                            state.play_times.insert(name.to_string(), play_time);
-                            state.write();
-                        }
-
-                        ctx.set_activity(Activity::playing(
-                            format!("{} Online", players_online.len())
-                        )).await;
-
-                        say_or_log(channel_id, ctx, &format!("{} left the server", name)).await;
-                    },
-
-                    // Chat message
-                    "minecraft/MinecraftServer" if content.starts_with("<") => {
-                        let end_bracket = content.find("> ");
-                        if let Some(end_bracket) = end_bracket {
-                            let user = &content[1..end_bracket];
-                            let msg = &content[(end_bracket + 2)..];
-
-                            if user == "Server" {
-                                continue;
-                            }
-
-                            say_or_log(channel_id, ctx, &format!("{}: {}", user, msg)).await;
-                        } else {
-                            println!("Invalid chat message {}", content);
-                        }
-                    },
-
-                    // Handle misc other messages (eg. PLAYER fell out of the world)
-                    "minecraft/MinecraftServer" => {
-                        for player in players_online.keys() {
-                            if content.starts_with(player) {
-                                say_or_log(channel_id, ctx, &content).await;
-                            }
-                        }
-                    },
-
-                    _ => {},
+            None => Err((api::StatusCode::SERVICE_UNAVAILABLE, "Not connected to Discord".to_string())),
+        },
+        // Admin DM users can use the console, anyone else needs a role the console
+        // is restricted to since it's otherwise only limited by the admin channel
+        api::ApiQuery::Authorize(user_id) => match &bot.ctx {
+            Some(ctx) => {
+                let user_id = serenity::model::id::UserId(user_id);
+                let roles = permissions::guild_member_roles(ctx, bot.channel_id, user_id).await;
+                let trusted = bot.admin_user_ids.contains(&user_id) || bot.permissions.is_restricted("console");
+                if trusted && bot.permissions.is_allowed("console", &roles) {
+                    Ok(serde_json::json!({ "ok": true }))
+                } else {
+                    Err((api::StatusCode::FORBIDDEN, "You don't have console access".to_string()))
                 }
             },
-            Packet::StdinLine(line) => {
-                let stdin = match &mut stdin {
-                    Some(v) => v,
-                    None => continue,
-                };
-                
-                if let Err(e) = stdin.write(line.as_bytes()).await {
-                    println!("Error writing to stdin {}", e);
-                }
+            None => Err((api::StatusCode::SERVICE_UNAVAILABLE, "Not connected to Discord".to_string())),
+        },
+    };
+    let _ = request.reply.send(result);
+}
+
+// Passes each packet to the subsystem that handles it. Subsystems keep their own state and share
+// the bot's, anything they need from each other is sent as another packet.
+async fn handle_packets(mut receiver: mpsc::UnboundedReceiver<Packet>, sender: mpsc::UnboundedSender<Packet>, mut config: ConfigToml, metrics: SharedMetrics, channel_id: ChannelId, admin_channel_id: Option<ChannelId>, log_mirror: Option<log_mirror::LogMirror>) {
+    let mut stats = stats_tracker::StatsTracker::new(&mut config);
+    let mut logs = log_events::LogEvents::new(&mut config);
+    let mut admin = server_admin::ServerAdmin::new(&config);
+    let mut discord = discord_events::DiscordEvents::new(&config);
+    let mut bot = bot::Bot::new(config, sender, metrics, channel_id, admin_channel_id, log_mirror);
+
+    while let Some(packet) = receiver.recv().await {
+        match packet {
+            Packet::DiscordReady(..) | Packet::DiscordMessage(_) | Packet::DiscordComponent(_) | Packet::DiscordMessageUpdate(_) | Packet::DiscordMessageDelete(..)
+                | Packet::CommandTimerElapsed(_) | Packet::PollEnded(_) | Packet::ChannelStatusTick() | Packet::PresenceTick() => {
+                discord.handle(&mut bot, &mut stats, &admin, packet).await;
             },
-            Packet::StopServer() => {
-                let ctx = match &ctx {
-                    Some(v) => v,
-                    None => continue,
-                };
-                
-                say_or_log(channel_id, ctx, "Server Shutdown").await;
-
-                std::process::exit(0);
+            Packet::LogLine(log_line) => logs.handle(&mut bot, &mut stats, &mut admin, log_line).await,
+            Packet::LogMirrorTick() => logs.flush_mirror(&mut bot),
+            Packet::MilestoneTick() => stats.check_milestones(&bot).await,
+            Packet::SummaryReport(period) => stats.send_report(&bot, period).await,
+            Packet::Announcement(index) => announcements::announce(&bot, index).await,
+            Packet::CalendarTick() => calendar::remind(&mut bot).await,
+            Packet::TempBanTick() => tempbans::expire(&mut bot).await,
+            Packet::CrossServerChat(chat) => cross_server::receive(&mut bot, chat).await,
+            Packet::QueryResult(result) => stats.query_result(&mut bot, result).await,
+            Packet::Api(request) => answer_api(&mut bot, &stats, &admin, request).await,
+            Packet::ApiSubscribe(reply) => {
+                let _ = reply.send(bot.events.subscribe());
             },
+            packet => admin.handle(&mut bot, &mut stats, packet).await,
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,124 @@
+// !maintenance on|off. While on the server is whitelist only, non-ops can be kicked, the MOTD and
+// the bot's status say so, join and leave messages aren't posted and only admin users can !start
+// the server. It's kept in a file so it lasts until it's turned off.
+//
+// The server rewrites server.properties from memory whenever the whitelist is toggled, so while it's
+// running the MOTD is only written once it stops.
+
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::properties::Properties;
+use crate::server_files::SERVER_PROPERTIES_PATH;
+
+pub const MAINTENANCE_PATH: &str = "mc-discord-bot-maintenance.json";
+
+const OPS_PATH: &str = "ops.json";
+
+// What the server shows when server.properties doesn't set a MOTD
+const DEFAULT_MOTD: &str = "A Minecraft Server";
+
+#[derive(Deserialize)]
+pub struct MaintenanceToml {
+    #[serde(default = "default_motd")]
+    pub motd: String,
+    // Kicks everyone who isn't an op when maintenance starts
+    #[serde(default)]
+    pub kick_non_ops: bool,
+}
+
+impl Default for MaintenanceToml {
+    fn default() -> MaintenanceToml {
+        MaintenanceToml { motd: default_motd(), kick_non_ops: false }
+    }
+}
+
+fn default_motd() -> String {
+    "Maintenance".to_string()
+}
+
+// What maintenance changed, so it can be put back
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MaintenanceState {
+    pub previous_motd: Option<String>,
+    pub whitelist_was_on: bool,
+}
+
+impl MaintenanceState {
+    pub fn new(properties: &Properties) -> MaintenanceState {
+        MaintenanceState {
+            previous_motd: properties.get("motd").map(|x| x.to_string()),
+            whitelist_was_on: properties.get("white-list") == Some("true"),
+        }
+    }
+
+    pub fn read() -> Option<MaintenanceState> {
+        serde_json::from_str(&std::fs::read_to_string(MAINTENANCE_PATH).ok()?).ok()
+    }
+
+    pub fn write(&self) {
+        let json_str = serde_json::to_string_pretty(self).unwrap();
+        if let Err(e) = std::fs::write(MAINTENANCE_PATH, json_str) {
+            error!("Error writing {}: {}", MAINTENANCE_PATH, e);
+        }
+    }
+
+    pub fn remove() {
+        if let Err(e) = std::fs::remove_file(MAINTENANCE_PATH) {
+            error!("Error removing {}: {}", MAINTENANCE_PATH, e);
+        }
+    }
+
+    pub fn restored_motd(&self) -> &str {
+        self.previous_motd.as_deref().unwrap_or(DEFAULT_MOTD)
+    }
+}
+
+// Writes the MOTD and, when the server isn't running, the whitelist to server.properties
+pub fn write_properties(motd: &str, whitelist: Option<bool>) -> Result<(), String> {
+    let mut properties = Properties::read(SERVER_PROPERTIES_PATH).unwrap_or_else(|_| Properties::parse(""));
+    properties.set("motd", motd);
+    if let Some(whitelist) = whitelist {
+        properties.set("white-list", &whitelist.to_string());
+    }
+    properties.write(SERVER_PROPERTIES_PATH).map_err(|e| format!("Error writing {}: {}", SERVER_PROPERTIES_PATH, e))
+}
+
+// Names of the players in ops.json
+fn parse_ops(json: &str) -> Vec<String> {
+    #[derive(Deserialize)]
+    struct Op {
+        name: String,
+    }
+    serde_json::from_str::<Vec<Op>>(json).map(|x| x.into_iter().map(|x| x.name).collect()).unwrap_or_default()
+}
+
+// The online players who aren't ops
+pub fn non_ops<'a>(online: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let ops = parse_ops(&std::fs::read_to_string(OPS_PATH).unwrap_or_default());
+    filter_non_ops(online, &ops)
+}
+
+fn filter_non_ops<'a>(online: impl Iterator<Item = &'a String>, ops: &[String]) -> Vec<String> {
+    let mut players: Vec<String> = online.filter(|x| !ops.iter().any(|op| op.eq_ignore_ascii_case(x))).cloned().collect();
+    players.sort_unstable();
+    players
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::maintenance::{filter_non_ops, parse_ops, MaintenanceState};
+    use crate::properties::Properties;
+
+    #[test]
+    fn test_maintenance() {
+        let state = MaintenanceState::new(&Properties::parse("motd=Welcome\nwhite-list=true\n"));
+        assert_eq!(state, MaintenanceState { previous_motd: Some("Welcome".to_string()), whitelist_was_on: true });
+        assert_eq!(MaintenanceState::new(&Properties::parse("")).restored_motd(), "A Minecraft Server");
+
+        let ops = parse_ops(r#"[{"uuid": "069a79f4-44e9-4726-a5be-fca90e38aaf5", "name": "Notch", "level": 4, "bypassesPlayerLimit": false}]"#);
+        assert_eq!(ops, vec!["Notch"]);
+        let online = ["Steve".to_string(), "notch".to_string(), "Alex".to_string()];
+        assert_eq!(filter_non_ops(online.iter(), &ops), vec!["Alex", "Steve"]);
+    }
+}
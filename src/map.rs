@@ -0,0 +1,47 @@
+// Links to the server's web map, eg. Dynmap or BlueMap. When player_url is set `!map <player>`
+// links straight to where the player is standing.
+
+use serde::Deserialize;
+
+use crate::console_commands::parse_position;
+
+#[derive(Deserialize)]
+pub struct MapToml {
+    pub url: String,
+    // A link to a position on the map, {x}, {y} and {z} are filled in with the player's block
+    pub player_url: Option<String>,
+}
+
+impl MapToml {
+    // Links to the position in the response to `data get entity <player> Pos`, or to the map
+    // when it can't link to positions
+    pub fn player_link(&self, lines: &[String]) -> Option<String> {
+        let position = lines.iter().find_map(|x| parse_position(x))?.1;
+        let template = match &self.player_url {
+            Some(v) => v,
+            None => return Some(self.url.clone()),
+        };
+
+        let mut link = template.clone();
+        for (placeholder, value) in [("{x}", position[0]), ("{y}", position[1]), ("{z}", position[2])] {
+            link = link.replace(placeholder, &(value.floor() as i64).to_string());
+        }
+        Some(link)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map::MapToml;
+
+    #[test]
+    fn test_player_link() {
+        let lines = vec!["Steve has the following entity data: [12.5d, 64.0d, -30.25d]".to_string()];
+        let mut config: MapToml = toml::from_str("url = 'https://map.example.com'").unwrap();
+        assert_eq!(config.player_link(&lines), Some("https://map.example.com".to_string()));
+
+        config.player_url = Some("https://map.example.com/?worldname=world&x={x}&y={y}&z={z}".to_string());
+        assert_eq!(config.player_link(&lines), Some("https://map.example.com/?worldname=world&x=12&y=64&z=-31".to_string()));
+        assert_eq!(config.player_link(&["No entity was found".to_string()]), None);
+    }
+}
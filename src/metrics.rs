@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+use crate::{Packet, send_or_log};
+
+#[derive(Deserialize)]
+pub struct MetricsToml {
+    pub port: u16,
+
+    // Console command that makes the server log its TPS, eg. "forge tps". TPS is only
+    // reported when this is set or something else causes the server to log it.
+    pub tps_command: Option<String>,
+    #[serde(default = "default_tps_interval_secs")]
+    pub tps_interval_secs: u64,
+}
+
+fn default_tps_interval_secs() -> u64 {
+    60
+}
+
+// Values exported on the metrics endpoint, updated by the packet handler
+pub struct Metrics {
+    pub bot_started: Instant,
+    pub server_started: Option<Instant>,
+    pub pid: Option<u32>,
+    pub players_online: usize,
+    pub restarts: u64,
+    pub tps: Option<f64>,
+    // Ticks the server has reported falling behind by
+    pub skipped_ticks: u64,
+    // As logged when the server starts
+    pub server_version: Option<String>,
+
+    // Updated by the usage sampler
+    pub memory: Option<u64>,
+    pub peak_memory: Option<u64>,
+    pub cpu_percent: Option<f64>,
+    // The latest memory samples, oldest first
+    pub memory_history: VecDeque<u64>,
+}
+
+pub type SharedMetrics = Arc<Mutex<Metrics>>;
+
+impl Metrics {
+    pub fn new() -> SharedMetrics {
+        Arc::new(Mutex::new(Metrics {
+            bot_started: Instant::now(),
+            server_started: None,
+            pid: None,
+            players_online: 0,
+            restarts: 0,
+            tps: None,
+            skipped_ticks: 0,
+            server_version: None,
+            memory: None,
+            peak_memory: None,
+            cpu_percent: None,
+            memory_history: VecDeque::new(),
+        }))
+    }
+
+    // Renders the metrics in the prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            let _ = writeln!(&mut text, "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}");
+        };
+
+        let server_uptime = self.server_started.map(|x| x.elapsed().as_secs()).unwrap_or(0);
+        metric("minecraft_server_up", "gauge", "Whether the server process is running.", (self.server_started.is_some() as u8).to_string());
+        metric("minecraft_players_online", "gauge", "Number of players online.", self.players_online.to_string());
+        metric("minecraft_server_uptime_seconds", "gauge", "Seconds since the server process started.", server_uptime.to_string());
+        metric("minecraft_bot_uptime_seconds", "gauge", "Seconds since the bot started.", self.bot_started.elapsed().as_secs().to_string());
+        metric("minecraft_server_restarts_total", "counter", "Number of restarts performed by the bot.", self.restarts.to_string());
+
+        metric("minecraft_skipped_ticks_total", "counter", "Ticks the server reported falling behind by.", self.skipped_ticks.to_string());
+
+        if let Some(tps) = self.tps {
+            metric("minecraft_tps", "gauge", "Most recently reported mean ticks per second.", format!("{:.3}", tps));
+        }
+
+        if let Some(memory) = self.memory {
+            metric("minecraft_memory_bytes", "gauge", "Resident memory of the server process tree.", memory.to_string());
+        }
+
+        if let Some(cpu_percent) = self.cpu_percent {
+            metric("minecraft_cpu_percent", "gauge", "CPU usage of the server process tree, 100 per fully used core.", format!("{:.1}", cpu_percent));
+        }
+
+        text
+    }
+}
+
+// Parses the overall TPS out of forge's tps report
+// Overall: Mean tick time: 12.345 ms. Mean TPS: 20.000
+pub fn parse_tps(content: &str) -> Option<f64> {
+    if !content.starts_with("Overall") {
+        return None;
+    }
+
+    let tps_start = content.find("Mean TPS: ")? + 10;
+    content[tps_start..].split_whitespace().next()?.parse().ok()
+}
+
+pub fn start_metrics_server(config: &MetricsToml, metrics: SharedMetrics, sender: &mpsc::UnboundedSender<Packet>) {
+    if let Some(tps_command) = &config.tps_command {
+        let sender = sender.clone();
+        let line = format!("{}\r\n", tps_command);
+        let interval = Duration::from_secs(config.tps_interval_secs.max(1));
+        tokio::task::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                send_or_log(&sender, Packet::StdinLine(line.clone()));
+            }
+        });
+    }
+
+    let port = config.port;
+    tokio::task::spawn(async move {
+        let listener = match TcpListener::bind(("0.0.0.0", port)).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Error binding metrics port {}: {}", port, e);
+                return;
+            },
+        };
+        info!("Serving metrics on port {}", port);
+
+        loop {
+            let (mut stream, _) = match listener.accept().await {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("Error accepting metrics connection {}", e);
+                    continue;
+                },
+            };
+
+            let metrics = metrics.clone();
+            tokio::task::spawn(async move {
+                let mut buffer = [0; 1024];
+                let bytes_read = stream.read(&mut buffer).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buffer[..bytes_read]);
+
+                let response = if request.starts_with("GET /metrics ") {
+                    let body = metrics.lock().unwrap().render();
+                    format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+                };
+
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::parse_tps;
+
+    #[test]
+    fn test_parse_tps() {
+        assert_eq!(parse_tps("Overall: Mean tick time: 12.345 ms. Mean TPS: 19.500"), Some(19.5));
+        assert_eq!(parse_tps("Dim minecraft:overworld (minecraft:overworld): Mean tick time: 1.0 ms. Mean TPS: 20.000"), None);
+        assert_eq!(parse_tps("Steve joined the game"), None);
+    }
+}
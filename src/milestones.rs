@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use crate::{Packet, send_or_log};
+
+const HOUR_MILLIS: u128 = 3600000;
+
+// How often online players' play time is checked against the milestones
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+// Announced in the chat channel when a player reaches them. {player} is replaced with the
+// player's name, {hours} with the hours played and {count} with eg. "100th".
+#[derive(Deserialize, Clone)]
+pub struct MilestonesToml {
+    #[serde(default = "default_play_hours")]
+    pub play_hours: Vec<u64>,
+    #[serde(default = "default_logins")]
+    pub logins: Vec<u64>,
+    #[serde(default = "default_play_time_message")]
+    pub play_time_message: String,
+    #[serde(default = "default_login_message")]
+    pub login_message: String,
+}
+
+impl Default for MilestonesToml {
+    fn default() -> Self {
+        MilestonesToml {
+            play_hours: default_play_hours(),
+            logins: default_logins(),
+            play_time_message: default_play_time_message(),
+            login_message: default_login_message(),
+        }
+    }
+}
+
+fn default_play_hours() -> Vec<u64> {
+    vec![10, 50, 100]
+}
+
+fn default_logins() -> Vec<u64> {
+    vec![100]
+}
+
+fn default_play_time_message() -> String {
+    "🎉 {player} has played for {hours} hours!".to_string()
+}
+
+fn default_login_message() -> String {
+    "🎉 {player} has joined for the {count} time!".to_string()
+}
+
+fn ordinal(n: u64) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+pub struct Milestones {
+    config: MilestonesToml,
+    // Play time each online player had when last checked
+    checked: HashMap<String, u128>,
+}
+
+impl Milestones {
+    pub fn new(config: MilestonesToml) -> Milestones {
+        Milestones { config, checked: HashMap::new() }
+    }
+
+    // Starts tracking a player that just joined, returning the announcement if this login is
+    // a milestone. logins includes this one.
+    pub fn joined(&mut self, player: &str, name: &str, play_time: u128, logins: u64) -> Option<String> {
+        self.checked.insert(player.to_string(), play_time);
+        self.config.logins.contains(&logins).then(|| {
+            self.config.login_message.replace("{player}", name).replace("{count}", &ordinal(logins))
+        })
+    }
+
+    // The announcement for the highest milestone the player passed since the last check.
+    // Players that weren't being tracked start being tracked without an announcement.
+    pub fn check(&mut self, player: &str, name: &str, play_time: u128) -> Option<String> {
+        let previous = match self.checked.get_mut(player) {
+            Some(v) => v,
+            None => {
+                self.checked.insert(player.to_string(), play_time);
+                return None;
+            },
+        };
+        let before = *previous;
+        *previous = before.max(play_time);
+
+        let hours = self.config.play_hours.iter()
+            .filter(|x| (before + 1..=play_time).contains(&(**x as u128 * HOUR_MILLIS)))
+            .max()?;
+        Some(self.config.play_time_message.replace("{player}", name).replace("{hours}", &hours.to_string()))
+    }
+
+    pub fn left(&mut self, player: &str, name: &str, play_time: u128) -> Option<String> {
+        let announcement = self.check(player, name, play_time);
+        self.checked.remove(player);
+        announcement
+    }
+}
+
+pub fn start_milestone_timer(sender: &mpsc::UnboundedSender<Packet>) {
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            send_or_log(&sender, Packet::MilestoneTick());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::milestones::{Milestones, MilestonesToml, HOUR_MILLIS};
+
+    #[test]
+    fn test_milestones() {
+        let mut milestones = Milestones::new(MilestonesToml::default());
+        assert_eq!(milestones.joined("Steve", "Steve", 9 * HOUR_MILLIS, 5), None);
+        assert_eq!(milestones.check("Steve", "Steve", 9 * HOUR_MILLIS + 1), None);
+        assert_eq!(milestones.check("Steve", "Steve", 10 * HOUR_MILLIS), Some("🎉 Steve has played for 10 hours!".to_string()));
+        assert_eq!(milestones.left("Steve", "Steve", 10 * HOUR_MILLIS + 1), None);
+
+        assert_eq!(milestones.joined("Alex", "Alex", 0, 100), Some("🎉 Alex has joined for the 100th time!".to_string()));
+        assert_eq!(milestones.check("Alex", "Alex", 60 * HOUR_MILLIS), Some("🎉 Alex has played for 50 hours!".to_string()));
+        assert_eq!(milestones.check("Bob", "Bob", 60 * HOUR_MILLIS), None);
+    }
+}
@@ -0,0 +1,145 @@
+use serde::Deserialize;
+
+const MODRINTH_API: &str = "https://api.modrinth.com/v2";
+const CURSEFORGE_API: &str = "https://api.curseforge.com/v1";
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModpackSource {
+    Modrinth,
+    Curseforge,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ModpackToml {
+    pub source: ModpackSource,
+    // Modrinth project id or slug, or CurseForge project id
+    pub project_id: String,
+    // Required by the CurseForge API
+    pub curseforge_api_key: Option<String>,
+}
+
+// A published version of the modpack
+pub struct PackVersion {
+    pub name: String,
+    // Modrinth file url, or the CurseForge server pack file id which is resolved when installing
+    location: String,
+    // The version's page with its changelog
+    pub page_url: String,
+}
+
+#[derive(Deserialize)]
+struct ModrinthVersion {
+    id: String,
+    version_number: String,
+    files: Vec<ModrinthFile>,
+}
+
+#[derive(Deserialize)]
+struct ModrinthFile {
+    url: String,
+    primary: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CurseforgeFile {
+    id: u64,
+    display_name: String,
+    file_date: String,
+    download_url: Option<String>,
+    server_pack_file_id: Option<u64>,
+    is_server_pack: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct CurseforgeResponse<T> {
+    data: T,
+}
+
+async fn get_json<T: serde::de::DeserializeOwned>(url: &str, config: &ModpackToml) -> Result<T, String> {
+    let client = reqwest::Client::new();
+    let mut request = client.get(url).header("User-Agent", "mc-discord-bot");
+    if config.source == ModpackSource::Curseforge {
+        let api_key = config.curseforge_api_key.as_deref().ok_or("curseforge_api_key is required for CurseForge modpacks")?;
+        request = request.header("x-api-key", api_key);
+    }
+
+    let body = request.send().await
+        .and_then(|x| x.error_for_status())
+        .map_err(|e| format!("Error querying {}: {}", url, e))?
+        .text().await
+        .map_err(|e| format!("Error querying {}: {}", url, e))?;
+
+    serde_json::from_str(&body).map_err(|e| format!("Error parsing response from {}: {}", url, e))
+}
+
+// Lists the modpack's versions that can be installed on a server, newest first
+pub async fn list_versions(config: &ModpackToml) -> Result<Vec<PackVersion>, String> {
+    match config.source {
+        ModpackSource::Modrinth => {
+            let url = format!("{}/project/{}/version", MODRINTH_API, config.project_id);
+            let versions: Vec<ModrinthVersion> = get_json(&url, config).await?;
+            Ok(versions.into_iter()
+                .filter_map(|version| {
+                    let file = version.files.iter().find(|x| x.primary).or(version.files.first())?;
+                    let page_url = format!("https://modrinth.com/project/{}/version/{}", config.project_id, version.id);
+                    Some(PackVersion { name: version.version_number, location: file.url.clone(), page_url })
+                })
+                .collect())
+        },
+        ModpackSource::Curseforge => {
+            let url = format!("{}/mods/{}/files?pageSize=50", CURSEFORGE_API, config.project_id);
+            let response: CurseforgeResponse<Vec<CurseforgeFile>> = get_json(&url, config).await?;
+            let mut files: Vec<CurseforgeFile> = response.data.into_iter()
+                .filter(|x| !x.is_server_pack.unwrap_or(false) && x.server_pack_file_id.is_some())
+                .collect();
+
+            // Dates are ISO 8601 so they sort correctly as strings
+            files.sort_by(|a, b| b.file_date.cmp(&a.file_date));
+            Ok(files.into_iter()
+                .map(|file| PackVersion {
+                    name: file.display_name,
+                    location: file.server_pack_file_id.unwrap_or(0).to_string(),
+                    page_url: format!("https://www.curseforge.com/projects/{}/files/{}", config.project_id, file.id),
+                })
+                .collect())
+        },
+    }
+}
+
+// Finds the named version, or the newest version if no name is given
+pub async fn find_version(config: &ModpackToml, name: Option<&str>) -> Result<PackVersion, String> {
+    let versions = list_versions(config).await?;
+    match name {
+        Some(name) => versions.into_iter().find(|x| x.name == name).ok_or(format!("No version named \"{}\" found", name)),
+        None => versions.into_iter().next().ok_or("No server installable versions found".to_string()),
+    }
+}
+
+// Gets the url the version's server files can be downloaded from
+pub async fn download_url(config: &ModpackToml, version: &PackVersion) -> Result<String, String> {
+    match config.source {
+        ModpackSource::Modrinth => Ok(version.location.clone()),
+        ModpackSource::Curseforge => {
+            let url = format!("{}/mods/{}/files/{}", CURSEFORGE_API, config.project_id, version.location);
+            let response: CurseforgeResponse<CurseforgeFile> = get_json(&url, config).await?;
+            response.data.download_url.ok_or("The server pack doesn't allow third party downloads".to_string())
+        },
+    }
+}
+
+// Works out which url to install from and the version name to record. modpack_path is used
+// directly when no modpack source is configured.
+pub async fn resolve(modpack_path: &str, config: Option<&ModpackToml>, version: Option<&str>) -> Result<(String, Option<String>), String> {
+    match config {
+        Some(config) => {
+            let version = find_version(config, version).await?;
+            let url = download_url(config, &version).await?;
+            Ok((url, Some(version.name)))
+        },
+        None if version.is_some() => Err("Versions can only be chosen when a [modpack] source is configured".to_string()),
+        None if modpack_path.is_empty() => Err("No modpack_path or [modpack] source configured".to_string()),
+        None => Ok((modpack_path.to_string(), None)),
+    }
+}
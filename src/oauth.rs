@@ -0,0 +1,203 @@
+// Discord login for the dashboard. Users are sent to Discord to log in, then the bot looks up
+// their roles in the server and only lets them in if they could use the console from Discord.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use axum::http::HeaderMap;
+use rand::RngCore;
+use serde::Deserialize;
+
+const AUTHORIZE_URL: &str = "https://discord.com/oauth2/authorize";
+const TOKEN_URL: &str = "https://discord.com/api/oauth2/token";
+const USER_URL: &str = "https://discord.com/api/users/@me";
+
+// Logins that take longer than this have to start again
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(600);
+// Logins anyone can start, the oldest are dropped past this so looping on /login can't use up memory
+const MAX_PENDING_LOGINS: usize = 256;
+
+pub const SESSION_COOKIE: &str = "mc_session";
+
+#[derive(Deserialize, Clone)]
+pub struct OAuthToml {
+    pub client_id: String,
+    pub client_secret: String,
+    // Must be added to the application's redirects, eg. http://HOST:PORT/auth/callback
+    pub redirect_url: String,
+    #[serde(default = "default_session_hours")]
+    pub session_hours: u64,
+}
+
+fn default_session_hours() -> u64 {
+    24
+}
+
+pub struct DiscordUser {
+    pub id: u64,
+    pub name: String,
+}
+
+struct Session {
+    user: DiscordUser,
+    expires: Instant,
+}
+
+#[derive(Default)]
+pub struct Sessions {
+    sessions: HashMap<String, Session>,
+    // Login states waiting for Discord to redirect back, so other sites can't log people in.
+    // Oldest first.
+    pending: VecDeque<(String, Instant)>,
+}
+
+fn random_token() -> String {
+    let mut bytes = [0; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+impl Sessions {
+    // Starts a login, returning the state to pass to Discord
+    pub fn start_login(&mut self) -> String {
+        let now = Instant::now();
+        self.pending.retain(|(_, started)| now.duration_since(*started) < LOGIN_TIMEOUT);
+        while self.pending.len() >= MAX_PENDING_LOGINS {
+            self.pending.pop_front();
+        }
+        let state = random_token();
+        self.pending.push_back((state.clone(), now));
+        state
+    }
+
+    pub fn finish_login(&mut self, state: &str) -> bool {
+        match self.pending.iter().position(|(x, _)| x == state) {
+            Some(index) => self.pending.remove(index).is_some_and(|(_, started)| started.elapsed() < LOGIN_TIMEOUT),
+            None => false,
+        }
+    }
+
+    pub fn create(&mut self, user: DiscordUser, length: Duration) -> String {
+        let now = Instant::now();
+        self.sessions.retain(|_, x| x.expires > now);
+        let id = random_token();
+        self.sessions.insert(id.clone(), Session { user, expires: now + length });
+        id
+    }
+
+    // The user logged in with the session cookie, if it hasn't expired
+    pub fn user(&self, headers: &HeaderMap) -> Option<&DiscordUser> {
+        let id = session_cookie(headers)?;
+        self.sessions.get(id).filter(|x| x.expires > Instant::now()).map(|x| &x.user)
+    }
+
+    pub fn remove(&mut self, headers: &HeaderMap) {
+        if let Some(id) = session_cookie(headers) {
+            self.sessions.remove(id);
+        }
+    }
+}
+
+fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers.get_all("cookie").iter()
+        .filter_map(|x| x.to_str().ok())
+        .flat_map(|x| x.split(';'))
+        .filter_map(|x| x.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value)
+}
+
+pub fn set_cookie(config: &OAuthToml, value: &str, max_age_secs: u64) -> String {
+    let secure = if config.redirect_url.starts_with("https://") { "; Secure" } else { "" };
+    format!("{}={}; Max-Age={}; Path=/; HttpOnly; SameSite=Lax{}", SESSION_COOKIE, value, max_age_secs, secure)
+}
+
+pub fn authorize_url(config: &OAuthToml, state: &str) -> Result<String, String> {
+    let url = reqwest::Url::parse_with_params(AUTHORIZE_URL, &[
+        ("client_id", config.client_id.as_str()),
+        ("redirect_uri", config.redirect_url.as_str()),
+        ("response_type", "code"),
+        ("scope", "identify"),
+        ("state", state),
+    ]).map_err(|e| e.to_string())?;
+    Ok(url.to_string())
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct UserResponse {
+    id: String,
+    username: String,
+}
+
+// Swaps the code Discord redirected back with for the user that logged in
+pub async fn fetch_user(config: &OAuthToml, code: &str) -> Result<DiscordUser, String> {
+    let client = reqwest::Client::new();
+    let body = client.post(TOKEN_URL)
+        .form(&[
+            ("client_id", config.client_id.as_str()),
+            ("client_secret", config.client_secret.as_str()),
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", config.redirect_url.as_str()),
+        ])
+        .send().await
+        .and_then(|x| x.error_for_status())
+        .map_err(|e| format!("Error getting token: {}", e))?
+        .text().await
+        .map_err(|e| format!("Error getting token: {}", e))?;
+    let token: TokenResponse = serde_json::from_str(&body).map_err(|e| format!("Error parsing token: {}", e))?;
+
+    let body = client.get(USER_URL)
+        .bearer_auth(&token.access_token)
+        .send().await
+        .and_then(|x| x.error_for_status())
+        .map_err(|e| format!("Error getting user: {}", e))?
+        .text().await
+        .map_err(|e| format!("Error getting user: {}", e))?;
+    let user: UserResponse = serde_json::from_str(&body).map_err(|e| format!("Error parsing user: {}", e))?;
+
+    let id = user.id.parse().map_err(|_| format!("Invalid user id {}", user.id))?;
+    Ok(DiscordUser { id, name: user.username })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::HeaderMap;
+
+    use crate::oauth::{DiscordUser, Sessions, MAX_PENDING_LOGINS, SESSION_COOKIE};
+
+    #[test]
+    fn test_sessions() {
+        let mut sessions = Sessions::default();
+        let state = sessions.start_login();
+        assert!(sessions.finish_login(&state));
+        assert!(!sessions.finish_login(&state));
+
+        let id = sessions.create(DiscordUser { id: 1, name: "Steve".to_string() }, Duration::from_secs(60));
+        let mut headers = HeaderMap::new();
+        assert!(sessions.user(&headers).is_none());
+        headers.insert("cookie", format!("theme=dark; {}={}", SESSION_COOKIE, id).parse().unwrap());
+        assert_eq!(sessions.user(&headers).map(|x| (x.id, x.name.as_str())), Some((1, "Steve")));
+
+        sessions.remove(&headers);
+        assert!(sessions.user(&headers).is_none());
+    }
+
+    #[test]
+    fn test_pending_logins_capped() {
+        let mut sessions = Sessions::default();
+        let first = sessions.start_login();
+        for _ in 0..MAX_PENDING_LOGINS {
+            sessions.start_login();
+        }
+        assert_eq!(sessions.pending.len(), MAX_PENDING_LOGINS);
+        assert!(!sessions.finish_login(&first));
+    }
+}
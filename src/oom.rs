@@ -0,0 +1,163 @@
+// Reports an OutOfMemoryError in the log to the admin channel with how memory use got there,
+// the JVM's heap stats from jcmd and optionally a heap dump. A larger -Xmx can be suggested or
+// written to user_jvm_args.txt so the next start has more room.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::Local;
+use serde::Deserialize;
+use serenity::model::id::ChannelId;
+use serenity::prelude::*;
+use tokio::process::Command;
+
+use crate::jvm_args::JVM_ARGS_PATH;
+use crate::usage::{format_bytes, parse_max_heap};
+use crate::{response, say_or_log};
+
+pub const HEAP_DUMP_DIR: &str = "heapdumps";
+
+const JCMD_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Deserialize, Default)]
+pub struct OomToml {
+    // Runs `jcmd <pid> GC.heap_dump` into heapdumps/, these are as large as the heap
+    #[serde(default)]
+    pub heap_dump: bool,
+    // The -Xmx suggested after an OutOfMemoryError, eg. "16G"
+    pub bump_heap_to: Option<String>,
+    // Writes bump_heap_to into user_jvm_args.txt instead of only suggesting it
+    #[serde(default)]
+    pub apply_bump: bool,
+}
+
+// How an OutOfMemoryError starts when it's logged on its own or in a stack trace. Only the start
+// of a line counts so chat like "<Steve> java.lang.OutOfMemoryError" can't trigger a report.
+const OOM_PREFIXES: &[&str] = &["java.lang.OutOfMemoryError", "Caused by: java.lang.OutOfMemoryError", "Exception in thread "];
+
+// "java.lang.OutOfMemoryError: Java heap space"
+pub fn is_oom(line: &str) -> bool {
+    let line = line.trim_start();
+    OOM_PREFIXES.iter().any(|x| line.starts_with(x)) && line.contains("java.lang.OutOfMemoryError")
+}
+
+// Summarises the samples the usage sampler took, oldest first
+pub fn describe_trend(samples: &[u64], interval_secs: u64, max_heap: Option<u64>) -> Option<String> {
+    let (first, last) = (samples.first()?, samples.last()?);
+    let peak = samples.iter().max()?;
+    let minutes = (samples.len() as u64 * interval_secs).div_ceil(60);
+    let mut trend = format!("Memory over the last {} minutes went from {} to {}, peaking at {}", minutes, format_bytes(*first), format_bytes(*last), format_bytes(*peak));
+    if let Some(max_heap) = max_heap {
+        trend.push_str(&format!(" of the {} max heap", format_bytes(max_heap)));
+    }
+    Some(trend)
+}
+
+// Replaces -Xmx with heap_size, and -Xms too when it was the same so the heap stays fixed
+pub fn bump_heap(jvm_args: &str, heap_size: &str) -> String {
+    let old_max = jvm_args.split_whitespace().rev().find_map(|x| x.strip_prefix("-Xmx"));
+    let replace = |arg: &str| match arg.starts_with("-Xmx") || (old_max.is_some() && arg.strip_prefix("-Xms") == old_max) {
+        true => format!("{}{}", &arg[..4], heap_size),
+        false => arg.to_string(),
+    };
+    let lines: Vec<String> = jvm_args.lines().map(|line| line.split(' ').map(replace).collect::<Vec<String>>().join(" ")).collect();
+
+    let mut contents = lines.join("\n");
+    if old_max.is_none() {
+        contents = match contents.is_empty() {
+            true => format!("-Xmx{}", heap_size),
+            false => format!("-Xmx{}\n{}", heap_size, contents),
+        };
+    }
+    if jvm_args.ends_with('\n') || jvm_args.is_empty() {
+        contents.push('\n');
+    }
+    contents
+}
+
+// Suggests or applies the configured -Xmx, the message says which
+pub fn handle_heap_bump(config: &OomToml) -> String {
+    let current = std::fs::read_to_string(JVM_ARGS_PATH).ok();
+    let current_max = current.as_deref().and_then(parse_max_heap);
+    let bump = match &config.bump_heap_to {
+        Some(v) => v,
+        None => return format!("Consider raising -Xmx in {} or set [oom] bump_heap_to", JVM_ARGS_PATH),
+    };
+    let bigger = match (parse_max_heap(&format!("-Xmx{}", bump)), current_max) {
+        (Some(bump), Some(current)) => bump > current,
+        _ => true,
+    };
+    if !bigger {
+        return format!("-Xmx is already at least {}, the server may need more memory than that or have a leak", bump);
+    }
+    if !config.apply_bump {
+        return format!("Consider raising -Xmx to {} in {}", bump, JVM_ARGS_PATH);
+    }
+    let contents = bump_heap(current.as_deref().unwrap_or(""), bump);
+    match std::fs::write(JVM_ARGS_PATH, contents) {
+        Ok(_) => format!("Raised -Xmx to {} in {}, it applies from the next start", bump, JVM_ARGS_PATH),
+        Err(e) => format!("Error writing {}: {}", JVM_ARGS_PATH, e),
+    }
+}
+
+async fn jcmd(pid: u32, args: &[&str]) -> Result<String, String> {
+    let output = tokio::time::timeout(JCMD_TIMEOUT, Command::new("jcmd").arg(pid.to_string()).args(args).output()).await
+        .map_err(|_| "jcmd didn't finish in time".to_string())?
+        .map_err(|e| format!("Couldn't run jcmd: {}", e))?;
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        false => Err(format!("jcmd failed: {}", String::from_utf8_lossy(&output.stderr).trim())),
+    }
+}
+
+// Collects the heap stats, and a heap dump when enabled, from another task since a dump can
+// take minutes. The JVM may already be gone, in which case only the error is posted.
+pub fn collect(ctx: &Context, channel_id: ChannelId, pid: u32, heap_dump: bool, attachment_threshold: usize) {
+    let ctx = ctx.clone();
+    tokio::task::spawn(async move {
+        match jcmd(pid, &["GC.heap_info"]).await {
+            Ok(text) => response::send_code_block(channel_id, &ctx, "GC.heap_info", &text, attachment_threshold).await,
+            Err(e) => say_or_log(channel_id, &ctx, &e).await,
+        }
+        if !heap_dump {
+            return;
+        }
+
+        if let Err(e) = std::fs::create_dir_all(HEAP_DUMP_DIR) {
+            return say_or_log(channel_id, &ctx, &format!("Error creating {}: {}", HEAP_DUMP_DIR, e)).await;
+        }
+        // jcmd resolves relative paths from the server's directory, which may not be the bot's
+        let dir = std::fs::canonicalize(HEAP_DUMP_DIR).unwrap_or_else(|_| PathBuf::from(HEAP_DUMP_DIR));
+        let path = dir.join(format!("heap-{}.hprof", Local::now().format("%Y%m%d-%H%M%S")));
+        let message = match jcmd(pid, &["GC.heap_dump", &path.to_string_lossy()]).await {
+            Ok(_) => format!("Heap dump written to {}", path.display()),
+            Err(e) => format!("Heap dump failed: {}", e),
+        };
+        say_or_log(channel_id, &ctx, &message).await;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::oom::{bump_heap, describe_trend, is_oom};
+
+    #[test]
+    fn test_oom() {
+        assert!(is_oom("Exception in thread \"Server thread\" java.lang.OutOfMemoryError: Java heap space"));
+        assert!(is_oom("java.lang.OutOfMemoryError: Java heap space"));
+        assert!(is_oom("\tCaused by: java.lang.OutOfMemoryError: Metaspace"));
+        assert!(!is_oom("Can't keep up! Is the server overloaded?"));
+        assert!(!is_oom("<Steve> java.lang.OutOfMemoryError"));
+        assert!(!is_oom("[Not Secure] <Steve> Exception in thread \"main\" java.lang.OutOfMemoryError"));
+
+        let gib = 1024 * 1024 * 1024;
+        assert_eq!(describe_trend(&[8 * gib, 12 * gib, 11 * gib], 60, Some(12 * gib)).as_deref(),
+            Some("Memory over the last 3 minutes went from 8.00 GiB to 11.00 GiB, peaking at 12.00 GiB of the 12.00 GiB max heap"));
+        assert_eq!(describe_trend(&[], 10, None), None);
+
+        assert_eq!(bump_heap("-Xms12G\n-Xmx12G\n-XX:+UseG1GC\n", "16G"), "-Xms16G\n-Xmx16G\n-XX:+UseG1GC\n");
+        assert_eq!(bump_heap("-Xms4G -Xmx12G -XX:+UseG1GC", "16G"), "-Xms4G -Xmx16G -XX:+UseG1GC");
+        assert_eq!(bump_heap("-XX:+UseG1GC\n", "16G"), "-Xmx16G\n-XX:+UseG1GC\n");
+        assert_eq!(bump_heap("", "16G"), "-Xmx16G\n");
+    }
+}
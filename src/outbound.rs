@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use serenity::http::error::Error as HttpError;
+use serenity::model::id::ChannelId;
+use serenity::prelude::*;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+// Lines that arrive within this long of the first one are sent as one message
+const BATCH_WINDOW: Duration = Duration::from_millis(750);
+
+// Discord's limit on message length
+const MAX_MESSAGE_LEN: usize = 2000;
+
+// Sends that are rate limited are retried this many times, waiting longer each time
+const RATE_LIMIT_RETRIES: u32 = 3;
+const RATE_LIMIT_DELAY: Duration = Duration::from_secs(2);
+
+// Queues game chat, deaths and advancements for the chat channel. Bursts of lines, eg. during
+// join floods, are batched into as few messages as possible and sent one at a time from their
+// own task so the packet loop isn't held up.
+#[derive(Clone)]
+pub struct OutboundQueue {
+    sender: mpsc::UnboundedSender<(ChannelId, String)>,
+}
+
+impl OutboundQueue {
+    pub fn start(ctx: Context) -> OutboundQueue {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<(ChannelId, String)>();
+        tokio::task::spawn(async move {
+            let mut pending: VecDeque<(ChannelId, String)> = VecDeque::new();
+            while let Some(line) = receiver.recv().await {
+                pending.push_back(line);
+
+                // Wait for the rest of the burst, a steady stream is still sent every BATCH_WINDOW
+                let deadline = tokio::time::Instant::now() + BATCH_WINDOW;
+                while let Ok(Some(line)) = tokio::time::timeout_at(deadline, receiver.recv()).await {
+                    pending.push_back(line);
+                }
+
+                while let Some((channel_id, text)) = take_batch(&mut pending) {
+                    send(channel_id, &ctx, &text).await;
+                }
+            }
+        });
+        OutboundQueue { sender }
+    }
+
+    pub fn say(&self, channel_id: ChannelId, text: &str) {
+        if self.sender.send((channel_id, text.to_string())).is_err() {
+            error!("Error queueing message");
+        }
+    }
+}
+
+// Joins the lines at the front of the queue that are for the same channel, up to discord's
+// message length. Lines that are too long on their own are cut short.
+fn take_batch(pending: &mut VecDeque<(ChannelId, String)>) -> Option<(ChannelId, String)> {
+    let (channel_id, first) = pending.pop_front()?;
+    let mut text: String = first.chars().take(MAX_MESSAGE_LEN).collect();
+
+    while let Some((next_channel_id, next)) = pending.front() {
+        if *next_channel_id != channel_id || text.len() + 1 + next.len() > MAX_MESSAGE_LEN {
+            break;
+        }
+        text.push('\n');
+        text.push_str(next);
+        pending.pop_front();
+    }
+    Some((channel_id, text))
+}
+
+fn is_rate_limited(e: &SerenityError) -> bool {
+    matches!(e, SerenityError::Http(e) if matches!(e.as_ref(), HttpError::UnsuccessfulRequest(response) if response.status_code.as_u16() == 429))
+}
+
+// serenity already waits out rate limits it knows about, this covers the ones it doesn't
+async fn send(channel_id: ChannelId, ctx: &Context, text: &str) {
+    for attempt in 0..=RATE_LIMIT_RETRIES {
+        match channel_id.say(&ctx.http, text).await {
+            Ok(_) => return,
+            Err(e) if is_rate_limited(&e) && attempt < RATE_LIMIT_RETRIES => {
+                warn!("Rate limited sending message, retrying");
+                tokio::time::sleep(RATE_LIMIT_DELAY * (attempt + 1)).await;
+            },
+            Err(e) => {
+                error!("Error sending message: {:?}", e);
+                return;
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use serenity::model::id::ChannelId;
+
+    use crate::outbound::{take_batch, MAX_MESSAGE_LEN};
+
+    #[test]
+    fn test_take_batch() {
+        let (chat, admin) = (ChannelId(1), ChannelId(2));
+        let mut pending = VecDeque::from([
+            (chat, "Steve joined".to_string()),
+            (chat, "Alex joined".to_string()),
+            (admin, "Alert".to_string()),
+            (chat, "x".repeat(MAX_MESSAGE_LEN)),
+            (chat, "Steve: hi".to_string()),
+        ]);
+
+        assert_eq!(take_batch(&mut pending), Some((chat, "Steve joined\nAlex joined".to_string())));
+        assert_eq!(take_batch(&mut pending), Some((admin, "Alert".to_string())));
+        assert_eq!(take_batch(&mut pending).unwrap().1.len(), MAX_MESSAGE_LEN);
+        assert_eq!(take_batch(&mut pending), Some((chat, "Steve: hi".to_string())));
+        assert_eq!(take_batch(&mut pending), None);
+    }
+}
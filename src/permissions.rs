@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use serenity::model::channel::{Channel, Message};
+use serenity::model::id::{ChannelId, RoleId, UserId};
+use serenity::prelude::*;
+use tracing::{error, warn};
+
+use crate::commands::COMMANDS;
+
+// Permissions that aren't commands. "chat" covers relaying game chat channel messages into
+// the game, "console" covers raw stdin passthrough from the admin channel.
+const MESSAGE_PERMISSIONS: &[&str] = &["chat", "console"];
+
+// Names of every permission that can be restricted in the [permissions] config table
+pub fn permission_names() -> Vec<&'static str> {
+    let mut names: Vec<&str> = COMMANDS.iter().map(|x| x.permission).collect();
+    names.extend(MESSAGE_PERMISSIONS);
+    names
+}
+
+// Maps permission names to the roles that are allowed to use them. Permissions that have
+// no entry are unrestricted, an entry with an empty role list denies everyone.
+pub struct Permissions {
+    roles: HashMap<String, Vec<RoleId>>,
+}
+
+impl Permissions {
+    pub fn new(config: &HashMap<String, Vec<String>>) -> Permissions {
+        let names = permission_names();
+        let mut roles = HashMap::new();
+        for (name, role_ids) in config {
+            if !names.contains(&name.as_str()) {
+                warn!("Unknown permission \"{}\" in config", name);
+            }
+
+            let mut parsed = Vec::with_capacity(role_ids.len());
+            for role_id in role_ids {
+                match role_id.parse::<u64>() {
+                    Ok(v) => parsed.push(RoleId(v)),
+                    Err(_) => warn!("Invalid role id \"{}\" for permission \"{}\"", role_id, name),
+                }
+            }
+
+            roles.insert(name.clone(), parsed);
+        }
+
+        Permissions { roles }
+    }
+
+    pub fn is_allowed(&self, name: &str, member_roles: &[RoleId]) -> bool {
+        match self.roles.get(name) {
+            Some(allowed) => allowed.iter().any(|role| member_roles.contains(role)),
+            None => true,
+        }
+    }
+
+    // Whether the permission is limited to some roles, rather than open to everyone
+    pub fn is_restricted(&self, name: &str) -> bool {
+        self.roles.contains_key(name)
+    }
+
+    // Builds the `!perms` reply, listing each permission and whether the given roles grant it
+    pub fn describe(&self, member_roles: &[RoleId]) -> String {
+        let mut description = "**Effective permissions**\n".to_string();
+        for name in permission_names() {
+            let allowed = if self.is_allowed(name, member_roles) { "allowed" } else { "denied" };
+            let _ = match self.roles.get(name) {
+                Some(roles) if roles.is_empty() => writeln!(&mut description, "`{name}` - {allowed} (disabled for everyone)"),
+                Some(roles) => {
+                    let mentions: Vec<String> = roles.iter().map(|role| format!("<@&{}>", role.0)).collect();
+                    writeln!(&mut description, "`{name}` - {allowed} (requires {})", mentions.join(", "))
+                },
+                None => writeln!(&mut description, "`{name}` - {allowed} (unrestricted)"),
+            };
+        }
+        description
+    }
+}
+
+// Roles of the message author, empty for direct messages
+pub fn member_roles(msg: &Message) -> &[RoleId] {
+    match &msg.member {
+        Some(member) => &member.roles,
+        None => &[],
+    }
+}
+
+// Users that can run admin commands by messaging the bot directly
+pub fn parse_user_ids(user_ids: &[String]) -> Vec<UserId> {
+    user_ids.iter()
+        .filter_map(|user_id| match user_id.parse::<u64>() {
+            Ok(v) => Some(UserId(v)),
+            Err(_) => {
+                warn!("Invalid admin user id \"{}\"", user_id);
+                None
+            },
+        })
+        .collect()
+}
+
+// Direct messages don't include the author's roles, so they're looked up in the guild that
+// channel_id is in
+pub async fn guild_member_roles(ctx: &Context, channel_id: ChannelId, user_id: UserId) -> Vec<RoleId> {
+    let guild_id = match channel_id.to_channel(ctx).await {
+        Ok(Channel::Guild(channel)) => channel.guild_id,
+        Ok(_) => return Vec::new(),
+        Err(e) => {
+            error!("Error getting channel: {:?}", e);
+            return Vec::new();
+        },
+    };
+
+    match guild_id.member(ctx, user_id).await {
+        Ok(member) => member.roles,
+        Err(e) => {
+            error!("Error getting member {}: {:?}", user_id, e);
+            Vec::new()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use serenity::model::id::{RoleId, UserId};
+    use crate::permissions::{parse_user_ids, Permissions};
+
+    #[test]
+    fn test_is_allowed() {
+        let mut config = HashMap::new();
+        config.insert("console".to_string(), vec!["10".to_string(), "20".to_string()]);
+        config.insert("chat".to_string(), vec![]);
+        let permissions = Permissions::new(&config);
+
+        assert!(permissions.is_allowed("online", &[]));
+        assert!(permissions.is_allowed("console", &[RoleId(20)]));
+        assert!(!permissions.is_allowed("console", &[RoleId(30)]));
+        assert!(!permissions.is_allowed("console", &[]));
+        assert!(!permissions.is_allowed("chat", &[RoleId(10)]));
+        assert!(permissions.is_restricted("console"));
+        assert!(!permissions.is_restricted("online"));
+
+        assert_eq!(parse_user_ids(&["123".to_string(), "someone".to_string()]), vec![UserId(123)]);
+    }
+}
@@ -0,0 +1,48 @@
+// The parts of running the server that differ between unix and windows. Forge and NeoForge
+// installers generate run.sh and run.bat, the bot starts whichever one the platform uses.
+
+use tokio::process::Command;
+
+// Windows' CREATE_NEW_PROCESS_GROUP
+#[cfg(windows)]
+const CREATE_NEW_PROCESS_GROUP: u32 = 0x00000200;
+
+// The program and arguments that start the server
+#[cfg(unix)]
+pub fn run_script() -> (&'static str, Vec<String>) {
+    ("./run.sh", Vec::new())
+}
+
+#[cfg(not(unix))]
+pub fn run_script() -> (&'static str, Vec<String>) {
+    ("cmd", vec!["/C".to_string(), "run.bat".to_string()])
+}
+
+// Runs command with sh -c, or cmd /C on windows
+pub fn shell(command: &str) -> Command {
+    #[cfg(unix)]
+    let mut cmd = Command::new("sh");
+    #[cfg(unix)]
+    cmd.arg("-c");
+    #[cfg(not(unix))]
+    let mut cmd = Command::new("cmd");
+    #[cfg(not(unix))]
+    cmd.arg("/C");
+
+    cmd.arg(command);
+    cmd
+}
+
+// Gives the server its own process group so it can be killed along with the run script, this
+// also stops ctrl-c reaching it so the bot can shut it down cleanly instead
+pub fn new_process_group(cmd: &mut Command) {
+    #[cfg(unix)]
+    unsafe {
+        cmd.pre_exec(|| {
+            libc::setpgid(0, 0);
+            Ok(())
+        });
+    }
+    #[cfg(windows)]
+    cmd.creation_flags(CREATE_NEW_PROCESS_GROUP);
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+
+// Play time and other stats are kept by UUID so they follow a player through name changes.
+// Players whose UUID hasn't been logged yet, eg. on servers that don't log it, are kept by
+// name instead.
+#[derive(Serialize, Deserialize, Default)]
+pub struct PlayerIds {
+    // Names each UUID has been seen with, oldest first
+    history: HashMap<String, Vec<String>>,
+    // The UUID that last logged in with each name
+    uuids: HashMap<String, String>,
+}
+
+impl PlayerIds {
+    // Records that uuid is logging in as name, returning the name it had before if it changed
+    pub fn record(&mut self, uuid: &str, name: &str) -> Option<String> {
+        self.uuids.insert(name.to_string(), uuid.to_string());
+
+        let names = self.history.entry(uuid.to_string()).or_default();
+        let previous = names.last().filter(|x| *x != name).cloned();
+        if names.last().map(|x| x.as_str()) != Some(name) {
+            names.retain(|x| x != name);
+            names.push(name.to_string());
+        }
+        previous
+    }
+
+    // The key a player's stats are kept under
+    pub fn id(&self, name: &str) -> String {
+        self.uuids.get(name).cloned().unwrap_or_else(|| name.to_string())
+    }
+
+    // The player's current name for a stats key
+    pub fn display_name<'a>(&'a self, id: &'a str) -> &'a str {
+        self.history.get(id).and_then(|x| x.last()).map(|x| x.as_str()).unwrap_or(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::player_ids::PlayerIds;
+
+    #[test]
+    fn test_player_ids() {
+        let mut ids = PlayerIds::default();
+        let uuid = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+        assert_eq!(ids.id("Steve"), "Steve");
+        assert_eq!(ids.record(uuid, "Steve"), None);
+        assert_eq!(ids.record(uuid, "Steve"), None);
+        assert_eq!(ids.id("Steve"), uuid);
+
+        assert_eq!(ids.record(uuid, "Steve2"), Some("Steve".to_string()));
+        assert_eq!(ids.display_name(uuid), "Steve2");
+        assert_eq!(ids.display_name("Alex"), "Alex");
+    }
+}
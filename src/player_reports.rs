@@ -0,0 +1,195 @@
+// `!report <player> <reason>` in game posts the report to the admin channel with buttons to
+// warn, kick or ban the player. The buttons do the same as !warn, !kick and !ban, and are recorded
+// in the audit log.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serenity::model::id::MessageId;
+use serenity::model::interactions::message_component::{ButtonStyle, MessageComponentInteraction};
+use tracing::error;
+
+use crate::audit::AuditEntry;
+use crate::bot::Bot;
+use crate::command_queue::ConsoleTask;
+use crate::console_commands::{self, ConsoleCommand};
+use crate::{confirm, run_console_task, warnings};
+
+// custom_id of the report buttons is this followed by the action
+const BUTTON_PREFIX: &str = "report_";
+
+// How long a player waits between reports
+const REPORT_COOLDOWN: Duration = Duration::from_secs(60);
+
+const REPORT_COLOUR: u32 = 0xE67E22;
+
+pub const USAGE: &str = "Usage: !report <player> <reason>";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReportAction {
+    Warn,
+    Kick,
+    Ban,
+}
+
+impl ReportAction {
+    const ALL: [ReportAction; 3] = [ReportAction::Warn, ReportAction::Kick, ReportAction::Ban];
+
+    fn name(&self) -> &'static str {
+        match self {
+            ReportAction::Warn => "warn",
+            ReportAction::Kick => "kick",
+            ReportAction::Ban => "ban",
+        }
+    }
+
+    // The buttons need the same permission as the command
+    pub fn permission(&self) -> &'static str {
+        self.name()
+    }
+
+    fn past_tense(&self) -> &'static str {
+        match self {
+            ReportAction::Warn => "warned",
+            ReportAction::Kick => "kicked",
+            ReportAction::Ban => "banned",
+        }
+    }
+}
+
+pub fn button_action(custom_id: &str) -> Option<ReportAction> {
+    let name = custom_id.strip_prefix(BUTTON_PREFIX)?;
+    ReportAction::ALL.into_iter().find(|x| x.name() == name)
+}
+
+// Splits "!report" arguments into the player and reason, None if either is missing or the
+// player's name isn't valid
+pub fn parse(args: &str) -> Option<(String, String)> {
+    let (player, reason) = args.trim().split_once(' ')?;
+    let reason = reason.split_whitespace().collect::<Vec<&str>>().join(" ");
+    (console_commands::is_valid_player(player) && !reason.is_empty()).then(|| (player.to_string(), reason))
+}
+
+pub struct Report {
+    pub reporter: String,
+    pub player: String,
+    pub reason: String,
+}
+
+// Reports waiting for an admin, keyed by their message in the admin channel
+#[derive(Default)]
+pub struct Reports {
+    open: HashMap<MessageId, Report>,
+    last_report: HashMap<String, Instant>,
+}
+
+impl Reports {
+    // Returns the seconds the reporter has to wait, if any
+    fn cooldown(&mut self, reporter: &str, now: Instant) -> Option<u64> {
+        if let Some(last) = self.last_report.get(reporter).filter(|x| now.duration_since(**x) < REPORT_COOLDOWN) {
+            return Some((REPORT_COOLDOWN - now.duration_since(*last)).as_secs().max(1));
+        }
+        self.last_report.insert(reporter.to_string(), now);
+        None
+    }
+}
+
+// Posts the report to the admin channel, returning the reply for the reporter
+pub async fn send_report(bot: &mut Bot, reporter: &str, player: String, reason: String) -> String {
+    let (ctx, admin_channel_id) = match (&bot.ctx, bot.admin_channel_id) {
+        (Some(ctx), Some(admin_channel_id)) => (ctx, admin_channel_id),
+        _ => return "Reports aren't set up on this server".to_string(),
+    };
+    if player.eq_ignore_ascii_case(reporter) {
+        return "You can't report yourself".to_string();
+    }
+    if let Some(secs) = bot.player_reports.cooldown(reporter, Instant::now()) {
+        return format!("You can report again in {} seconds", secs);
+    }
+
+    let result = admin_channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title(format!("{} was reported", player))
+                .colour(REPORT_COLOUR)
+                .field("Reported by", reporter, true)
+                .field("Reason", &reason, false)
+        }).components(|c| c.create_action_row(|row| {
+            for action in ReportAction::ALL {
+                let style = if action == ReportAction::Warn { ButtonStyle::Secondary } else { ButtonStyle::Danger };
+                let label = format!("{}{}", action.name()[..1].to_uppercase(), &action.name()[1..]);
+                row.create_button(|b| b.custom_id(format!("{}{}", BUTTON_PREFIX, action.name())).label(label).style(style));
+            }
+            row
+        }))
+    }).await;
+
+    match result {
+        Ok(message) => {
+            bot.player_reports.open.insert(message.id, Report { reporter: reporter.to_string(), player, reason });
+            "Your report has been sent to the moderators".to_string()
+        },
+        Err(e) => {
+            error!("Error sending message: {:?}", e);
+            "Your report couldn't be sent, try again later".to_string()
+        },
+    }
+}
+
+// Runs the action for a report button press, the permission is the same as the discord command's
+pub async fn button_pressed(bot: &mut Bot, component: &MessageComponentInteraction, action: ReportAction, allowed: bool) {
+    let ctx = match &bot.ctx {
+        Some(v) => v.clone(),
+        None => return,
+    };
+    if !allowed {
+        return confirm::respond(component, &ctx, &format!("You don't have permission to use `{}`", action.permission()), false).await;
+    }
+    let report = match bot.player_reports.open.remove(&component.message.id) {
+        Some(v) => v,
+        None => return confirm::respond(component, &ctx, "This report has already been handled", false).await,
+    };
+
+    let text = format!("{} was {} by {} after a report by {}", report.player, action.past_tense(), component.user.tag(), report.reporter);
+    confirm::respond(component, &ctx, &text, true).await;
+
+    let reason = format!("Reported by {}: {}", report.reporter, report.reason);
+    match action {
+        ReportAction::Warn => {
+            let reply = warnings::warn(bot, component.channel_id, &report.player, &reason, component.user.tag(), component.user.id.0).await;
+            bot.say(component.channel_id, &reply).await;
+        },
+        ReportAction::Kick | ReportAction::Ban => {
+            let command = if action == ReportAction::Kick { ConsoleCommand::Kick } else { ConsoleCommand::Ban };
+            let console_line = format!("{} {} {}", action.name(), report.player, reason);
+            let audit_entry = AuditEntry::new(component.user.tag(), component.user.id.0, &console_line);
+            let task = ConsoleTask::new(component.channel_id, command, console_line, Some(audit_entry));
+            if let Err(e) = run_console_task(&bot.console, &mut bot.command_queue, &bot.sender, &ctx, task).await {
+                bot.say(component.channel_id, e).await;
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::player_reports::{button_action, parse, ReportAction, Reports};
+
+    #[test]
+    fn test_player_reports() {
+        assert_eq!(parse("Steve  griefing my\nhouse"), Some(("Steve".to_string(), "griefing my house".to_string())));
+        assert_eq!(parse("Steve"), None);
+        assert_eq!(parse("Steve; griefing"), None);
+
+        assert_eq!(button_action("report_kick"), Some(ReportAction::Kick));
+        assert_eq!(button_action("report_op"), None);
+        assert_eq!(button_action("confirm"), None);
+
+        let mut reports = Reports::default();
+        let now = Instant::now();
+        assert_eq!(reports.cooldown("Alex", now), None);
+        assert_eq!(reports.cooldown("Alex", now + Duration::from_secs(20)), Some(40));
+        assert_eq!(reports.cooldown("Alex", now + Duration::from_secs(61)), None);
+    }
+}
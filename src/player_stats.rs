@@ -0,0 +1,137 @@
+use std::fmt::Write;
+
+use chrono::{Local, TimeZone};
+use serde::{Serialize, Deserialize};
+use serenity::model::id::ChannelId;
+use serenity::prelude::*;
+use tracing::error;
+
+use crate::join_messages::avatar_url;
+use crate::playtime::{format_duration, MEDALS};
+
+// Counted for each player alongside their play time and sessions
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq, Eq)]
+pub struct PlayerStats {
+    // Unix time in milliseconds, players from before this was kept use their first session
+    #[serde(default)]
+    pub first_seen: Option<u64>,
+    #[serde(default)]
+    pub deaths: u64,
+    #[serde(default)]
+    pub messages: u64,
+    // The most recent death message without the player's name, eg. "was slain by Zombie"
+    #[serde(default)]
+    pub last_death: Option<String>,
+}
+
+impl PlayerStats {
+    // Adds another set of stats for the same player, keeping the earliest first seen
+    pub fn merge(&mut self, other: &PlayerStats) {
+        self.first_seen = match (self.first_seen, other.first_seen) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.deaths += other.deaths;
+        self.messages += other.messages;
+        if self.last_death.is_none() {
+            self.last_death = other.last_death.clone();
+        }
+    }
+
+    pub fn record_death(&mut self, player: &str, message: &str) {
+        self.deaths += 1;
+        self.last_death = Some(message.strip_prefix(player).unwrap_or(message).trim().to_string());
+    }
+}
+
+// Players with the most deaths first, each with how they last died
+pub fn format_deaths(deaths: &[(String, &PlayerStats)]) -> String {
+    let mut leaderboard = "Deaths:\n".to_string();
+    if deaths.is_empty() {
+        leaderboard.push_str("Nobody has died yet\n");
+        return leaderboard;
+    }
+
+    let max_player_name = deaths.iter().map(|x| x.0.len()).max().unwrap_or(0);
+    for (i, (player, stats)) in deaths.iter().enumerate() {
+        let rank = match MEDALS.get(i) {
+            Some(medal) => medal.to_string(),
+            None => format!("{: >2}", i + 1),
+        };
+        let count = stats.deaths;
+        let _ = match &stats.last_death {
+            Some(cause) => writeln!(&mut leaderboard, "{rank} {player: <max_player_name$} | {count: <4} last {cause}"),
+            None => writeln!(&mut leaderboard, "{rank} {player: <max_player_name$} | {count}"),
+        };
+    }
+    leaderboard
+}
+
+// Everything !stats shows about a player
+pub struct Profile {
+    pub name: String,
+    pub play_time: u128,
+    pub first_seen: Option<u64>,
+    // None while the player is online
+    pub last_seen: Option<u64>,
+    pub sessions: usize,
+    pub stats: PlayerStats,
+}
+
+fn format_date(unix_millis: u64) -> String {
+    match Local.timestamp_millis_opt(unix_millis as i64).single() {
+        Some(v) => v.format("%Y-%m-%d").to_string(),
+        None => "Unknown".to_string(),
+    }
+}
+
+pub fn fields(profile: &Profile, now: u64) -> Vec<(&'static str, String)> {
+    let last_seen = match profile.last_seen {
+        None => "Online now".to_string(),
+        Some(time) => format!("{} ({} ago)", format_date(time), format_duration(now.saturating_sub(time) as u128)),
+    };
+
+    vec![
+        ("Play time", format_duration(profile.play_time)),
+        ("Sessions", profile.sessions.to_string()),
+        ("First seen", profile.first_seen.map(format_date).unwrap_or_else(|| "Unknown".to_string())),
+        ("Last seen", last_seen),
+        ("Deaths", profile.stats.deaths.to_string()),
+        ("Messages", profile.stats.messages.to_string()),
+    ]
+}
+
+pub async fn send_profile(ctx: &Context, channel_id: ChannelId, profile: &Profile, now: u64) {
+    let fields = fields(profile, now);
+    let result = channel_id.send_message(&ctx.http, |m| m.embed(|e| {
+        e.title(format!("Stats for {}", profile.name))
+            .thumbnail(avatar_url(&profile.name))
+            .colour(0x5865F2)
+            .fields(fields.into_iter().map(|(name, value)| (name, value, true)))
+    })).await;
+
+    if let Err(e) = result {
+        error!("Error sending message: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::player_stats::{fields, format_deaths, PlayerStats, Profile};
+
+    #[test]
+    fn test_profile_fields() {
+        let mut stats = PlayerStats { first_seen: Some(2000), deaths: 1, messages: 10, last_death: None };
+        stats.record_death("Steve", "Steve was slain by Zombie");
+        stats.merge(&PlayerStats { first_seen: Some(1000), deaths: 1, messages: 5, last_death: Some("fell".to_string()) });
+        assert_eq!(stats, PlayerStats { first_seen: Some(1000), deaths: 3, messages: 15, last_death: Some("was slain by Zombie".to_string()) });
+        assert_eq!(format_deaths(&[("Steve".to_string(), &stats)]), "Deaths:\n🥇 Steve | 3    last was slain by Zombie\n");
+
+        let profile = Profile { name: "Steve".to_string(), play_time: 90 * 60 * 1000, first_seen: None, last_seen: None, sessions: 4, stats };
+        let fields = fields(&profile, 0);
+        assert_eq!(fields[0], ("Play time", "1h 30m".to_string()));
+        assert_eq!(fields[2], ("First seen", "Unknown".to_string()));
+        assert_eq!(fields[3], ("Last seen", "Online now".to_string()));
+        assert_eq!(fields[4], ("Deaths", "3".to_string()));
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Serialize, Deserialize};
+use tokio::sync::mpsc;
+
+use crate::{Packet, send_or_log};
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+pub const MEDALS: [&str; 3] = ["🥇", "🥈", "🥉"];
+
+// How often the server is asked which players are online
+const LIST_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// A single completed play session, times are unix timestamps in milliseconds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    pub player: String,
+    pub start: u64,
+    pub end: u64,
+    // Milliseconds the player was AFK during the session
+    #[serde(default)]
+    pub afk: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Window {
+    Week,
+    Month,
+    All,
+}
+
+impl Window {
+    pub fn parse(arg: Option<&str>) -> Option<Window> {
+        match arg {
+            Some("week") => Some(Window::Week),
+            Some("month") => Some(Window::Month),
+            Some("all") | None => Some(Window::All),
+            _ => None,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Window::Week => "Play time this week",
+            Window::Month => "Play time this month",
+            Window::All => "Total play time",
+        }
+    }
+
+    // Length of the window, None for all time
+    fn length_millis(&self) -> Option<u64> {
+        match self {
+            Window::Week => Some(7 * DAY_MILLIS),
+            Window::Month => Some(30 * DAY_MILLIS),
+            Window::All => None,
+        }
+    }
+}
+
+pub fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|x| x.as_millis() as u64).unwrap_or(0)
+}
+
+// Compares the players the server lists with the ones the bot thinks are online, returning
+// the players missing from online and the ones that are no longer listed
+pub fn reconcile(listed: &[String], online: &HashMap<String, Instant>) -> (Vec<String>, Vec<String>) {
+    let missing = listed.iter().filter(|x| !online.contains_key(*x)).cloned().collect();
+    let mut gone: Vec<String> = online.keys().filter(|x| !listed.contains(x)).cloned().collect();
+    gone.sort();
+    (missing, gone)
+}
+
+// Sends a ListPlayers packet every interval so the online players can be reconciled
+pub fn start_list_timer(sender: &mpsc::UnboundedSender<Packet>) {
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        loop {
+            tokio::time::sleep(LIST_INTERVAL).await;
+            send_or_log(&sender, Packet::ListPlayers());
+        }
+    });
+}
+
+// Calculates each player's play time within window, sorted from most to least played.
+// online maps currently online players to the unix time they logged in at. All time totals
+// come from play_times since they predate session tracking.
+pub fn window_play_times(play_times: &HashMap<String, u128>, sessions: &[Session], online: &HashMap<String, u64>, window: Window, now: u64) -> Vec<(String, u128)> {
+    let mut totals: HashMap<String, u128> = HashMap::new();
+
+    match window.length_millis() {
+        None => {
+            for (player, play_time) in play_times {
+                totals.insert(player.clone(), *play_time);
+            }
+        },
+        Some(length) => {
+            let window_start = now.saturating_sub(length);
+            for session in sessions {
+                let start = session.start.max(window_start);
+                if session.end > start {
+                    *totals.entry(session.player.clone()).or_insert(0) += (session.end - start) as u128;
+                }
+            }
+        },
+    }
+
+    // Include the time of sessions that are still in progress
+    let window_start = window.length_millis().map(|length| now.saturating_sub(length)).unwrap_or(0);
+    for (player, login_time) in online {
+        let start = (*login_time).max(window_start);
+        *totals.entry(player.clone()).or_insert(0) += now.saturating_sub(start) as u128;
+    }
+
+    let mut totals: Vec<(String, u128)> = totals.into_iter().filter(|x| x.1 > 0).collect();
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    totals
+}
+
+// Adds up each player's AFK time from sessions that ended within window
+pub fn window_afk_times(sessions: &[Session], window: Window, now: u64) -> HashMap<String, u128> {
+    let window_start = window.length_millis().map(|length| now.saturating_sub(length)).unwrap_or(0);
+    let mut totals: HashMap<String, u128> = HashMap::new();
+    for session in sessions.iter().filter(|x| x.afk > 0 && x.end >= window_start) {
+        *totals.entry(session.player.clone()).or_insert(0) += session.afk as u128;
+    }
+    totals
+}
+
+// Formats a duration as hours and minutes, eg. "2h 05m"
+pub fn format_duration(millis: u128) -> String {
+    let minutes = millis / 60000;
+    if minutes < 60 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    }
+}
+
+pub fn format_leaderboard(window: Window, play_times: &[(String, u128)], afk_times: &HashMap<String, u128>) -> String {
+    let mut leaderboard = format!("{}:\n", window.title());
+    if play_times.is_empty() {
+        leaderboard.push_str("Nobody has played yet\n");
+        return leaderboard;
+    }
+
+    let max_player_name = play_times.iter().map(|x| x.0.len()).max().unwrap_or(0);
+    for (i, (player, play_time)) in play_times.iter().enumerate() {
+        let rank = match MEDALS.get(i) {
+            Some(medal) => medal.to_string(),
+            None => format!("{: >2}", i + 1),
+        };
+        let total_hours = (*play_time as f64) / 3600000.0;
+        match afk_times.get(player) {
+            Some(afk) => {
+                let afk_hours = (*afk as f64) / 3600000.0;
+                let _ = writeln!(&mut leaderboard, "{rank} {player: <max_player_name$} | {total_hours: <6.2} hr ({afk_hours:.2} hr AFK)");
+            },
+            None => {
+                let _ = writeln!(&mut leaderboard, "{rank} {player: <max_player_name$} | {total_hours: <6.2} hr");
+            },
+        }
+    }
+    leaderboard
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Instant;
+    use crate::playtime::{format_duration, reconcile, window_play_times, Session, Window, DAY_MILLIS};
+
+    #[test]
+    fn test_reconcile() {
+        let online = HashMap::from([("Steve".to_string(), Instant::now()), ("Alex".to_string(), Instant::now()), ("Herobrine".to_string(), Instant::now())]);
+        let listed = vec!["Steve".to_string(), "Notch".to_string()];
+        assert_eq!(reconcile(&listed, &online), (vec!["Notch".to_string()], vec!["Alex".to_string(), "Herobrine".to_string()]));
+    }
+
+    #[test]
+    fn test_window_play_times() {
+        let now = 100 * DAY_MILLIS;
+        let mut play_times = HashMap::new();
+        play_times.insert("A".to_string(), 50);
+        play_times.insert("B".to_string(), 10);
+
+        let sessions = vec![
+            Session { player: "A".to_string(), start: now - 40 * DAY_MILLIS, end: now - 40 * DAY_MILLIS + 50, afk: 0 },
+            Session { player: "B".to_string(), start: now - 7 * DAY_MILLIS - 5, end: now - 7 * DAY_MILLIS + 5, afk: 0 },
+        ];
+
+        let mut online = HashMap::new();
+        online.insert("C".to_string(), now - 20);
+
+        assert_eq!(window_play_times(&play_times, &sessions, &online, Window::Week, now), vec![("C".to_string(), 20), ("B".to_string(), 5)]);
+        assert_eq!(window_play_times(&play_times, &sessions, &online, Window::Month, now), vec![("C".to_string(), 20), ("B".to_string(), 10)]);
+        assert_eq!(window_play_times(&play_times, &sessions, &online, Window::All, now), vec![("A".to_string(), 50), ("C".to_string(), 20), ("B".to_string(), 10)]);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(59_999), "0m");
+        assert_eq!(format_duration(45 * 60000), "45m");
+        assert_eq!(format_duration(125 * 60000), "2h 05m");
+    }
+}
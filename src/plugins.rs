@@ -0,0 +1,286 @@
+// WebAssembly plugins loaded from the plugins directory. Plugins can add ! commands and watch
+// log lines, and only get to the bot through the functions imported from the "bot" module:
+//   register_command(ptr, len)  adds a ! command, called from the plugin's init export
+//   say_in_game(ptr, len)       shows text to everyone in game
+//   post_discord(ptr, len)      posts to the channel the command was used in, or the chat channel
+//   players_online() -> i32
+//   play_time(ptr, len) -> i64  a player's total play time in milliseconds, -1 if unknown
+// Strings are UTF-8 in the plugin's memory. A plugin exports memory and alloc(len) -> ptr, and
+// optionally init(), on_command(ptr, len) which is passed JSON with the command, args, user and
+// whether it was used in the admin channel, and on_log_line(ptr, len).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde_json::json;
+use tracing::{error, info, warn};
+use wasmi::{Caller, Config, Engine, Extern, Instance, Linker, Module, Store};
+
+// Fuel each call gets, roughly the number of instructions it can run before being stopped
+const CALL_FUEL: u64 = 10_000_000;
+// on_log_line runs on the packet loop for every line the server logs, so it gets much less
+const LOG_LINE_FUEL: u64 = 100_000;
+
+// Longest text a plugin can send at once
+const MAX_TEXT_LEN: usize = 2000;
+
+pub fn default_dir() -> String {
+    "plugins".to_string()
+}
+
+// What plugins can read, rebuilt before each command or log line and shared by every plugin
+#[derive(Default)]
+pub struct PluginStats {
+    pub players_online: usize,
+    // Play time in milliseconds by player name
+    pub play_times: HashMap<String, u128>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PluginAction {
+    SayInGame(String),
+    PostDiscord(String),
+}
+
+#[derive(Default)]
+struct HostState {
+    commands: Vec<String>,
+    actions: Vec<PluginAction>,
+    stats: Arc<PluginStats>,
+}
+
+fn read_string(caller: &Caller<HostState>, ptr: i32, len: i32) -> Option<String> {
+    let memory = caller.get_export("memory").and_then(Extern::into_memory)?;
+    let len = usize::try_from(len).ok()?.min(MAX_TEXT_LEN * 4);
+    let mut buffer = vec![0; len];
+    memory.read(caller, usize::try_from(ptr).ok()?, &mut buffer).ok()?;
+    let text = String::from_utf8_lossy(&buffer);
+    Some(text.chars().take(MAX_TEXT_LEN).collect())
+}
+
+fn linker(engine: &Engine) -> Result<Linker<HostState>, String> {
+    let mut linker = Linker::new(engine);
+    let result = linker
+        .func_wrap("bot", "register_command", |mut caller: Caller<HostState>, ptr: i32, len: i32| {
+            if let Some(name) = read_string(&caller, ptr, len).filter(|x| !x.is_empty() && !x.contains(char::is_whitespace)) {
+                caller.data_mut().commands.push(name);
+            }
+        })
+        .and_then(|x| x.func_wrap("bot", "say_in_game", |mut caller: Caller<HostState>, ptr: i32, len: i32| {
+            if let Some(text) = read_string(&caller, ptr, len) {
+                caller.data_mut().actions.push(PluginAction::SayInGame(text));
+            }
+        }))
+        .and_then(|x| x.func_wrap("bot", "post_discord", |mut caller: Caller<HostState>, ptr: i32, len: i32| {
+            if let Some(text) = read_string(&caller, ptr, len) {
+                caller.data_mut().actions.push(PluginAction::PostDiscord(text));
+            }
+        }))
+        .and_then(|x| x.func_wrap("bot", "players_online", |caller: Caller<HostState>| caller.data().stats.players_online as i32))
+        .and_then(|x| x.func_wrap("bot", "play_time", |caller: Caller<HostState>, ptr: i32, len: i32| {
+            read_string(&caller, ptr, len)
+                .and_then(|name| caller.data().stats.play_times.get(&name).copied())
+                .map(|x| x as i64)
+                .unwrap_or(-1)
+        }));
+    result.map_err(|e| format!("Error defining plugin functions: {}", e))?;
+    Ok(linker)
+}
+
+struct Plugin {
+    name: String,
+    store: Store<HostState>,
+    instance: Instance,
+    fuel_added: u64,
+}
+
+impl Plugin {
+    fn load(engine: &Engine, linker: &Linker<HostState>, name: &str, wasm: &[u8]) -> Result<Plugin, String> {
+        let module = Module::new(engine, wasm).map_err(|e| e.to_string())?;
+        let mut store = Store::new(engine, HostState::default());
+        // The module's start function, eg. AssemblyScript's top level code, needs fuel too
+        store.add_fuel(CALL_FUEL).map_err(|e| e.to_string())?;
+        let instance = linker.instantiate(&mut store, &module)
+            .and_then(|x| x.start(&mut store))
+            .map_err(|e| e.to_string())?;
+
+        let mut plugin = Plugin { name: name.to_string(), store, instance, fuel_added: CALL_FUEL };
+        if plugin.has_export("init") {
+            plugin.call("init", None, CALL_FUEL)?;
+        }
+        Ok(plugin)
+    }
+
+    fn has_export(&self, name: &str) -> bool {
+        self.instance.get_export(&self.store, name).is_some()
+    }
+
+    // Tops the fuel back up to fuel, any left over from the last call counts towards it
+    fn refuel(&mut self, fuel: u64) {
+        let remaining = self.fuel_added.saturating_sub(self.store.fuel_consumed().unwrap_or(0));
+        let delta = fuel.saturating_sub(remaining);
+        if self.store.add_fuel(delta).is_ok() {
+            self.fuel_added += delta;
+        }
+    }
+
+    // Calls an export, passing input by copying it into memory from the plugin's alloc
+    fn call(&mut self, export: &str, input: Option<&str>, fuel: u64) -> Result<Vec<PluginAction>, String> {
+        self.refuel(fuel);
+        let result = match input {
+            None => self.instance.get_typed_func::<(), ()>(&self.store, export)
+                .map_err(|e| e.to_string())
+                .and_then(|func| func.call(&mut self.store, ()).map_err(|e| e.to_string())),
+            Some(input) => {
+                let alloc = self.instance.get_typed_func::<i32, i32>(&self.store, "alloc").map_err(|e| e.to_string())?;
+                let memory = self.instance.get_memory(&self.store, "memory").ok_or("No memory export")?;
+                let func = self.instance.get_typed_func::<(i32, i32), ()>(&self.store, export).map_err(|e| e.to_string())?;
+
+                let len = i32::try_from(input.len()).map_err(|e| e.to_string())?;
+                let ptr = alloc.call(&mut self.store, len).map_err(|e| e.to_string())?;
+                memory.write(&mut self.store, ptr as usize, input.as_bytes()).map_err(|e| e.to_string())?;
+                func.call(&mut self.store, (ptr, len)).map_err(|e| e.to_string())
+            },
+        };
+        let actions = std::mem::take(&mut self.store.data_mut().actions);
+        result.map(|_| actions)
+    }
+}
+
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+}
+
+impl Plugins {
+    // Loads every .wasm file in dir, plugins that fail to load are skipped
+    pub fn load(dir: &str) -> Plugins {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(v) => v,
+            Err(_) => return Plugins::default(),
+        };
+
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let linker = match linker(&engine) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{}", e);
+                return Plugins::default();
+            },
+        };
+
+        let mut paths: Vec<_> = entries.filter_map(|x| x.ok()).map(|x| x.path()).filter(|x| x.extension().is_some_and(|x| x == "wasm")).collect();
+        paths.sort();
+
+        let mut plugins = Vec::new();
+        for path in paths {
+            let name = path.file_stem().map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+            match std::fs::read(&path).map_err(|e| e.to_string()).and_then(|wasm| Plugin::load(&engine, &linker, &name, &wasm)) {
+                Ok(plugin) => {
+                    info!("Loaded plugin {} with commands {:?}", name, plugin.store.data().commands);
+                    plugins.push(plugin);
+                },
+                Err(e) => error!("Error loading plugin {}: {}", path.display(), e),
+            }
+        }
+        Plugins { plugins }
+    }
+
+    pub fn command_names(&self) -> Vec<&str> {
+        self.plugins.iter().flat_map(|x| x.store.data().commands.iter().map(|x| x.as_str())).collect()
+    }
+
+    pub fn has_command(&self, command: &str) -> bool {
+        self.plugins.iter().any(|x| x.store.data().commands.iter().any(|x| x == command))
+    }
+
+    pub fn watches_log(&self) -> bool {
+        self.plugins.iter().any(|x| x.has_export("on_log_line"))
+    }
+
+    // Runs a command in the plugin that registered it
+    pub fn run_command(&mut self, command: &str, args: &str, user: &str, admin: bool, stats: PluginStats) -> Vec<PluginAction> {
+        let plugin = match self.plugins.iter_mut().find(|x| x.store.data().commands.iter().any(|x| x == command)) {
+            Some(v) => v,
+            None => return Vec::new(),
+        };
+
+        plugin.store.data_mut().stats = Arc::new(stats);
+        let input = json!({ "command": command, "args": args, "user": user, "admin": admin }).to_string();
+        plugin.call("on_command", Some(&input), CALL_FUEL).unwrap_or_else(|e| {
+            warn!("Plugin {} failed running !{}: {}", plugin.name, command, e);
+            Vec::new()
+        })
+    }
+
+    pub fn log_line(&mut self, content: &str, stats: PluginStats) -> Vec<PluginAction> {
+        let stats = Arc::new(stats);
+        let mut actions = Vec::new();
+        for plugin in self.plugins.iter_mut().filter(|x| x.has_export("on_log_line")) {
+            plugin.store.data_mut().stats = Arc::clone(&stats);
+            match plugin.call("on_log_line", Some(content), LOG_LINE_FUEL) {
+                Ok(v) => actions.extend(v),
+                Err(e) => warn!("Plugin {} failed handling a log line: {}", plugin.name, e),
+            }
+        }
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use wasmi::{Config, Engine};
+
+    use crate::plugins::{linker, Plugin, PluginAction, PluginStats, CALL_FUEL, LOG_LINE_FUEL};
+
+    const PLUGIN: &str = r#"
+        (module
+            (import "bot" "register_command" (func $register (param i32 i32)))
+            (import "bot" "post_discord" (func $post (param i32 i32)))
+            (import "bot" "players_online" (func $online (result i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 0) "hello")
+            (data (i32.const 8) "0 online")
+            (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+            (func (export "init") (call $register (i32.const 0) (i32.const 5)))
+            (func (export "on_command") (param i32 i32)
+                (i32.store8 (i32.const 8) (i32.add (i32.const 48) (call $online)))
+                (call $post (i32.const 8) (i32.const 8)))
+            (func (export "on_log_line") (param i32 i32) (loop $forever (br $forever))))
+    "#;
+
+    // Top level code compiles to a start function that runs while the module is instantiated
+    const START_PLUGIN: &str = r#"
+        (module
+            (import "bot" "register_command" (func $register (param i32 i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 0) "hi")
+            (global $ready (mut i32) (i32.const 0))
+            (func $setup (global.set $ready (i32.const 1)))
+            (start $setup)
+            (func (export "init") (if (global.get $ready) (then (call $register (i32.const 0) (i32.const 2))))))
+    "#;
+
+    #[test]
+    fn test_plugin() {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let mut plugin = Plugin::load(&engine, &linker(&engine).unwrap(), "test", &wat::parse_str(PLUGIN).unwrap()).unwrap();
+        assert_eq!(plugin.store.data().commands, vec!["hello".to_string()]);
+
+        plugin.store.data_mut().stats = Arc::new(PluginStats { players_online: 3, ..PluginStats::default() });
+        assert_eq!(plugin.call("on_command", Some("{}"), CALL_FUEL), Ok(vec![PluginAction::PostDiscord("3 online".to_string())]));
+
+        // Plugins that never return run out of fuel
+        assert!(plugin.call("on_log_line", Some("Steve joined the game"), LOG_LINE_FUEL).is_err());
+        assert!(plugin.call("on_command", Some("{}"), CALL_FUEL).is_ok());
+
+        let plugin = Plugin::load(&engine, &linker(&engine).unwrap(), "start", &wat::parse_str(START_PLUGIN).unwrap()).unwrap();
+        assert_eq!(plugin.store.data().commands, vec!["hi".to_string()]);
+    }
+}
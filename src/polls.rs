@@ -0,0 +1,223 @@
+// Community polls started with !vote. The poll is posted in the chat channel with a button for
+// each option and announced in game, where players vote with `!vote <number>`. Everyone gets one
+// vote, voting again changes it, and the results are posted when the poll ends.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serenity::builder::CreateComponents;
+use serenity::model::id::{ChannelId, MessageId};
+use serenity::model::interactions::message_component::ButtonStyle;
+use serenity::prelude::*;
+use tokio::sync::mpsc;
+use tracing::error;
+
+use crate::{send_or_log, Packet};
+
+// custom_id of an option's button is this followed by its index
+const BUTTON_PREFIX: &str = "poll:";
+
+// Discord fits 5 buttons in a row
+const MAX_OPTIONS: usize = 5;
+
+pub const USAGE: &str = "Usage: `!vote \"question\" option1 option2 ...`, options with spaces can be quoted too";
+
+#[derive(Deserialize)]
+pub struct PollsToml {
+    #[serde(default = "default_duration_mins")]
+    pub duration_mins: u64,
+}
+
+impl Default for PollsToml {
+    fn default() -> PollsToml {
+        PollsToml { duration_mins: default_duration_mins() }
+    }
+}
+
+fn default_duration_mins() -> u64 {
+    10
+}
+
+// Splits on whitespace, keeping words in double quotes together
+fn split_quoted(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' | '“' | '”' => {
+                if quoted && !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                quoted = !quoted;
+            },
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            },
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+// Parses !vote's arguments into the question and options
+pub fn parse_poll(args: &str) -> Result<(String, Vec<String>), String> {
+    let mut words = split_quoted(args).into_iter();
+    let question = words.next().ok_or_else(|| USAGE.to_string())?;
+    let options: Vec<String> = words.collect();
+    if options.len() < 2 {
+        return Err(USAGE.to_string());
+    }
+    if options.len() > MAX_OPTIONS {
+        return Err(format!("Polls can have at most {} options", MAX_OPTIONS));
+    }
+    Ok((question, options))
+}
+
+// The option index a poll button stands for
+pub fn button_option(custom_id: &str) -> Option<usize> {
+    custom_id.strip_prefix(BUTTON_PREFIX)?.parse().ok()
+}
+
+// Discord voters are kept by user id and players by name so they can't clash
+#[derive(Hash, PartialEq, Eq, Debug)]
+pub enum Voter {
+    Discord(u64),
+    Player(String),
+}
+
+pub struct Poll {
+    pub id: u64,
+    pub channel_id: ChannelId,
+    // The poll's message, whose buttons are removed when it ends
+    pub message_id: Option<MessageId>,
+    pub question: String,
+    pub options: Vec<String>,
+    votes: HashMap<Voter, usize>,
+}
+
+impl Poll {
+    pub fn new(id: u64, channel_id: ChannelId, question: String, options: Vec<String>) -> Poll {
+        Poll { id, channel_id, message_id: None, question, options, votes: HashMap::new() }
+    }
+
+    // Finds the option a player typed, either its number or its text
+    pub fn find_option(&self, text: &str) -> Option<usize> {
+        match text.parse::<usize>() {
+            Ok(number) => (1..=self.options.len()).contains(&number).then(|| number - 1),
+            Err(_) => self.options.iter().position(|x| x.eq_ignore_ascii_case(text)),
+        }
+    }
+
+    // Records a vote, replacing the voter's earlier one. Returns the option voted for.
+    pub fn vote(&mut self, voter: Voter, option: usize) -> Option<&str> {
+        let name = self.options.get(option)?;
+        self.votes.insert(voter, option);
+        Some(name)
+    }
+
+    fn tally(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.options.len()];
+        for option in self.votes.values() {
+            counts[*option] += 1;
+        }
+        counts
+    }
+
+    // How to vote in game, eg. "!vote 1 (yes), !vote 2 (no)"
+    pub fn game_instructions(&self) -> String {
+        let options: Vec<String> = self.options.iter().enumerate().map(|(i, x)| format!("!vote {} ({})", i + 1, x)).collect();
+        format!("Poll: {} Vote with {}", self.question, options.join(", "))
+    }
+
+    pub fn results(&self) -> String {
+        let counts = self.tally();
+        let mut results = format!("Poll ended: {}", self.question);
+        for (option, count) in self.options.iter().zip(&counts) {
+            let plural = if *count == 1 { "" } else { "s" };
+            results.push_str(&format!("\n{}: {} vote{}", option, count, plural));
+        }
+
+        let most = counts.iter().copied().max().unwrap_or(0);
+        let winners: Vec<&str> = self.options.iter().zip(&counts).filter(|x| *x.1 == most).map(|x| x.0.as_str()).collect();
+        match (most, winners.as_slice()) {
+            (0, _) => results.push_str("\nNobody voted"),
+            (_, [winner]) => results.push_str(&format!("\nWinner: {}", winner)),
+            _ => results.push_str(&format!("\nTied: {}", winners.join(", "))),
+        }
+        results
+    }
+}
+
+// Posts the poll with a button for each option, returning the message's id
+pub async fn send_poll(ctx: &Context, poll: &Poll, ends_at: i64) -> Option<MessageId> {
+    let text = format!("📊 **{}**\nVote with the buttons below or `!vote <number>` in game, the poll ends <t:{}:R>", poll.question, ends_at);
+    let result = poll.channel_id.send_message(&ctx.http, |m| {
+        m.content(text).components(|c| c.create_action_row(|row| {
+            for (i, option) in poll.options.iter().enumerate() {
+                row.create_button(|b| b.custom_id(format!("{}{}", BUTTON_PREFIX, i)).label(format!("{}. {}", i + 1, option)).style(ButtonStyle::Primary));
+            }
+            row
+        }))
+    }).await;
+
+    match result {
+        Ok(v) => Some(v.id),
+        Err(e) => {
+            error!("Error sending message: {:?}", e);
+            None
+        },
+    }
+}
+
+// Removes the buttons from an ended poll so nobody tries to vote
+pub async fn close_poll(ctx: &Context, channel_id: ChannelId, message_id: MessageId) {
+    if let Err(e) = channel_id.edit_message(&ctx.http, message_id, |m| m.set_components(CreateComponents::default())).await {
+        error!("Error editing message: {:?}", e);
+    }
+}
+
+// Sends a PollEnded packet once the poll's time is up
+pub fn start_poll_timer(sender: &mpsc::UnboundedSender<Packet>, id: u64, duration: Duration) {
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        tokio::time::sleep(duration).await;
+        send_or_log(&sender, Packet::PollEnded(id));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::id::ChannelId;
+
+    use crate::polls::{button_option, parse_poll, Poll, Voter};
+
+    #[test]
+    fn test_poll() {
+        let (question, options) = parse_poll("\"Which biome next?\" desert \"dark forest\" jungle").unwrap();
+        assert_eq!(question, "Which biome next?");
+        assert_eq!(options, vec!["desert", "dark forest", "jungle"]);
+        assert!(parse_poll("\"Yes?\" yes").is_err());
+        assert!(parse_poll("q a b c d e f").is_err());
+        assert_eq!(button_option("poll:2"), Some(2));
+        assert_eq!(button_option("confirm"), None);
+
+        let mut poll = Poll::new(1, ChannelId(1), question, options);
+        assert_eq!(poll.find_option("2"), Some(1));
+        assert_eq!(poll.find_option("Jungle"), Some(2));
+        assert_eq!(poll.find_option("4"), None);
+        assert_eq!(poll.game_instructions(), "Poll: Which biome next? Vote with !vote 1 (desert), !vote 2 (dark forest), !vote 3 (jungle)");
+
+        poll.vote(Voter::Player("Steve".to_string()), 0);
+        poll.vote(Voter::Discord(1), 2);
+        poll.vote(Voter::Player("Alex".to_string()), 2);
+        poll.vote(Voter::Player("Steve".to_string()), 2);
+        assert_eq!(poll.results(), "Poll ended: Which biome next?\ndesert: 0 votes\ndark forest: 0 votes\njungle: 3 votes\nWinner: jungle");
+    }
+}
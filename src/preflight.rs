@@ -0,0 +1,199 @@
+// Checks run before starting the server or installing an update, so a missing run script, the
+// wrong java or a full disk is reported in the admin channel rather than as a cryptic crash.
+// Only servers the bot starts itself are checked, systemd units and panels manage their own.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::process::Command;
+
+use crate::jvm_args::EULA_PATH;
+use crate::process::LaunchToml;
+
+const JAVA_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[cfg(unix)]
+const RUN_SCRIPT: &str = "run.sh";
+#[cfg(not(unix))]
+const RUN_SCRIPT: &str = "run.bat";
+
+#[derive(Deserialize, Clone)]
+pub struct PreflightToml {
+    #[serde(default = "default_min_free_gb")]
+    pub min_free_gb: u64,
+    // The java major version the server needs, worked out from the run script when not set
+    pub java_version: Option<u32>,
+}
+
+impl Default for PreflightToml {
+    fn default() -> PreflightToml {
+        PreflightToml { min_free_gb: default_min_free_gb(), java_version: None }
+    }
+}
+
+fn default_min_free_gb() -> u64 {
+    2
+}
+
+// Finds the Minecraft version in a Forge or NeoForge run script, eg.
+// "libraries/net/minecraftforge/forge/1.20.1-47.2.0/unix_args.txt" is 1.20.1 and
+// "libraries/net/neoforged/neoforge/20.4.190/unix_args.txt" is 1.20.4
+pub fn minecraft_version_from_script(script: &str) -> Option<String> {
+    if let Some((_, rest)) = script.split_once("net/minecraftforge/forge/") {
+        return rest.split_once('-').map(|x| x.0.to_string());
+    }
+    let version = script.split_once("net/neoforged/neoforge/")?.1.split(['/', '-']).next()?;
+    let mut parts = version.split('.');
+    let (minor, patch) = (parts.next()?, parts.next()?);
+    match patch {
+        "0" => Some(format!("1.{}", minor)),
+        patch => Some(format!("1.{}.{}", minor, patch)),
+    }
+}
+
+// The oldest java each Minecraft version runs on
+pub fn required_java(minecraft_version: &str) -> Option<u32> {
+    let mut parts = minecraft_version.split('.').skip(1).map(|x| x.parse::<u32>().ok());
+    let minor = parts.next()??;
+    let patch = parts.next().flatten().unwrap_or(0);
+    Some(match (minor, patch) {
+        (21.., _) | (20, 5..) => 21,
+        (18.., _) => 17,
+        (17, _) => 16,
+        _ => 8,
+    })
+}
+
+// Reads the major version from `java -version`, eg. "17.0.8" is 17 and "1.8.0_381" is 8
+pub fn parse_java_version(output: &str) -> Option<u32> {
+    let version = output.split_once("version \"")?.1.split('"').next()?;
+    let mut parts = version.split(['.', '_', '-']);
+    match parts.next()? {
+        "1" => parts.next()?.parse().ok(),
+        major => major.parse().ok(),
+    }
+}
+
+// Free space on the disk holding path in bytes, None where it can't be found
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    match unsafe { libc::statvfs(path.as_ptr(), &mut stat) } {
+        0 => Some(stat.f_bavail as u64 * stat.f_frsize as u64),
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+fn working_dir(launch: &LaunchToml) -> PathBuf {
+    PathBuf::from(launch.working_dir.as_deref().unwrap_or("."))
+}
+
+async fn check_java(launch: &LaunchToml, config: &PreflightToml, problems: &mut Vec<String>) {
+    let java = match launch.env.get("JAVA_HOME") {
+        Some(java_home) => Path::new(java_home).join("bin").join("java"),
+        None => PathBuf::from("java"),
+    };
+    let output = match tokio::time::timeout(JAVA_TIMEOUT, Command::new(&java).arg("-version").output()).await {
+        Ok(Ok(v)) => v,
+        Ok(Err(e)) => return problems.push(format!("Java couldn't be run as `{}`: {}. Install it or set JAVA_HOME in [launch.env]", java.display(), e)),
+        Err(_) => return problems.push(format!("`{} -version` didn't finish", java.display())),
+    };
+
+    // java -version prints to stderr
+    let text = String::from_utf8_lossy(&output.stderr).to_string() + &String::from_utf8_lossy(&output.stdout);
+    let installed = match parse_java_version(&text) {
+        Some(v) => v,
+        None => return problems.push(format!("Couldn't read the version from `{} -version`", java.display())),
+    };
+
+    let script = std::fs::read_to_string(working_dir(launch).join(RUN_SCRIPT)).unwrap_or_default();
+    let minecraft_version = minecraft_version_from_script(&script);
+    let required = config.java_version.or_else(|| minecraft_version.as_deref().and_then(required_java));
+    if let Some(required) = required.filter(|x| installed < *x) {
+        let needed_by = minecraft_version.map(|x| format!("Minecraft {}", x)).unwrap_or("The server".to_string());
+        problems.push(format!("{} needs Java {} or newer but Java {} is installed, install it or point JAVA_HOME in [launch.env] at it", needed_by, required, installed));
+    }
+}
+
+fn check_disk(launch: &LaunchToml, config: &PreflightToml, problems: &mut Vec<String>) {
+    let free = match free_space(&working_dir(launch)) {
+        Some(v) => v,
+        None => return,
+    };
+    let gb = 1024 * 1024 * 1024;
+    if free < config.min_free_gb * gb {
+        problems.push(format!("Only {:.1} GB of disk space is free, at least {} GB is needed. Remove old backups or logs to make room", free as f64 / gb as f64, config.min_free_gb));
+    }
+}
+
+// Problems that would stop the server starting, empty if there are none
+pub async fn check_start(launch: &LaunchToml, config: &PreflightToml) -> Vec<String> {
+    let mut problems = Vec::new();
+    let dir = working_dir(launch);
+    if !dir.is_dir() {
+        problems.push(format!("The working directory {} doesn't exist", dir.display()));
+        return problems;
+    }
+
+    // A custom command may not run java here directly, eg. in a container, so only the run
+    // script's server is checked
+    if launch.command.is_none() {
+        if !dir.join(RUN_SCRIPT).is_file() {
+            problems.push(format!("{} is missing from {}, install the server or set [launch] command", RUN_SCRIPT, dir.display()));
+        }
+        let eula = std::fs::read_to_string(dir.join(EULA_PATH)).unwrap_or_default();
+        if !eula.lines().any(|x| x.trim() == "eula=true") {
+            problems.push(format!("The EULA hasn't been accepted, set eula=true in {} or run `mc-discord-bot setup`", EULA_PATH));
+        }
+        check_java(launch, config, &mut problems).await;
+    }
+    check_disk(launch, config, &mut problems);
+    problems
+}
+
+// Problems that would stop an update installing or the server starting after it
+pub async fn check_update(launch: &LaunchToml, config: &PreflightToml) -> Vec<String> {
+    let mut problems = Vec::new();
+    if launch.command.is_none() {
+        check_java(launch, config, &mut problems).await;
+    }
+    check_disk(launch, config, &mut problems);
+    problems
+}
+
+pub fn describe(problems: &[String]) -> String {
+    let lines: Vec<String> = problems.iter().map(|x| format!("- {}", x)).collect();
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::preflight::{minecraft_version_from_script, parse_java_version, required_java};
+
+    #[test]
+    fn test_preflight() {
+        assert_eq!(minecraft_version_from_script("java @user_jvm_args.txt @libraries/net/minecraftforge/forge/1.20.1-47.2.0/unix_args.txt \"$@\"").as_deref(), Some("1.20.1"));
+        assert_eq!(minecraft_version_from_script("java @user_jvm_args.txt @libraries/net/neoforged/neoforge/20.4.190/unix_args.txt \"$@\"").as_deref(), Some("1.20.4"));
+        assert_eq!(minecraft_version_from_script("java @libraries/net/neoforged/neoforge/21.0.167/unix_args.txt").as_deref(), Some("1.21"));
+        assert_eq!(minecraft_version_from_script("java -jar server.jar nogui"), None);
+
+        assert_eq!(required_java("1.20.1"), Some(17));
+        assert_eq!(required_java("1.20.6"), Some(21));
+        assert_eq!(required_java("1.21"), Some(21));
+        assert_eq!(required_java("1.16.5"), Some(8));
+
+        assert_eq!(parse_java_version("openjdk version \"17.0.8\" 2023-07-18\nOpenJDK Runtime Environment"), Some(17));
+        assert_eq!(parse_java_version("java version \"1.8.0_381\""), Some(8));
+        assert_eq!(parse_java_version("openjdk version \"21\" 2023-09-19"), Some(21));
+        assert_eq!(parse_java_version("bash: java: command not found"), None);
+    }
+}
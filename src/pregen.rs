@@ -0,0 +1,175 @@
+// `!pregen <radius>` generates the chunks around spawn ahead of time with Chunky or Forge's
+// generate command. The progress the server logs is shown in a single discord message that's
+// edited as it goes.
+
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serenity::model::id::{ChannelId, MessageId};
+use tracing::error;
+
+use crate::bot::Bot;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PregenTool {
+    // The Chunky plugin or mod
+    #[default]
+    Chunky,
+    // `forge generate`, built into Forge 1.16 to 1.18
+    Forge,
+}
+
+#[derive(Deserialize)]
+pub struct PregenToml {
+    #[serde(default)]
+    pub tool: PregenTool,
+    // Discord rate limits edits so the message is only updated this often
+    #[serde(default = "default_update_interval_secs")]
+    pub update_interval_secs: u64,
+}
+
+impl Default for PregenToml {
+    fn default() -> PregenToml {
+        PregenToml { tool: PregenTool::default(), update_interval_secs: default_update_interval_secs() }
+    }
+}
+
+fn default_update_interval_secs() -> u64 {
+    15
+}
+
+// The console commands that generate every chunk within radius blocks of 0, 0
+pub fn commands(tool: PregenTool, radius: u32) -> Vec<String> {
+    match tool {
+        PregenTool::Chunky => vec![format!("chunky radius {}", radius), "chunky start".to_string()],
+        PregenTool::Forge => {
+            let side = (radius / 16) * 2 + 1;
+            vec![format!("forge generate 0 64 0 {}", side * side)]
+        },
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Progress {
+    pub percent: f64,
+    pub finished: bool,
+    pub detail: String,
+}
+
+// Reads a progress line from either tool, eg.
+// "[Chunky] Task running for world. Processed: 1250 chunks (1.27%), ETA: 0:09:46, Rate: 165.3 cps, Current: -23, 48"
+// "[Chunky] Task finished for world. Processed: 98596 chunks (100.00%), Total time: 0:10:12"
+// "Generation Progress: 1234/5000"
+// "Finished generating 4000 new chunks (out of 5000) for minecraft:overworld"
+// Markers have to start the line, so chat quoting them is ignored.
+pub fn parse_progress(line: &str) -> Option<Progress> {
+    // The Chunky mod doesn't add the prefix the plugin does
+    let chunky_line = line.strip_prefix("[Chunky] ").unwrap_or(line);
+    for (marker, finished) in [("Task running for ", false), ("Task finished for ", true), ("Task cancelled for ", true), ("Task stopped for ", true)] {
+        if let Some(rest) = chunky_line.strip_prefix(marker) {
+            let detail = rest.split_once("Processed: ")?.1;
+            let detail = detail.split(", Current:").next().unwrap_or(detail);
+            let percent = detail.split_once('(')?.1.split_once("%)")?.0.parse().ok()?;
+            let detail = match marker {
+                "Task cancelled for " | "Task stopped for " => format!("Stopped, {}", detail),
+                _ => detail.to_string(),
+            };
+            return Some(Progress { percent, finished, detail });
+        }
+    }
+
+    if let Some(rest) = line.strip_prefix("Generation Progress: ") {
+        let (done, total) = rest.trim().split_once('/')?;
+        let (done, total) = (done.parse::<f64>().ok()?, total.parse::<f64>().ok()?);
+        let percent = if total > 0.0 { done / total * 100.0 } else { 0.0 };
+        return Some(Progress { percent, finished: false, detail: format!("{}/{} chunks", done, total) });
+    }
+    if line.starts_with("Finished generating ") {
+        return Some(Progress { percent: 100.0, finished: true, detail: line.to_string() });
+    }
+    None
+}
+
+fn progress_bar(percent: f64) -> String {
+    let filled = ((percent / 10.0).round() as usize).min(10);
+    format!("[{}{}] {:.1}%", "#".repeat(filled), "-".repeat(10 - filled), percent)
+}
+
+pub fn describe(radius: u32, progress: &Progress) -> String {
+    let title = match progress.finished {
+        true => format!("Pregeneration of a {} block radius finished", radius),
+        false => format!("Pregenerating a {} block radius", radius),
+    };
+    format!("{}\n`{}` {}", title, progress_bar(progress.percent), progress.detail)
+}
+
+pub struct PregenTask {
+    pub radius: u32,
+    channel_id: ChannelId,
+    message_id: Option<MessageId>,
+    last_edit: Option<Instant>,
+}
+
+impl PregenTask {
+    pub fn new(radius: u32, channel_id: ChannelId) -> PregenTask {
+        PregenTask { radius, channel_id, message_id: None, last_edit: None }
+    }
+}
+
+// Posts or edits the progress message for a progress line, and forgets the task once it's done
+pub async fn log_line(bot: &mut Bot, line: &str) {
+    let (ctx, task) = match (&bot.ctx, &mut bot.pregen) {
+        (Some(ctx), Some(task)) => (ctx, task),
+        _ => return,
+    };
+    let progress = match parse_progress(line) {
+        Some(v) => v,
+        None => return,
+    };
+
+    let interval = Duration::from_secs(bot.config.pregen.update_interval_secs);
+    if !progress.finished && task.last_edit.is_some_and(|x| x.elapsed() < interval) {
+        return;
+    }
+    task.last_edit = Some(Instant::now());
+
+    let text = describe(task.radius, &progress);
+    match task.message_id {
+        Some(message_id) => {
+            if let Err(e) = task.channel_id.edit_message(&ctx.http, message_id, |m| m.content(&text)).await {
+                error!("Error editing message: {:?}", e);
+            }
+        },
+        None => match task.channel_id.say(&ctx.http, &text).await {
+            Ok(message) => task.message_id = Some(message.id),
+            Err(e) => error!("Error sending message: {:?}", e),
+        },
+    }
+
+    if progress.finished {
+        bot.pregen = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pregen::{commands, describe, parse_progress, PregenTool, Progress};
+
+    #[test]
+    fn test_pregen() {
+        assert_eq!(commands(PregenTool::Chunky, 2000), vec!["chunky radius 2000", "chunky start"]);
+        assert_eq!(commands(PregenTool::Forge, 160), vec!["forge generate 0 64 0 441"]);
+
+        let running = parse_progress("[Chunky] Task running for world. Processed: 1250 chunks (1.27%), ETA: 0:09:46, Rate: 165.3 cps, Current: -23, 48").unwrap();
+        assert_eq!(running, Progress { percent: 1.27, finished: false, detail: "1250 chunks (1.27%), ETA: 0:09:46, Rate: 165.3 cps".to_string() });
+        assert_eq!(describe(2000, &running), "Pregenerating a 2000 block radius\n`[----------] 1.3%` 1250 chunks (1.27%), ETA: 0:09:46, Rate: 165.3 cps");
+        assert!(parse_progress("Task finished for minecraft:overworld. Processed: 98596 chunks (100.00%), Total time: 0:10:12").unwrap().finished);
+
+        assert_eq!(parse_progress("Generation Progress: 250/1000").map(|x| x.percent), Some(25.0));
+        assert!(parse_progress("Finished generating 900 new chunks (out of 1000) for minecraft:overworld").unwrap().finished);
+        assert_eq!(parse_progress("Steve joined the game"), None);
+        assert_eq!(parse_progress("<Steve> Task finished for world. Processed: 1 chunks (100.00%)"), None);
+        assert_eq!(parse_progress("<Steve> Finished generating 1 new chunks"), None);
+    }
+}
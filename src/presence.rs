@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use crate::{Packet, send_or_log};
+use crate::metrics::Metrics;
+use crate::playtime::format_duration;
+use crate::properties::Properties;
+use crate::server_files::SERVER_PROPERTIES_PATH;
+
+// Templates can use {players}, {tps}, {uptime} and {motd}
+#[derive(Deserialize)]
+pub struct PresenceToml {
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default = "default_templates")]
+    pub templates: Vec<String>,
+}
+
+impl Default for PresenceToml {
+    fn default() -> PresenceToml {
+        PresenceToml {
+            interval_secs: default_interval_secs(),
+            templates: default_templates(),
+        }
+    }
+}
+
+fn default_interval_secs() -> u64 {
+    30
+}
+
+fn default_templates() -> Vec<String> {
+    vec!["{players} Online".to_string(), "Up {uptime}".to_string(), "TPS {tps}".to_string()]
+}
+
+// Values that can be shown in the presence, None when they aren't known
+pub struct PresenceValues {
+    pub players: usize,
+    pub tps: Option<f64>,
+    pub uptime: Option<String>,
+    pub motd: Option<String>,
+}
+
+impl PresenceValues {
+    pub fn new(players: usize, metrics: &Metrics) -> PresenceValues {
+        let motd = Properties::read(SERVER_PROPERTIES_PATH).ok()
+            .and_then(|x| x.get("motd").map(strip_formatting))
+            .filter(|x| !x.is_empty());
+
+        PresenceValues {
+            players,
+            tps: metrics.tps,
+            uptime: metrics.server_started.map(|x| format_duration(x.elapsed().as_millis())),
+            motd,
+        }
+    }
+}
+
+// Removes § formatting codes, eg. "§aGreen" becomes "Green"
+pub fn strip_formatting(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '§' {
+            chars.next();
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+// Fills in a template, returns None if it uses a value that isn't known so it can be skipped
+pub fn render(template: &str, values: &PresenceValues) -> Option<String> {
+    let mut text = template.replace("{players}", &values.players.to_string());
+    for (placeholder, value) in [("{tps}", values.tps.map(|x| format!("{:.1}", x))), ("{uptime}", values.uptime.clone()), ("{motd}", values.motd.clone())] {
+        if text.contains(placeholder) {
+            text = text.replace(placeholder, &value?);
+        }
+    }
+    Some(text)
+}
+
+// Rotates through the configured templates
+pub struct Presence {
+    templates: Vec<String>,
+    index: usize,
+    // Shown instead of the templates while set, eg. during maintenance
+    pub fixed: Option<String>,
+}
+
+impl Presence {
+    pub fn new(config: &PresenceToml) -> Presence {
+        Presence { templates: config.templates.clone(), index: 0, fixed: None }
+    }
+
+    // Renders the current template, or the next one that can be rendered
+    pub fn current(&mut self, values: &PresenceValues) -> String {
+        if let Some(text) = &self.fixed {
+            return text.clone();
+        }
+        for _ in 0..self.templates.len() {
+            if let Some(text) = render(&self.templates[self.index], values) {
+                return text;
+            }
+            self.index = (self.index + 1) % self.templates.len();
+        }
+        format!("{} Online", values.players)
+    }
+
+    pub fn next(&mut self, values: &PresenceValues) -> String {
+        if !self.templates.is_empty() {
+            self.index = (self.index + 1) % self.templates.len();
+        }
+        self.current(values)
+    }
+}
+
+// Sends a PresenceTick packet every interval so the presence rotates
+pub fn start_presence_rotation(config: &PresenceToml, sender: &mpsc::UnboundedSender<Packet>) {
+    let interval = Duration::from_secs(config.interval_secs.max(5));
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            send_or_log(&sender, Packet::PresenceTick());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::presence::{render, strip_formatting, Presence, PresenceToml, PresenceValues};
+
+    #[test]
+    fn test_rotation() {
+        let values = PresenceValues { players: 3, tps: None, uptime: Some("2h 05m".to_string()), motd: Some(strip_formatting("§aHello")) };
+        assert_eq!(render("{players} Online | {motd}", &values).as_deref(), Some("3 Online | Hello"));
+        assert_eq!(render("TPS {tps}", &values), None);
+
+        let mut presence = Presence::new(&PresenceToml::default());
+        assert_eq!(presence.current(&values), "3 Online");
+        assert_eq!(presence.next(&values), "Up 2h 05m");
+
+        // TPS isn't known so it's skipped
+        assert_eq!(presence.next(&values), "3 Online");
+    }
+}
@@ -1,116 +1,148 @@
+use serde::Deserialize;
 use tokio::sync::mpsc;
-use tokio::process::Command;
+use tokio::process::{Child, Command};
+use std::collections::HashMap;
 use std::process::Stdio;
+use std::time::Duration;
 use tokio::io::AsyncReadExt;
+use tracing::{debug, error, info, warn};
 
-use crate::{Packet, send_or_log};
+use crate::console::Console;
+use crate::{platform, Packet, send_or_log};
+use crate::log_parser::{parse_line, LogLine};
 
-// This parses the label and content out of a log line assuming that the line is formatted as follows:
-// [__:__:__] [src] [label]: content
-pub fn parse_line(line: &str) -> Result<(&str, &str), &'static str> {
-    if line.len() < 13 {
-        return Err("too short");
-    }
-    
-    // ensure line is formatted as such
-    // [__:__:__] [
-    let line_bytes = line.as_bytes();
-    if (line_bytes[0]  != b'[') ||
-       (line_bytes[3]  != b':') ||
-       (line_bytes[6]  != b':') ||
-       (line_bytes[9]  != b']') ||
-       (line_bytes[10] != b' ') ||
-       (line_bytes[11] != b'[')
-    {
-        return Err("invalid format");
-    }
-
-    // Label starts 3 bytes after the `src` segment's ']' byte
-    let label_start = line_bytes[12..].iter().position(|x| *x == b']').ok_or("invalid format, no src segment found")? + 15;
+// How the server is started, by default with run.sh (run.bat on windows) in the bot's directory
+#[derive(Deserialize, Default, Clone)]
+pub struct LaunchToml {
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub working_dir: Option<String>,
+    // Added to the bot's own environment
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+}
 
-    // Ensure label_start is within the line's length 
-    if line_bytes.len() <= label_start {
-        return Err("error finding label start");
+impl LaunchToml {
+    fn command(&self) -> Command {
+        let mut cmd = match &self.command {
+            Some(command) => {
+                let mut cmd = Command::new(command);
+                cmd.args(&self.args);
+                cmd
+            },
+            None => {
+                let (command, args) = platform::run_script();
+                let mut cmd = Command::new(command);
+                cmd.args(args);
+                cmd
+            },
+        };
+        if let Some(working_dir) = &self.working_dir {
+            cmd.current_dir(working_dir);
+        }
+        cmd.envs(&self.env);
+        cmd
     }
 
-    // Find label end
-    let label_end = line_bytes[label_start..].iter().position(|x| *x == b']').ok_or("error finding label end")? + label_start;
-
-    // Ensure label_end is within the line's length 
-    if line_bytes.len() <= label_end {
-        return Err("error finding label end");
+    pub fn describe(&self) -> String {
+        match &self.command {
+            Some(command) => std::iter::once(command).chain(&self.args).cloned().collect::<Vec<_>>().join(" "),
+            None => platform::run_script().0.to_string(),
+        }
     }
+}
 
-    // Content starts 3 bytes after the label's end
-    let content_start = label_end + 3;
-
-    // ensure content_start is within the line's length 
-    if line_bytes.len() <= content_start {
-        return Err("invalid content");
+// Turns raw output into a line of text. Invalid UTF-8 is replaced rather than dropping the whole
+// line, and ANSI escape sequences and other control characters are removed so colored console
+// output doesn't break parsing or leak into discord.
+pub fn clean_line(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    let mut cleaned = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // CSI, eg. \x1b[0;32m, ends with a byte in @..~
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                },
+                // OSC, eg. window titles, ends with BEL or ESC \
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                },
+                _ => {},
+            },
+            '\t' => cleaned.push(' '),
+            c if c.is_control() => {},
+            c => cleaned.push(c),
+        }
     }
+    cleaned
+}
 
-    let label = match std::str::from_utf8(&line_bytes[label_start..label_end]) {
-        Ok(v) => v,
-        Err(_) => return Err("label not utf8"),
-    };
-
-    let content = match std::str::from_utf8(&line_bytes[content_start..]) {
+pub fn process_line(line: &str, sender: &mpsc::UnboundedSender<Packet>) {
+    // Lines that aren't in a known format, eg. stack traces, are still passed on without a
+    // label so they can be searched and alerted on
+    let log_line = match parse_line(line) {
         Ok(v) => v,
-        Err(_) => return Err("content not utf8"),
+        Err(_) if line.trim().is_empty() => return,
+        Err(_) => LogLine::unparsed(line),
     };
 
-    return Ok((label, content));
+    info!(target: "server", "[{}] {}", log_line.label(), log_line.content);
+    send_or_log(sender, Packet::LogLine(log_line));
 }
 
-fn process_line(line: &str, sender: &mpsc::UnboundedSender<Packet>) {
-    let (label, content) = match parse_line(line) {
-        Ok(v) => v,
-        Err(e) => {
-            println!("{} {}", e, line);
-            return;
-        },
-    };
-
-    send_or_log(sender, Packet::LogLine(label.to_string(), content.to_string()));
-    println!("Processed [{}] {}", label, content);
+// Splits off the complete lines, returning them and how many bytes they took up. Lines may end
+// with \n or \r\n since windows servers and run.bat output the latter.
+pub fn complete_lines(bytes: &[u8]) -> (Vec<String>, usize) {
+    let mut lines = Vec::new();
+    let mut line_start: usize = 0;
+    while let Some(offset) = bytes[line_start..].iter().position(|x| *x == b'\n') {
+        let line = &bytes[line_start..line_start + offset];
+        lines.push(clean_line(line.strip_suffix(b"\r").unwrap_or(line)));
+        line_start += offset + 1;
+    }
+    (lines, line_start)
 }
 
-fn spawn_line_processing_task<T: AsyncReadExt + Unpin + Send + 'static>(mut stdio: T, sender: mpsc::UnboundedSender<Packet>) {
+// When forward_lines is false the output is only printed, this is used when log lines are
+// read from the log file instead
+pub fn spawn_line_processing_task<T: AsyncReadExt + Unpin + Send + 'static>(mut stdio: T, sender: mpsc::UnboundedSender<Packet>, forward_lines: bool) {
     tokio::task::spawn(async move {
         let mut used: usize = 0;
         let mut buffer: [u8; 1000] = [0; 1000];
         loop {
             // drop data if buffer fills without any lines
             if used == buffer.len() {
-                println!("Buffer filled, dropping data");
+                warn!("Buffer filled, dropping data");
                 used = 0;
             }
 
             // read from 
             let bytes_read = match stdio.read(&mut buffer[used..]).await {
+                Ok(0) | Err(_) => break,
                 Ok(v) => v,
-                Err(_) => break,
             };
 
-            let old_used = used;
             used += bytes_read;
 
             // process completed lines
-            let mut line_start: usize = 0;
-            for i in old_used..used {
-                if buffer[i] == ('\n' as u8) {
-                    let line_end = if (line_start < i) && (buffer[i - 1] == '\r' as u8) { i - 1 } else { i };
-
-                    let line = match std::str::from_utf8(&buffer[line_start..line_end]) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            println!("Error: {}", e);
-                            continue;
-                        },
-                    };
-
-                    process_line(line, &sender);
-                    line_start = i + 1;
+            let (lines, line_start) = complete_lines(&buffer[..used]);
+            for line in lines {
+                if forward_lines {
+                    process_line(&line, &sender);
+                } else {
+                    info!(target: "server", "{}", line);
                 }
             }
 
@@ -121,49 +153,133 @@ fn spawn_line_processing_task<T: AsyncReadExt + Unpin + Send + 'static>(mut stdi
             }
         }
 
-        println!("stdio loop exited");
+        debug!("stdio loop exited");
     });
 }
 
-pub async fn start_process_wrapper(server_command: &str, server_command_args: &[String], sender: &mpsc::UnboundedSender<Packet>) {
-    let mut cmd = Command::new(server_command);
-    cmd.args(server_command_args);
+// How long the server gets to exit after SIGTERM before it is killed
+pub const KILL_GRACE: Duration = Duration::from_secs(10);
+
+// Lets the bot kill the server, eg. when it has hung. The server is sent SIGTERM first and
+// SIGKILL if it is still running after KILL_GRACE.
+#[derive(Clone)]
+pub struct KillHandle(mpsc::UnboundedSender<()>);
+
+impl KillHandle {
+    // The receiver gets a message each time the server should be killed
+    pub fn new() -> (KillHandle, mpsc::UnboundedReceiver<()>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (KillHandle(sender), receiver)
+    }
 
+    pub fn kill(&self) {
+        if self.0.send(()).is_err() {
+            error!("Error sending kill, server already exited");
+        }
+    }
+}
+
+// run.sh doesn't pass signals on to java, so the whole process group is signalled
+#[cfg(unix)]
+fn signal_process(child: &mut Child, force: bool) {
+    let pid = match child.id() {
+        Some(v) => v as libc::pid_t,
+        None => return,
+    };
+
+    let signal = if force { libc::SIGKILL } else { libc::SIGTERM };
+    if unsafe { libc::kill(-pid, signal) } != 0 {
+        error!("Error signalling child process {}", std::io::Error::last_os_error());
+    }
+}
+
+// There's nothing like SIGTERM on windows so the process is always killed. The whole tree is
+// killed since java is a child of the cmd running run.bat.
+#[cfg(not(unix))]
+fn signal_process(child: &mut Child, _force: bool) {
+    let pid = match child.id() {
+        Some(v) => v,
+        None => return,
+    };
+
+    let result = std::process::Command::new("taskkill").args(["/T", "/F", "/PID", &pid.to_string()]).status();
+    if let Err(e) = result {
+        error!("Error running taskkill {}", e);
+        if let Err(e) = child.start_kill() {
+            error!("Error killing child process {}", e);
+        }
+    }
+}
+
+pub async fn start_process_wrapper(launch: &LaunchToml, sender: &mpsc::UnboundedSender<Packet>, forward_lines: bool) {
+    let mut cmd = launch.command();
     cmd.stdout(Stdio::piped());
     cmd.stdin(Stdio::piped());
     cmd.stderr(Stdio::piped());
 
-    println!("Spawning child process");
-    let mut child = cmd.spawn().expect("failed to spawn command");
+    platform::new_process_group(&mut cmd);
+
+    info!("Spawning child process");
+    let mut child = match cmd.spawn() {
+        Ok(v) => v,
+        Err(e) => {
+            let problem = format!("Error starting the server with {}: {}", launch.describe(), e);
+            error!("{}", problem);
+            // Lets the server admin know the server isn't starting, so it can be started again
+            send_or_log(sender, Packet::PreflightFailed(vec![problem]));
+            return;
+        },
+    };
     
+    let (kill_handle, mut kill_receiver) = KillHandle::new();
     let stdin = child.stdin.take().expect("child did not have a handle to stdin");
-    send_or_log(sender, Packet::ProcessStarted(stdin));
+    send_or_log(sender, Packet::ProcessStarted(Console::start(stdin), child.id(), kill_handle));
     
     let stdout = child.stdout.take().expect("child did not have a handle to stdout");
-    spawn_line_processing_task(stdout, sender.clone());
+    spawn_line_processing_task(stdout, sender.clone(), forward_lines);
     
     let stderr = child.stderr.take().expect("child did not have a handle to stderr");
-    spawn_line_processing_task(stderr, sender.clone());
+    spawn_line_processing_task(stderr, sender.clone(), forward_lines);
 
-    let exit_status = child.wait().await;
-    println!("process exited {:?}", exit_status);
+    let exit_status = loop {
+        tokio::select! {
+            status = child.wait() => break status,
+            Some(()) = kill_receiver.recv() => {
+                info!("Terminating child process");
+                signal_process(&mut child, false);
+                tokio::select! {
+                    status = child.wait() => break status,
+                    _ = tokio::time::sleep(KILL_GRACE) => {},
+                }
 
-    send_or_log(sender, Packet::StopServer());
+                warn!("Child process didn't exit, killing it");
+                signal_process(&mut child, true);
+            },
+        }
+    };
+    info!("process exited {:?}", exit_status);
+
+    send_or_log(sender, Packet::ProcessExited());
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::process::parse_line;
+    use crate::process::{clean_line, complete_lines, LaunchToml};
+
+    #[test]
+    fn test_clean_line() {
+        assert_eq!(clean_line(b"\x1b[32m[12:00:00] [A] [INFO]: hi\x1b[0m"), "[12:00:00] [A] [INFO]: hi");
+        assert_eq!(clean_line("\x1b]0;title\x07Zoë said ☃".as_bytes()), "Zoë said ☃");
+        assert_eq!(clean_line(b"bad \xff byte"), "bad \u{fffd} byte");
+
+        assert_eq!(complete_lines(b"one\r\ntwo\n\r\nthr"), (vec!["one".to_string(), "two".to_string(), String::new()], 11));
+        assert_eq!(complete_lines(b"partial"), (Vec::new(), 0));
+    }
 
     #[test]
-    fn test_parse_line() {
-        assert_eq!(parse_line("[__:__:__] [A] [TEST1]: content").unwrap(), ("TEST1", "content"));
-        assert_eq!(parse_line("[__:__:__] [B] [TEST2]: A").unwrap(), ("TEST2", "A"));
-        assert_eq!(parse_line("[__:__:__] [] [TEST2]: A").unwrap(), ("TEST2", "A"));
-        assert_eq!(parse_line("[__:__:__] [] [TEST3]: ").unwrap_err(), "invalid content");
-        assert_eq!(parse_line("[__:__:__] [] [").unwrap_err(), "error finding label start");
-        assert_eq!(parse_line("[__:__:__] [] [abcdefg").unwrap_err(), "error finding label end");
-        assert_eq!(parse_line("[__:__:__] ").unwrap_err(), "too short");
-        assert_eq!(parse_line("A__:__:__] [] [").unwrap_err(), "invalid format");
+    fn test_launch() {
+        let launch: LaunchToml = toml::from_str("command = 'docker'\nargs = ['exec', '-i', 'mc', './run.sh']\nenv = { JAVA_HOME = '/opt/java' }").unwrap();
+        assert_eq!(launch.describe(), "docker exec -i mc ./run.sh");
+        assert_eq!(LaunchToml::default().describe(), crate::platform::run_script().0);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,79 @@
+// Reads resource usage of the server process from /proc. The server command is usually a shell
+// script that starts java as a child, so usage is summed over the whole process tree.
+
+#[cfg(target_os = "linux")]
+fn parent_pid(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The process name is wrapped in parentheses and can contain spaces, the parent pid is the
+    // second field after it
+    let after_name = &stat[stat.rfind(')')? + 2..];
+    after_name.split(' ').nth(1)?.parse().ok()
+}
+
+// Returns pid and the pids of all of its descendants
+#[cfg(target_os = "linux")]
+fn process_tree(pid: u32) -> Vec<u32> {
+    let mut parents: Vec<(u32, u32)> = Vec::new();
+    if let Ok(entries) = std::fs::read_dir("/proc") {
+        for entry in entries.flatten() {
+            let child: u32 = match entry.file_name().to_str().and_then(|x| x.parse().ok()) {
+                Some(v) => v,
+                None => continue,
+            };
+            if let Some(parent) = parent_pid(child) {
+                parents.push((child, parent));
+            }
+        }
+    }
+
+    let mut tree = vec![pid];
+    let mut i = 0;
+    while i < tree.len() {
+        let parent = tree[i];
+        tree.extend(parents.iter().filter(|x| x.1 == parent).map(|x| x.0));
+        i += 1;
+    }
+    tree
+}
+
+// Linux reports cpu time in clock ticks, this is 100 on practically every system
+pub const CLOCK_TICKS_PER_SEC: u64 = 100;
+
+pub struct Usage {
+    // Resident memory in bytes
+    pub memory: u64,
+    // Total user and system cpu time in clock ticks
+    pub cpu_ticks: u64,
+}
+
+// Samples the resource usage of the process tree
+#[cfg(target_os = "linux")]
+pub fn sample(pid: u32) -> Option<Usage> {
+    let mut usage = Usage { memory: 0, cpu_ticks: 0 };
+    for pid in process_tree(pid) {
+        if let Ok(status) = std::fs::read_to_string(format!("/proc/{}/status", pid)) {
+            // VmRSS:	  123456 kB
+            let rss_kb: u64 = status.lines()
+                .find_map(|line| line.strip_prefix("VmRSS:"))
+                .and_then(|x| x.trim().trim_end_matches("kB").trim().parse().ok())
+                .unwrap_or(0);
+            usage.memory += rss_kb * 1024;
+        }
+
+        // utime and stime are the 12th and 13th fields after the process name
+        if let Ok(stat) = std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+            if let Some(name_end) = stat.rfind(')') {
+                let fields: Vec<&str> = stat[name_end + 2..].split(' ').collect();
+                let utime: u64 = fields.get(11).and_then(|x| x.parse().ok()).unwrap_or(0);
+                let stime: u64 = fields.get(12).and_then(|x| x.parse().ok()).unwrap_or(0);
+                usage.cpu_ticks += utime + stime;
+            }
+        }
+    }
+    Some(usage)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn sample(_pid: u32) -> Option<Usage> {
+    None
+}
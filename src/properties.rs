@@ -0,0 +1,214 @@
+// Parser for java .properties files such as server.properties. Comments, blank lines and the
+// order of keys are kept so the file can be edited without losing anything.
+
+use std::path::Path;
+
+enum Line {
+    // Comments and blank lines, written back as they were
+    Other(String),
+    // The original text is kept so unchanged entries are written back as they were
+    Entry { key: String, value: String, text: String },
+}
+
+pub struct Properties {
+    lines: Vec<Line>,
+}
+
+// Counts the backslashes at the end of a line, an odd number means the line continues
+fn continues(line: &str) -> bool {
+    line.chars().rev().take_while(|x| *x == '\\').count() % 2 == 1
+}
+
+// Reads characters up to the first unescaped separator, resolving escapes
+fn unescape(chars: &mut std::iter::Peekable<std::str::Chars>, is_key: bool) -> String {
+    let mut text = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('t') => text.push('\t'),
+                Some('n') => text.push('\n'),
+                Some('r') => text.push('\r'),
+                Some('f') => text.push('\x0c'),
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let unit = u16::from_str_radix(&hex, 16).unwrap_or(0xFFFD);
+
+                    // Characters outside the BMP are written as a surrogate pair of escapes
+                    let mut units = vec![unit];
+                    if (0xD800..0xDC00).contains(&unit) && chars.clone().take(2).collect::<String>() == "\\u" {
+                        let hex: String = chars.clone().skip(2).take(4).collect();
+                        if let Ok(low) = u16::from_str_radix(&hex, 16) {
+                            units.push(low);
+                            chars.nth(5);
+                        }
+                    }
+                    text.extend(char::decode_utf16(units).map(|x| x.unwrap_or('\u{FFFD}')));
+                },
+                Some(c) => text.push(c),
+                None => {},
+            },
+            '=' | ':' if is_key => break,
+            c if is_key && c.is_whitespace() => break,
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+// Escapes text for a properties file, non-ascii characters are written as \uXXXX escapes
+// since the file is read as ISO-8859-1
+fn escape(text: &str, is_key: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (i, c) in text.chars().enumerate() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '=' | ':' | '#' | '!' if is_key => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            ' ' if is_key || i == 0 => escaped.push_str("\\ "),
+            c if c.is_ascii() => escaped.push(c),
+            c => {
+                let mut utf16 = [0; 2];
+                for unit in c.encode_utf16(&mut utf16) {
+                    escaped.push_str(&format!("\\u{:04X}", unit));
+                }
+            },
+        }
+    }
+    escaped
+}
+
+fn parse_entry(logical_line: &str) -> (String, String) {
+    let mut chars = logical_line.trim_start().chars().peekable();
+    let key = unescape(&mut chars, true);
+
+    // The separator may be surrounded by whitespace, and may itself be just whitespace
+    while chars.peek().map(|x| x.is_whitespace()).unwrap_or(false) {
+        chars.next();
+    }
+    if matches!(chars.peek(), Some('=') | Some(':')) {
+        chars.next();
+    }
+    while chars.peek().map(|x| x.is_whitespace()).unwrap_or(false) {
+        chars.next();
+    }
+
+    (key, unescape(&mut chars, false))
+}
+
+impl Properties {
+    pub fn parse(contents: &str) -> Properties {
+        let mut lines = Vec::new();
+        let mut physical_lines = contents.lines();
+        while let Some(line) = physical_lines.next() {
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('!') {
+                lines.push(Line::Other(line.to_string()));
+                continue;
+            }
+
+            // Join continuation lines, dropping the backslash and the next line's leading whitespace
+            let mut text = line.to_string();
+            let mut logical_line = line.to_string();
+            while continues(&logical_line) {
+                logical_line.pop();
+                match physical_lines.next() {
+                    Some(next) => {
+                        text.push('\n');
+                        text.push_str(next);
+                        logical_line.push_str(next.trim_start());
+                    },
+                    None => break,
+                }
+            }
+
+            let (key, value) = parse_entry(&logical_line);
+            lines.push(Line::Entry { key, value, text });
+        }
+        Properties { lines }
+    }
+
+    // Reads a properties file, which java writes as ISO-8859-1
+    pub fn read(path: impl AsRef<Path>) -> std::io::Result<Properties> {
+        let bytes = std::fs::read(path)?;
+        Ok(Properties::parse(&bytes.iter().map(|x| *x as char).collect::<String>()))
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        // Anything that isn't ISO-8859-1 was escaped when it was set
+        let bytes: Vec<u8> = self.to_string().chars().map(|x| if (x as u32) < 256 { x as u8 } else { b'?' }).collect();
+        std::fs::write(path, bytes)
+    }
+
+    // The last entry wins if a key is repeated, as in java
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.lines.iter().rev().find_map(|line| match line {
+            Line::Entry { key: k, value, .. } if k == key => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    // Replaces the value of every entry with key, appending it if the key isn't present
+    pub fn set(&mut self, key: &str, value: &str) {
+        let text = format!("{}={}", escape(key, true), escape(value, false));
+
+        let mut found = false;
+        for line in &mut self.lines {
+            if let Line::Entry { key: k, value: v, text: t } = line {
+                if k == key {
+                    *v = value.to_string();
+                    *t = text.clone();
+                    found = true;
+                }
+            }
+        }
+
+        if !found {
+            self.lines.push(Line::Entry { key: key.to_string(), value: value.to_string(), text });
+        }
+    }
+}
+
+impl std::fmt::Display for Properties {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Other(text) | Line::Entry { text, .. } => writeln!(f, "{}", text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::properties::Properties;
+
+    #[test]
+    fn test_parse() {
+        let properties = Properties::parse("#Minecraft server properties\n! also a comment\nmotd = A \\u00A7aMinecraft Server\nlevel-name:world\nlong=a \\\n    b\nspaced\\ key value\n");
+        assert_eq!(properties.get("motd"), Some("A §aMinecraft Server"));
+        assert_eq!(properties.get("level-name"), Some("world"));
+        assert_eq!(properties.get("long"), Some("a b"));
+        assert_eq!(properties.get("spaced key"), Some("value"));
+        assert_eq!(properties.get("also"), None);
+        assert_eq!(Properties::parse("emoji=\\uD83D\\uDE00").get("emoji"), Some("😀"));
+    }
+
+    #[test]
+    fn test_set() {
+        let mut properties = Properties::parse("#Minecraft server properties\n\nmotd=A Minecraft Server\npvp=true\n");
+        properties.set("motd", "Hello §a");
+        assert_eq!(properties.to_string(), "#Minecraft server properties\n\nmotd=Hello \\u00A7a\npvp=true\n");
+
+        let mut properties = Properties::parse("pvp=true");
+        properties.set("motd", "Hi");
+        properties.set("a key", " x");
+        assert_eq!(properties.to_string(), "pvp=true\nmotd=Hi\na\\ key=\\ x\n");
+        assert_eq!(Properties::parse(&properties.to_string()).get("a key"), Some(" x"));
+    }
+}
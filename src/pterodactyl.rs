@@ -0,0 +1,228 @@
+// Controls a server hosted on a Pterodactyl panel through its client API. Power actions and
+// console commands are API requests, log lines and the server's state come from the console
+// websocket that Wings serves.
+
+use std::time::Duration;
+
+use async_tungstenite::tungstenite::client::IntoClientRequest;
+use async_tungstenite::tungstenite::Message;
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::AsyncBufReadExt;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+
+use crate::console::Console;
+use crate::process::{clean_line, process_line, KillHandle};
+use crate::{send_or_log, Packet};
+
+// How long to wait before reconnecting to the console websocket
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+// Wings reports starting within a few seconds of the start request, if the server is still
+// offline after this it stopped before the console connected
+const START_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize, Clone)]
+pub struct PterodactylToml {
+    // The panel's address, eg. https://panel.example.com
+    pub url: String,
+    // A client API key from the account's API credentials page
+    pub api_key: String,
+    // The short id in the server's panel URL
+    pub server_id: String,
+}
+
+#[derive(Deserialize)]
+struct WebsocketResponse {
+    data: WebsocketDetails,
+}
+
+#[derive(Deserialize)]
+struct WebsocketDetails {
+    token: String,
+    socket: String,
+}
+
+// Events from Wings, eg. {"event":"console output","args":["[12:00:00] [Server thread/INFO]: Done"]}
+#[derive(Deserialize, PartialEq, Eq, Debug)]
+struct SocketEvent {
+    event: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+impl PterodactylToml {
+    async fn request(&self, method: reqwest::Method, path: &str, body: Option<Value>) -> Result<String, String> {
+        let url = format!("{}/api/client/servers/{}/{}", self.url.trim_end_matches('/'), self.server_id, path);
+        let mut request = reqwest::Client::new().request(method, &url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Accept", "application/json");
+        if let Some(body) = body {
+            request = request.header("Content-Type", "application/json").body(body.to_string());
+        }
+
+        request.send().await
+            .and_then(|x| x.error_for_status())
+            .map_err(|e| format!("Error querying {}: {}", url, e))?
+            .text().await
+            .map_err(|e| format!("Error querying {}: {}", url, e))
+    }
+
+    async fn power(&self, signal: &str) -> Result<(), String> {
+        self.request(reqwest::Method::POST, "power", Some(json!({ "signal": signal }))).await.map(|_| ())
+    }
+
+    async fn send_command(&self, command: &str) -> Result<(), String> {
+        self.request(reqwest::Method::POST, "command", Some(json!({ "command": command }))).await.map(|_| ())
+    }
+
+    async fn websocket_details(&self) -> Result<WebsocketDetails, String> {
+        let body = self.request(reqwest::Method::GET, "websocket", None).await?;
+        serde_json::from_str::<WebsocketResponse>(&body)
+            .map(|x| x.data)
+            .map_err(|e| format!("Error parsing websocket details: {}", e))
+    }
+}
+
+fn auth_message(token: &str) -> Message {
+    Message::Text(json!({ "event": "auth", "args": [token] }).to_string())
+}
+
+// What the bot does about an event from the console websocket
+#[derive(PartialEq, Eq, Debug)]
+enum SocketAction {
+    Line(String),
+    Status(String),
+    RefreshToken,
+    Reconnect,
+    Ignore,
+}
+
+fn parse_event(text: &str) -> SocketAction {
+    let event: SocketEvent = match serde_json::from_str(text) {
+        Ok(v) => v,
+        Err(_) => return SocketAction::Ignore,
+    };
+
+    match (event.event.as_str(), event.args.into_iter().next()) {
+        ("console output", Some(line)) => SocketAction::Line(line),
+        ("status", Some(status)) => SocketAction::Status(status),
+        ("token expiring", _) => SocketAction::RefreshToken,
+        ("token expired", _) | ("jwt error", _) => SocketAction::Reconnect,
+        _ => SocketAction::Ignore,
+    }
+}
+
+// Follows the console until the server goes offline, returning false if the connection was lost
+// first. seen_running is kept across reconnects so the offline status from before the server
+// starts isn't taken as it stopping, unless it's still offline at start_deadline.
+async fn follow_console(config: &PterodactylToml, sender: &mpsc::UnboundedSender<Packet>, forward_lines: bool, seen_running: &mut bool, start_deadline: Instant) -> Result<bool, String> {
+    let details = config.websocket_details().await?;
+    let mut request = details.socket.as_str().into_client_request().map_err(|e| format!("Invalid websocket address {}: {}", details.socket, e))?;
+    // Wings only accepts connections from the panel
+    let origin = config.url.trim_end_matches('/').parse().map_err(|_| format!("Invalid panel url {}", config.url))?;
+    request.headers_mut().insert("Origin", origin);
+
+    let (mut socket, _) = async_tungstenite::tokio::connect_async(request).await.map_err(|e| format!("Error connecting to {}: {}", details.socket, e))?;
+    socket.send(auth_message(&details.token)).await.map_err(|e| format!("Error authenticating console websocket: {}", e))?;
+    info!("Connected to the console websocket");
+
+    let mut offline = false;
+    loop {
+        let message = tokio::select! {
+            message = socket.next() => message,
+            _ = tokio::time::sleep_until(start_deadline), if offline && !*seen_running => {
+                warn!("The server stopped before the console connected");
+                return Ok(true);
+            },
+        };
+        let text = match message {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+            Some(Ok(_)) => continue,
+        };
+
+        match parse_event(&text) {
+            SocketAction::Line(line) if forward_lines => process_line(&clean_line(line.as_bytes()), sender),
+            SocketAction::Status(status) if status == "offline" => {
+                if *seen_running {
+                    return Ok(true);
+                }
+                offline = true;
+            },
+            SocketAction::Status(_) => *seen_running = true,
+            SocketAction::RefreshToken => {
+                let details = config.websocket_details().await?;
+                socket.send(auth_message(&details.token)).await.map_err(|e| format!("Error authenticating console websocket: {}", e))?;
+            },
+            SocketAction::Reconnect => break,
+            SocketAction::Line(_) | SocketAction::Ignore => {},
+        }
+    }
+    Ok(false)
+}
+
+// Starts the server and follows its console until it goes offline, sending the same packets as
+// a child process would. When forward_lines is false console output isn't passed on.
+pub async fn run_server(config: &PterodactylToml, sender: &mpsc::UnboundedSender<Packet>, forward_lines: bool) {
+    info!("Starting the server through the panel");
+    if let Err(e) = config.power("start").await {
+        error!("{}", e);
+        send_or_log(sender, Packet::PreflightFailed(vec![e]));
+        return;
+    }
+
+    // Console commands are sent one line at a time through the API
+    let (stdin, commands) = tokio::io::duplex(4096);
+    let command_config = config.clone();
+    tokio::task::spawn(async move {
+        let mut lines = tokio::io::BufReader::new(commands).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Err(e) = command_config.send_command(&line).await {
+                error!("{}", e);
+            }
+        }
+    });
+
+    let (kill_handle, mut kill_receiver) = KillHandle::new();
+    send_or_log(sender, Packet::ProcessStarted(Console::start(stdin), None, kill_handle));
+
+    let mut seen_running = false;
+    let start_deadline = Instant::now() + START_TIMEOUT;
+    loop {
+        tokio::select! {
+            result = follow_console(config, sender, forward_lines, &mut seen_running, start_deadline) => match result {
+                Ok(true) => break,
+                Ok(false) => warn!("Console websocket closed, reconnecting"),
+                Err(e) => error!("{}, reconnecting", e),
+            },
+            Some(()) = kill_receiver.recv() => {
+                info!("Killing the server through the panel");
+                if let Err(e) = config.power("kill").await {
+                    error!("{}", e);
+                }
+                continue;
+            },
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+    info!("Server is offline");
+
+    send_or_log(sender, Packet::ProcessExited());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pterodactyl::{parse_event, SocketAction};
+
+    #[test]
+    fn test_parse_event() {
+        assert_eq!(parse_event(r#"{"event":"console output","args":["[12:00:00] [Server thread/INFO]: Done"]}"#), SocketAction::Line("[12:00:00] [Server thread/INFO]: Done".to_string()));
+        assert_eq!(parse_event(r#"{"event":"status","args":["offline"]}"#), SocketAction::Status("offline".to_string()));
+        assert_eq!(parse_event(r#"{"event":"token expiring"}"#), SocketAction::RefreshToken);
+        assert_eq!(parse_event(r#"{"event":"stats","args":["{}"]}"#), SocketAction::Ignore);
+        assert_eq!(parse_event("not json"), SocketAction::Ignore);
+    }
+}
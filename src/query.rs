@@ -0,0 +1,189 @@
+// Client for the server's UDP query protocol (GS4), enabled with enable-query in
+// server.properties. The player list it returns is what the server itself thinks is online, so
+// it's used to fix up the players worked out from the log, along with world details for !status.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+
+use crate::properties::Properties;
+use crate::server_files::SERVER_PROPERTIES_PATH;
+use crate::{send_or_log, Packet};
+
+const MAGIC: [u8; 2] = [0xFE, 0xFD];
+const HANDSHAKE: u8 = 0x09;
+const STAT: u8 = 0x00;
+
+// Only the low 4 bits of each byte of the session id are used
+const SESSION_ID: i32 = 0x0102_0304;
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Deserialize)]
+pub struct QueryToml {
+    #[serde(default = "default_host")]
+    pub host: String,
+    // Read from query.port in server.properties when not set
+    pub port: Option<u16>,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct QueryInfo {
+    pub motd: String,
+    pub version: String,
+    pub map: String,
+    pub max_players: u32,
+    pub players: Vec<String>,
+}
+
+fn request(kind: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = MAGIC.to_vec();
+    packet.push(kind);
+    packet.extend(SESSION_ID.to_be_bytes());
+    packet.extend(payload);
+    packet
+}
+
+// Checks the type and session id, returning what comes after them
+fn response_body(response: &[u8], kind: u8) -> Result<&[u8], String> {
+    match response.split_first() {
+        Some((x, rest)) if *x == kind && rest.get(..4) == Some(&SESSION_ID.to_be_bytes()[..]) => Ok(&rest[4..]),
+        _ => Err("Unexpected query response".to_string()),
+    }
+}
+
+// Takes a null terminated string off the front of bytes
+fn take_string(bytes: &mut &[u8]) -> Option<String> {
+    let end = bytes.iter().position(|x| *x == 0)?;
+    let text = String::from_utf8_lossy(&bytes[..end]).to_string();
+    *bytes = &bytes[end + 1..];
+    Some(text)
+}
+
+// The handshake response holds the challenge token as a decimal string
+fn parse_challenge(response: &[u8]) -> Result<i32, String> {
+    let mut body = response_body(response, HANDSHAKE)?;
+    take_string(&mut body)
+        .and_then(|x| x.parse().ok())
+        .ok_or_else(|| "Invalid query challenge token".to_string())
+}
+
+// The full stat response is key value pairs then the player names, each list ends with an
+// empty string and is preceded by fixed padding
+fn parse_full_stat(response: &[u8]) -> Result<QueryInfo, String> {
+    let invalid = || "Invalid query response".to_string();
+    let mut body = response_body(response, STAT)?.get(11..).ok_or_else(invalid)?;
+
+    let mut info = QueryInfo::default();
+    loop {
+        let key = take_string(&mut body).ok_or_else(invalid)?;
+        if key.is_empty() {
+            break;
+        }
+        let value = take_string(&mut body).ok_or_else(invalid)?;
+        match key.as_str() {
+            "hostname" => info.motd = value,
+            "version" => info.version = value,
+            "map" => info.map = value,
+            "maxplayers" => info.max_players = value.parse().unwrap_or(0),
+            _ => {},
+        }
+    }
+
+    body = body.get(10..).ok_or_else(invalid)?;
+    while let Some(player) = take_string(&mut body) {
+        if player.is_empty() {
+            break;
+        }
+        info.players.push(player);
+    }
+    Ok(info)
+}
+
+async fn exchange(socket: &UdpSocket, packet: &[u8]) -> Result<Vec<u8>, String> {
+    socket.send(packet).await.map_err(|e| format!("Error sending query: {}", e))?;
+    let mut buffer = [0; 4096];
+    match tokio::time::timeout(TIMEOUT, socket.recv(&mut buffer)).await {
+        Ok(Ok(len)) => Ok(buffer[..len].to_vec()),
+        Ok(Err(e)) => Err(format!("Error receiving query response: {}", e)),
+        Err(_) => Err("No query response, is enable-query set in server.properties?".to_string()),
+    }
+}
+
+pub async fn query(host: &str, port: u16) -> Result<QueryInfo, String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await.map_err(|e| format!("Error binding query socket: {}", e))?;
+    socket.connect((host, port)).await.map_err(|e| format!("Error connecting to {}:{}: {}", host, port, e))?;
+
+    let challenge = parse_challenge(&exchange(&socket, &request(HANDSHAKE, &[])).await?)?;
+    let mut payload = challenge.to_be_bytes().to_vec();
+    payload.extend([0; 4]);
+    parse_full_stat(&exchange(&socket, &request(STAT, &payload)).await?)
+}
+
+impl QueryToml {
+    fn port(&self) -> u16 {
+        self.port
+            .or_else(|| Properties::read(SERVER_PROPERTIES_PATH).ok()?.get("query.port")?.parse().ok())
+            .unwrap_or(25565)
+    }
+}
+
+// Sends a QueryResult packet every interval
+pub fn start_query_timer(config: &QueryToml, sender: &mpsc::UnboundedSender<Packet>) {
+    let sender = sender.clone();
+    let host = config.host.clone();
+    let interval = Duration::from_secs(config.interval_secs.max(5));
+    let port = config.port();
+    tokio::task::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            send_or_log(&sender, Packet::QueryResult(query(&host, port).await));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query::{parse_challenge, parse_full_stat, request, QueryInfo, HANDSHAKE, SESSION_ID};
+
+    #[test]
+    fn test_query() {
+        assert_eq!(request(HANDSHAKE, &[]), vec![0xFE, 0xFD, 0x09, 0x01, 0x02, 0x03, 0x04]);
+
+        let session = SESSION_ID.to_be_bytes();
+        let mut handshake = vec![0x09];
+        handshake.extend(session);
+        handshake.extend(b"9513307\0");
+        assert_eq!(parse_challenge(&handshake), Ok(9513307));
+
+        let mut stat = vec![0x00];
+        stat.extend(session);
+        stat.extend(b"splitnum\0\x80\0");
+        for text in ["hostname", "A Minecraft Server", "gametype", "SMP", "version", "1.20.1", "map", "world", "numplayers", "2", "maxplayers", "20", ""] {
+            stat.extend(text.as_bytes());
+            stat.push(0);
+        }
+        stat.extend(b"\x01player_\0\0");
+        stat.extend(b"Steve\0Alex\0\0");
+        assert_eq!(parse_full_stat(&stat), Ok(QueryInfo {
+            motd: "A Minecraft Server".to_string(),
+            version: "1.20.1".to_string(),
+            map: "world".to_string(),
+            max_players: 20,
+            players: vec!["Steve".to_string(), "Alex".to_string()],
+        }));
+        assert!(parse_full_stat(&stat[..20]).is_err());
+    }
+}
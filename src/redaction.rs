@@ -0,0 +1,86 @@
+// Strips players' IP addresses and coordinates from log output before it's posted to discord,
+// eg. "Steve[/1.2.3.4:5555] logged in with entity id 123 at (100.5, 64.0, -200.5)". The admin
+// channel can be allowed to see them.
+
+use std::borrow::Cow;
+
+use regex::Regex;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct RedactionToml {
+    #[serde(default = "default_true")]
+    pub ips: bool,
+    #[serde(default = "default_true")]
+    pub coordinates: bool,
+    // Leaves log output posted to the admin channel as it is
+    #[serde(default)]
+    pub unredacted_in_admin: bool,
+}
+
+impl Default for RedactionToml {
+    fn default() -> RedactionToml {
+        RedactionToml { ips: true, coordinates: true, unredacted_in_admin: false }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+pub struct Redactor {
+    patterns: Vec<(Regex, &'static str)>,
+    unredacted_in_admin: bool,
+}
+
+impl Redactor {
+    pub fn new(config: &RedactionToml) -> Redactor {
+        let mut patterns = Vec::new();
+        if config.ips {
+            // Versions like 1.20.1-15.2.0.27 look like an address, so one can't follow a word,
+            // dot or dash
+            patterns.push((Regex::new(r"(^|[^\w.\-])/?\d{1,3}(?:\.\d{1,3}){3}(?::\d{1,5})?\b").unwrap(), "${1}[ip]"));
+            patterns.push((Regex::new(r"/\[[0-9a-fA-F:]*:[0-9a-fA-F:]*(?:%\w+)?\](?::\d{1,5})?").unwrap(), "[ip]"));
+        }
+        if config.coordinates {
+            let number = r"(?:[xyzXYZ]\s?[:=]\s?)?-?\d+(?:\.\d+)?";
+            patterns.push((Regex::new(&format!(r"(^|[^\w.\-]){n},\s?{n},\s?{n}\b", n = number)).unwrap(), "${1}[coords]"));
+        }
+        Redactor { patterns, unredacted_in_admin: config.unredacted_in_admin }
+    }
+
+    // admin is whether the text is going to the admin channel
+    pub fn redact<'a>(&self, text: &'a str, admin: bool) -> Cow<'a, str> {
+        if admin && self.unredacted_in_admin {
+            return Cow::Borrowed(text);
+        }
+        let mut text = Cow::Borrowed(text);
+        for (pattern, replacement) in &self.patterns {
+            if let Cow::Owned(replaced) = pattern.replace_all(&text, *replacement) {
+                text = Cow::Owned(replaced);
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::redaction::{RedactionToml, Redactor};
+
+    #[test]
+    fn test_redaction() {
+        let redactor = Redactor::new(&RedactionToml::default());
+        assert_eq!(redactor.redact("Steve[/1.2.3.4:5555] logged in with entity id 123 at (100.5, 64.0, -200.5)", false),
+            "Steve[[ip]] logged in with entity id 123 at ([coords])");
+        assert_eq!(redactor.redact("/[0:0:0:0:0:0:0:1]:53210 lost connection: Disconnected", false), "[ip] lost connection: Disconnected");
+        assert_eq!(redactor.redact("Steve moved too quickly! 12.3,4.5,-6.7", false), "Steve moved too quickly! [coords]");
+        assert_eq!(redactor.redact("Loading jei-1.20.1-15.2.0.27 at [12:00:00]", false), "Loading jei-1.20.1-15.2.0.27 at [12:00:00]");
+        assert_eq!(redactor.redact("Chunky Current: -23, 48", true), "Chunky Current: -23, 48");
+
+        let config: RedactionToml = toml::from_str("coordinates = false\nunredacted_in_admin = true").unwrap();
+        let redactor = Redactor::new(&config);
+        assert_eq!(redactor.redact("1.2.3.4 at 1, 2, 3", false), "[ip] at 1, 2, 3");
+        assert_eq!(redactor.redact("1.2.3.4 at 1, 2, 3", true), "1.2.3.4 at 1, 2, 3");
+    }
+}
@@ -0,0 +1,92 @@
+// Channels other than the chat channel whose messages are shown in game, eg. an announcements
+// channel. They're one way, nothing from the game is sent back and commands aren't run there.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serenity::model::id::ChannelId;
+use tracing::warn;
+
+#[derive(Deserialize)]
+pub struct RelayChannelToml {
+    pub channel_id: String,
+    // Shown before each message in game
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    // A Minecraft color name for the prefix
+    #[serde(default = "default_color")]
+    pub color: String,
+    // Shows who posted the message after the prefix
+    #[serde(default)]
+    pub show_author: bool,
+}
+
+fn default_prefix() -> String {
+    "[Announcement]".to_string()
+}
+
+fn default_color() -> String {
+    "gold".to_string()
+}
+
+pub struct RelayChannel {
+    pub prefix: String,
+    pub color: String,
+    pub show_author: bool,
+}
+
+#[derive(Default)]
+pub struct RelayChannels {
+    channels: HashMap<ChannelId, RelayChannel>,
+}
+
+impl RelayChannels {
+    pub fn new(config: &[RelayChannelToml]) -> RelayChannels {
+        let mut channels = HashMap::new();
+        for channel in config {
+            match channel.channel_id.parse() {
+                Ok(v) => {
+                    let relay = RelayChannel { prefix: channel.prefix.clone(), color: channel.color.clone(), show_author: channel.show_author };
+                    channels.insert(ChannelId(v), relay);
+                },
+                Err(_) => warn!("Invalid relay channel id \"{}\"", channel.channel_id),
+            }
+        }
+        RelayChannels { channels }
+    }
+
+    pub fn get(&self, channel_id: ChannelId) -> Option<&RelayChannel> {
+        self.channels.get(&channel_id)
+    }
+}
+
+// The text to show for a message. Announcements posted by bots and webhooks are often only an
+// embed, so its title and description are used when there's no content.
+pub fn message_text(content: &str, embed_title: Option<&str>, embed_description: Option<&str>) -> Option<String> {
+    if !content.trim().is_empty() {
+        return Some(content.trim().to_string());
+    }
+    let parts: Vec<&str> = [embed_title, embed_description].into_iter().flatten().collect();
+    (!parts.is_empty()).then(|| parts.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::id::ChannelId;
+
+    use crate::relay_channels::{message_text, RelayChannels, RelayChannelToml};
+
+    #[test]
+    fn test_relay_channels() {
+        let config: Vec<RelayChannelToml> = ["channel_id = '123'", "channel_id = 'news'\nprefix = '[News]'"].iter()
+            .map(|x| toml::from_str(x).unwrap())
+            .collect();
+        let channels = RelayChannels::new(&config);
+        assert_eq!(channels.get(ChannelId(123)).map(|x| (x.prefix.as_str(), x.color.as_str())), Some(("[Announcement]", "gold")));
+        assert!(channels.get(ChannelId(456)).is_none());
+
+        assert_eq!(message_text(" Server restarting at 6 ", Some("Ignored"), None).as_deref(), Some("Server restarting at 6"));
+        assert_eq!(message_text("", Some("Update 1.2"), Some("New mods")).as_deref(), Some("Update 1.2\nNew mods"));
+        assert_eq!(message_text("", None, None), None);
+    }
+}
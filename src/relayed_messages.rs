@@ -0,0 +1,54 @@
+use std::collections::VecDeque;
+
+use serenity::model::id::MessageId;
+
+// Edits and deletes are only mirrored for this many of the most recent relayed messages
+const MAX_RELAYED: usize = 200;
+
+// Discord messages that were relayed into the game and who sent them, so edits and deletes
+// are only mirrored for messages that passed the chat permission check
+#[derive(Default)]
+pub struct RelayedMessages {
+    messages: VecDeque<(MessageId, String)>,
+}
+
+impl RelayedMessages {
+    pub fn push(&mut self, id: MessageId, author: &str) {
+        if self.messages.len() == MAX_RELAYED {
+            self.messages.pop_front();
+        }
+        self.messages.push_back((id, author.to_string()));
+    }
+
+    // Returns the author of a relayed message
+    pub fn author(&self, id: MessageId) -> Option<&str> {
+        self.messages.iter().find(|x| x.0 == id).map(|x| x.1.as_str())
+    }
+
+    // Forgets a deleted message, returning its author
+    pub fn remove(&mut self, id: MessageId) -> Option<String> {
+        let index = self.messages.iter().position(|x| x.0 == id)?;
+        self.messages.remove(index).map(|x| x.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::id::MessageId;
+
+    use crate::relayed_messages::{RelayedMessages, MAX_RELAYED};
+
+    #[test]
+    fn test_relayed_messages() {
+        let mut relayed = RelayedMessages::default();
+        for i in 0..=MAX_RELAYED as u64 {
+            relayed.push(MessageId(i), "alice");
+        }
+
+        // The oldest message was dropped
+        assert_eq!(relayed.author(MessageId(0)), None);
+        assert_eq!(relayed.author(MessageId(1)), Some("alice"));
+        assert_eq!(relayed.remove(MessageId(1)), Some("alice".to_string()));
+        assert_eq!(relayed.remove(MessageId(1)), None);
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use serde::{Serialize, Deserialize};
+
+use crate::playtime::{format_duration, Session};
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+// Deaths older than this are dropped, they're only kept for weekly reports
+const DEATH_HISTORY_MILLIS: u64 = 8 * DAY_MILLIS;
+
+const TOP_PLAYERS: usize = 3;
+const REPORT_DEATHS: usize = 5;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Death {
+    pub player: String,
+    pub message: String,
+    // Unix time in milliseconds
+    pub time: u64,
+}
+
+// Adds a death, dropping any that are too old to be in a report
+pub fn record_death(deaths: &mut Vec<Death>, death: Death) {
+    let cutoff = death.time.saturating_sub(DEATH_HISTORY_MILLIS);
+    deaths.retain(|x| x.time >= cutoff);
+    deaths.push(death);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReportPeriod {
+    Day,
+    Week,
+}
+
+impl ReportPeriod {
+    fn length_millis(&self) -> u64 {
+        match self {
+            ReportPeriod::Day => DAY_MILLIS,
+            ReportPeriod::Week => 7 * DAY_MILLIS,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            ReportPeriod::Day => "Daily summary",
+            ReportPeriod::Week => "Weekly summary",
+        }
+    }
+}
+
+// Finds the most players that were online at once, from (start, end) intervals
+fn peak_concurrency(intervals: &[(u64, u64)]) -> usize {
+    // Ends sort before starts at the same time so back to back sessions don't overlap
+    let mut changes: Vec<(u64, i32)> = intervals.iter().flat_map(|(start, end)| [(*start, 1), (*end, -1)]).collect();
+    changes.sort();
+
+    let (mut online, mut peak) = (0, 0);
+    for (_, change) in changes {
+        online += change;
+        peak = peak.max(online);
+    }
+    peak as usize
+}
+
+// Summarises activity in the period ending at now. online maps players that are still online
+// to the unix time they logged in at, display_name gives the name to show for a player's id.
+pub fn build_report(period: ReportPeriod, sessions: &[Session], online: &HashMap<String, u64>, deaths: &[Death], display_name: impl Fn(&str) -> String, now: u64) -> String {
+    let start = now.saturating_sub(period.length_millis());
+
+    let intervals: Vec<(&str, u64, u64)> = sessions.iter()
+        .map(|x| (x.player.as_str(), x.start, x.end))
+        .chain(online.iter().map(|(player, login_time)| (player.as_str(), *login_time, now)))
+        .map(|(player, session_start, session_end)| (player, session_start.max(start), session_end.min(now)))
+        .filter(|(_, session_start, session_end)| session_end > session_start)
+        .collect();
+
+    let mut totals: HashMap<&str, u64> = HashMap::new();
+    for (player, session_start, session_end) in &intervals {
+        *totals.entry(player).or_insert(0) += session_end - session_start;
+    }
+    let mut totals: Vec<(&str, u64)> = totals.into_iter().collect();
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    let mut report = format!("**{}**\n", period.title());
+    if totals.is_empty() {
+        report.push_str("Nobody played");
+        return report;
+    }
+
+    let total: u64 = totals.iter().map(|x| x.1).sum();
+    let peak = peak_concurrency(&intervals.iter().map(|x| (x.1, x.2)).collect::<Vec<_>>());
+    let _ = writeln!(report, "Players: {}\nPeak online: {}\nTotal play time: {}", totals.len(), peak, format_duration(total as u128));
+
+    let top: Vec<String> = totals.iter().take(TOP_PLAYERS).map(|(player, millis)| format!("{} ({})", display_name(player), format_duration(*millis as u128))).collect();
+    let _ = write!(report, "Top players: {}", top.join(", "));
+
+    let deaths: Vec<&Death> = deaths.iter().filter(|x| x.time >= start && x.time <= now).collect();
+    if !deaths.is_empty() {
+        let _ = write!(report, "\nDeaths: {}", deaths.len());
+        for death in deaths.iter().rev().take(REPORT_DEATHS) {
+            let _ = write!(report, "\n- {}", death.message);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::playtime::Session;
+    use crate::report::{build_report, record_death, peak_concurrency, Death, ReportPeriod, DAY_MILLIS};
+
+    #[test]
+    fn test_build_report() {
+        assert_eq!(peak_concurrency(&[(0, 10), (5, 15), (10, 20)]), 2);
+
+        let hour = 60 * 60 * 1000;
+        let now = 10 * DAY_MILLIS;
+        let sessions = vec![
+            Session { player: "Steve".to_string(), start: now - 3 * hour, end: now - hour, afk: 0 },
+            Session { player: "Alex".to_string(), start: now - 2 * hour, end: now - hour, afk: 0 },
+            // Only the last hour of this one is in the report
+            Session { player: "Alex".to_string(), start: now - DAY_MILLIS - hour, end: now - DAY_MILLIS + hour, afk: 0 },
+            Session { player: "Notch".to_string(), start: now - 3 * DAY_MILLIS, end: now - 2 * DAY_MILLIS, afk: 0 },
+        ];
+        let online = HashMap::from([("Jöhn".to_string(), now - hour / 2)]);
+
+        let mut deaths = Vec::new();
+        record_death(&mut deaths, Death { player: "Notch".to_string(), message: "Notch fell out of the world".to_string(), time: 0 });
+        record_death(&mut deaths, Death { player: "Steve".to_string(), message: "Steve was slain by Zombie".to_string(), time: now - hour });
+        assert_eq!(deaths.len(), 1);
+
+        assert_eq!(
+            build_report(ReportPeriod::Day, &sessions, &online, &deaths, |x| x.to_string(), now),
+            "**Daily summary**\nPlayers: 3\nPeak online: 2\nTotal play time: 4h 30m\nTop players: Alex (2h 00m), Steve (2h 00m), Jöhn (30m)\nDeaths: 1\n- Steve was slain by Zombie"
+        );
+        assert_eq!(build_report(ReportPeriod::Day, &[], &HashMap::new(), &[], |x| x.to_string(), now), "**Daily summary**\nNobody played");
+    }
+}
@@ -0,0 +1,103 @@
+use std::borrow::Cow;
+
+use serenity::model::channel::AttachmentType;
+use serenity::model::id::ChannelId;
+use serenity::prelude::*;
+use tracing::error;
+
+// Discord rejects messages longer than this many characters
+pub const MESSAGE_LIMIT: usize = 2000;
+
+// Default number of characters after which responses are uploaded as a .txt file instead
+pub fn default_attachment_threshold() -> usize {
+    6000
+}
+
+// Splits text into chunks that each fit in a single message once wrapped in a code block.
+// Chunks are split on line boundaries where possible, lines that are too long by themselves
+// are split on character boundaries.
+pub fn split_into_code_blocks(text: &str) -> Vec<String> {
+    // Leave room for the "```\n" and "```" fences
+    let max_len = MESSAGE_LIMIT - 7;
+
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    for line in text.lines() {
+        let mut line = line;
+        loop {
+            let needed = if chunk.is_empty() { line.len() } else { line.len() + 1 };
+            if chunk.len() + needed <= max_len {
+                if !chunk.is_empty() {
+                    chunk.push('\n');
+                }
+                chunk.push_str(line);
+                break;
+            }
+
+            if !chunk.is_empty() {
+                chunks.push(std::mem::take(&mut chunk));
+                continue;
+            }
+
+            // The line doesn't fit in an empty chunk, so split it
+            let mut split = max_len;
+            while !line.is_char_boundary(split) {
+                split -= 1;
+            }
+            chunks.push(line[..split].to_string());
+            line = &line[split..];
+        }
+    }
+
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+
+    chunks.into_iter().map(|chunk| format!("```\n{}```", chunk)).collect()
+}
+
+// Sends text as one or more code block messages, or as a .txt attachment when it is longer
+// than attachment_threshold characters
+pub async fn send_code_block(channel_id: ChannelId, ctx: &Context, title: &str, text: &str, attachment_threshold: usize) {
+    if text.chars().count() > attachment_threshold {
+        let file = AttachmentType::Bytes {
+            data: Cow::Borrowed(text.as_bytes()),
+            filename: "response.txt".to_string(),
+        };
+
+        if let Err(e) = channel_id.send_files(&ctx.http, [file], |m| m.content(title)).await {
+            error!("Error sending attachment: {:?}", e);
+        }
+        return;
+    }
+
+    if !title.is_empty() {
+        crate::say_or_log(channel_id, ctx, title).await;
+    }
+
+    for chunk in split_into_code_blocks(text) {
+        crate::say_or_log(channel_id, ctx, &chunk).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::response::{split_into_code_blocks, MESSAGE_LIMIT};
+
+    #[test]
+    fn test_split_into_code_blocks() {
+        assert_eq!(split_into_code_blocks("a\nb"), vec!["```\na\nb```"]);
+        assert!(split_into_code_blocks("").is_empty());
+
+        let line = "x".repeat(1000);
+        let text = format!("{line}\n{line}\n{line}");
+        let chunks = split_into_code_blocks(&text);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= MESSAGE_LIMIT));
+
+        let long_line = "é".repeat(3000);
+        let chunks = split_into_code_blocks(&long_line);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= MESSAGE_LIMIT));
+        assert_eq!(chunks.concat().replace("```\n", "").replace("```", ""), long_line);
+    }
+}
@@ -0,0 +1,209 @@
+use std::time::Duration;
+
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::{Packet, send_or_log};
+use crate::report::ReportPeriod;
+use crate::uptime::RestartReason;
+
+// Minutes before a scheduled restart that players are warned in game
+pub const RESTART_WARNINGS: [u64; 3] = [15, 5, 1];
+
+#[derive(Deserialize, Default)]
+pub struct ScheduleToml {
+    // Local times of day, formatted as "HH:MM", that the server is restarted at
+    #[serde(default)]
+    pub daily_restarts: Vec<String>,
+
+    // Local times of day that the world is backed up at
+    #[serde(default)]
+    pub daily_backups: Vec<String>,
+
+    // Activity summaries posted to the chat channel, daily as "HH:MM" and weekly as
+    // eg. "mon 09:00"
+    pub daily_report: Option<String>,
+    pub weekly_report: Option<String>,
+}
+
+pub fn parse_times(times: &[String]) -> Result<Vec<NaiveTime>, String> {
+    times.iter()
+        .map(|time| NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| format!("Invalid time \"{}\", expected HH:MM", time)))
+        .collect()
+}
+
+pub fn parse_weekly_time(text: &str) -> Result<(Weekday, NaiveTime), String> {
+    let error = || format!("Invalid weekly time \"{}\", expected eg. \"mon 09:00\"", text);
+    let (day, time) = text.split_once(' ').ok_or_else(error)?;
+    let day = day.parse::<Weekday>().map_err(|_| error())?;
+    let time = NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|_| error())?;
+    Ok((day, time))
+}
+
+// Finds the first time after now that is on the given day of the week at time
+pub fn next_weekly_occurrence(day: Weekday, time: NaiveTime, now: NaiveDateTime) -> NaiveDateTime {
+    let days_ahead = (7 + day.num_days_from_monday() as i64 - now.weekday().num_days_from_monday() as i64) % 7;
+    let next = (now.date() + chrono::Duration::days(days_ahead)).and_time(time);
+    if next > now { next } else { next + chrono::Duration::days(7) }
+}
+
+// Finds the first time after now that matches one of the times of day
+pub fn next_occurrence(times: &[NaiveTime], now: NaiveDateTime) -> Option<NaiveDateTime> {
+    times.iter()
+        .map(|time| {
+            let today = now.date().and_time(*time);
+            if today > now { today } else { today + chrono::Duration::days(1) }
+        })
+        .min()
+}
+
+// Sleeps until the given local time, returns immediately if it has already passed
+async fn sleep_until(time: NaiveDateTime) {
+    let time = match Local.from_local_datetime(&time).earliest() {
+        Some(v) => v,
+        None => return,
+    };
+
+    if let Ok(duration) = (time - Local::now()).to_std() {
+        tokio::time::sleep(duration).await;
+    }
+}
+
+// Sends RestartWarning packets ahead of each scheduled restart, followed by ScheduledRestart
+pub fn start_scheduler(times: Vec<NaiveTime>, sender: &mpsc::UnboundedSender<Packet>) {
+    if times.is_empty() {
+        return;
+    }
+
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        loop {
+            let now = Local::now().naive_local();
+            let next_restart = match next_occurrence(&times, now) {
+                Some(v) => v,
+                None => return,
+            };
+            info!("Next scheduled restart at {}", next_restart);
+
+            for minutes in RESTART_WARNINGS {
+                let warning_time = next_restart - chrono::Duration::minutes(minutes as i64);
+                if warning_time > Local::now().naive_local() {
+                    sleep_until(warning_time).await;
+                    send_or_log(&sender, Packet::RestartWarning(minutes));
+                }
+            }
+
+            sleep_until(next_restart).await;
+            send_or_log(&sender, Packet::ScheduledRestart(RestartReason::Scheduled));
+
+            // Make sure we've moved past this restart's time before scheduling the next one
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    });
+}
+
+// Restarts the server after the given number of minutes, warning players along the way
+// Sends a ScheduledBackup packet at each of the times
+pub fn start_backup_scheduler(times: Vec<NaiveTime>, sender: &mpsc::UnboundedSender<Packet>) {
+    if times.is_empty() {
+        return;
+    }
+
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        loop {
+            let next_backup = match next_occurrence(&times, Local::now().naive_local()) {
+                Some(v) => v,
+                None => return,
+            };
+
+            sleep_until(next_backup).await;
+            send_or_log(&sender, Packet::ScheduledBackup());
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    });
+}
+
+// Sends a SummaryReport packet at each of the report times
+pub fn start_report_scheduler(daily: Option<NaiveTime>, weekly: Option<(Weekday, NaiveTime)>, sender: &mpsc::UnboundedSender<Packet>) {
+    if let Some(time) = daily {
+        let sender = sender.clone();
+        tokio::task::spawn(async move {
+            loop {
+                sleep_until(next_occurrence(&[time], Local::now().naive_local()).unwrap()).await;
+                send_or_log(&sender, Packet::SummaryReport(ReportPeriod::Day));
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        });
+    }
+
+    if let Some((day, time)) = weekly {
+        let sender = sender.clone();
+        tokio::task::spawn(async move {
+            loop {
+                sleep_until(next_weekly_occurrence(day, time, Local::now().naive_local())).await;
+                send_or_log(&sender, Packet::SummaryReport(ReportPeriod::Week));
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        });
+    }
+}
+
+pub fn start_restart_countdown(minutes: u64, sender: &mpsc::UnboundedSender<Packet>, reason: RestartReason) {
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        send_or_log(&sender, Packet::RestartWarning(minutes));
+
+        let mut remaining = minutes;
+        for warning in RESTART_WARNINGS {
+            if warning < remaining {
+                tokio::time::sleep(Duration::from_secs((remaining - warning) * 60)).await;
+                send_or_log(&sender, Packet::RestartWarning(warning));
+                remaining = warning;
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(remaining * 60)).await;
+        send_or_log(&sender, Packet::ScheduledRestart(reason));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveTime, Weekday};
+    use crate::scheduler::{next_occurrence, next_weekly_occurrence, parse_times, parse_weekly_time};
+
+    #[test]
+    fn test_next_occurrence() {
+        let times = parse_times(&["04:00".to_string(), "16:30".to_string()]).unwrap();
+        let day = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+        let now = day.and_hms_opt(3, 0, 0).unwrap();
+        assert_eq!(next_occurrence(&times, now), Some(day.and_hms_opt(4, 0, 0).unwrap()));
+
+        let now = day.and_hms_opt(16, 30, 0).unwrap();
+        let tomorrow = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        assert_eq!(next_occurrence(&times, now), Some(tomorrow.and_hms_opt(4, 0, 0).unwrap()));
+
+        assert_eq!(next_occurrence(&[], now), None);
+        assert!(parse_times(&["4pm".to_string()]).is_err());
+        assert_eq!(parse_times(&["23:59".to_string()]).unwrap(), vec![NaiveTime::from_hms_opt(23, 59, 0).unwrap()]);
+    }
+
+    #[test]
+    fn test_next_weekly_occurrence() {
+        let (day, time) = parse_weekly_time("mon 09:00").unwrap();
+        assert_eq!(day, Weekday::Mon);
+        assert!(parse_weekly_time("09:00").is_err());
+        assert!(parse_weekly_time("someday 09:00").is_err());
+
+        // 2024-01-01 is a Monday
+        let monday = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        assert_eq!(next_weekly_occurrence(day, time, monday.and_hms_opt(8, 0, 0).unwrap()), monday.and_time(time));
+        let next_monday = NaiveDate::from_ymd_opt(2024, 1, 8).unwrap();
+        assert_eq!(next_weekly_occurrence(day, time, monday.and_hms_opt(9, 0, 0).unwrap()), next_monday.and_time(time));
+        assert_eq!(next_weekly_occurrence(day, time, NaiveDate::from_ymd_opt(2024, 1, 5).unwrap().and_hms_opt(12, 0, 0).unwrap()), next_monday.and_time(time));
+    }
+}
@@ -0,0 +1,553 @@
+// Starting, stopping, restarting, updating and backing up the server. Tracks what the server
+// process is doing so these don't overlap, and reports their progress to the admin channel.
+
+use std::time::Instant;
+
+use serenity::model::id::ChannelId;
+use tracing::{error, info, warn};
+
+use crate::bot::Bot;
+use crate::confirm::ConfirmAction;
+use crate::jvm_args::JVM_ARGS_PATH;
+use crate::disk_monitor::DiskMonitor;
+use crate::process::KillHandle;
+use crate::server_backend::ServerBackend;
+use crate::stats_tracker::StatsTracker;
+use crate::status_ping::{PingInfo, PingMonitor};
+use crate::update::UpdateKind;
+use crate::uptime::{LastRestart, RestartReason};
+use crate::watchdog::{self, TickStall, Watchdog, WatchdogAction, WatchdogCheck};
+use crate::{backup, disk_monitor, hooks, incident, log_buffer, maintenance, oom, preflight, response, shutdown, usage};
+use crate::{finish_shutdown, say_or_log, start_save_for_backup, start_server, start_update, ConfigToml, Packet};
+
+#[derive(Default)]
+pub struct ServerAdmin {
+    // Used to kill the server, set while it is running
+    kill_handle: Option<KillHandle>,
+
+    // Set while a restart is in progress, holds when the restart started
+    restart_started: Option<Instant>,
+
+    // Set when the server is stopping so an update or rollback can run, and while it is running
+    update_pending: Option<UpdateKind>,
+    updating: Option<UpdateKind>,
+
+    // Set while waiting for the server to save before a backup
+    backup_pending: Option<backup::BackupRequest>,
+    backup_running: bool,
+
+    // Set when the server was stopped with !stop, so the bot keeps running
+    stop_requested: bool,
+
+    // Set once the bot has been asked to exit and is waiting for the server to stop
+    shutting_down: bool,
+
+    // Set after an update until the server finishes booting, so a failed boot can be reported
+    awaiting_boot_after_update: bool,
+
+    // Thread that the running update's progress is posted in
+    update_thread: Option<ChannelId>,
+
+    watchdog: Option<Watchdog>,
+
+    // How long the tick the server's own watchdog reported took, and whether it's stopping the
+    // server because of it
+    stalled_tick_secs: Option<f64>,
+    stall_restart: bool,
+
+    // Set once an OutOfMemoryError has been reported, until the server is started again
+    oom_reported: bool,
+
+    // Set once the server has finished starting and should be answering pings
+    server_ready: bool,
+    ping: Option<PingMonitor>,
+
+    disk: DiskMonitor,
+}
+
+impl ServerAdmin {
+    pub fn new(config: &ConfigToml) -> ServerAdmin {
+        ServerAdmin {
+            watchdog: config.watchdog.as_ref().map(Watchdog::new),
+            ping: config.status_ping.as_ref().map(PingMonitor::new),
+            disk: DiskMonitor::new(&config.disk),
+            ..ServerAdmin::default()
+        }
+    }
+
+    // Whether an update is waiting for the server to stop or is running
+    pub fn is_updating(&self) -> bool {
+        self.update_pending.is_some() || self.updating.is_some()
+    }
+
+    pub fn is_restarting(&self) -> bool {
+        self.restart_started.is_some()
+    }
+
+    pub fn is_killable(&self) -> bool {
+        self.kill_handle.is_some()
+    }
+
+    pub fn latest_ping(&self) -> Option<PingInfo> {
+        self.ping.as_ref().and_then(|x| x.latest.clone())
+    }
+
+    // Called for each line the server logs, announcing when a hung server is responding again
+    pub async fn server_output(&mut self, bot: &Bot) {
+        if self.watchdog.as_mut().is_some_and(|x| x.output()) {
+            bot.say_admin("Server is responding again").await;
+        }
+    }
+
+    // The server finished starting
+    pub async fn server_done(&mut self, bot: &Bot) {
+        bot.say(bot.channel_id, bot.locale.get("server.started")).await;
+        bot.hooks.run(hooks::HookEvent::ServerStarted);
+        self.awaiting_boot_after_update = false;
+        self.server_ready = true;
+        if let Some(thread) = self.update_thread.take() {
+            bot.say(thread, "Server started").await;
+        }
+
+        if let Some(started) = self.restart_started.take() {
+            bot.say_admin(&format!("Restart complete, server was down for {} seconds", started.elapsed().as_secs())).await;
+        }
+    }
+
+    // The server saved the world, so a backup waiting for it can start
+    pub fn world_saved(&mut self, bot: &Bot) {
+        if let (Some(request), Some(ctx)) = (self.backup_pending.take(), &bot.ctx) {
+            self.backup_running = true;
+            backup::start_backup(ctx.clone(), request, &bot.config.backup, &bot.sender);
+        }
+    }
+
+    pub async fn handle(&mut self, bot: &mut Bot, stats: &mut StatsTracker, packet: Packet) {
+        match packet {
+            Packet::ProcessStarted(console, pid, new_kill_handle) => {
+                bot.console = Some(console);
+                self.kill_handle = Some(new_kill_handle);
+                self.server_ready = false;
+                self.oom_reported = false;
+                if let Some(watchdog) = &mut self.watchdog {
+                    watchdog.reset();
+                }
+
+                let mut metrics = bot.metrics.lock().unwrap();
+                metrics.server_started = Some(Instant::now());
+                metrics.pid = pid;
+                info!("Process started");
+            },
+            Packet::ProcessExited() => self.process_exited(bot, stats).await,
+            Packet::StdinLine(line) => bot.write_console(&line).await,
+            Packet::ListPlayers() => bot.write_console("list").await,
+            Packet::RestartWarning(minutes) => {
+                let plural = if minutes == 1 { "" } else { "s" };
+                bot.write_console(&format!("say Server restarting in {} minute{}", minutes, plural)).await;
+            },
+            Packet::ScheduledRestart(reason) => {
+                if bot.console.is_none() {
+                    info!("Server not running, skipping scheduled restart");
+                    return;
+                }
+
+                stats.state.last_restart = Some(LastRestart::now(reason));
+                stats.state.write();
+
+                bot.write_console("save-all").await;
+                bot.write_console("stop").await;
+                self.restart_started = Some(Instant::now());
+                bot.metrics.lock().unwrap().restarts += 1;
+
+                bot.say(bot.channel_id, bot.locale.get("server.restarting")).await;
+                bot.say_admin("Restart started, world saved and server stopping").await;
+            },
+            Packet::Confirmed(channel_id, action) => self.confirmed(bot, stats, channel_id, action).await,
+            Packet::MemoryWarning(memory, max_heap) => {
+                bot.say_admin(&format!("Warning: server memory usage is {} of the {} max heap", usage::format_bytes(memory), usage::format_bytes(max_heap))).await;
+            },
+            Packet::UpdateProgress(progress) => {
+                info!("{}", progress);
+                if let Some(progress_channel_id) = self.update_thread.or(bot.admin_channel_id) {
+                    bot.say(progress_channel_id, &progress).await;
+                }
+            },
+            Packet::UpdateFinished(result) => {
+                let name = self.updating.take().map(|x| x.name()).unwrap_or("Update");
+
+                let message = match &result {
+                    Ok(_) => format!("{} finished, starting server", name),
+                    Err(e) => format!("{} failed: {}\nThe server has not been started, use `!start` once the problem is fixed", name, e),
+                };
+                info!("{}", message);
+                if let Some(progress_channel_id) = self.update_thread.or(bot.admin_channel_id) {
+                    bot.say(progress_channel_id, &message).await;
+                }
+                if result.is_err() {
+                    self.update_thread = None;
+                }
+
+                if result.is_ok() {
+                    self.awaiting_boot_after_update = true;
+                    start_server(&bot.sender, &bot.config);
+                }
+            },
+            Packet::ScheduledBackup() => {
+                let request = backup::BackupRequest { channel_id: bot.admin_channel_id, scheduled: true };
+                self.start_backup(bot, request).await;
+            },
+            Packet::BackupRequested(request) => self.start_backup(bot, request).await,
+            Packet::BackupFinished(path) => {
+                self.backup_running = false;
+                bot.write_console("save-on").await;
+                if let Some(path) = path {
+                    bot.hooks.run(hooks::HookEvent::BackupComplete { path: path.display().to_string() });
+                }
+            },
+            Packet::WatchdogTick() => self.check_watchdog(bot, stats).await,
+            Packet::NewVersion(message) => bot.say_admin(&message).await,
+            Packet::DiskTick() => {
+                for message in self.disk.tick(&bot.config) {
+                    warn!("{}", message);
+                    bot.say_admin(&message).await;
+                }
+            },
+            Packet::PreflightFailed(problems) => {
+                warn!("Server not started: {:?}", problems);
+                self.restart_started = None;
+                self.awaiting_boot_after_update = false;
+                let message = format!("The server wasn't started:\n{}\nUse `!start` once it's fixed", preflight::describe(&problems));
+                match self.update_thread.take() {
+                    Some(thread) => bot.say(thread, &message).await,
+                    None => bot.say_admin(&message).await,
+                }
+            },
+            Packet::StatusPing(result) => self.status_ping(bot, stats, result).await,
+            Packet::Shutdown() => {
+                // Sessions are saved first in case the server doesn't stop in time
+                stats.end_sessions("Bot shutting down");
+
+                if bot.config.stop_server_on_shutdown && bot.console.is_some() {
+                    self.shutting_down = true;
+                    stats.state.last_restart = Some(LastRestart::now(RestartReason::Manual));
+                    stats.state.write();
+                    bot.say(bot.channel_id, bot.locale.get("server.stopping_for_bot")).await;
+                    bot.write_console("stop").await;
+                    shutdown::start_stop_timeout(&bot.sender);
+                    return;
+                }
+
+                finish_shutdown(&bot.ctx, &mut bot.log_mirror).await;
+            },
+            Packet::StallTimedOut() => {
+                if let (true, Some(kill_handle)) = (self.stall_restart, &self.kill_handle) {
+                    warn!("Server didn't exit after its watchdog stopped it, killing it");
+                    kill_handle.kill();
+                }
+            },
+            Packet::ShutdownTimedOut() => {
+                warn!("Server didn't stop in time, exiting anyway");
+                finish_shutdown(&bot.ctx, &mut bot.log_mirror).await;
+            },
+            Packet::StopServer() if bot.ctx.is_some() => {
+                bot.say(bot.channel_id, bot.locale.get("server.shutdown")).await;
+                std::process::exit(0);
+            },
+            _ => {},
+        }
+    }
+
+    // Runs an admin action once it has been confirmed
+    async fn confirmed(&mut self, bot: &mut Bot, stats: &mut StatsTracker, channel_id: ChannelId, action: ConfirmAction) {
+        match action {
+            ConfirmAction::Update(kind) => {
+                if self.is_updating() {
+                    bot.say(channel_id, "An update is already running").await;
+                    return;
+                }
+                if ServerBackend::from_config(&bot.config).is_process() {
+                    let problems = preflight::check_update(&bot.config.launch, &bot.config.preflight).await;
+                    if !problems.is_empty() {
+                        bot.say(channel_id, &format!("The {} wasn't started:\n{}", kind.name(), preflight::describe(&problems))).await;
+                        return;
+                    }
+                }
+
+                if let (true, Some(ctx)) = (bot.config.incident_threads, &bot.ctx) {
+                    let parent = bot.admin_channel_id.unwrap_or(channel_id);
+                    self.update_thread = Some(incident::start_thread(parent, ctx, kind.name()).await);
+                }
+
+                if bot.console.is_some() {
+                    stats.state.last_restart = Some(LastRestart::now(RestartReason::Manual));
+                    stats.state.write();
+                    bot.say(self.update_thread.unwrap_or(channel_id), "Stopping server").await;
+                    bot.write_console("stop").await;
+                    self.update_pending = Some(kind);
+                } else {
+                    self.updating = Some(kind.clone());
+                    start_update(&bot.config, &bot.sender, kind);
+                }
+            },
+            ConfirmAction::Stop => {
+                if bot.console.is_none() {
+                    bot.say(channel_id, "Server is not running").await;
+                    return;
+                }
+
+                self.stop_requested = true;
+                stats.state.last_restart = Some(LastRestart::now(RestartReason::Manual));
+                stats.state.write();
+                bot.write_console("save-all").await;
+                bot.write_console("stop").await;
+            },
+            ConfirmAction::Kill => {
+                let kill_handle = match &self.kill_handle {
+                    Some(v) => v,
+                    None => {
+                        bot.say(channel_id, "Server is not running").await;
+                        return;
+                    },
+                };
+
+                // Handled like !stop once the process exits, so it isn't restarted
+                self.stop_requested = true;
+                self.restart_started = None;
+                stats.state.last_restart = Some(LastRestart::now(RestartReason::Manual));
+                stats.state.write();
+                bot.say(channel_id, "Killing server").await;
+                kill_handle.kill();
+            },
+            // Console commands are run by the discord handler
+            ConfirmAction::Console(..) => {},
+        }
+    }
+
+    // Manual backups reply in the channel they were requested from
+    async fn start_backup(&mut self, bot: &Bot, request: backup::BackupRequest) {
+        let ctx = match &bot.ctx {
+            Some(v) => v,
+            None => return,
+        };
+        let reply_channel_id = request.channel_id.filter(|_| !request.scheduled);
+
+        if self.backup_pending.is_some() || self.backup_running {
+            match reply_channel_id {
+                Some(channel_id) => say_or_log(channel_id, ctx, "A backup is already running").await,
+                None => info!("A backup is already running, skipping scheduled backup"),
+            }
+            return;
+        }
+
+        if let Some(problem) = disk_monitor::check_backup(&bot.config) {
+            warn!("{}", problem);
+            match request.channel_id {
+                Some(channel_id) => say_or_log(channel_id, ctx, &problem).await,
+                None => bot.say_admin(&problem).await,
+            }
+            return;
+        }
+
+        if bot.console.is_some() {
+            if let Some(channel_id) = reply_channel_id {
+                say_or_log(channel_id, ctx, "Saving the world").await;
+            }
+            start_save_for_backup(&bot.console).await;
+            self.backup_pending = Some(request);
+        } else {
+            if let Some(channel_id) = reply_channel_id {
+                say_or_log(channel_id, ctx, "Backing up the world").await;
+            }
+            self.backup_running = true;
+            backup::start_backup(ctx.clone(), request, &bot.config.backup, &bot.sender);
+        }
+    }
+
+    // Called for the lines the server's own watchdog logs when a tick takes too long. It stops the
+    // server itself, which is then started again rather than treated as a crash.
+    pub async fn tick_stall(&mut self, bot: &Bot, stats: &mut StatsTracker, stall: TickStall) {
+        match stall {
+            TickStall::Took(seconds) => self.stalled_tick_secs = Some(seconds),
+            TickStall::Crashed if self.stall_restart || self.restart_started.is_some() || self.shutting_down => {},
+            TickStall::Crashed => {
+                stats.state.last_restart = Some(LastRestart::now(RestartReason::Hung));
+                stats.state.write();
+                self.restart_started = Some(Instant::now());
+                self.stall_restart = true;
+                bot.metrics.lock().unwrap().restarts += 1;
+                bot.hooks.run(hooks::HookEvent::Crash);
+                watchdog::start_stall_timeout(&bot.sender);
+
+                let message = match self.stalled_tick_secs {
+                    Some(seconds) => format!("A server tick took {:.0} seconds so the server's watchdog is stopping it, it will be restarted once it's down", seconds),
+                    None => "The server's watchdog is stopping it, it will be restarted once it's down".to_string(),
+                };
+                warn!("{}", message);
+                bot.say_admin(&message).await;
+            },
+        }
+    }
+
+    // Called for each log line mentioning an OutOfMemoryError, only the first is reported since
+    // the stack traces repeat it
+    pub async fn out_of_memory(&mut self, bot: &Bot) {
+        if std::mem::replace(&mut self.oom_reported, true) {
+            return;
+        }
+
+        let (samples, pid) = {
+            let metrics = bot.metrics.lock().unwrap();
+            (metrics.memory_history.iter().copied().collect::<Vec<u64>>(), metrics.pid)
+        };
+        let max_heap = std::fs::read_to_string(JVM_ARGS_PATH).ok().and_then(|x| usage::parse_max_heap(&x));
+        let mut message = "The server ran out of memory".to_string();
+        if let Some(trend) = oom::describe_trend(&samples, bot.config.usage.sample_interval_secs, max_heap) {
+            message.push_str(&format!("\n{}", trend));
+        }
+        message.push_str(&format!("\n{}", oom::handle_heap_bump(&bot.config.oom)));
+        warn!("{}", message);
+        bot.say_admin(&message).await;
+
+        if let (Some(ctx), Some(admin_channel_id), Some(pid)) = (&bot.ctx, bot.admin_channel_id, pid) {
+            oom::collect(ctx, admin_channel_id, pid, bot.config.oom.heap_dump, bot.config.response_attachment_threshold);
+        }
+    }
+
+    // Posts the last log lines in a new thread in the admin channel
+    async fn post_log_excerpt(&self, bot: &Bot, title: &str) {
+        if let (Some(ctx), Some(admin_channel_id), true) = (&bot.ctx, bot.admin_channel_id, bot.config.incident_threads) {
+            let thread = incident::start_thread(admin_channel_id, ctx, title).await;
+            let text = log_buffer::format_entries(&bot.log_buffer.query(&log_buffer::LogQuery::Last(incident::CRASH_LOG_LINES)));
+            let text = bot.redactor.redact(&text, true);
+            response::send_code_block(thread, ctx, "Last log lines", &text, bot.config.response_attachment_threshold).await;
+        }
+    }
+
+    async fn check_watchdog(&mut self, bot: &Bot, stats: &mut StatsTracker) {
+        let watchdog = match (&mut self.watchdog, &bot.console) {
+            (Some(watchdog), Some(_)) => watchdog,
+            _ => return,
+        };
+
+        let quiet_mins = watchdog.timeout().as_secs() / 60;
+        let message = match watchdog.check() {
+            WatchdogCheck::Fine | WatchdogCheck::StillHung => return,
+            WatchdogCheck::Probe => {
+                bot.write_console("list").await;
+                return;
+            },
+            WatchdogCheck::Hung(WatchdogAction::Warn) => {
+                format!("Server hasn't logged anything for {} minutes, it may have hung", quiet_mins)
+            },
+            WatchdogCheck::Hung(WatchdogAction::Restart) => {
+                stats.state.last_restart = Some(LastRestart::now(RestartReason::Hung));
+                stats.state.write();
+                self.restart_started = Some(Instant::now());
+                bot.metrics.lock().unwrap().restarts += 1;
+                if let Some(kill_handle) = &self.kill_handle {
+                    kill_handle.kill();
+                }
+                format!("Server hasn't logged anything for {} minutes, killing and restarting it", quiet_mins)
+            },
+        };
+
+        warn!("{}", message);
+        bot.say_admin(&message).await;
+    }
+
+    async fn status_ping(&mut self, bot: &Bot, stats: &mut StatsTracker, result: Result<PingInfo, String>) {
+        let ping = match &mut self.ping {
+            Some(v) => v,
+            None => return,
+        };
+
+        // The port isn't open until the server has finished starting
+        if !self.server_ready || bot.console.is_none() {
+            ping.reset();
+            return;
+        }
+
+        if let Ok(info) = &result {
+            stats.capacity.ping_max = Some(info.max_players);
+        }
+        if let Some(message) = ping.result(result) {
+            warn!("{}", message);
+            bot.say_admin(&message).await;
+        }
+    }
+
+    async fn process_exited(&mut self, bot: &mut Bot, stats: &mut StatsTracker) {
+        bot.console = None;
+        self.kill_handle = None;
+        self.server_ready = false;
+
+        // Everyone was disconnected when the server stopped
+        stats.end_sessions("Server stopped");
+        bot.metrics.lock().unwrap().players_online = 0;
+
+        // The server saves when it stops so the backup can go ahead
+        self.world_saved(bot);
+
+        // Chunky carries on once the server is back, but there's no telling when
+        bot.pregen = None;
+
+        if let Some(motd) = bot.pending_motd.take() {
+            if let Err(e) = maintenance::write_properties(&motd, None) {
+                error!("{}", e);
+            }
+        }
+
+        {
+            let mut metrics = bot.metrics.lock().unwrap();
+            metrics.server_started = None;
+            metrics.pid = None;
+        }
+
+        if self.shutting_down {
+            finish_shutdown(&bot.ctx, &mut bot.log_mirror).await;
+        }
+
+        if let Some(kind) = self.update_pending.take() {
+            self.updating = Some(kind.clone());
+            start_update(&bot.config, &bot.sender, kind);
+            return;
+        }
+
+        // Keep the bot running so the update can be rolled back
+        if self.awaiting_boot_after_update {
+            self.awaiting_boot_after_update = false;
+            if let Some(progress_channel_id) = self.update_thread.take().or(bot.admin_channel_id) {
+                bot.say(progress_channel_id, "Server stopped before it finished starting after the update, use `!rollback` to restore the previous version").await;
+            }
+            return;
+        }
+
+        if self.stop_requested {
+            self.stop_requested = false;
+            bot.say(bot.channel_id, bot.locale.get("server.shutdown")).await;
+            bot.say_admin("Server stopped, use `!start` to start it again").await;
+            return;
+        }
+
+        if std::mem::take(&mut self.stall_restart) {
+            self.stalled_tick_secs = None;
+            self.post_log_excerpt(bot, "Server stalled").await;
+        }
+
+        if self.restart_started.is_some() {
+            info!("Restarting server");
+            start_server(&bot.sender, &bot.config);
+            return;
+        }
+
+        // Nothing asked the server to stop, the next !uptime reports it as a crash
+        stats.state.last_restart = Some(LastRestart::now(RestartReason::Crash));
+        stats.state.write();
+        bot.hooks.run_and_wait(hooks::HookEvent::Crash).await;
+
+        bot.say(bot.channel_id, bot.locale.get("server.shutdown")).await;
+        self.post_log_excerpt(bot, "Server crashed").await;
+
+        bot.exit();
+    }
+}
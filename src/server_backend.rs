@@ -0,0 +1,50 @@
+// Where the server runs. The bot runs it as a child process unless a systemd unit or a
+// Pterodactyl panel is configured, every backend sends the same packets as the server starts
+// and stops.
+
+use tokio::sync::mpsc;
+
+use crate::preflight::PreflightToml;
+use crate::process::LaunchToml;
+use crate::pterodactyl::PterodactylToml;
+use crate::systemd::SystemdToml;
+use crate::{preflight, process, pterodactyl, send_or_log, systemd, ConfigToml, Packet};
+
+pub enum ServerBackend {
+    Process(LaunchToml, PreflightToml),
+    Systemd(SystemdToml),
+    Pterodactyl(PterodactylToml),
+}
+
+impl ServerBackend {
+    // systemd takes priority if both are configured
+    pub fn from_config(config: &ConfigToml) -> ServerBackend {
+        match (&config.systemd, &config.pterodactyl) {
+            (Some(systemd), _) => ServerBackend::Systemd(systemd.clone()),
+            (None, Some(pterodactyl)) => ServerBackend::Pterodactyl(pterodactyl.clone()),
+            (None, None) => ServerBackend::Process(config.launch.clone(), config.preflight.clone()),
+        }
+    }
+
+    // Only a child process is started from files the bot can check first
+    pub fn is_process(&self) -> bool {
+        matches!(self, ServerBackend::Process(..))
+    }
+
+    // Starts the server and follows it until it stops. ProcessStarted and ProcessExited are sent
+    // as it starts and stops, or PreflightFailed if it couldn't be started.
+    pub async fn run(&self, sender: &mpsc::UnboundedSender<Packet>, forward_lines: bool) {
+        match self {
+            ServerBackend::Process(launch, preflight) => {
+                let problems = preflight::check_start(launch, preflight).await;
+                if !problems.is_empty() {
+                    send_or_log(sender, Packet::PreflightFailed(problems));
+                    return;
+                }
+                process::start_process_wrapper(launch, sender, forward_lines).await;
+            },
+            ServerBackend::Systemd(config) => systemd::run_unit(config, sender, forward_lines).await,
+            ServerBackend::Pterodactyl(config) => pterodactyl::run_server(config, sender, forward_lines).await,
+        }
+    }
+}
@@ -0,0 +1,51 @@
+// Helpers for editing files in the server directory from discord
+
+pub const SERVER_PROPERTIES_PATH: &str = "server.properties";
+pub const SERVER_ICON_PATH: &str = "server-icon.png";
+
+// Minecraft only accepts 64x64 server icons
+const ICON_SIZE: u32 = 64;
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+// The server only reads server.properties when it starts, these keys can also be changed while
+// it's running
+pub fn property_restart_note(key: &str) -> &'static str {
+    match key {
+        "difficulty" => "use `!difficulty` to change it now or `!restart` to apply it",
+        "white-list" => "use `whitelist on` or `whitelist off` in the console to change it now or `!restart` to apply it",
+        _ => "the server only reads server.properties on startup, use `!restart` to apply it",
+    }
+}
+
+// Checks that data is a PNG of the size Minecraft expects
+pub fn validate_icon(data: &[u8]) -> Result<(), String> {
+    // The IHDR chunk always comes first, its width and height follow the chunk length and type
+    if data.len() < 24 || !data.starts_with(PNG_SIGNATURE) || &data[12..16] != b"IHDR" {
+        return Err("Server icon must be a PNG".to_string());
+    }
+
+    let width = u32::from_be_bytes([data[16], data[17], data[18], data[19]]);
+    let height = u32::from_be_bytes([data[20], data[21], data[22], data[23]]);
+    if width != ICON_SIZE || height != ICON_SIZE {
+        return Err(format!("Server icon must be {ICON_SIZE}x{ICON_SIZE}, got {width}x{height}"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server_files::validate_icon;
+
+    #[test]
+    fn test_validate_icon() {
+        let mut icon = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        icon.extend_from_slice(&64u32.to_be_bytes());
+        icon.extend_from_slice(&64u32.to_be_bytes());
+        assert!(validate_icon(&icon).is_ok());
+
+        icon[19] = 128;
+        assert!(validate_icon(&icon).is_err());
+        assert!(validate_icon(b"GIF89a").is_err());
+    }
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::{Packet, send_or_log};
+
+// How long the server gets to stop before the bot exits anyway
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub fn default_stop_server() -> bool {
+    true
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Error listening for SIGTERM {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        },
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = terminate.recv() => {},
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+// Sends a Shutdown packet on ctrl-c or SIGTERM, a second signal exits straight away
+pub fn start_signal_handler(sender: &mpsc::UnboundedSender<Packet>) {
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        wait_for_signal().await;
+        info!("Shutting down");
+        send_or_log(&sender, Packet::Shutdown());
+
+        wait_for_signal().await;
+        warn!("Exiting without waiting for shutdown");
+        std::process::exit(1);
+    });
+}
+
+// Sends a ShutdownTimedOut packet if the server hasn't stopped in time
+pub fn start_stop_timeout(sender: &mpsc::UnboundedSender<Packet>) {
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        tokio::time::sleep(STOP_TIMEOUT).await;
+        send_or_log(&sender, Packet::ShutdownTimedOut());
+    });
+}
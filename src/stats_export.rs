@@ -0,0 +1,197 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use serde::{Serialize, Deserialize};
+use serenity::model::channel::AttachmentType;
+use serenity::model::id::ChannelId;
+use serenity::prelude::*;
+use tracing::error;
+
+use crate::log_parser::is_uuid;
+use crate::player_ids::PlayerIds;
+use crate::player_stats::PlayerStats;
+use crate::playtime::Session;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    // Defaults to CSV
+    pub fn parse(arg: Option<&str>) -> Option<ExportFormat> {
+        match arg {
+            None | Some("csv") => Some(ExportFormat::Csv),
+            Some("json") => Some(ExportFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ExportedPlayer {
+    // The UUID stats are kept under, or the name for players whose UUID isn't known
+    pub id: String,
+    pub name: String,
+    // Milliseconds
+    pub play_time: u64,
+    #[serde(default)]
+    pub stats: PlayerStats,
+}
+
+// Everything !export stats writes, sessions are only in the JSON export
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct StatsExport {
+    pub players: Vec<ExportedPlayer>,
+    #[serde(default)]
+    pub sessions: Vec<Session>,
+}
+
+impl StatsExport {
+    // Players are sorted by play time, most first
+    pub fn new(play_times: &HashMap<String, u128>, sessions: &[Session], stats: &HashMap<String, PlayerStats>, name: impl Fn(&str) -> String) -> StatsExport {
+        let mut players: Vec<ExportedPlayer> = play_times.iter()
+            .map(|(id, play_time)| ExportedPlayer {
+                id: id.clone(),
+                name: name(id),
+                play_time: *play_time as u64,
+                stats: stats.get(id).cloned().unwrap_or_default(),
+            })
+            .collect();
+        players.sort_by(|a, b| b.play_time.cmp(&a.play_time).then_with(|| a.name.cmp(&b.name)));
+        StatsExport { players, sessions: sessions.to_vec() }
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = "name,id,play_time_hours,sessions,first_seen,last_seen,deaths,messages\n".to_string();
+        for player in &self.players {
+            let player_sessions: Vec<&Session> = self.sessions.iter().filter(|x| x.player == player.id).collect();
+            let first_seen = player.stats.first_seen.or_else(|| player_sessions.iter().map(|x| x.start).min());
+            let last_seen = player_sessions.iter().map(|x| x.end).max();
+            let _ = writeln!(&mut csv, "{},{},{:.2},{},{},{},{},{}",
+                csv_field(&player.name),
+                csv_field(&player.id),
+                player.play_time as f64 / 3600000.0,
+                player_sessions.len(),
+                first_seen.map(|x| x.to_string()).unwrap_or_default(),
+                last_seen.map(|x| x.to_string()).unwrap_or_default(),
+                player.stats.deaths,
+                player.stats.messages,
+            );
+        }
+        csv
+    }
+
+    pub fn file(&self, format: ExportFormat) -> Result<(String, String), String> {
+        match format {
+            ExportFormat::Csv => Ok(("stats.csv".to_string(), self.to_csv())),
+            ExportFormat::Json => serde_json::to_string_pretty(self)
+                .map(|x| ("stats.json".to_string(), x))
+                .map_err(|e| format!("Error writing stats: {}", e)),
+        }
+    }
+}
+
+pub fn parse_import(text: &str) -> Result<StatsExport, String> {
+    serde_json::from_str(text).map_err(|e| format!("Error reading stats, only JSON exports can be imported: {}", e))
+}
+
+// Adds exported stats to this bot's, players in both have their play time and counts added
+// together. Players exported by name are matched to the UUID this bot knows them by.
+// Returns the number of players imported.
+pub fn merge(export: StatsExport, play_times: &mut HashMap<String, u128>, sessions: &mut Vec<Session>, stats: &mut HashMap<String, PlayerStats>, ids: &mut PlayerIds) -> usize {
+    let mut keys = HashMap::new();
+    for player in &export.players {
+        let key = if is_uuid(&player.id) {
+            if !player.name.is_empty() && ids.display_name(&player.id) == player.id {
+                ids.record(&player.id, &player.name);
+            }
+            player.id.clone()
+        } else {
+            ids.id(&player.id)
+        };
+        keys.insert(player.id.clone(), key);
+    }
+
+    for player in &export.players {
+        let key = &keys[&player.id];
+        *play_times.entry(key.clone()).or_insert(0) += player.play_time as u128;
+        stats.entry(key.clone()).or_default().merge(&player.stats);
+    }
+
+    for mut session in export.sessions {
+        if let Some(key) = keys.get(&session.player) {
+            session.player = key.clone();
+        }
+        if !sessions.contains(&session) {
+            sessions.push(session);
+        }
+    }
+    sessions.sort_by_key(|x| x.start);
+    export.players.len()
+}
+
+// Quotes fields that contain commas, quotes or newlines
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+pub async fn send_file(ctx: &Context, channel_id: ChannelId, filename: String, data: String) {
+    let file = AttachmentType::Bytes {
+        data: Cow::Owned(data.into_bytes()),
+        filename,
+    };
+
+    if let Err(e) = channel_id.send_files(&ctx.http, [file], |m| m).await {
+        error!("Error sending attachment: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::player_ids::PlayerIds;
+    use crate::player_stats::PlayerStats;
+    use crate::playtime::Session;
+    use crate::stats_export::{merge, parse_import, ExportFormat, StatsExport};
+
+    #[test]
+    fn test_stats_export() {
+        let play_times = HashMap::from([("uuid-1".to_string(), 7200000), ("Bob, Jr".to_string(), 0)]);
+        let sessions = vec![Session { player: "uuid-1".to_string(), start: 1000, end: 7201000, afk: 0 }];
+        let stats = HashMap::from([("uuid-1".to_string(), PlayerStats { deaths: 2, ..PlayerStats::default() })]);
+        let export = StatsExport::new(&play_times, &sessions, &stats, |id| if id == "uuid-1" { "Steve".to_string() } else { id.to_string() });
+
+        assert_eq!(export.to_csv(), "name,id,play_time_hours,sessions,first_seen,last_seen,deaths,messages\nSteve,uuid-1,2.00,1,1000,7201000,2,0\n\"Bob, Jr\",\"Bob, Jr\",0.00,0,,,0,0\n");
+        assert_eq!(ExportFormat::parse(Some("json")), Some(ExportFormat::Json));
+        assert_eq!(ExportFormat::parse(Some("xml")), None);
+    }
+
+    #[test]
+    fn test_stats_import() {
+        let uuid = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+        let mut ids = PlayerIds::default();
+        ids.record(uuid, "Steve");
+        let mut play_times = HashMap::from([(uuid.to_string(), 1000)]);
+        let mut sessions = vec![Session { player: uuid.to_string(), start: 5000, end: 6000, afk: 0 }];
+        let mut stats = HashMap::new();
+
+        // Steve was only known by name on the old host
+        let export = parse_import(r#"{"players": [{"id": "Steve", "name": "Steve", "play_time": 500, "stats": {"deaths": 1}}, {"id": "Alex", "name": "Alex", "play_time": 200}],
+            "sessions": [{"player": "Steve", "start": 1000, "end": 1500}]}"#).unwrap();
+        assert_eq!(merge(export, &mut play_times, &mut sessions, &mut stats, &mut ids), 2);
+
+        assert_eq!(play_times[uuid], 1500);
+        assert_eq!(play_times["Alex"], 200);
+        assert_eq!(stats[uuid].deaths, 1);
+        assert_eq!(sessions.iter().map(|x| (x.player.as_str(), x.start)).collect::<Vec<_>>(), vec![(uuid, 1000), (uuid, 5000)]);
+        assert!(parse_import("name,id").is_err());
+    }
+}
@@ -0,0 +1,457 @@
+// Keeps track of who is online and everything counted about players: play time, sessions,
+// deaths, chat and milestones. Player events parsed from the server log are passed to handle.
+
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+use tracing::{debug, info};
+
+use crate::afk::{self, AfkTracker};
+use crate::bot::Bot;
+use crate::capacity::{self, Capacity, CapacityChange};
+use crate::log_parser::LogEvent;
+use crate::milestones::Milestones;
+use crate::polls::Voter;
+use crate::query::QueryInfo;
+use crate::report::{self, ReportPeriod};
+use crate::subscriptions::{self, Subscriptions};
+use crate::vote_actions::{self, VoteAction};
+use crate::waypoints::{CoordsCommand, Waypoints};
+use crate::{api, chat_filter, chat_log, game_commands, hooks, join_messages, player_ids, player_reports, playtime, tellraw, welcome, BotState, ConfigToml};
+
+pub struct StatsTracker {
+    // Online players and when they logged in
+    pub online: HashMap<String, Instant>,
+    pub state: BotState,
+    afk: Option<AfkTracker>,
+    // Players logging in and whether it's their first time
+    first_joins: welcome::FirstJoins,
+    milestones: Milestones,
+    // The latest query response while the server is running
+    pub query: Option<QueryInfo>,
+    pub waypoints: Waypoints,
+    pub capacity: Capacity,
+    pub subscriptions: Subscriptions,
+}
+
+impl StatsTracker {
+    pub fn new(config: &mut ConfigToml) -> StatsTracker {
+        let state = match fs::read_to_string("mc-discord-bot.json") {
+            Ok(v) => serde_json::from_str(&v).unwrap(),
+            Err(_) => BotState {
+                play_times: HashMap::new(),
+                sessions: Vec::new(),
+                last_restart: None,
+                online: HashMap::new(),
+                deaths: Vec::new(),
+                stats: HashMap::new(),
+                players: player_ids::PlayerIds::default(),
+            },
+        };
+
+        StatsTracker {
+            online: HashMap::new(),
+            state,
+            afk: config.afk.take().map(AfkTracker::new),
+            first_joins: welcome::FirstJoins::default(),
+            milestones: Milestones::new(config.milestones.clone()),
+            query: None,
+            waypoints: Waypoints::read(),
+            capacity: Capacity::default(),
+            subscriptions: Subscriptions::read(),
+        }
+    }
+
+    // Every server log line, used to spot AFK players
+    pub fn check_line(&mut self, line: &str) {
+        if let Some(afk) = &mut self.afk {
+            afk.check_line(line);
+        }
+    }
+
+    // When each online player logged in by their stats key, in unix milliseconds
+    pub fn online_since(&self, now_unix: u64) -> HashMap<String, u64> {
+        let now = Instant::now();
+        self.online.iter()
+            .map(|(player, login_time)| (self.state.players.id(player), now_unix.saturating_sub((now - *login_time).as_millis() as u64)))
+            .collect()
+    }
+
+    // Ends everyone's session, eg. when the server stops
+    pub fn end_sessions(&mut self, reason: &str) {
+        for (name, login_time) in self.online.drain() {
+            let (afk_millis, exclude_afk) = afk::left(&mut self.afk, &name);
+            let (dt, play_time) = self.state.end_session(&name, login_time, afk_millis, exclude_afk);
+            info!("{name} {reason}, dt millis {dt}, afk millis {afk_millis}, play time {play_time}");
+        }
+        self.state.write();
+    }
+
+    pub async fn handle(&mut self, bot: &mut Bot, event: LogEvent) {
+        match event {
+            LogEvent::PlayerJoined(name) => self.joined(bot, &name).await,
+            LogEvent::PlayerAuthenticated { player, uuid } => {
+                self.first_joins.authenticated(&player, &uuid);
+                if let Some(previous) = self.state.identify(&player, &uuid) {
+                    info!("{previous} is now known as {player}");
+                }
+                self.state.write();
+            },
+            LogEvent::PlayerLeft(name) => self.left(bot, &name).await,
+            LogEvent::Chat { user, message } => self.chat(bot, &user, &message).await,
+
+            // Fixes up the online players if the bot missed a join or leave, eg. while it was
+            // restarting. Players whose login time was saved keep their session.
+            LogEvent::PlayerList(listed) => {
+                let (missing, gone) = playtime::reconcile(&listed, &self.online);
+                if missing.is_empty() && gone.is_empty() {
+                    return;
+                }
+
+                let now_unix = playtime::unix_millis(SystemTime::now());
+                for name in missing {
+                    let id = self.state.players.id(&name);
+                    let online_millis = self.state.online.get(&id).map(|x| now_unix.saturating_sub(*x)).unwrap_or(0);
+                    let login_time = Instant::now().checked_sub(Duration::from_millis(online_millis)).unwrap_or_else(Instant::now);
+                    info!("{name} Listed but not online, online for {online_millis} millis");
+                    self.state.play_times.entry(id.clone()).or_insert(0);
+                    self.state.online.insert(id, now_unix - online_millis);
+                    if let Some(afk) = &mut self.afk {
+                        afk.joined(&name);
+                    }
+                    self.online.insert(name, login_time);
+                }
+                for name in gone {
+                    if let Some(login_time) = self.online.remove(&name) {
+                        let (afk_millis, exclude_afk) = afk::left(&mut self.afk, &name);
+                        let (dt, play_time) = self.state.end_session(&name, login_time, afk_millis, exclude_afk);
+                        info!("{name} Online but not listed, dt millis {dt}, afk millis {afk_millis}, play time {play_time}");
+                    }
+                }
+                self.state.write();
+                bot.players_changed(self.online.len()).await;
+            },
+
+            // Deaths and advancements (eg. PLAYER fell out of the world)
+            LogEvent::Death { player, message } => {
+                if let Some(afk) = &mut self.afk {
+                    afk.activity(&player);
+                }
+                self.state.stats_mut(&player).record_death(&player, &message);
+                let message = bot.bedrock.message(&player, &message);
+                bot.say_game(&message);
+                report::record_death(&mut self.state.deaths, report::Death { player: self.state.players.id(&player), message, time: playtime::unix_millis(SystemTime::now()) });
+                self.state.write();
+            },
+            LogEvent::Advancement { player, message } => {
+                if let Some(afk) = &mut self.afk {
+                    afk.activity(&player);
+                }
+                bot.say_game(&bot.bedrock.message(&player, &message));
+            },
+
+            _ => {},
+        }
+    }
+
+    async fn joined(&mut self, bot: &mut Bot, name: &str) {
+        let now = Instant::now();
+        self.online.insert(name.to_string(), now);
+        info!("{name} Joined: {now:?}");
+        bot.events.publish(api::ApiEvent::Join { player: bot.bedrock.display(name).to_string() });
+        if let Some(afk) = &mut self.afk {
+            afk.joined(name);
+        }
+
+        let id = self.state.players.id(name);
+        let first_join = self.first_joins.is_first_join(name, self.state.play_times.contains_key(&id));
+        if !self.state.play_times.contains_key(&id) {
+            self.state.stats_mut(name).first_seen = Some(playtime::unix_millis(SystemTime::now()));
+        }
+        self.state.play_times.entry(id.clone()).or_insert(0);
+        self.state.online.insert(id, playtime::unix_millis(SystemTime::now()));
+        self.state.write();
+
+        bot.players_changed(self.online.len()).await;
+
+        let display_name = bot.bedrock.display(name);
+        let announce_first_join = first_join && bot.config.welcome.announce;
+        match &bot.ctx {
+            // Players coming and going during maintenance would only be noise
+            _ if bot.maintenance.is_some() => {},
+            Some(ctx) if announce_first_join => join_messages::send_first_join(bot.channel_id, ctx, display_name, self.online.len(), bot.config.plain_join_messages, &bot.locale).await,
+            Some(ctx) => join_messages::send_join(bot.channel_id, ctx, display_name, self.online.len(), bot.config.plain_join_messages, &bot.locale).await,
+            None => bot.say(bot.channel_id, &join_messages::join_title(display_name, self.online.len(), announce_first_join, &bot.locale)).await,
+        }
+        bot.hooks.run(hooks::HookEvent::PlayerJoined { player: name.to_string(), first_join });
+        self.capacity_changed(bot).await;
+
+        let subscribers = self.subscriptions.joined(bot.bedrock.display(name));
+        if !subscribers.is_empty() {
+            self.subscriptions.write();
+            subscriptions::notify(bot, &subscribers, bot.bedrock.display(name)).await;
+        }
+
+        let logins = self.state.sessions.iter().filter(|x| x.player == self.state.players.id(name)).count() as u64 + 1;
+        let play_time = self.state.play_times.get(&self.state.players.id(name)).copied().unwrap_or(0);
+        if let Some(announcement) = self.milestones.joined(name, bot.bedrock.display(name), play_time, logins) {
+            bot.say(bot.channel_id, &announcement).await;
+        }
+
+        if let (true, Some(message)) = (first_join, &bot.config.welcome.message) {
+            if let Some(line) = tellraw::to_player(name, &welcome::render_message(message, name), None) {
+                bot.write_console(&line).await;
+            }
+        }
+    }
+
+    async fn left(&mut self, bot: &mut Bot, name: &str) {
+        bot.events.publish(api::ApiEvent::Leave { player: bot.bedrock.display(name).to_string() });
+        let mut session = None;
+        if let Some(login_time) = self.online.remove(name) {
+            let (afk_millis, exclude_afk) = afk::left(&mut self.afk, name);
+            let (dt, play_time) = self.state.end_session(name, login_time, afk_millis, exclude_afk);
+            info!("{name} Left: login time {login_time:?}, logout time {:?}, dt millis {dt}, afk millis {afk_millis}, play time {play_time}", Instant::now());
+            session = Some((dt, play_time, afk_millis));
+            self.state.write();
+        }
+
+        bot.players_changed(self.online.len()).await;
+
+        match &bot.ctx {
+            _ if bot.maintenance.is_some() => {},
+            Some(ctx) => join_messages::send_leave(bot.channel_id, ctx, bot.bedrock.display(name), self.online.len(), session, bot.config.plain_join_messages, &bot.locale).await,
+            None => bot.say(bot.channel_id, &join_messages::leave_title(bot.bedrock.display(name), self.online.len(), session, &bot.locale)).await,
+        }
+        self.capacity_changed(bot).await;
+        if let Some(announcement) = session.and_then(|x| self.milestones.left(name, bot.bedrock.display(name), x.1)) {
+            bot.say(bot.channel_id, &announcement).await;
+        }
+        bot.hooks.run(hooks::HookEvent::PlayerLeft { player: name.to_string(), session_millis: session.map(|x| x.0) });
+    }
+
+    async fn chat(&mut self, bot: &mut Bot, user: &str, message: &str) {
+        if let Some(afk) = &mut self.afk {
+            afk.activity(user);
+        }
+        let verdict = chat_filter::check(bot, true, user, message).await;
+        if let Some(command) = verdict.warning.as_deref().and_then(|x| tellraw::to_player(user, x, None)) {
+            bot.write_console(&command).await;
+        }
+        if let Some(relay) = &verdict.relay {
+            bot.say_game(&format!("{}: {}", bot.bedrock.display(user), relay));
+            // Commands are only for this server
+            if let (Some(cross_server), None) = (&bot.cross_server, game_commands::parse(message)) {
+                cross_server.forward(bot.bedrock.display(user), relay);
+            }
+        }
+        chat_log::record(&chat_log::ChatEntry::new(chat_log::ChatSource::Game, user, message));
+        // Saved with the next write, chat is too frequent to write for
+        self.state.stats_mut(user).messages += 1;
+        bot.events.publish(api::ApiEvent::Chat { source: "game", player: bot.bedrock.display(user).to_string(), message: message.to_string() });
+
+        let command = match game_commands::parse(message) {
+            Some(v) if bot.config.game_commands && verdict.relay.is_some() => v,
+            _ => return,
+        };
+
+        let mut link = None;
+        let reply = match command {
+            game_commands::GameCommand::Help => game_commands::HELP.to_string(),
+            game_commands::GameCommand::Online => {
+                let mut names: Vec<&str> = self.online.keys().map(|x| x.as_str()).collect();
+                names.sort_unstable();
+                format!("Online ({}): {}", names.len(), names.join(", "))
+            },
+            game_commands::GameCommand::Time => {
+                let session = self.online.get(user).map(|x| x.elapsed().as_millis()).unwrap_or(0);
+                let total = self.state.play_times.get(&self.state.players.id(user)).copied().unwrap_or(0) + session;
+                format!("Your play time: {}", playtime::format_duration(total))
+            },
+            game_commands::GameCommand::Discord => match &bot.config.discord_invite {
+                Some(invite) => {
+                    link = Some(invite.as_str());
+                    format!("Join us on Discord: {}", invite)
+                },
+                None => "Chat is bridged to our Discord".to_string(),
+            },
+            game_commands::GameCommand::Vote(choice) => match &mut bot.poll {
+                Some(poll) if choice.is_empty() => poll.game_instructions(),
+                Some(poll) => match poll.find_option(&choice) {
+                    Some(option) => format!("You voted for {}", poll.vote(Voter::Player(user.to_string()), option).unwrap_or_default()),
+                    None => format!("Pick an option from 1 to {}", poll.options.len()),
+                },
+                None => "There's no poll running".to_string(),
+            },
+            game_commands::GameCommand::VoteDay => match self.vote_action(bot, user, VoteAction::Day).await {
+                Err(e) => e,
+                Ok(()) => return,
+            },
+            game_commands::GameCommand::VoteRestart => match self.vote_action(bot, user, VoteAction::Restart).await {
+                Err(e) => e,
+                Ok(()) => return,
+            },
+            game_commands::GameCommand::Coords(None) => game_commands::COORDS_USAGE.to_string(),
+            game_commands::GameCommand::Coords(Some(command)) => match self.coords(bot, user, command).await {
+                Some(v) => v,
+                None => return,
+            },
+            game_commands::GameCommand::Report(None) => player_reports::USAGE.to_string(),
+            game_commands::GameCommand::Report(Some((player, reason))) => player_reports::send_report(bot, user, player, reason).await,
+        };
+
+        if let Some(line) = tellraw::to_player(user, &reply, link) {
+            bot.write_console(&line).await;
+        }
+    }
+
+    // Votes are announced to everyone so the player is only replied to when it wasn't counted
+    async fn vote_action(&self, bot: &mut Bot, user: &str, action: VoteAction) -> Result<(), String> {
+        let name = bot.bedrock.display(user).to_string();
+        vote_actions::cast_vote(bot, action, Voter::Player(user.to_string()), &name, self.online.len()).await
+    }
+
+    // Replies to !coords in game, saves are replied to once the server says where the player is
+    async fn coords(&mut self, bot: &Bot, user: &str, command: CoordsCommand) -> Option<String> {
+        let id = self.state.players.id(user);
+        let reply = match command {
+            CoordsCommand::Save(name) => {
+                for line in self.waypoints.request_save(user, &name, None) {
+                    bot.write_console(&line).await;
+                }
+                return None;
+            },
+            CoordsCommand::Get(name) => {
+                let found = self.waypoints.find(&name);
+                // The player's own waypoint is shown over anyone else's
+                match found.iter().find(|x| x.0 == id).or(found.first()) {
+                    Some((owner, waypoint)) if *owner == id => waypoint.describe(),
+                    Some((owner, waypoint)) => format!("{}'s {}", bot.bedrock.display(self.state.players.display_name(owner)), waypoint.describe()),
+                    None => format!("No waypoint called {}", name),
+                }
+            },
+            CoordsCommand::List => {
+                let names: Vec<&str> = self.waypoints.list(&id).iter().map(|x| x.name.as_str()).collect();
+                match names.is_empty() {
+                    true => "You haven't saved any waypoints, use !coords save <name>".to_string(),
+                    false => format!("Your waypoints: {}", names.join(", ")),
+                }
+            },
+            CoordsCommand::Delete(name) => match self.waypoints.delete(&id, &name) {
+                true => {
+                    self.waypoints.write();
+                    format!("Deleted {}", name)
+                },
+                false => format!("You don't have a waypoint called {}", name),
+            },
+        };
+        Some(reply)
+    }
+
+    // Saves waypoints once the server answers with the player's position
+    pub async fn position_line(&mut self, bot: &Bot, line: &str) {
+        let players = &self.state.players;
+        let saved = match self.waypoints.position_line(line, |x| players.id(x)) {
+            Some(v) => v,
+            None => return,
+        };
+        self.waypoints.write();
+
+        if let Some(line) = tellraw::to_player(&saved.player, &format!("Saved {}", saved.waypoint.describe()), None) {
+            bot.write_console(&line).await;
+        }
+        if let Some(channel_id) = saved.channel_id {
+            bot.say(channel_id, &format!("Saved {}'s {}", bot.bedrock.display(&saved.player), saved.waypoint.describe())).await;
+        }
+    }
+
+    // The server's own player list fixes up the online players the same way the list command does
+    // The player limit from the query or status ping, falling back to server.properties
+    pub fn max_players(&self) -> Option<u32> {
+        self.query.as_ref().map(|x| x.max_players).or(self.capacity.ping_max).or_else(capacity::properties_max)
+    }
+
+    async fn capacity_changed(&mut self, bot: &Bot) {
+        let max = match self.max_players() {
+            Some(v) => v,
+            None => return,
+        };
+        match self.capacity.players_changed(self.online.len(), max) {
+            Some(CapacityChange::Full(max)) => bot.say(bot.channel_id, &format!("The server is full ({}/{}), use `!notify` to be pinged when a slot frees up", self.online.len(), max)).await,
+            Some(CapacityChange::SlotFree(user_ids)) => {
+                let mentions: Vec<String> = user_ids.iter().map(|x| format!("<@{}>", x)).collect();
+                bot.say(bot.channel_id, &format!("{} A slot is free on the server ({}/{})", mentions.join(" "), self.online.len(), max)).await;
+            },
+            None => {},
+        }
+    }
+
+    pub async fn query_result(&mut self, bot: &mut Bot, result: Result<QueryInfo, String>) {
+        if bot.console.is_none() {
+            self.query = None;
+            return;
+        }
+
+        match result {
+            Ok(info) => {
+                let players = info.players.clone();
+                self.query = Some(info);
+                self.handle(bot, LogEvent::PlayerList(players)).await;
+            },
+            Err(e) => {
+                debug!("{}", e);
+                self.query = None;
+            },
+        }
+    }
+
+    pub async fn check_milestones(&mut self, bot: &Bot) {
+        for (name, login_time) in &self.online {
+            let play_time = self.state.play_times.get(&self.state.players.id(name)).copied().unwrap_or(0) + login_time.elapsed().as_millis();
+            if let Some(announcement) = self.milestones.check(name, bot.bedrock.display(name), play_time) {
+                bot.say(bot.channel_id, &announcement).await;
+            }
+        }
+    }
+
+    pub async fn send_report(&self, bot: &Bot, period: ReportPeriod) {
+        let now_unix = playtime::unix_millis(SystemTime::now());
+        let online_since = self.online_since(now_unix);
+        let report = report::build_report(period, &self.state.sessions, &online_since, &self.state.deaths, |id| bot.bedrock.display(self.state.players.display_name(id)).to_string(), now_unix);
+        bot.say(bot.channel_id, &report).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_online_since() {
+        let mut tracker = StatsTracker {
+            online: HashMap::new(),
+            state: BotState {
+                play_times: HashMap::new(),
+                sessions: Vec::new(),
+                last_restart: None,
+                online: HashMap::new(),
+                deaths: Vec::new(),
+                stats: HashMap::new(),
+                players: player_ids::PlayerIds::default(),
+            },
+            afk: None,
+            first_joins: welcome::FirstJoins::default(),
+            milestones: Milestones::new(Default::default()),
+            query: None,
+            waypoints: Waypoints::default(),
+            capacity: Capacity::default(),
+            subscriptions: Subscriptions::default(),
+        };
+        tracker.online.insert("Steve".to_string(), Instant::now() - Duration::from_secs(60));
+
+        let online_since = tracker.online_since(1_000_000);
+        let since = online_since[&tracker.state.players.id("Steve")];
+        assert!((939_000..=940_000).contains(&since));
+    }
+}
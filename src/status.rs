@@ -0,0 +1,238 @@
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+use serenity::model::id::ChannelId;
+use serenity::prelude::*;
+use tracing::error;
+
+use crate::backup;
+use crate::jvm_args::JVM_ARGS_PATH;
+use crate::locale::Locale;
+use crate::metrics::Metrics;
+use crate::playtime::format_duration;
+use crate::query::QueryInfo;
+use crate::status_ping::PingInfo;
+use crate::usage::{format_bytes, parse_max_heap};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ServerState {
+    Running,
+    Restarting,
+    Updating,
+    Stopped,
+}
+
+impl ServerState {
+    // Updates restart the server too, so they're reported over restarts
+    pub fn new(updating: bool, restarting: bool, running: bool) -> ServerState {
+        if updating {
+            ServerState::Updating
+        } else if restarting {
+            ServerState::Restarting
+        } else if running {
+            ServerState::Running
+        } else {
+            ServerState::Stopped
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ServerState::Running => "Running",
+            ServerState::Restarting => "Restarting",
+            ServerState::Updating => "Updating",
+            ServerState::Stopped => "Stopped",
+        }
+    }
+
+    fn key(&self) -> &'static str {
+        match self {
+            ServerState::Running => "status.running",
+            ServerState::Restarting => "status.restarting",
+            ServerState::Updating => "status.updating",
+            ServerState::Stopped => "status.stopped",
+        }
+    }
+
+    fn colour(&self) -> u32 {
+        match self {
+            ServerState::Running => 0x55FF55,
+            ServerState::Restarting | ServerState::Updating => 0xFFAA00,
+            ServerState::Stopped => 0xFF5555,
+        }
+    }
+}
+
+// Values for !status that the packet handler knows about, the rest are read from disk
+pub struct Status {
+    pub state: ServerState,
+    pub uptime: Option<String>,
+    pub players: Vec<String>,
+    pub tps: Option<f64>,
+    pub memory: Option<u64>,
+    // From the query protocol when it's enabled
+    pub query: Option<QueryInfo>,
+    // From pinging the server like the multiplayer screen does
+    pub ping: Option<PingInfo>,
+    pub map_url: Option<String>,
+}
+
+impl Status {
+    pub fn new(state: ServerState, players: Vec<String>, metrics: &Metrics, query: Option<QueryInfo>, ping: Option<PingInfo>) -> Status {
+        Status {
+            state,
+            uptime: metrics.server_started.map(|x| format_duration(x.elapsed().as_millis())),
+            players,
+            tps: metrics.tps,
+            memory: metrics.memory,
+            query,
+            ping,
+            map_url: None,
+        }
+    }
+}
+
+// Adds up the size of every file under path, symlinks aren't followed
+pub fn dir_size(path: &Path) -> u64 {
+    let entries = match std::fs::read_dir(path) {
+        Ok(v) => v,
+        Err(_) => return 0,
+    };
+
+    entries.filter_map(|x| x.ok())
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => dir_size(&entry.path()),
+            Ok(kind) if kind.is_file() => entry.metadata().map(|x| x.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+// Backup ids end with the time they were made, eg. world-20240101-030000
+pub fn backup_time(id: &str) -> Option<NaiveDateTime> {
+    let timestamp = id.get(id.len().checked_sub(15)?..)?;
+    NaiveDateTime::parse_from_str(timestamp, "%Y%m%d-%H%M%S").ok()
+}
+
+// Builds the embed's fields, max_heap and world_size are None when they couldn't be read
+pub fn fields<'a>(status: &Status, max_heap: Option<u64>, world_size: Option<u64>, last_backup: Option<NaiveDateTime>, locale: &'a Locale) -> Vec<(&'a str, String)> {
+    let unknown = || locale.get("status.unknown").to_string();
+    let max_players = status.query.as_ref().map(|x| x.max_players).or(status.ping.as_ref().map(|x| x.max_players));
+    let count = match max_players {
+        Some(max_players) => format!("{}/{}", status.players.len(), max_players),
+        None => status.players.len().to_string(),
+    };
+    let players = match status.players.is_empty() {
+        true => count,
+        false => format!("{}: {}", count, status.players.join(", ")),
+    };
+    let memory = match (status.memory, max_heap) {
+        (Some(memory), Some(max_heap)) => locale.format("status.memory_of", &[("memory", &format_bytes(memory)), ("max", &format_bytes(max_heap))]),
+        (Some(memory), None) => format_bytes(memory),
+        (None, _) => unknown(),
+    };
+
+    let mut fields = vec![
+        (locale.get("status.state"), locale.get(status.state.key()).to_string()),
+        (locale.get("status.uptime"), status.uptime.clone().unwrap_or_else(|| "-".to_string())),
+        (locale.get("status.players"), players),
+        (locale.get("status.tps"), status.tps.map(|x| format!("{:.1}", x)).unwrap_or_else(unknown)),
+        (locale.get("status.memory"), memory),
+        (locale.get("status.world_size"), world_size.map(format_bytes).unwrap_or_else(unknown)),
+        (locale.get("status.last_backup"), last_backup.map(|x| x.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_else(|| locale.get("status.never").to_string())),
+    ];
+    // The ping shows the version and MOTD as players see them, eg. through a proxy
+    let version = status.ping.as_ref().map(|x| &x.version).or(status.query.as_ref().map(|x| &x.version));
+    if let Some(version) = version {
+        fields.push((locale.get("status.version"), version.clone()));
+    }
+    if let Some(query) = &status.query {
+        fields.push((locale.get("status.world"), query.map.clone()));
+    }
+    let motd = status.ping.as_ref().map(|x| &x.motd).or(status.query.as_ref().map(|x| &x.motd));
+    if let Some(motd) = motd {
+        fields.push((locale.get("status.motd"), motd.clone()));
+    }
+    if let Some(ping) = &status.ping {
+        fields.push((locale.get("status.latency"), locale.format("status.latency_ms", &[("ms", &ping.latency_ms.to_string())])));
+    }
+    if let Some(map_url) = &status.map_url {
+        fields.push((locale.get("status.map"), map_url.clone()));
+    }
+    fields
+}
+
+// Sends the !status embed from another task since adding up the world's size can take a while
+pub fn send_status(ctx: &Context, channel_id: ChannelId, status: Status, locale: Locale) {
+    let ctx = ctx.clone();
+    tokio::task::spawn(async move {
+        let world_dir = backup::world_dir();
+        let world_size = tokio::task::spawn_blocking(move || Path::new(&world_dir).is_dir().then(|| dir_size(Path::new(&world_dir)))).await.ok().flatten();
+        let max_heap = std::fs::read_to_string(JVM_ARGS_PATH).ok().and_then(|x| parse_max_heap(&x));
+        let last_backup = backup::list_backups().first().and_then(|x| backup_time(x));
+
+        let fields = fields(&status, max_heap, world_size, last_backup, &locale);
+        let result = channel_id.send_message(&ctx.http, |m| m.embed(|e| {
+            e.title(locale.get("status.title"))
+                .colour(status.state.colour())
+                .fields(fields.into_iter().map(|(name, value)| (name, value, true)))
+        })).await;
+
+        if let Err(e) = result {
+            error!("Error sending message: {:?}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::locale::Locale;
+    use crate::query::QueryInfo;
+    use crate::status::{backup_time, fields, ServerState, Status};
+    use crate::status_ping::PingInfo;
+
+    #[test]
+    fn test_status_fields() {
+        let time = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap().and_hms_opt(3, 4, 5).unwrap();
+        assert_eq!(backup_time("my-world-20240102-030405"), Some(time));
+        assert_eq!(backup_time("world"), None);
+
+        let status = Status {
+            state: ServerState::Running,
+            uptime: Some("1h 00m".to_string()),
+            players: vec!["Alex".to_string(), "Steve".to_string()],
+            tps: Some(19.96),
+            memory: Some(2 * 1024 * 1024 * 1024),
+            query: None,
+            ping: None,
+            map_url: None,
+        };
+        let locale = Locale::new("en");
+        let fields = fields(&status, Some(4 * 1024 * 1024 * 1024), None, Some(time), &locale);
+        assert_eq!(fields[2], ("Players", "2: Alex, Steve".to_string()));
+        assert_eq!(fields[3], ("TPS", "20.0".to_string()));
+        assert_eq!(fields[4], ("Memory", "2.00 GiB of 4.00 GiB".to_string()));
+        assert_eq!(fields[5], ("World size", "Unknown".to_string()));
+        assert_eq!(fields[6], ("Last backup", "2024-01-02 03:04".to_string()));
+        assert_eq!(fields.len(), 7);
+
+        let query = QueryInfo { motd: "A Minecraft Server".to_string(), version: "1.20.1".to_string(), map: "world".to_string(), max_players: 20, players: Vec::new() };
+        let status = Status { query: Some(query), ..status };
+        let fields = super::fields(&status, None, None, None, &locale);
+        assert_eq!(fields[2], ("Players", "2/20: Alex, Steve".to_string()));
+        assert_eq!(fields[8], ("World", "world".to_string()));
+
+        let ping = PingInfo { latency_ms: 12, version: "Velocity 1.7.2-1.20.4".to_string(), motd: "Welcome".to_string(), online: 2, max_players: 100 };
+        let status = Status { query: None, ping: Some(ping), map_url: Some("https://map.example.com".to_string()), ..status };
+        let fields = super::fields(&status, None, None, None, &locale);
+        assert_eq!(fields[2], ("Players", "2/100: Alex, Steve".to_string()));
+        assert_eq!(&fields[7..], &[
+            ("Version", "Velocity 1.7.2-1.20.4".to_string()),
+            ("MOTD", "Welcome".to_string()),
+            ("Latency", "12 ms".to_string()),
+            ("Map", "https://map.example.com".to_string()),
+        ]);
+    }
+}
@@ -0,0 +1,414 @@
+// Client for the server list ping that the multiplayer screen uses, so the bot sees the server the
+// way players do. The address can be the public one players connect to, in which case its SRV
+// record is looked up like the client does. When the server is running but doesn't answer, eg.
+// because of a firewall or it failed to bind its port, the admin channel is told.
+
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+
+use crate::presence::strip_formatting;
+use crate::properties::Properties;
+use crate::server_files::SERVER_PROPERTIES_PATH;
+use crate::{send_or_log, Packet};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_PORT: u16 = 25565;
+
+// Sent in the handshake by clients that don't know which version the server is
+const UNKNOWN_PROTOCOL: i32 = -1;
+
+const SRV: u16 = 33;
+
+#[derive(Deserialize, Clone)]
+pub struct StatusPingToml {
+    // The address players connect with, eg. play.example.com, defaults to this machine
+    pub address: Option<String>,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    // How many pings in a row have to fail before the admin channel is told
+    #[serde(default = "default_failures_before_alert")]
+    pub failures_before_alert: u32,
+}
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+fn default_failures_before_alert() -> u32 {
+    3
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct PingInfo {
+    pub latency_ms: u64,
+    pub version: String,
+    pub motd: String,
+    pub online: u32,
+    pub max_players: u32,
+}
+
+fn write_varint(buffer: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            buffer.push(byte);
+            return;
+        }
+        buffer.push(byte | 0x80);
+    }
+}
+
+// Takes a varint off the front of bytes
+fn take_varint(bytes: &mut &[u8]) -> Option<i32> {
+    let mut value = 0u32;
+    for i in 0..5 {
+        let (byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= ((byte & 0x7F) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some(value as i32);
+        }
+    }
+    None
+}
+
+fn write_string(buffer: &mut Vec<u8>, text: &str) {
+    write_varint(buffer, text.len() as i32);
+    buffer.extend(text.as_bytes());
+}
+
+// Packets are their length, then their id, then the payload
+fn packet(id: i32, payload: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_varint(&mut body, id);
+    body.extend(payload);
+
+    let mut packet = Vec::new();
+    write_varint(&mut packet, body.len() as i32);
+    packet.extend(body);
+    packet
+}
+
+fn handshake(host: &str, port: u16) -> Vec<u8> {
+    let mut payload = Vec::new();
+    write_varint(&mut payload, UNKNOWN_PROTOCOL);
+    write_string(&mut payload, host);
+    payload.extend(port.to_be_bytes());
+    // Asks for the status rather than logging in
+    write_varint(&mut payload, 1);
+    packet(0x00, &payload)
+}
+
+// Reads a packet, returning its id and payload
+async fn read_packet(stream: &mut (impl AsyncRead + Unpin)) -> Result<(i32, Vec<u8>), String> {
+    let error = |e: std::io::Error| format!("Error reading status response: {}", e);
+    let mut length_bytes = Vec::new();
+    loop {
+        let byte = stream.read_u8().await.map_err(error)?;
+        length_bytes.push(byte);
+        if byte & 0x80 == 0 || length_bytes.len() == 5 {
+            break;
+        }
+    }
+    let length = take_varint(&mut length_bytes.as_slice()).filter(|x| (1..=1 << 20).contains(x)).ok_or("Invalid status response length")?;
+
+    let mut body = vec![0; length as usize];
+    stream.read_exact(&mut body).await.map_err(error)?;
+    let mut payload = body.as_slice();
+    let id = take_varint(&mut payload).ok_or("Invalid status response")?;
+    Ok((id, payload.to_vec()))
+}
+
+// Descriptions are either plain text or a chat component with nested extra parts
+fn component_text(component: &Value) -> String {
+    match component {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts.iter().map(component_text).collect(),
+        Value::Object(fields) => {
+            let mut text = fields.get("text").and_then(|x| x.as_str()).unwrap_or("").to_string();
+            if let Some(extra) = fields.get("extra") {
+                text.push_str(&component_text(extra));
+            }
+            text
+        },
+        _ => String::new(),
+    }
+}
+
+// The status response's payload is a JSON string
+fn parse_status(mut payload: &[u8], latency_ms: u64) -> Result<PingInfo, String> {
+    let length = take_varint(&mut payload).ok_or("Invalid status response")? as usize;
+    let json = payload.get(..length).ok_or("Invalid status response")?;
+    let status: Value = serde_json::from_slice(json).map_err(|e| format!("Error parsing status response: {}", e))?;
+
+    Ok(PingInfo {
+        latency_ms,
+        version: status["version"]["name"].as_str().unwrap_or_default().to_string(),
+        motd: strip_formatting(component_text(&status["description"]).trim()),
+        online: status["players"]["online"].as_u64().unwrap_or(0) as u32,
+        max_players: status["players"]["max"].as_u64().unwrap_or(0) as u32,
+    })
+}
+
+async fn exchange(host: &str, port: u16) -> Result<PingInfo, String> {
+    let mut stream = TcpStream::connect((host, port)).await.map_err(|e| format!("Error connecting to {}:{}: {}", host, port, e))?;
+    let write_error = |e: std::io::Error| format!("Error sending status request: {}", e);
+
+    stream.write_all(&handshake(host, port)).await.map_err(write_error)?;
+    stream.write_all(&packet(0x00, &[])).await.map_err(write_error)?;
+    let (id, payload) = read_packet(&mut stream).await?;
+    if id != 0x00 {
+        return Err("Unexpected status response".to_string());
+    }
+
+    // The round trip of a ping packet is the latency the multiplayer screen shows
+    let sent = Instant::now();
+    stream.write_all(&packet(0x01, &[0; 8])).await.map_err(write_error)?;
+    read_packet(&mut stream).await?;
+    parse_status(&payload, sent.elapsed().as_millis() as u64)
+}
+
+pub async fn ping(host: &str, port: u16) -> Result<PingInfo, String> {
+    tokio::time::timeout(TIMEOUT, exchange(host, port)).await
+        .unwrap_or_else(|_| Err(format!("No status response from {}:{}", host, port)))
+}
+
+fn srv_query(name: &str, id: u16) -> Vec<u8> {
+    let mut query = id.to_be_bytes().to_vec();
+    // Recursion desired and one question
+    query.extend([0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    for label in name.trim_end_matches('.').split('.') {
+        query.push(label.len() as u8);
+        query.extend(label.as_bytes());
+    }
+    query.push(0);
+    query.extend(SRV.to_be_bytes());
+    query.extend(1u16.to_be_bytes());
+    query
+}
+
+// Reads a name that may point back to an earlier one, returning it and the offset after it
+fn read_name(response: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Limits how many labels and pointers are followed in case they loop
+    for _ in 0..128 {
+        let length = *response.get(offset)? as usize;
+        if length & 0xC0 == 0xC0 {
+            end.get_or_insert(offset + 2);
+            offset = ((length & 0x3F) << 8) | *response.get(offset + 1)? as usize;
+        } else if length == 0 {
+            return Some((labels.join("."), end.unwrap_or(offset + 1)));
+        } else {
+            labels.push(String::from_utf8_lossy(response.get(offset + 1..offset + 1 + length)?).to_string());
+            offset += 1 + length;
+        }
+    }
+    None
+}
+
+fn read_u16(response: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(response.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+// Returns the target and port of the SRV record with the lowest priority
+fn parse_srv(response: &[u8], id: u16) -> Option<(String, u16)> {
+    // Checks the id and that there was no error
+    if read_u16(response, 0)? != id || response.get(3)? & 0x0F != 0 {
+        return None;
+    }
+    let questions = read_u16(response, 4)?;
+    let answers = read_u16(response, 6)?;
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = read_name(response, offset)?.1 + 4;
+    }
+
+    let mut best: Option<(u16, String, u16)> = None;
+    for _ in 0..answers {
+        offset = read_name(response, offset)?.1;
+        let kind = read_u16(response, offset)?;
+        let data_length = read_u16(response, offset + 8)? as usize;
+        let data = offset + 10;
+        if kind == SRV {
+            let priority = read_u16(response, data)?;
+            let port = read_u16(response, data + 4)?;
+            let (target, _) = read_name(response, data + 6)?;
+            if best.as_ref().is_none_or(|x| priority < x.0) {
+                best = Some((priority, target, port));
+            }
+        }
+        offset = data + data_length;
+    }
+    best.map(|(_, target, port)| (target, port))
+}
+
+fn nameserver() -> Option<String> {
+    let resolv = std::fs::read_to_string("/etc/resolv.conf").ok()?;
+    resolv.lines()
+        .find_map(|x| x.trim().strip_prefix("nameserver"))
+        .map(|x| x.trim().to_string())
+}
+
+// Looks up _minecraft._tcp.host, returning None when there isn't a record
+async fn lookup_srv(host: &str) -> Option<(String, u16)> {
+    let nameserver: std::net::IpAddr = nameserver()?.parse().ok()?;
+    let bind = if nameserver.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+    let socket = UdpSocket::bind(bind).await.ok()?;
+    socket.connect((nameserver, 53)).await.ok()?;
+
+    let id = rand::random();
+    socket.send(&srv_query(&format!("_minecraft._tcp.{}", host), id)).await.ok()?;
+    let mut buffer = [0; 1500];
+    let length = tokio::time::timeout(TIMEOUT, socket.recv(&mut buffer)).await.ok()?.ok()?;
+    parse_srv(&buffer[..length], id)
+}
+
+// Splits host:port, an IPv6 address needs to be in brackets to have a port
+fn split_port(address: &str) -> (&str, Option<u16>) {
+    if let Some((host, port)) = address.rsplit_once(':') {
+        if let Ok(port) = port.parse() {
+            if !host.contains(':') || host.starts_with('[') {
+                return (host.trim_start_matches('[').trim_end_matches(']'), Some(port));
+            }
+        }
+    }
+    (address, None)
+}
+
+impl StatusPingToml {
+    pub fn describe(&self) -> String {
+        self.address.clone().unwrap_or_else(|| "the server's port".to_string())
+    }
+
+    // Works out where to connect the same way the client does
+    async fn resolve(&self) -> (String, u16) {
+        let address = match &self.address {
+            Some(v) => v,
+            None => {
+                let port = Properties::read(SERVER_PROPERTIES_PATH).ok()
+                    .and_then(|x| x.get("server-port")?.parse().ok())
+                    .unwrap_or(DEFAULT_PORT);
+                return ("127.0.0.1".to_string(), port);
+            },
+        };
+
+        match split_port(address) {
+            (host, Some(port)) => (host.to_string(), port),
+            (host, None) if host.parse::<std::net::IpAddr>().is_err() => match lookup_srv(host).await {
+                Some((target, port)) => (target.trim_end_matches('.').to_string(), port),
+                None => (host.to_string(), DEFAULT_PORT),
+            },
+            (host, None) => (host.to_string(), DEFAULT_PORT),
+        }
+    }
+}
+
+// Sends a StatusPing packet every interval
+pub fn start_ping_timer(config: &StatusPingToml, sender: &mpsc::UnboundedSender<Packet>) {
+    let sender = sender.clone();
+    let config = config.clone();
+    let interval = Duration::from_secs(config.interval_secs.max(5));
+    tokio::task::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            let (host, port) = config.resolve().await;
+            send_or_log(&sender, Packet::StatusPing(ping(&host, port).await));
+        }
+    });
+}
+
+// Keeps the latest ping and decides when to tell the admin channel the server can't be reached
+pub struct PingMonitor {
+    address: String,
+    failures_before_alert: u32,
+    failures: u32,
+    alerted: bool,
+    pub latest: Option<PingInfo>,
+}
+
+impl PingMonitor {
+    pub fn new(config: &StatusPingToml) -> PingMonitor {
+        PingMonitor {
+            address: config.describe(),
+            failures_before_alert: config.failures_before_alert.max(1),
+            failures: 0,
+            alerted: false,
+            latest: None,
+        }
+    }
+
+    // Returns a message for the admin channel when the server stops or starts answering
+    pub fn result(&mut self, result: Result<PingInfo, String>) -> Option<String> {
+        match result {
+            Ok(info) => {
+                self.latest = Some(info);
+                self.failures = 0;
+                std::mem::take(&mut self.alerted).then(|| format!("Server can be reached at {} again", self.address))
+            },
+            Err(e) => {
+                self.latest = None;
+                self.failures += 1;
+                if self.alerted || self.failures < self.failures_before_alert {
+                    return None;
+                }
+                self.alerted = true;
+                Some(format!("Server is running but can't be reached at {}, check its firewall and that it could bind its port: {}", self.address, e))
+            },
+        }
+    }
+
+    // Called while the server isn't running, since the port being closed is expected then
+    pub fn reset(&mut self) {
+        self.latest = None;
+        self.failures = 0;
+        self.alerted = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_ping() {
+        assert_eq!(handshake("localhost", 25565), vec![
+            0x13, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F, 0x09, b'l', b'o', b'c', b'a', b'l', b'h', b'o', b's', b't', 0x63, 0xDD, 0x01,
+        ]);
+        let mut bytes = &[0xDD, 0xC7, 0x01, 0x05][..];
+        assert_eq!(take_varint(&mut bytes), Some(25565));
+        assert_eq!(bytes, &[0x05]);
+
+        let json = r#"{"version":{"name":"1.20.1","protocol":763},"players":{"max":20,"online":2},"description":{"text":"A ","extra":[{"text":"§aMinecraft"}," Server"]}}"#;
+        let mut payload = Vec::new();
+        write_string(&mut payload, json);
+        assert_eq!(parse_status(&payload, 12), Ok(PingInfo { latency_ms: 12, version: "1.20.1".to_string(), motd: "A Minecraft Server".to_string(), online: 2, max_players: 20 }));
+
+        // An answer to _minecraft._tcp.example.com pointing at mc.example.com:25570
+        let mut response = srv_query("_minecraft._tcp.example.com", 7);
+        response[2..8].copy_from_slice(&[0x81, 0x80, 0x00, 0x01, 0x00, 0x01]);
+        response.extend([0xC0, 0x0C, 0x00, 0x21, 0x00, 0x01, 0x00, 0x00, 0x0E, 0x10, 0x00, 0x0B]);
+        response.extend([0x00, 0x00, 0x00, 0x05, 0x63, 0xE2, 0x02, b'm', b'c', 0xC0, 0x1C]);
+        assert_eq!(parse_srv(&response, 7), Some(("mc.example.com".to_string(), 25570)));
+        assert_eq!(parse_srv(&response, 8), None);
+
+        assert_eq!(split_port("play.example.com:25570"), ("play.example.com", Some(25570)));
+        assert_eq!(split_port("[::1]:25565"), ("::1", Some(25565)));
+        assert_eq!(split_port("::1"), ("::1", None));
+
+        let config: StatusPingToml = toml::from_str("address = 'play.example.com'\nfailures_before_alert = 2").unwrap();
+        let mut monitor = PingMonitor::new(&config);
+        assert_eq!(monitor.result(Err("refused".to_string())), None);
+        assert!(monitor.result(Err("refused".to_string())).unwrap().starts_with("Server is running but can't be reached at play.example.com"));
+        assert_eq!(monitor.result(Err("refused".to_string())), None);
+        assert_eq!(monitor.result(Ok(PingInfo::default())), Some("Server can be reached at play.example.com again".to_string()));
+    }
+}
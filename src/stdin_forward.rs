@@ -1,4 +1,5 @@
 use tokio::sync::mpsc;
+use tracing::error;
 use crate::{Packet, send_or_log};
 
 use std::io::BufRead;
@@ -13,7 +14,7 @@ pub fn start_stdin_forwarding(sender: &mpsc::UnboundedSender<Packet>) {
         loop {
             let mut buffer = String::new();
             if let Err(e) = handle.read_line(&mut buffer) {
-                println!("Error reading line {}", e);
+                error!("Error reading line {}", e);
                 continue;    
             }
 
@@ -0,0 +1,97 @@
+// `!notify when <player> online` subscriptions. Each one is used up the next time the player
+// joins, the subscriber gets a DM or a mention in the chat channel if DMs are closed.
+
+use std::collections::HashMap;
+
+use serenity::model::id::UserId;
+use tracing::error;
+
+use crate::bot::Bot;
+
+pub const SUBSCRIPTIONS_PATH: &str = "mc-discord-bot-subscriptions.json";
+
+// Players are matched by the name shown in discord, ignoring case
+fn key(player: &str) -> String {
+    player.to_lowercase()
+}
+
+#[derive(Default)]
+pub struct Subscriptions {
+    // Discord user ids waiting for each player
+    waiting: HashMap<String, Vec<u64>>,
+}
+
+impl Subscriptions {
+    pub fn read() -> Subscriptions {
+        let waiting = std::fs::read_to_string(SUBSCRIPTIONS_PATH).ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default();
+        Subscriptions { waiting }
+    }
+
+    pub fn write(&self) {
+        let json_str = serde_json::to_string_pretty(&self.waiting).unwrap();
+        if let Err(e) = std::fs::write(SUBSCRIPTIONS_PATH, json_str) {
+            error!("Error writing {}: {}", SUBSCRIPTIONS_PATH, e);
+        }
+    }
+
+    // Subscribes the user to the player, or unsubscribes them if they already were. Returns
+    // whether they're now subscribed.
+    pub fn toggle(&mut self, player: &str, user_id: u64) -> bool {
+        let users = self.waiting.entry(key(player)).or_default();
+        let subscribed = match users.iter().position(|x| *x == user_id) {
+            Some(i) => {
+                users.remove(i);
+                false
+            },
+            None => {
+                users.push(user_id);
+                true
+            },
+        };
+        self.waiting.retain(|_, x| !x.is_empty());
+        subscribed
+    }
+
+    // Removes and returns everyone waiting for the player
+    pub fn joined(&mut self, player: &str) -> Vec<u64> {
+        self.waiting.remove(&key(player)).unwrap_or_default()
+    }
+}
+
+// DMs each user that the player is online, mentioning them in the chat channel instead when
+// the DM can't be sent
+pub async fn notify(bot: &Bot, user_ids: &[u64], player: &str) {
+    let text = format!("{} is online", player);
+    for user_id in user_ids {
+        let sent = match &bot.ctx {
+            Some(ctx) => match UserId(*user_id).create_dm_channel(&ctx.http).await {
+                Ok(channel) => channel.say(&ctx.http, &text).await.is_ok(),
+                Err(_) => false,
+            },
+            None => false,
+        };
+        if !sent {
+            bot.say(bot.channel_id, &format!("<@{}> {}", user_id, text)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::subscriptions::Subscriptions;
+
+    #[test]
+    fn test_subscriptions() {
+        let mut subscriptions = Subscriptions::default();
+        assert!(subscriptions.toggle("Steve", 1));
+        assert!(subscriptions.toggle("steve", 2));
+        assert!(subscriptions.toggle("Alex", 1));
+        assert!(!subscriptions.toggle("alex", 1));
+
+        assert!(subscriptions.joined("Alex").is_empty());
+        assert_eq!(subscriptions.joined("STEVE"), vec![1, 2]);
+        assert!(subscriptions.joined("Steve").is_empty());
+    }
+}
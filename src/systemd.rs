@@ -0,0 +1,152 @@
+// Controls a server that systemd supervises instead of running it as a child process. The unit
+// is started and killed with systemctl, log lines come from its journal and console commands are
+// written to the FIFO the unit reads its stdin from. The server keeps running when the bot
+// restarts.
+
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::console::Console;
+use crate::process::{spawn_line_processing_task, KillHandle, KILL_GRACE};
+use crate::{send_or_log, Packet};
+
+// How often the unit is checked to see if the server has stopped
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Deserialize, Clone)]
+pub struct SystemdToml {
+    pub unit: String,
+    // The FIFO from the unit's socket, see the readme
+    pub console_path: String,
+    // Controls a user unit with systemctl --user
+    #[serde(default)]
+    pub user: bool,
+}
+
+impl SystemdToml {
+    async fn systemctl(&self, args: &[&str]) -> Result<String, String> {
+        let mut cmd = Command::new("systemctl");
+        if self.user {
+            cmd.arg("--user");
+        }
+        let output = cmd.args(args).arg(&self.unit).output().await
+            .map_err(|e| format!("Error running systemctl: {}", e))?;
+        if !output.status.success() {
+            return Err(format!("systemctl {} {} failed: {}", args.join(" "), self.unit, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    // Anything but inactive or failed, the unit may still be starting or stopping
+    async fn is_running(&self) -> bool {
+        match self.systemctl(&["show", "--property=ActiveState", "--value"]).await {
+            Ok(state) => is_running_state(&state),
+            Err(e) => {
+                error!("{}", e);
+                true
+            },
+        }
+    }
+}
+
+fn is_running_state(state: &str) -> bool {
+    !matches!(state, "inactive" | "failed")
+}
+
+// Starts the unit if it isn't already running and follows it until it stops, sending the same
+// packets as a child process would. When forward_lines is false the journal isn't read.
+pub async fn run_unit(config: &SystemdToml, sender: &mpsc::UnboundedSender<Packet>, forward_lines: bool) {
+    // Following the journal first means no lines are missed while the unit starts
+    let mut journal = None;
+    if forward_lines {
+        let unit_arg = if config.user { "--user-unit" } else { "--unit" };
+        let result = Command::new("journalctl")
+            .args([unit_arg, &config.unit, "--follow", "--output=cat", "--lines=0"])
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn();
+        match result {
+            Ok(mut child) => {
+                if let Some(stdout) = child.stdout.take() {
+                    spawn_line_processing_task(stdout, sender.clone(), true);
+                }
+                journal = Some(child);
+            },
+            Err(e) => error!("Error following the journal for {}: {}", config.unit, e),
+        }
+    }
+
+    info!("Starting {}", config.unit);
+    if let Err(e) = config.systemctl(&["start"]).await {
+        error!("{}", e);
+        send_or_log(sender, Packet::PreflightFailed(vec![e]));
+        return;
+    }
+
+    let stdin = match tokio::fs::OpenOptions::new().write(true).open(&config.console_path).await {
+        Ok(v) => v,
+        Err(e) => {
+            // Without its console the server can't be stopped cleanly, so it isn't left running
+            let problem = format!("Error opening {}: {}", config.console_path, e);
+            error!("{}", problem);
+            if let Err(e) = config.systemctl(&["stop"]).await {
+                error!("{}", e);
+            }
+            send_or_log(sender, Packet::PreflightFailed(vec![problem]));
+            return;
+        },
+    };
+    let pid = config.systemctl(&["show", "--property=MainPID", "--value"]).await.ok()
+        .and_then(|x| x.parse().ok())
+        .filter(|x| *x != 0);
+
+    let (kill_handle, mut kill_receiver) = KillHandle::new();
+    send_or_log(sender, Packet::ProcessStarted(Console::start(stdin), pid, kill_handle));
+
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if !config.is_running().await {
+                    break;
+                }
+            },
+            Some(()) = kill_receiver.recv() => {
+                info!("Terminating {}", config.unit);
+                if let Err(e) = config.systemctl(&["kill", "--signal=SIGTERM"]).await {
+                    error!("{}", e);
+                }
+                tokio::time::sleep(KILL_GRACE).await;
+                if config.is_running().await {
+                    warn!("{} didn't exit, killing it", config.unit);
+                    if let Err(e) = config.systemctl(&["kill", "--signal=SIGKILL"]).await {
+                        error!("{}", e);
+                    }
+                }
+            },
+        }
+    }
+    info!("{} stopped", config.unit);
+
+    drop(journal);
+    send_or_log(sender, Packet::ProcessExited());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::systemd::{is_running_state, SystemdToml};
+
+    #[test]
+    fn test_systemd() {
+        let config: SystemdToml = toml::from_str("unit = 'minecraft.service'\nconsole_path = '/run/minecraft.stdin'").unwrap();
+        assert!(!config.user);
+        assert!(is_running_state("active"));
+        assert!(is_running_state("deactivating"));
+        assert!(!is_running_state("failed"));
+    }
+}
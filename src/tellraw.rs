@@ -0,0 +1,139 @@
+use serde_json::{json, Value};
+
+// Colors used for discord chat in game
+const PREFIX_COLOR: &str = "blue";
+const AUTHOR_COLOR: &str = "aqua";
+const LINK_COLOR: &str = "gray";
+
+// How much of a replied to message is shown in game
+const REPLY_PREVIEW_CHARS: usize = 40;
+
+// Player names can't be mistaken for target selectors or other arguments. Bedrock players'
+// names can start with Floodgate's prefix, eg. ".Steve".
+fn is_player_name(name: &str) -> bool {
+    let name = name.trim_start_matches(|c: char| c.is_ascii_punctuation() && !matches!(c, '@' | '"' | '{' | '['));
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn link(label: &str, url: &str) -> Value {
+    json!({
+        "text": label,
+        "color": LINK_COLOR,
+        "underlined": true,
+        "clickEvent": { "action": "open_url", "value": url },
+    })
+}
+
+// Builds a tellraw console command that shows text to one player, url makes it clickable.
+// Returns None if the name isn't a valid player name.
+pub fn to_player(player: &str, text: &str, url: Option<&str>) -> Option<String> {
+    if !is_player_name(player) {
+        return None;
+    }
+
+    let component = match url {
+        Some(url) => link(text, url),
+        None => json!({ "text": text, "color": AUTHOR_COLOR }),
+    };
+    Some(format!("tellraw {} {}", player, component))
+}
+
+// Builds a tellraw console command that shows plain text to everyone
+pub fn to_all(text: &str) -> String {
+    format!("tellraw @a {}", json!({ "text": text }))
+}
+
+// Names an attachment by its type so players know what the link is, eg. "image: base.png"
+pub fn attachment_label(filename: &str, content_type: Option<&str>) -> String {
+    let kind = match content_type.and_then(|x| x.split('/').next()) {
+        Some("image") => "image",
+        Some("video") => "video",
+        Some("audio") => "audio",
+        _ => "file",
+    };
+    format!("{}: {}", kind, filename)
+}
+
+// Shows who a message replies to and the start of what they said
+pub fn reply_preview(author: &str, message: &str) -> String {
+    let mut preview: String = message.chars().take(REPLY_PREVIEW_CHARS).collect();
+    if preview.len() < message.len() {
+        preview.push_str("...");
+    }
+    format!("replying to {}: {}", author, preview)
+}
+
+// Builds a tellraw console command that shows a discord message to everyone, with the author in
+// their own color and links as clickable (label, url) pairs after the message. serde_json does
+// the escaping so the message can't break out of the component.
+pub fn discord_chat(author: &str, message: &str, reply: Option<&str>, links: &[(String, String)]) -> String {
+    let mut components = vec![
+        json!(""),
+        json!({ "text": "[Discord] ", "color": PREFIX_COLOR }),
+        json!({ "text": author, "color": AUTHOR_COLOR }),
+    ];
+    if let Some(reply) = reply {
+        components.push(json!({ "text": format!(" ({})", reply), "color": LINK_COLOR, "italic": true }));
+    }
+    components.push(json!({ "text": format!(": {}", message) }));
+    for (label, url) in links {
+        components.push(json!(" "));
+        components.push(link(&format!("[{}]", label), url));
+    }
+    format!("tellraw @a {}", Value::Array(components))
+}
+
+// Builds a tellraw console command that shows a message from a relay channel to everyone, with
+// the prefix in the channel's color
+pub fn relay_message(prefix: &str, color: &str, author: Option<&str>, message: &str, links: &[(String, String)]) -> String {
+    let mut components = vec![json!(""), json!({ "text": format!("{} ", prefix), "color": color })];
+    if let Some(author) = author {
+        components.push(json!({ "text": format!("{}: ", author), "color": AUTHOR_COLOR }));
+    }
+    components.push(json!({ "text": message }));
+    for (label, url) in links {
+        components.push(json!(" "));
+        components.push(link(&format!("[{}]", label), url));
+    }
+    format!("tellraw @a {}", Value::Array(components))
+}
+
+// Tells everyone a relayed discord message was deleted
+pub fn retraction(author: &str) -> String {
+    let components = json!([
+        "",
+        { "text": "[Discord] ", "color": PREFIX_COLOR },
+        { "text": format!("{} deleted a message", author), "color": LINK_COLOR, "italic": true },
+    ]);
+    format!("tellraw @a {}", components)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tellraw::{attachment_label, discord_chat, relay_message, reply_preview, retraction, to_all, to_player};
+
+    #[test]
+    fn test_tellraw() {
+        assert_eq!(to_player("Steve", "Say \"hi\"", None).unwrap(), r#"tellraw Steve {"color":"aqua","text":"Say \"hi\""}"#);
+        assert!(to_player("Steve", "Join", Some("https://discord.gg/abc")).unwrap().contains(r#""clickEvent":{"action":"open_url","value":"https://discord.gg/abc"}"#));
+        assert_eq!(to_player("@a", "hi", None), None);
+        assert!(to_player(".Steve", "hi", None).is_some());
+        assert_eq!(to_all("Say \"hi\""), r#"tellraw @a {"text":"Say \"hi\""}"#);
+
+        assert_eq!(
+            discord_chat("alice", "look \"here\" \\o/", None, &[]),
+            r#"tellraw @a ["",{"color":"blue","text":"[Discord] "},{"color":"aqua","text":"alice"},{"text":": look \"here\" \\o/"}]"#
+        );
+        let label = attachment_label("base.png", Some("image/png"));
+        let line = discord_chat("alice", "", None, &[(label, "https://cdn.example.com/base.png".to_string())]);
+        assert!(line.ends_with(r#"," ",{"clickEvent":{"action":"open_url","value":"https://cdn.example.com/base.png"},"color":"gray","text":"[image: base.png]","underlined":true}]"#));
+
+        let reply = reply_preview("bob", "does anyone know where the nether portal is?");
+        assert_eq!(reply, "replying to bob: does anyone know where the nether portal...");
+        assert!(discord_chat("alice", "by spawn", Some(&reply), &[]).contains(r#"{"color":"gray","italic":true,"text":" (replying to bob: does anyone know where the nether portal...)"}"#));
+        assert_eq!(attachment_label("notes.txt", None), "file: notes.txt");
+        assert_eq!(relay_message("[News]", "gold", None, "Restart at 6", &[]), r#"tellraw @a ["",{"color":"gold","text":"[News] "},{"text":"Restart at 6"}]"#);
+        assert!(relay_message("[News]", "gold", Some("alice"), "hi", &[]).contains(r#"{"color":"aqua","text":"alice: "}"#));
+        assert_eq!(retraction("alice"), r#"tellraw @a ["",{"color":"blue","text":"[Discord] "},{"color":"gray","italic":true,"text":"alice deleted a message"}]"#);
+    }
+}
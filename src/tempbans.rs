@@ -0,0 +1,217 @@
+// Temporary bans from !tempban. The ban itself is the server's, the bot keeps when each one ends in
+// a file and pardons the player once it has, so bans still expire after the bot restarts.
+
+use std::fmt::Write;
+use std::time::Duration;
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::ChannelId;
+use tokio::sync::mpsc;
+use tracing::error;
+
+use crate::audit::AuditEntry;
+use crate::bot::Bot;
+use crate::command_queue::ConsoleTask;
+use crate::console_commands::{self, ConsoleCommand};
+use crate::{run_console_task, send_or_log, Packet};
+
+pub const TEMPBANS_PATH: &str = "mc-discord-bot-tempbans.json";
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// Shown in the audit log as who pardoned the player
+const EXPIRY_USER: &str = "tempban expiry";
+
+// Parses durations like "30m", "12h", "7d", "2w" or "1d12h" into seconds
+pub fn parse_duration(text: &str) -> Option<u64> {
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+        total = total.checked_add(number.parse::<u64>().ok()?.checked_mul(unit)?)?;
+        number.clear();
+    }
+    (number.is_empty() && total > 0).then_some(total)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TempBan {
+    pub player: String,
+    // Unix timestamp in seconds
+    pub expires: i64,
+    pub banned_by: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Default)]
+pub struct TempBans {
+    bans: Vec<TempBan>,
+}
+
+impl TempBans {
+    pub fn read() -> TempBans {
+        let bans = std::fs::read_to_string(TEMPBANS_PATH).ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default();
+        TempBans { bans }
+    }
+
+    pub fn write(&self) {
+        let json_str = serde_json::to_string_pretty(&self.bans).unwrap();
+        if let Err(e) = std::fs::write(TEMPBANS_PATH, json_str) {
+            error!("Error writing {}: {}", TEMPBANS_PATH, e);
+        }
+    }
+
+    // Banning a player again replaces their earlier tempban
+    pub fn add(&mut self, ban: TempBan) {
+        self.remove(&ban.player);
+        self.bans.push(ban);
+        self.bans.sort_by_key(|x| x.expires);
+    }
+
+    // Forgets a player's tempban, eg. when they're pardoned early
+    pub fn remove(&mut self, player: &str) -> bool {
+        let count = self.bans.len();
+        self.bans.retain(|x| !x.player.eq_ignore_ascii_case(player));
+        self.bans.len() != count
+    }
+
+    // The bans that have ended by now, they're kept until the player has been pardoned
+    pub fn expired(&self, now: i64) -> Vec<TempBan> {
+        self.bans.iter().filter(|x| x.expires <= now).cloned().collect()
+    }
+
+    pub fn describe(&self) -> String {
+        if self.bans.is_empty() {
+            return "No temporary bans".to_string();
+        }
+        let mut text = "**Temporary bans**\n".to_string();
+        for ban in &self.bans {
+            let _ = write!(&mut text, "{} until <t:{}:f> (<t:{}:R>) by {}", ban.player, ban.expires, ban.expires, ban.banned_by);
+            if let Some(reason) = &ban.reason {
+                let _ = write!(&mut text, ": {}", reason);
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+// Parses !tempban's arguments into the ban and its console command
+pub fn parse_command(args: &str, banned_by: &str, now: i64) -> Option<(TempBan, String)> {
+    // The duration comes between the player and the reason
+    let (player, rest) = console_commands::parse_player_reason(args)?;
+    let rest = rest?;
+    let (duration, reason) = match rest.split_once(' ') {
+        Some((duration, reason)) => (duration, Some(reason.to_string())),
+        None => (rest.as_str(), None),
+    };
+    Some(new_ban(player, duration, parse_duration(duration)?, reason, banned_by, now))
+}
+
+// Builds a ban lasting secs, duration is how it's described in the ban reason
+pub fn new_ban(player: &str, duration: &str, secs: u64, reason: Option<String>, banned_by: &str, now: i64) -> (TempBan, String) {
+    let console_line = match &reason {
+        Some(reason) => format!("ban {} {} (for {})", player, reason, duration),
+        None => format!("ban {} Banned for {}", player, duration),
+    };
+    let ban = TempBan { player: player.to_string(), expires: now.saturating_add(secs as i64), banned_by: banned_by.to_string(), reason };
+    (ban, console_line)
+}
+
+// Bans the player through the console and remembers when to pardon them
+pub async fn start(bot: &mut Bot, channel_id: ChannelId, ban: TempBan, console_line: String, audit_entry: AuditEntry) -> Result<(), &'static str> {
+    let ctx = bot.ctx.clone().ok_or("Discord isn't ready")?;
+    let task = ConsoleTask::new(channel_id, ConsoleCommand::Ban, console_line, Some(audit_entry));
+    run_console_task(&bot.console, &mut bot.command_queue, &bot.sender, &ctx, task).await?;
+    bot.tempbans.add(ban);
+    bot.tempbans.write();
+    Ok(())
+}
+
+pub fn start_tempban_timer(sender: &mpsc::UnboundedSender<Packet>) {
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            send_or_log(&sender, Packet::TempBanTick());
+        }
+    });
+}
+
+// Pardons players whose tempban has ended. This waits for the server to be running, since the
+// pardon has to go through its console.
+pub async fn expire(bot: &mut Bot) {
+    let ctx = match (&bot.ctx, &bot.console) {
+        (Some(ctx), Some(_)) => ctx.clone(),
+        _ => return,
+    };
+    let expired = bot.tempbans.expired(Local::now().timestamp());
+    if expired.is_empty() {
+        return;
+    }
+
+    // A ban is only forgotten once its pardon is queued, otherwise it's tried again next time
+    let channel_id = bot.admin_channel_id.unwrap_or(bot.channel_id);
+    for ban in expired {
+        let console_line = format!("pardon {}", ban.player);
+        let audit_entry = AuditEntry::new(EXPIRY_USER.to_string(), 0, &console_line);
+        let task = ConsoleTask::new(channel_id, ConsoleCommand::Pardon, console_line, Some(audit_entry));
+        match run_console_task(&bot.console, &mut bot.command_queue, &bot.sender, &ctx, task).await {
+            Ok(()) => {
+                bot.tempbans.remove(&ban.player);
+            },
+            Err(e) => {
+                error!("Error pardoning {} after their tempban: {}", ban.player, e);
+                bot.say_admin(&format!("Couldn't pardon {} after their tempban ended: {}", ban.player, e)).await;
+            },
+        }
+    }
+    bot.tempbans.write();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tempbans::{parse_command, parse_duration, TempBans};
+
+    #[test]
+    fn test_tempbans() {
+        assert_eq!(parse_duration("30m"), Some(1800));
+        assert_eq!(parse_duration("1d12h"), Some(129_600));
+        assert_eq!(parse_duration("2w"), Some(1_209_600));
+        assert_eq!(parse_duration("12"), None);
+        assert_eq!(parse_duration("0h"), None);
+        assert_eq!(parse_duration("3y"), None);
+
+        let (ban, console_line) = parse_command("Steve 2h griefing\nspawn", "alice", 1_000).unwrap();
+        assert_eq!((ban.expires, ban.reason.as_deref()), (8_200, Some("griefing spawn")));
+        assert_eq!(console_line, "ban Steve griefing spawn (for 2h)");
+        assert_eq!(parse_command("Alex 1d", "alice", 0).unwrap().1, "ban Alex Banned for 1d");
+        assert!(parse_command("Steve forever", "alice", 0).is_none());
+        assert!(parse_command("Steve;op 1d", "alice", 0).is_none());
+
+        let mut tempbans = TempBans::default();
+        tempbans.add(ban);
+        tempbans.add(parse_command("Alex 1m", "bob", 1_000).unwrap().0);
+        tempbans.add(parse_command("alex 10m", "bob", 1_000).unwrap().0);
+        assert!(tempbans.expired(1_500).is_empty());
+        assert_eq!(tempbans.expired(1_600).iter().map(|x| x.player.as_str()).collect::<Vec<&str>>(), vec!["alex"]);
+        assert_eq!(tempbans.expired(1_600).len(), 1);
+        assert!(tempbans.remove("Alex"));
+        assert!(tempbans.describe().starts_with("**Temporary bans**\nSteve until <t:8200:f>"));
+        assert!(tempbans.remove("steve"));
+        assert_eq!(tempbans.describe(), "No temporary bans");
+    }
+}
@@ -0,0 +1,88 @@
+// Messages with {placeholder}s filled in by the bot. Announcements can be replaced with
+// templates from the config, which are checked on startup so a typo in a placeholder isn't
+// posted as is.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+// Each template's name in the config, the message it replaces and the placeholders it can use
+const TEMPLATES: &[(&str, &str, &[&str])] = &[
+    ("join", "join.joined", &["player", "online"]),
+    ("first_join", "join.first_join", &["player", "online"]),
+    ("leave", "join.left", &["player", "online", "session", "total_play_time"]),
+    ("server_started", "server.started", &[]),
+    ("server_shutdown", "server.shutdown", &[]),
+];
+
+// Templates by the key of the message they replace
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(try_from = "HashMap<String, String>")]
+pub struct TemplatesToml {
+    pub messages: HashMap<String, String>,
+}
+
+impl TryFrom<HashMap<String, String>> for TemplatesToml {
+    type Error = String;
+
+    fn try_from(templates: HashMap<String, String>) -> Result<TemplatesToml, String> {
+        let mut messages = HashMap::new();
+        for (name, template) in templates {
+            let (_, key, allowed) = TEMPLATES.iter().find(|x| x.0 == name).ok_or_else(|| {
+                format!("Unknown template \"{}\", templates are {}", name, TEMPLATES.iter().map(|x| x.0).collect::<Vec<&str>>().join(", "))
+            })?;
+            validate(&template, allowed).map_err(|e| format!("{} in the {} template", e, name))?;
+            messages.insert(key.to_string(), template);
+        }
+        Ok(TemplatesToml { messages })
+    }
+}
+
+// Names of the placeholders in a template. Braces that don't surround a name, eg. in
+// "{ }", are left as text.
+pub fn placeholders(template: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        if end > 0 && rest[end..].starts_with('}') {
+            names.push(&rest[..end]);
+            rest = &rest[end + 1..];
+        }
+    }
+    names
+}
+
+pub fn validate(template: &str, allowed: &[&str]) -> Result<(), String> {
+    match placeholders(template).into_iter().find(|x| !allowed.contains(x)) {
+        Some(unknown) if allowed.is_empty() => Err(format!("Unknown placeholder {{{}}}, there are none", unknown)),
+        Some(unknown) => Err(format!("Unknown placeholder {{{}}}, use one of {}", unknown, allowed.iter().map(|x| format!("{{{}}}", x)).collect::<Vec<String>>().join(", "))),
+        None => Ok(()),
+    }
+}
+
+// Fills in placeholders that have a value, others are left as they are
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut text = template.to_string();
+    for (name, value) in values {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::templates::{placeholders, render, TemplatesToml};
+
+    #[test]
+    fn test_templates() {
+        assert_eq!(placeholders("➡️ {player} hopped on ({online} online) { }"), vec!["player", "online"]);
+        assert_eq!(render("{player} hopped on ({online} online)", &[("player", "Steve"), ("online", "3")]), "Steve hopped on (3 online)");
+
+        let config: TemplatesToml = toml::from_str("join = '{player} hopped on'").unwrap();
+        assert_eq!(config.messages["join.joined"], "{player} hopped on");
+        assert!(toml::from_str::<TemplatesToml>("join = '{playre} hopped on'").unwrap_err().to_string().contains("Unknown placeholder {playre}, use one of {player}, {online} in the join template"));
+        assert!(toml::from_str::<TemplatesToml>("joined = '{player}'").is_err());
+    }
+}
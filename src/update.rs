@@ -0,0 +1,414 @@
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::time::SystemTime;
+
+use serde::{Serialize, Deserialize};
+use tokio::io::AsyncWriteExt;
+
+const PACK_PATH: &str = "pack.zip";
+const TEMP_PACK_DIR: &str = "temp-pack";
+
+// Previous installations are moved into numbered directories here so they can be rolled back to
+const VERSIONS_DIR: &str = "modpack-versions";
+const VERSION_HISTORY_PATH: &str = "mc-discord-bot-modpack.json";
+const KEEP_VERSIONS: usize = 3;
+
+// Directories copied from the modpack into the server directory, replacing the existing ones
+const PACK_DIRS: &[&str] = &["mods", "config", "defaultconfigs"];
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum UpdateKind {
+    // Installs the given modpack version, or the newest one when not set
+    Update(Option<String>),
+    Rollback,
+    // Swaps the world for the backup with the given id
+    Restore(String),
+}
+
+impl UpdateKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            UpdateKind::Update(_) => "Update",
+            UpdateKind::Rollback => "Rollback",
+            UpdateKind::Restore(_) => "Restore",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InstalledVersion {
+    pub url: String,
+    // Version name from the modpack's CurseForge/Modrinth project
+    #[serde(default)]
+    pub version: Option<String>,
+    // Unix timestamp in seconds
+    pub installed_at: u64,
+    // Where this version's files were moved to when it was replaced
+    pub backup_dir: Option<String>,
+}
+
+// Tracks the live modpack version and the previous versions that can be rolled back to
+#[derive(Serialize, Deserialize, Default)]
+pub struct VersionHistory {
+    pub current: Option<InstalledVersion>,
+    // Most recent first
+    #[serde(default)]
+    pub previous: Vec<InstalledVersion>,
+}
+
+impl VersionHistory {
+    pub async fn read() -> VersionHistory {
+        match tokio::fs::read_to_string(VERSION_HISTORY_PATH).await {
+            Ok(v) => serde_json::from_str(&v).unwrap_or_default(),
+            Err(_) => VersionHistory::default(),
+        }
+    }
+
+    async fn write(&self) -> Result<(), String> {
+        let json_str = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        tokio::fs::write(VERSION_HISTORY_PATH, json_str).await.map_err(|e| format!("Error writing {}: {}", VERSION_HISTORY_PATH, e))
+    }
+
+    pub fn describe(&self) -> String {
+        let mut description = match &self.current {
+            Some(current) => match &current.version {
+                Some(version) => format!("Live modpack: version {} (installed <t:{}:R>)", version, current.installed_at),
+                None => format!("Live modpack: {} (installed <t:{}:R>)", current.url, current.installed_at),
+            },
+            None => "No modpack has been installed by the bot".to_string(),
+        };
+        description.push_str(&format!("\n{} previous version(s) available for rollback", self.previous.len()));
+        description
+    }
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0)
+}
+
+// Removes a file or directory, treating it not existing as success
+pub async fn remove_path(path: &Path) -> Result<(), String> {
+    let result = match tokio::fs::metadata(path).await {
+        Ok(metadata) if metadata.is_dir() => tokio::fs::remove_dir_all(path).await,
+        Ok(_) => tokio::fs::remove_file(path).await,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => Err(e),
+    };
+    result.map_err(|e| format!("Error removing {}: {}", path.display(), e))
+}
+
+// Downloads url to path, reporting progress every 25% when the size is known
+pub async fn download(url: &str, path: &str, report: &(impl Fn(String) + Sync)) -> Result<(), String> {
+    let mut response = reqwest::get(url).await
+        .and_then(|x| x.error_for_status())
+        .map_err(|e| format!("Error downloading {}: {}", url, e))?;
+
+    let total = response.content_length();
+    let mut file = tokio::fs::File::create(path).await.map_err(|e| format!("Error creating {}: {}", path, e))?;
+
+    let mut downloaded: u64 = 0;
+    let mut last_reported = 0;
+    while let Some(chunk) = response.chunk().await.map_err(|e| format!("Error downloading {}: {}", url, e))? {
+        file.write_all(&chunk).await.map_err(|e| format!("Error writing {}: {}", path, e))?;
+        downloaded += chunk.len() as u64;
+
+        if let Some(total) = total.filter(|x| *x > 0) {
+            let percent = downloaded * 100 / total;
+            if percent >= last_reported + 25 {
+                last_reported = percent - (percent % 25);
+                report(format!("Downloaded {}%", last_reported));
+            }
+        }
+    }
+
+    file.flush().await.map_err(|e| format!("Error writing {}: {}", path, e))?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct MrpackIndex {
+    files: Vec<MrpackFile>,
+}
+
+#[derive(Deserialize)]
+struct MrpackFile {
+    path: String,
+    downloads: Vec<String>,
+    env: Option<MrpackEnv>,
+}
+
+#[derive(Deserialize)]
+struct MrpackEnv {
+    server: String,
+}
+
+// Moves everything in source into destination, replacing existing files
+fn merge_dir<'a>(source: &'a Path, destination: &'a Path) -> Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>> {
+    Box::pin(async move {
+        tokio::fs::create_dir_all(destination).await.map_err(|e| format!("Error creating {}: {}", destination.display(), e))?;
+        let mut entries = tokio::fs::read_dir(source).await.map_err(|e| format!("Error reading {}: {}", source.display(), e))?;
+        while let Some(entry) = entries.next_entry().await.map_err(|e| format!("Error reading {}: {}", source.display(), e))? {
+            let target = destination.join(entry.file_name());
+            if entry.path().is_dir() {
+                merge_dir(&entry.path(), &target).await?;
+            } else {
+                remove_path(&target).await?;
+                tokio::fs::rename(entry.path(), &target).await.map_err(|e| format!("Error moving {}: {}", target.display(), e))?;
+            }
+        }
+        Ok(())
+    })
+}
+
+// Modrinth packs list their mods as downloads instead of including them, this downloads the
+// server side files into the pack's overrides directory and merges in the server overrides
+async fn install_mrpack(pack_dir: &Path, report: &(impl Fn(String) + Sync)) -> Result<(), String> {
+    let index_path = pack_dir.join("modrinth.index.json");
+    let index = tokio::fs::read_to_string(&index_path).await.map_err(|e| format!("Error reading {}: {}", index_path.display(), e))?;
+    let index: MrpackIndex = serde_json::from_str(&index).map_err(|e| format!("Error parsing {}: {}", index_path.display(), e))?;
+
+    let overrides = pack_dir.join("overrides");
+    let files: Vec<&MrpackFile> = index.files.iter()
+        .filter(|x| x.env.as_ref().map(|env| env.server != "unsupported").unwrap_or(true))
+        .collect();
+    report(format!("Downloading {} mod files", files.len()));
+
+    for file in files {
+        // Don't let the index write outside of the pack directory
+        let relative = Path::new(&file.path);
+        if relative.components().any(|x| !matches!(x, Component::Normal(_))) {
+            return Err(format!("Invalid file path in modpack \"{}\"", file.path));
+        }
+
+        let url = file.downloads.first().ok_or(format!("No download for {}", file.path))?;
+        let target = overrides.join(relative);
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|e| format!("Error creating {}: {}", parent.display(), e))?;
+        }
+        download(url, &target.to_string_lossy(), &|_| {}).await?;
+    }
+
+    let server_overrides = pack_dir.join("server-overrides");
+    if tokio::fs::metadata(&server_overrides).await.is_ok() {
+        merge_dir(&server_overrides, &overrides).await?;
+    }
+    Ok(())
+}
+
+// Finds the directory in an extracted pack that holds the mods and config directories, packs
+// exported from launchers use .minecraft, CurseForge server packs are usually the root or a
+// single top level directory
+fn find_pack_root(pack_dir: &Path) -> PathBuf {
+    for candidate in [pack_dir.join(".minecraft"), pack_dir.join("overrides"), pack_dir.to_path_buf()] {
+        if candidate.join("mods").is_dir() {
+            return candidate;
+        }
+    }
+
+    let subdirs: Vec<PathBuf> = std::fs::read_dir(pack_dir)
+        .map(|entries| entries.flatten().map(|x| x.path()).filter(|x| x.is_dir()).collect())
+        .unwrap_or_default();
+    if subdirs.len() == 1 && subdirs[0].join("mods").is_dir() {
+        return subdirs[0].clone();
+    }
+
+    pack_dir.join(".minecraft")
+}
+
+pub async fn extract(archive_path: &str, directory: &str) -> Result<(), String> {
+    let path = archive_path.to_string();
+    let directory = directory.to_string();
+
+    // The zip crate is synchronous so extraction runs on the blocking thread pool
+    tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(&path).map_err(|e| format!("Error opening {}: {}", path, e))?;
+        let mut archive = zip::ZipArchive::new(file).map_err(|e| format!("Error reading {}: {}", path, e))?;
+        archive.extract(&directory).map_err(|e| format!("Error extracting {}: {}", path, e))
+    }).await.map_err(|e| format!("Error extracting {}: {}", archive_path, e))?
+}
+
+// Matches a file name against a pattern where * matches any number of characters and ? matches
+// a single character, eg. "Oculus-*.jar"
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // Position to retry from when a mismatch happens after a *
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|x| *x == '*')
+}
+
+// Removes mods matching the client_mods patterns, reporting what was removed and any patterns
+// that didn't match anything
+async fn remove_client_mods(patterns: &[String], report: &(impl Fn(String) + Sync)) -> Result<(), String> {
+    if patterns.is_empty() {
+        return Ok(());
+    }
+
+    let mut mods = Vec::new();
+    let mut entries = match tokio::fs::read_dir("mods").await {
+        Ok(v) => v,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            report("No mods directory, skipping client mod removal".to_string());
+            return Ok(());
+        },
+        Err(e) => return Err(format!("Error reading mods: {}", e)),
+    };
+    while let Some(entry) = entries.next_entry().await.map_err(|e| format!("Error reading mods: {}", e))? {
+        mods.push(entry.file_name().to_string_lossy().to_string());
+    }
+    mods.sort();
+
+    let mut removed = Vec::new();
+    for pattern in patterns {
+        let matched: Vec<&String> = mods.iter().filter(|x| matches_pattern(pattern, x)).collect();
+        if matched.is_empty() {
+            report(format!("Client mod pattern \"{}\" didn't match any mods", pattern));
+        }
+
+        for name in matched {
+            if !removed.contains(name) {
+                remove_path(&Path::new("mods").join(name)).await?;
+                removed.push(name.clone());
+            }
+        }
+    }
+
+    if !removed.is_empty() {
+        report(format!("Removed client mods: {}", removed.join(", ")));
+    }
+    Ok(())
+}
+
+// Replaces the server's mods and configs with the ones from the modpack at modpack_url, then
+// removes client only mods
+pub async fn run_update(modpack_url: &str, version: Option<String>, client_mods: &[String], report: impl Fn(String) + Sync) -> Result<(), String> {
+    report(format!("Downloading {}", modpack_url));
+    download(modpack_url, PACK_PATH, &report).await?;
+
+    report("Extracting modpack".to_string());
+    remove_path(Path::new(TEMP_PACK_DIR)).await?;
+    extract(PACK_PATH, TEMP_PACK_DIR).await?;
+    remove_path(Path::new(PACK_PATH)).await?;
+
+    if Path::new(TEMP_PACK_DIR).join("modrinth.index.json").is_file() {
+        install_mrpack(Path::new(TEMP_PACK_DIR), &report).await?;
+    }
+    let pack_root = find_pack_root(Path::new(TEMP_PACK_DIR));
+
+    // Keep the current installation around so it can be rolled back to
+    let mut history = VersionHistory::read().await;
+    let installed_at = unix_secs();
+    let backup_dir = Path::new(VERSIONS_DIR).join(installed_at.to_string());
+    report(format!("Saving current mods and configs to {}", backup_dir.display()));
+    tokio::fs::create_dir_all(&backup_dir).await.map_err(|e| format!("Error creating {}: {}", backup_dir.display(), e))?;
+    for dir in PACK_DIRS {
+        if tokio::fs::metadata(dir).await.is_ok() {
+            tokio::fs::rename(dir, backup_dir.join(dir)).await.map_err(|e| format!("Error saving {}: {}", dir, e))?;
+        }
+    }
+
+    let mut previous = history.current.take().unwrap_or(InstalledVersion {
+        url: "unknown".to_string(),
+        version: None,
+        installed_at: 0,
+        backup_dir: None,
+    });
+    previous.backup_dir = Some(backup_dir.to_string_lossy().to_string());
+    history.previous.insert(0, previous);
+
+    for old_version in history.previous.drain(KEEP_VERSIONS.min(history.previous.len())..) {
+        if let Some(old_backup_dir) = old_version.backup_dir {
+            remove_path(Path::new(&old_backup_dir)).await?;
+        }
+    }
+
+    report("Replacing mods and configs".to_string());
+    for dir in PACK_DIRS {
+        let source = pack_root.join(dir);
+        if tokio::fs::metadata(&source).await.is_err() {
+            report(format!("Modpack has no {} directory, skipping", dir));
+            continue;
+        }
+
+        tokio::fs::rename(&source, dir).await.map_err(|e| format!("Error moving {} into place: {}", dir, e))?;
+    }
+    remove_path(Path::new(TEMP_PACK_DIR)).await?;
+
+    history.current = Some(InstalledVersion {
+        url: modpack_url.to_string(),
+        version,
+        installed_at,
+        backup_dir: None,
+    });
+    history.write().await?;
+
+    remove_client_mods(client_mods, &report).await?;
+
+    report("Update complete".to_string());
+    Ok(())
+}
+
+// Restores the most recently replaced installation, discarding the current one
+pub async fn run_rollback(report: impl Fn(String) + Sync) -> Result<(), String> {
+    let mut history = VersionHistory::read().await;
+    if history.previous.is_empty() {
+        return Err("No previous version to roll back to".to_string());
+    }
+
+    let mut previous = history.previous.remove(0);
+    let backup_dir = previous.backup_dir.take().ok_or("Previous version has no saved files")?;
+    report(format!("Rolling back to {}", previous.url));
+
+    for dir in PACK_DIRS {
+        remove_path(Path::new(dir)).await?;
+
+        let source = Path::new(&backup_dir).join(dir);
+        if tokio::fs::metadata(&source).await.is_ok() {
+            tokio::fs::rename(&source, dir).await.map_err(|e| format!("Error restoring {}: {}", dir, e))?;
+        }
+    }
+    remove_path(Path::new(&backup_dir)).await?;
+
+    history.current = Some(previous);
+    history.write().await?;
+
+    report("Rollback complete".to_string());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::update::matches_pattern;
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("Oculus-*.jar", "Oculus-mc1.20.1-1.6.9.jar"));
+        assert!(matches_pattern("Oculus-1.jar", "Oculus-1.jar"));
+        assert!(matches_pattern("*", "anything.jar"));
+        assert!(matches_pattern("a?c*.jar", "abcdef.jar"));
+        assert!(matches_pattern("*-client-*.jar", "mod-client-forge-1.2.jar"));
+        assert!(!matches_pattern("Oculus-*.jar", "Embeddium-1.0.jar"));
+        assert!(!matches_pattern("Oculus-*.jar", "Oculus-1.zip"));
+        assert!(!matches_pattern("a?c.jar", "ac.jar"));
+    }
+}
@@ -0,0 +1,60 @@
+use std::time::SystemTime;
+
+use chrono::{Local, TimeZone};
+use serde::{Serialize, Deserialize};
+
+use crate::metrics::Metrics;
+use crate::playtime::{format_duration, unix_millis};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RestartReason {
+    Manual,
+    Crash,
+    Scheduled,
+    Hung,
+    Vote,
+}
+
+impl RestartReason {
+    fn name(&self) -> &'static str {
+        match self {
+            RestartReason::Manual => "manual",
+            RestartReason::Crash => "crash",
+            RestartReason::Scheduled => "scheduled",
+            RestartReason::Hung => "hung",
+            RestartReason::Vote => "vote",
+        }
+    }
+}
+
+// Kept in the bot state so a crash can still be reported after the bot restarts.
+// time is a unix timestamp in milliseconds.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LastRestart {
+    pub reason: RestartReason,
+    pub time: u64,
+}
+
+impl LastRestart {
+    pub fn now(reason: RestartReason) -> LastRestart {
+        LastRestart { reason, time: unix_millis(SystemTime::now()) }
+    }
+}
+
+// Builds the !uptime reply
+pub fn format_uptime(metrics: &Metrics, last_restart: Option<&LastRestart>) -> String {
+    let mut uptime = match metrics.server_started {
+        Some(started) => format!("Server uptime: {}", format_duration(started.elapsed().as_millis())),
+        None => "Server is not running".to_string(),
+    };
+    uptime.push_str(&format!("\nBot uptime: {}", format_duration(metrics.bot_started.elapsed().as_millis())));
+
+    if let Some(last_restart) = last_restart {
+        let time = Local.timestamp_millis_opt(last_restart.time as i64).single()
+            .map(|x| x.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        uptime.push_str(&format!("\nLast restart: {} at {}", last_restart.reason.name(), time));
+    }
+    uptime
+}
@@ -0,0 +1,152 @@
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use crate::{Packet, send_or_log, process_stats};
+use crate::jvm_args::JVM_ARGS_PATH;
+use crate::metrics::{Metrics, SharedMetrics};
+
+#[derive(Deserialize)]
+pub struct UsageToml {
+    #[serde(default = "default_sample_interval_secs")]
+    pub sample_interval_secs: u64,
+
+    // Admins are warned when memory usage passes this percentage of -Xmx
+    #[serde(default = "default_memory_warning_percent")]
+    pub memory_warning_percent: u64,
+}
+
+impl Default for UsageToml {
+    fn default() -> UsageToml {
+        UsageToml {
+            sample_interval_secs: default_sample_interval_secs(),
+            memory_warning_percent: default_memory_warning_percent(),
+        }
+    }
+}
+
+// Memory samples kept for the trend posted after an OutOfMemoryError
+const MEMORY_HISTORY_LEN: usize = 30;
+
+fn default_sample_interval_secs() -> u64 {
+    10
+}
+
+fn default_memory_warning_percent() -> u64 {
+    90
+}
+
+// Finds the max heap size in bytes from JVM arguments, eg. -Xmx12G. None for sizes that are 0 or
+// too large, since callers divide by it.
+pub fn parse_max_heap(jvm_args: &str) -> Option<u64> {
+    let arg = jvm_args.split_whitespace().rev().find_map(|x| x.strip_prefix("-Xmx"))?;
+    let (number, multiplier) = match arg.chars().last()? {
+        'k' | 'K' => (&arg[..arg.len() - 1], 1024),
+        'm' | 'M' => (&arg[..arg.len() - 1], 1024 * 1024),
+        'g' | 'G' => (&arg[..arg.len() - 1], 1024 * 1024 * 1024),
+        't' | 'T' => (&arg[..arg.len() - 1], 1024 * 1024 * 1024 * 1024),
+        _ => (arg, 1),
+    };
+    number.parse::<u64>().ok()?.checked_mul(multiplier).filter(|x| *x > 0)
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    format!("{:.2} GiB", bytes as f64 / (1024.0 * 1024.0 * 1024.0))
+}
+
+// Builds the `!usage` reply
+pub fn format_usage(metrics: &Metrics) -> String {
+    let memory = match metrics.memory {
+        Some(v) => v,
+        None => return "No usage data, the server isn't running or usage sampling isn't supported on this platform".to_string(),
+    };
+
+    let max_heap = std::fs::read_to_string(JVM_ARGS_PATH).ok().and_then(|x| parse_max_heap(&x));
+    let mut usage = match max_heap {
+        Some(max_heap) => format!("Memory: {} (max heap {}, {}%)", format_bytes(memory), format_bytes(max_heap), memory * 100 / max_heap),
+        None => format!("Memory: {}", format_bytes(memory)),
+    };
+    usage.push_str(&format!("\nPeak memory: {}", format_bytes(metrics.peak_memory.unwrap_or(memory))));
+    if let Some(cpu) = metrics.cpu_percent {
+        usage.push_str(&format!("\nCPU: {:.1}%", cpu));
+    }
+    usage
+}
+
+// Periodically samples the server's resource usage into metrics, sending MemoryWarning once
+// each time memory usage passes the warning threshold
+pub fn start_usage_sampler(config: &UsageToml, metrics: SharedMetrics, sender: &mpsc::UnboundedSender<Packet>) {
+    let interval = Duration::from_secs(config.sample_interval_secs.max(1));
+    let warning_percent = config.memory_warning_percent;
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        let mut last_sample: Option<(u32, u64, Instant)> = None;
+        let mut warned = false;
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let pid = metrics.lock().unwrap().pid;
+            let (pid, usage) = match pid.and_then(|pid| process_stats::sample(pid).map(|usage| (pid, usage))) {
+                Some(v) => v,
+                None => {
+                    let mut metrics = metrics.lock().unwrap();
+                    metrics.memory = None;
+                    metrics.cpu_percent = None;
+                    metrics.memory_history.clear();
+                    last_sample = None;
+                    continue;
+                },
+            };
+            let now = Instant::now();
+
+            // CPU usage is the cpu time used since the last sample of the same process
+            let cpu_percent = match last_sample {
+                Some((last_pid, last_ticks, last_time)) if last_pid == pid => {
+                    let ticks = usage.cpu_ticks.saturating_sub(last_ticks) as f64;
+                    let seconds = (now - last_time).as_secs_f64();
+                    Some(ticks / process_stats::CLOCK_TICKS_PER_SEC as f64 / seconds * 100.0)
+                },
+                _ => None,
+            };
+            last_sample = Some((pid, usage.cpu_ticks, now));
+
+            {
+                let mut metrics = metrics.lock().unwrap();
+                metrics.memory = Some(usage.memory);
+                metrics.peak_memory = Some(metrics.peak_memory.unwrap_or(0).max(usage.memory));
+                metrics.cpu_percent = cpu_percent;
+                metrics.memory_history.push_back(usage.memory);
+                if metrics.memory_history.len() > MEMORY_HISTORY_LEN {
+                    metrics.memory_history.pop_front();
+                }
+            }
+
+            let max_heap = std::fs::read_to_string(JVM_ARGS_PATH).ok().and_then(|x| parse_max_heap(&x));
+            if let Some(max_heap) = max_heap {
+                let over_threshold = usage.memory * 100 >= max_heap * warning_percent;
+                if over_threshold && !warned {
+                    send_or_log(&sender, Packet::MemoryWarning(usage.memory, max_heap));
+                }
+                warned = over_threshold;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::parse_max_heap;
+
+    #[test]
+    fn test_parse_max_heap() {
+        assert_eq!(parse_max_heap("-Xms12G -Xmx12G -XX:+UseG1GC"), Some(12 * 1024 * 1024 * 1024));
+        assert_eq!(parse_max_heap("-Xmx512m"), Some(512 * 1024 * 1024));
+        assert_eq!(parse_max_heap("-Xmx1024"), Some(1024));
+        assert_eq!(parse_max_heap("-Xms4G"), None);
+        assert_eq!(parse_max_heap("-Xmx0"), None);
+        assert_eq!(parse_max_heap("-Xmx0G"), None);
+        assert_eq!(parse_max_heap("-Xmx99999999T"), None);
+    }
+}
@@ -0,0 +1,159 @@
+// Checks Mojang's version manifest and the modpack source for releases newer than what's
+// installed, posting each new one to the admin channel once.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::metrics::SharedMetrics;
+use crate::modpack_source::{self, ModpackToml};
+use crate::{send_or_log, update, Packet};
+
+const MANIFEST_URL: &str = "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json";
+
+// Gives the server time to log its version before the first check
+const FIRST_CHECK_DELAY: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Deserialize, Clone)]
+pub struct VersionCheckToml {
+    #[serde(default = "default_interval_hours")]
+    pub interval_hours: u64,
+    #[serde(default = "default_true")]
+    pub minecraft: bool,
+    // Also announce snapshots, only useful for servers that run them
+    #[serde(default)]
+    pub snapshots: bool,
+}
+
+fn default_interval_hours() -> u64 {
+    6
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct Latest {
+    release: String,
+    snapshot: String,
+}
+
+#[derive(Deserialize)]
+struct ManifestVersion {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    latest: Latest,
+    // Newest first
+    versions: Vec<ManifestVersion>,
+}
+
+// "Starting minecraft server version 1.20.1"
+pub fn parse_server_version(line: &str) -> Option<&str> {
+    line.strip_prefix("Starting minecraft server version ").map(|x| x.trim())
+}
+
+// The article minecraft.net publishes for each release or snapshot
+fn changelog_url(version: &str, snapshot: bool) -> String {
+    match snapshot {
+        true => format!("https://www.minecraft.net/en-us/article/minecraft-snapshot-{}", version),
+        false => format!("https://www.minecraft.net/en-us/article/minecraft-java-edition-{}", version.replace('.', "-")),
+    }
+}
+
+// The newest release or snapshot when it's newer than installed. Versions the manifest doesn't
+// know, eg. a modded server's, are only compared by name.
+fn newer_minecraft(manifest: &Manifest, installed: &str, snapshots: bool) -> Option<String> {
+    let latest = match snapshots {
+        true => &manifest.latest.snapshot,
+        false => &manifest.latest.release,
+    };
+    let position = |id: &str| manifest.versions.iter().position(|x| x.id == id);
+    let newer = match (position(latest), position(installed)) {
+        (Some(latest), Some(installed)) => latest < installed,
+        _ => latest != installed,
+    };
+    newer.then(|| {
+        let snapshot = *latest != manifest.latest.release;
+        format!("Minecraft {} is out, the server is running {}\n{}", latest, installed, changelog_url(latest, snapshot))
+    })
+}
+
+async fn fetch_manifest() -> Result<Manifest, String> {
+    let body = reqwest::Client::new().get(MANIFEST_URL).header("User-Agent", "mc-discord-bot").send().await
+        .and_then(|x| x.error_for_status())
+        .map_err(|e| format!("Error querying {}: {}", MANIFEST_URL, e))?
+        .text().await
+        .map_err(|e| format!("Error querying {}: {}", MANIFEST_URL, e))?;
+    serde_json::from_str(&body).map_err(|e| format!("Error parsing response from {}: {}", MANIFEST_URL, e))
+}
+
+async fn check(config: &VersionCheckToml, modpack: Option<&ModpackToml>, server_version: Option<String>) -> Vec<String> {
+    let mut found = Vec::new();
+    if let (true, Some(installed)) = (config.minecraft, server_version) {
+        match fetch_manifest().await {
+            Ok(manifest) => found.extend(newer_minecraft(&manifest, &installed, config.snapshots)),
+            Err(e) => debug!("{}", e),
+        }
+    }
+
+    if let Some(modpack) = modpack {
+        let installed = update::VersionHistory::read().await.current.and_then(|x| x.version);
+        match (modpack_source::list_versions(modpack).await, installed) {
+            (Ok(versions), Some(installed)) => {
+                if let Some(newest) = versions.first().filter(|x| x.name != installed) {
+                    found.push(format!("Modpack version {} is out, {} is installed. Use `!update` to install it\n{}", newest.name, installed, newest.page_url));
+                }
+            },
+            (Err(e), _) => debug!("{}", e),
+            (Ok(_), None) => {},
+        }
+    }
+    found
+}
+
+// Checks every interval_hours, sending a NewVersion packet for each release that hasn't been
+// announced yet
+pub fn start_version_checker(config: &VersionCheckToml, modpack: Option<ModpackToml>, metrics: SharedMetrics, sender: &mpsc::UnboundedSender<Packet>) {
+    let config = config.clone();
+    let interval = Duration::from_secs(config.interval_hours.max(1) * 60 * 60);
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        let mut announced = HashSet::new();
+        tokio::time::sleep(FIRST_CHECK_DELAY).await;
+        loop {
+            let server_version = metrics.lock().unwrap().server_version.clone();
+            for message in check(&config, modpack.as_ref(), server_version).await {
+                if announced.insert(message.clone()) {
+                    send_or_log(&sender, Packet::NewVersion(message));
+                }
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::version_check::{newer_minecraft, parse_server_version, Manifest};
+
+    #[test]
+    fn test_newer_minecraft() {
+        assert_eq!(parse_server_version("Starting minecraft server version 1.20.1"), Some("1.20.1"));
+
+        let manifest: Manifest = serde_json::from_str(r#"{
+            "latest": {"release": "1.20.4", "snapshot": "24w03a"},
+            "versions": [{"id": "24w03a"}, {"id": "1.20.4"}, {"id": "1.20.3"}, {"id": "1.20.1"}]
+        }"#).unwrap();
+        assert_eq!(newer_minecraft(&manifest, "1.20.1", false).as_deref(), Some("Minecraft 1.20.4 is out, the server is running 1.20.1\nhttps://www.minecraft.net/en-us/article/minecraft-java-edition-1-20-4"));
+        assert_eq!(newer_minecraft(&manifest, "1.20.4", false), None);
+        assert_eq!(newer_minecraft(&manifest, "24w03a", false), None);
+        assert_eq!(newer_minecraft(&manifest, "1.20.4", true).as_deref(), Some("Minecraft 24w03a is out, the server is running 1.20.4\nhttps://www.minecraft.net/en-us/article/minecraft-snapshot-24w03a"));
+    }
+}
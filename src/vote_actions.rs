@@ -0,0 +1,191 @@
+// !voteday and !voterestart, which run once enough of the online players agree. Votes can be
+// cast in game or from discord, each vote stays open for timeout_secs and can't be started
+// again for cooldown_mins after it passes.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::bot::Bot;
+use crate::polls::Voter;
+use crate::uptime::RestartReason;
+use crate::{scheduler, tellraw};
+
+#[derive(Deserialize)]
+pub struct VotesToml {
+    // Share of the online players that have to vote, rounded up
+    #[serde(default = "default_quorum_percent")]
+    pub quorum_percent: u32,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_cooldown_mins")]
+    pub cooldown_mins: u64,
+    #[serde(default = "default_day_command")]
+    pub day_command: String,
+    // Players are warned this long before a restart that was voted for
+    #[serde(default = "default_restart_delay_mins")]
+    pub restart_delay_mins: u64,
+}
+
+fn default_quorum_percent() -> u32 {
+    50
+}
+
+fn default_timeout_secs() -> u64 {
+    120
+}
+
+fn default_cooldown_mins() -> u64 {
+    10
+}
+
+fn default_day_command() -> String {
+    "time set day".to_string()
+}
+
+fn default_restart_delay_mins() -> u64 {
+    1
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum VoteAction {
+    Day,
+    Restart,
+}
+
+impl VoteAction {
+    fn command(&self) -> &'static str {
+        match self {
+            VoteAction::Day => "!voteday",
+            VoteAction::Restart => "!voterestart",
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            VoteAction::Day => "make it day",
+            VoteAction::Restart => "restart the server",
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum VoteOutcome {
+    Counted { votes: usize, needed: usize },
+    AlreadyVoted,
+    Passed,
+    // Seconds until the vote can be started again
+    Cooldown(u64),
+}
+
+struct OpenVote {
+    voters: HashSet<Voter>,
+    started: Instant,
+}
+
+pub struct CommunityVotes {
+    quorum_percent: u32,
+    timeout: Duration,
+    cooldown: Duration,
+    open: HashMap<VoteAction, OpenVote>,
+    last_passed: HashMap<VoteAction, Instant>,
+}
+
+impl CommunityVotes {
+    pub fn new(config: &VotesToml) -> CommunityVotes {
+        CommunityVotes {
+            quorum_percent: config.quorum_percent.clamp(1, 100),
+            timeout: Duration::from_secs(config.timeout_secs),
+            cooldown: Duration::from_secs(config.cooldown_mins * 60),
+            open: HashMap::new(),
+            last_passed: HashMap::new(),
+        }
+    }
+
+    fn needed(&self, online: usize) -> usize {
+        (online * self.quorum_percent as usize).div_ceil(100).max(1)
+    }
+
+    pub fn vote_at(&mut self, action: VoteAction, voter: Voter, online: usize, now: Instant) -> VoteOutcome {
+        if let Some(passed) = self.last_passed.get(&action) {
+            let remaining = self.cooldown.saturating_sub(now.saturating_duration_since(*passed));
+            if !remaining.is_zero() {
+                return VoteOutcome::Cooldown(remaining.as_secs().max(1));
+            }
+        }
+
+        // Votes nobody finished start over
+        self.open.retain(|_, x| now.saturating_duration_since(x.started) < self.timeout);
+        let vote = self.open.entry(action).or_insert_with(|| OpenVote { voters: HashSet::new(), started: now });
+        if !vote.voters.insert(voter) {
+            return VoteOutcome::AlreadyVoted;
+        }
+
+        let votes = vote.voters.len();
+        let needed = self.needed(online);
+        if votes < needed {
+            return VoteOutcome::Counted { votes, needed };
+        }
+
+        self.open.remove(&action);
+        self.last_passed.insert(action, now);
+        VoteOutcome::Passed
+    }
+}
+
+// Counts a vote from discord or in game, announcing it in both places and running the action
+// once it passes. Errors are for replying to the voter.
+pub async fn cast_vote(bot: &mut Bot, action: VoteAction, voter: Voter, name: &str, online: usize) -> Result<(), String> {
+    if bot.console.is_none() {
+        return Err("Server is not running".to_string());
+    }
+    let (votes, config) = match (&mut bot.votes, &bot.config.votes) {
+        (Some(votes), Some(config)) => (votes, config),
+        _ => return Err("Voting isn't enabled".to_string()),
+    };
+
+    let (passed, announcement) = match votes.vote_at(action, voter, online, Instant::now()) {
+        VoteOutcome::AlreadyVoted => return Err(format!("You've already voted to {}", action.describe())),
+        VoteOutcome::Cooldown(secs) => return Err(format!("A vote to {} can't start again for {} seconds", action.describe(), secs)),
+        VoteOutcome::Counted { votes, needed } => (false, format!("{} voted to {} ({}/{}), type {} to agree", name, action.describe(), votes, needed, action.command())),
+        VoteOutcome::Passed => (true, format!("Vote passed, going to {}", action.describe())),
+    };
+    let (day_command, restart_delay_mins) = (config.day_command.clone(), config.restart_delay_mins.max(1));
+    bot.write_console(&tellraw::to_all(&announcement)).await;
+    bot.say(bot.channel_id, &announcement).await;
+
+    match (passed, action) {
+        (false, _) => {},
+        (true, VoteAction::Day) => bot.write_console(&day_command).await,
+        (true, VoteAction::Restart) => scheduler::start_restart_countdown(restart_delay_mins, &bot.sender, RestartReason::Vote),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::polls::Voter;
+    use crate::vote_actions::{CommunityVotes, VoteAction, VoteOutcome, VotesToml};
+
+    #[test]
+    fn test_community_votes() {
+        let config: VotesToml = toml::from_str("quorum_percent = 50").unwrap();
+        let mut votes = CommunityVotes::new(&config);
+        let start = Instant::now();
+        let player = |name: &str| Voter::Player(name.to_string());
+
+        assert_eq!(votes.vote_at(VoteAction::Day, player("Steve"), 5, start), VoteOutcome::Counted { votes: 1, needed: 3 });
+        assert_eq!(votes.vote_at(VoteAction::Day, player("Steve"), 5, start), VoteOutcome::AlreadyVoted);
+        assert_eq!(votes.vote_at(VoteAction::Day, Voter::Discord(1), 5, start), VoteOutcome::Counted { votes: 2, needed: 3 });
+
+        // The vote timed out so it starts over
+        let later = start + Duration::from_secs(121);
+        assert_eq!(votes.vote_at(VoteAction::Day, player("Alex"), 5, later), VoteOutcome::Counted { votes: 1, needed: 3 });
+        assert_eq!(votes.vote_at(VoteAction::Day, player("Steve"), 2, later), VoteOutcome::Passed);
+        assert_eq!(votes.vote_at(VoteAction::Day, player("Steve"), 2, later + Duration::from_secs(60)), VoteOutcome::Cooldown(540));
+        assert_eq!(votes.vote_at(VoteAction::Restart, player("Steve"), 1, later), VoteOutcome::Passed);
+    }
+}
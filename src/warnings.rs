@@ -0,0 +1,220 @@
+// Strikes given with !warn. Each warning is kept per player in a file and shown to them in game,
+// and reaching kick_at or tempban_at warnings kicks or tempbans them automatically.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use serenity::model::id::ChannelId;
+use tracing::error;
+
+use crate::audit::{self, AuditEntry};
+use crate::bot::Bot;
+use crate::command_queue::ConsoleTask;
+use crate::console_commands::{self, ConsoleCommand};
+use crate::{run_console_task, tellraw, tempbans};
+
+pub const WARNINGS_PATH: &str = "mc-discord-bot-warnings.json";
+
+#[derive(Deserialize)]
+pub struct WarningsToml {
+    // A player is kicked when they reach this many warnings, 0 turns it off
+    #[serde(default = "default_kick_at")]
+    pub kick_at: usize,
+    // And tempbanned from this many on, 0 turns it off
+    #[serde(default = "default_tempban_at")]
+    pub tempban_at: usize,
+    #[serde(default = "default_tempban_duration")]
+    pub tempban_duration: BanDuration,
+}
+
+impl Default for WarningsToml {
+    fn default() -> WarningsToml {
+        WarningsToml { kick_at: default_kick_at(), tempban_at: default_tempban_at(), tempban_duration: default_tempban_duration() }
+    }
+}
+
+fn default_kick_at() -> usize {
+    3
+}
+
+fn default_tempban_at() -> usize {
+    5
+}
+
+fn default_tempban_duration() -> BanDuration {
+    BanDuration::try_from("1d".to_string()).unwrap()
+}
+
+// A duration like "1d" or "12h", kept as written for the ban reason
+#[derive(Deserialize, Clone, Debug)]
+#[serde(try_from = "String")]
+pub struct BanDuration {
+    pub text: String,
+    pub secs: u64,
+}
+
+impl TryFrom<String> for BanDuration {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<BanDuration, String> {
+        match tempbans::parse_duration(&text) {
+            Some(secs) => Ok(BanDuration { text, secs }),
+            None => Err(format!("Invalid tempban duration \"{}\", use eg. 30m, 12h, 7d or 1d12h", text)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    // Unix timestamp in seconds
+    pub time: i64,
+    pub warned_by: String,
+    pub reason: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Consequence {
+    Kick,
+    TempBan,
+}
+
+// Keyed by the player's name in lowercase
+#[derive(Default)]
+pub struct Warnings {
+    players: HashMap<String, Vec<Warning>>,
+}
+
+impl Warnings {
+    pub fn read() -> Warnings {
+        let players = std::fs::read_to_string(WARNINGS_PATH).ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default();
+        Warnings { players }
+    }
+
+    pub fn write(&self) {
+        let json_str = serde_json::to_string_pretty(&self.players).unwrap();
+        if let Err(e) = std::fs::write(WARNINGS_PATH, json_str) {
+            error!("Error writing {}: {}", WARNINGS_PATH, e);
+        }
+    }
+
+    // Returns how many warnings the player now has
+    pub fn add(&mut self, player: &str, warning: Warning) -> usize {
+        let warnings = self.players.entry(player.to_lowercase()).or_default();
+        warnings.push(warning);
+        warnings.len()
+    }
+
+    pub fn get(&self, player: &str) -> &[Warning] {
+        self.players.get(&player.to_lowercase()).map(|x| x.as_slice()).unwrap_or_default()
+    }
+
+    pub fn describe(&self, player: &str) -> String {
+        let warnings = self.get(player);
+        if warnings.is_empty() {
+            return format!("{} has no warnings", player);
+        }
+        let mut text = format!("**{} has {} warning{}**\n", player, warnings.len(), if warnings.len() == 1 { "" } else { "s" });
+        for warning in warnings {
+            let time = Local.timestamp_opt(warning.time, 0).single()
+                .map(|x| x.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            let _ = writeln!(&mut text, "{} by {}: {}", time, warning.warned_by, warning.reason);
+        }
+        text
+    }
+}
+
+// Parses !warn's arguments into the player and reason
+pub fn parse_command(args: &str) -> Option<(&str, String)> {
+    let (player, reason) = console_commands::parse_player_reason(args)?;
+    Some((player, reason?))
+}
+
+// What reaching count warnings leads to, a tempban takes priority over a kick
+pub fn consequence(config: &WarningsToml, count: usize) -> Option<Consequence> {
+    if config.tempban_at > 0 && count >= config.tempban_at {
+        Some(Consequence::TempBan)
+    } else if config.kick_at > 0 && count >= config.kick_at {
+        Some(Consequence::Kick)
+    } else {
+        None
+    }
+}
+
+// Warns the player in game and applies any consequence, returning the reply for whoever warned them
+pub async fn warn(bot: &mut Bot, channel_id: ChannelId, player: &str, reason: &str, warned_by: String, warned_by_id: u64) -> String {
+    let warning = Warning { time: Local::now().timestamp(), warned_by: warned_by.clone(), reason: reason.to_string() };
+    let count = bot.warnings.add(player, warning);
+    bot.warnings.write();
+
+    let mut audit_entry = AuditEntry::new(warned_by.clone(), warned_by_id, &format!("warn {} {}", player, reason));
+    if let (Some(line), true) = (tellraw::to_player(player, &format!("You've been warned ({}): {}", count, reason), None), bot.console.is_some()) {
+        bot.write_console(&line).await;
+        audit_entry.succeeded = true;
+    }
+    if let Err(e) = audit::record(&audit_entry) {
+        error!("Error writing audit log: {}", e);
+    }
+
+    let plural = if count == 1 { "" } else { "s" };
+    let mut reply = format!("{} has been warned, they have {} warning{}", player, count, plural);
+    let reason = format!("{} warning{}: {}", count, plural, reason);
+    let result = match consequence(&bot.config.warnings, count) {
+        Some(Consequence::Kick) => {
+            reply.push_str(" and have been kicked");
+            let console_line = format!("kick {} {}", player, reason);
+            let task = ConsoleTask::new(channel_id, ConsoleCommand::Kick, console_line.clone(), Some(AuditEntry::new(warned_by, warned_by_id, &console_line)));
+            match bot.ctx.clone() {
+                Some(ctx) => run_console_task(&bot.console, &mut bot.command_queue, &bot.sender, &ctx, task).await,
+                None => Err("Discord isn't ready"),
+            }
+        },
+        Some(Consequence::TempBan) => {
+            let duration = bot.config.warnings.tempban_duration.clone();
+            reply.push_str(&format!(" and have been banned for {}", duration.text));
+            let (ban, console_line) = tempbans::new_ban(player, &duration.text, duration.secs, Some(reason), &warned_by, Local::now().timestamp());
+            let audit_entry = AuditEntry::new(warned_by, warned_by_id, &console_line);
+            tempbans::start(bot, channel_id, ban, console_line, audit_entry).await
+        },
+        None => Ok(()),
+    };
+    if let Err(e) = result {
+        reply = format!("{} has been warned, they have {} warning{} but couldn't be punished: {}", player, count, plural, e);
+    }
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::warnings::{consequence, parse_command, Consequence, Warning, Warnings, WarningsToml};
+
+    #[test]
+    fn test_warnings() {
+        let config = WarningsToml::default();
+        assert_eq!(consequence(&config, 2), None);
+        assert_eq!(consequence(&config, 3), Some(Consequence::Kick));
+        assert_eq!(consequence(&config, 4), Some(Consequence::Kick));
+        assert_eq!(consequence(&config, 6), Some(Consequence::TempBan));
+        assert_eq!(config.tempban_duration.secs, 86_400);
+
+        let config: WarningsToml = toml::from_str("kick_at = 0\ntempban_duration = '12h'").unwrap();
+        assert_eq!(consequence(&config, 3), None);
+        assert_eq!(config.tempban_duration.secs, 43_200);
+        assert!(toml::from_str::<WarningsToml>("tempban_duration = 'forever'").is_err());
+
+        assert_eq!(parse_command("Steve spamming\nchat"), Some(("Steve", "spamming chat".to_string())));
+        assert_eq!(parse_command("Steve"), None);
+
+        let mut warnings = Warnings::default();
+        let warning = |reason: &str| Warning { time: 0, warned_by: "alice".to_string(), reason: reason.to_string() };
+        assert_eq!(warnings.add("Steve", warning("spam")), 1);
+        assert_eq!(warnings.add("steve", warning("griefing")), 2);
+        assert_eq!(warnings.get("STEVE").len(), 2);
+        assert!(warnings.describe("Steve").starts_with("**Steve has 2 warnings**\n"));
+        assert_eq!(warnings.describe("Alex"), "Alex has no warnings");
+    }
+}
@@ -0,0 +1,172 @@
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use crate::{Packet, send_or_log};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// A quiet server is sent `list` after this long, a server that is still running always replies
+const PROBE_AFTER: Duration = Duration::from_secs(60);
+
+// The server's own watchdog writes a crash report before exiting, it's killed if that takes longer
+const STALL_KILL_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchdogAction {
+    // Only tells the admin channel
+    #[default]
+    Warn,
+    // Kills the server and starts it again
+    Restart,
+}
+
+// The server counts as hung once it has gone timeout_secs without any log output
+#[derive(Deserialize)]
+pub struct WatchdogToml {
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default)]
+    pub action: WatchdogAction,
+}
+
+fn default_timeout_secs() -> u64 {
+    300
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum WatchdogCheck {
+    Fine,
+    // Nothing has been logged for a while, the server should be sent `list`
+    Probe,
+    // The server has just been found to be hung
+    Hung(WatchdogAction),
+    // The server is still hung and has already been reported
+    StillHung,
+}
+
+pub struct Watchdog {
+    timeout: Duration,
+    action: WatchdogAction,
+    last_output: Instant,
+    hung: bool,
+}
+
+impl Watchdog {
+    pub fn new(config: &WatchdogToml) -> Watchdog {
+        Watchdog {
+            timeout: Duration::from_secs(config.timeout_secs),
+            action: config.action,
+            last_output: Instant::now(),
+            hung: false,
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    // Called for every log line, returns true if the server had been reported as hung
+    pub fn output(&mut self) -> bool {
+        self.output_at(Instant::now())
+    }
+
+    fn output_at(&mut self, now: Instant) -> bool {
+        self.last_output = now;
+        std::mem::replace(&mut self.hung, false)
+    }
+
+    // Starts timing from scratch, eg. when the server is started
+    pub fn reset(&mut self) {
+        self.output();
+    }
+
+    pub fn check(&mut self) -> WatchdogCheck {
+        self.check_at(Instant::now())
+    }
+
+    fn check_at(&mut self, now: Instant) -> WatchdogCheck {
+        let quiet = now.saturating_duration_since(self.last_output);
+        if self.hung {
+            WatchdogCheck::StillHung
+        } else if quiet >= self.timeout {
+            self.hung = true;
+            WatchdogCheck::Hung(self.action)
+        } else if quiet >= PROBE_AFTER {
+            WatchdogCheck::Probe
+        } else {
+            WatchdogCheck::Fine
+        }
+    }
+}
+
+// Lines the server's own watchdog logs when a tick runs past max-tick-time, eg.
+// "A single server tick took 60.00 seconds (should be max 0.05)" followed by
+// "Considering it to be crashed, server will forcibly shutdown."
+#[derive(PartialEq, Debug)]
+pub enum TickStall {
+    Took(f64),
+    Crashed,
+}
+
+pub fn parse_tick_stall(line: &str) -> Option<TickStall> {
+    if line.starts_with("Considering it to be crashed") {
+        return Some(TickStall::Crashed);
+    }
+    let seconds = line.strip_prefix("A single server tick took ")?.split_once(" seconds")?.0;
+    seconds.parse().ok().map(TickStall::Took)
+}
+
+// Sends a StallTimedOut packet if a server its watchdog stopped hasn't exited in time
+pub fn start_stall_timeout(sender: &mpsc::UnboundedSender<Packet>) {
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        tokio::time::sleep(STALL_KILL_AFTER).await;
+        send_or_log(&sender, Packet::StallTimedOut());
+    });
+}
+
+// Sends a WatchdogTick packet every CHECK_INTERVAL
+pub fn start_watchdog_timer(sender: &mpsc::UnboundedSender<Packet>) {
+    let sender = sender.clone();
+    tokio::task::spawn(async move {
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            send_or_log(&sender, Packet::WatchdogTick());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::watchdog::{parse_tick_stall, TickStall, Watchdog, WatchdogAction, WatchdogCheck, WatchdogToml};
+
+    #[test]
+    fn test_watchdog() {
+        let config: WatchdogToml = toml::from_str("timeout_secs = 120\naction = 'restart'").unwrap();
+        let mut watchdog = Watchdog::new(&config);
+        let start = Instant::now();
+        let seconds = |x: u64| start + Duration::from_secs(x);
+
+        watchdog.output_at(start);
+        assert_eq!(watchdog.check_at(seconds(30)), WatchdogCheck::Fine);
+        assert_eq!(watchdog.check_at(seconds(60)), WatchdogCheck::Probe);
+        assert_eq!(watchdog.check_at(seconds(120)), WatchdogCheck::Hung(WatchdogAction::Restart));
+        assert_eq!(watchdog.check_at(seconds(150)), WatchdogCheck::StillHung);
+
+        // Output clears it
+        assert!(watchdog.output_at(seconds(160)));
+        assert!(!watchdog.output_at(seconds(170)));
+        assert_eq!(watchdog.check_at(seconds(200)), WatchdogCheck::Fine);
+
+        assert_eq!(toml::from_str::<WatchdogToml>("").unwrap().action, WatchdogAction::Warn);
+
+        assert_eq!(parse_tick_stall("A single server tick took 60.00 seconds (should be max 0.05)"), Some(TickStall::Took(60.0)));
+        assert_eq!(parse_tick_stall("Considering it to be crashed, server will forcibly shutdown."), Some(TickStall::Crashed));
+        assert_eq!(parse_tick_stall("Can't keep up! Is the server overloaded? Running 5000ms or 100 ticks behind"), None);
+    }
+}
@@ -0,0 +1,191 @@
+// Named coordinates players save with !coords, in game or from discord. A player's position is
+// read with `data get entity`, so saving takes a moment while the server answers.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serenity::model::id::ChannelId;
+use tracing::error;
+
+use crate::console_commands::parse_position;
+
+pub const WAYPOINTS_PATH: &str = "mc-discord-bot-waypoints.json";
+
+const MAX_NAME_LEN: usize = 32;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CoordsCommand {
+    Save(String),
+    Get(String),
+    List,
+    Delete(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Waypoint {
+    pub name: String,
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+    pub dimension: String,
+}
+
+impl Waypoint {
+    // eg. "base: 12, 64, -31 (overworld)"
+    pub fn describe(&self) -> String {
+        let dimension = self.dimension.strip_prefix("minecraft:").unwrap_or(&self.dimension);
+        format!("{}: {}, {}, {} ({})", self.name, self.x, self.y, self.z, dimension)
+    }
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    (1..=MAX_NAME_LEN).contains(&name.len()) && name.chars().all(|x| x.is_ascii_alphanumeric() || x == '_' || x == '-')
+}
+
+// Parses what comes after !coords in game, None if it isn't valid
+pub fn parse_command(args: &str) -> Option<CoordsCommand> {
+    let mut args = args.split_whitespace();
+    let command = match (args.next()?, args.next()) {
+        ("save", Some(name)) if is_valid_name(name) => CoordsCommand::Save(name.to_string()),
+        ("get", Some(name)) => CoordsCommand::Get(name.to_string()),
+        ("list", None) => CoordsCommand::List,
+        ("delete", Some(name)) => CoordsCommand::Delete(name.to_string()),
+        _ => return None,
+    };
+    args.next().is_none().then_some(command)
+}
+
+// Steve has the following entity data: "minecraft:the_nether"
+fn parse_dimension(line: &str) -> Option<(&str, &str)> {
+    let (player, data) = line.split_once(" has the following entity data: ")?;
+    Some((player, data.strip_prefix('"')?.strip_suffix('"')?))
+}
+
+// A save waiting for the server to say where the player is
+struct PendingSave {
+    name: String,
+    dimension: Option<String>,
+    // Discord channel the save was asked for from
+    channel_id: Option<ChannelId>,
+}
+
+// A waypoint that was saved once the player's position came back
+pub struct Saved {
+    pub player: String,
+    pub waypoint: Waypoint,
+    pub channel_id: Option<ChannelId>,
+}
+
+#[derive(Default)]
+pub struct Waypoints {
+    // Each player's waypoints by their stats key
+    saved: HashMap<String, Vec<Waypoint>>,
+    // By player name
+    pending: HashMap<String, PendingSave>,
+}
+
+impl Waypoints {
+    pub fn read() -> Waypoints {
+        let saved = std::fs::read_to_string(WAYPOINTS_PATH).ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default();
+        Waypoints { saved, pending: HashMap::new() }
+    }
+
+    pub fn write(&self) {
+        let json_str = serde_json::to_string_pretty(&self.saved).unwrap();
+        if let Err(e) = std::fs::write(WAYPOINTS_PATH, json_str) {
+            error!("Error writing {}: {}", WAYPOINTS_PATH, e);
+        }
+    }
+
+    // Returns the console commands that ask for the player's position
+    pub fn request_save(&mut self, player: &str, name: &str, channel_id: Option<ChannelId>) -> [String; 2] {
+        self.pending.insert(player.to_string(), PendingSave { name: name.to_string(), dimension: None, channel_id });
+        [format!("data get entity {} Dimension", player), format!("data get entity {} Pos", player)]
+    }
+
+    // Checks a log line for the position a pending save is waiting for, adding the waypoint once
+    // it's known. id turns a player's name into their stats key.
+    pub fn position_line(&mut self, line: &str, id: impl Fn(&str) -> String) -> Option<Saved> {
+        if let Some((player, dimension)) = parse_dimension(line) {
+            if let Some(pending) = self.pending.get_mut(player) {
+                pending.dimension = Some(dimension.to_string());
+            }
+            return None;
+        }
+
+        let (player, [x, y, z]) = parse_position(line)?;
+        let pending = self.pending.remove(player)?;
+        let waypoint = Waypoint {
+            name: pending.name,
+            x: x.floor() as i64,
+            y: y.floor() as i64,
+            z: z.floor() as i64,
+            dimension: pending.dimension.unwrap_or_else(|| "minecraft:overworld".to_string()),
+        };
+
+        let waypoints = self.saved.entry(id(player)).or_default();
+        waypoints.retain(|x| !x.name.eq_ignore_ascii_case(&waypoint.name));
+        waypoints.push(waypoint.clone());
+        Some(Saved { player: player.to_string(), waypoint, channel_id: pending.channel_id })
+    }
+
+    pub fn list(&self, id: &str) -> &[Waypoint] {
+        self.saved.get(id).map(|x| x.as_slice()).unwrap_or(&[])
+    }
+
+    // Every player's waypoints called name, by their stats key
+    pub fn find(&self, name: &str) -> Vec<(&str, &Waypoint)> {
+        let mut found: Vec<(&str, &Waypoint)> = self.saved.iter()
+            .flat_map(|(id, waypoints)| waypoints.iter().filter(|x| x.name.eq_ignore_ascii_case(name)).map(move |x| (id.as_str(), x)))
+            .collect();
+        found.sort_by_key(|x| x.0);
+        found
+    }
+
+    pub fn players(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self.saved.iter().filter(|x| !x.1.is_empty()).map(|x| x.0.as_str()).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    pub fn delete(&mut self, id: &str, name: &str) -> bool {
+        let waypoints = match self.saved.get_mut(id) {
+            Some(v) => v,
+            None => return false,
+        };
+        let count = waypoints.len();
+        waypoints.retain(|x| !x.name.eq_ignore_ascii_case(name));
+        waypoints.len() != count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::waypoints::{parse_command, CoordsCommand, Waypoint, Waypoints};
+
+    #[test]
+    fn test_waypoints() {
+        assert_eq!(parse_command("save base"), Some(CoordsCommand::Save("base".to_string())));
+        assert_eq!(parse_command("save my base"), None);
+        assert_eq!(parse_command("save @a"), None);
+        assert_eq!(parse_command("list"), Some(CoordsCommand::List));
+
+        let mut waypoints = Waypoints::default();
+        assert_eq!(waypoints.request_save("Steve", "base", None)[1], "data get entity Steve Pos");
+        let id = |x: &str| x.to_lowercase();
+        assert!(waypoints.position_line("Alex has the following entity data: \"minecraft:the_nether\"", id).is_none());
+        assert!(waypoints.position_line("Steve has the following entity data: \"minecraft:the_nether\"", id).is_none());
+        let saved = waypoints.position_line("Steve has the following entity data: [12.5d, 64.0d, -30.25d]", id).unwrap();
+
+        let waypoint = Waypoint { name: "base".to_string(), x: 12, y: 64, z: -31, dimension: "minecraft:the_nether".to_string() };
+        assert_eq!(saved.waypoint, waypoint);
+        assert_eq!(waypoint.describe(), "base: 12, 64, -31 (the_nether)");
+        assert_eq!(waypoints.find("BASE"), vec![("steve", &waypoint)]);
+        assert_eq!(waypoints.players(), vec!["steve"]);
+        assert!(waypoints.position_line("Steve has the following entity data: [0.0d, 0.0d, 0.0d]", id).is_none());
+        assert!(waypoints.delete("steve", "Base"));
+        assert!(waypoints.list("steve").is_empty());
+    }
+}
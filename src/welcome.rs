@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::backup::world_dir;
+
+// Announces players joining for the first time, message is shown to them in game and can use
+// {player}
+#[derive(Deserialize)]
+pub struct WelcomeToml {
+    #[serde(default = "default_announce")]
+    pub announce: bool,
+    pub message: Option<String>,
+}
+
+impl Default for WelcomeToml {
+    fn default() -> WelcomeToml {
+        WelcomeToml { announce: default_announce(), message: None }
+    }
+}
+
+fn default_announce() -> bool {
+    true
+}
+
+// The server saves a player's data file the first time the world is saved after they join, so
+// a player without one hasn't played here before
+fn has_player_data(uuid: &str) -> bool {
+    Path::new(&world_dir()).join("playerdata").join(format!("{}.dat", uuid)).is_file()
+}
+
+// Whether players that are logging in have played before, worked out from their UUID when the
+// server authenticates them
+#[derive(Default)]
+pub struct FirstJoins {
+    pending: HashMap<String, bool>,
+}
+
+impl FirstJoins {
+    pub fn authenticated(&mut self, player: &str, uuid: &str) {
+        self.pending.insert(player.to_string(), !has_player_data(uuid));
+    }
+
+    // Servers that don't log UUIDs fall back to whether the bot has seen the player before
+    pub fn is_first_join(&mut self, player: &str, known_player: bool) -> bool {
+        self.pending.remove(player).unwrap_or(!known_player)
+    }
+}
+
+pub fn render_message(template: &str, player: &str) -> String {
+    template.replace("{player}", player)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::welcome::{render_message, FirstJoins};
+
+    #[test]
+    fn test_first_joins() {
+        let mut first_joins = FirstJoins::default();
+        first_joins.authenticated("Steve", "069a79f4-44e9-4726-a5be-fca90e38aaf5");
+        assert!(first_joins.is_first_join("Steve", true));
+        assert!(!first_joins.is_first_join("Steve", true));
+        assert!(first_joins.is_first_join("Alex", false));
+
+        assert_eq!(render_message("Welcome {player}!", "Alex"), "Welcome Alex!");
+    }
+}
@@ -0,0 +1,149 @@
+// `!world info`, the world's size on disk per dimension and how much it has grown since the
+// last backup. Adding up a large world takes a while so it runs on the blocking thread pool.
+
+use std::path::{Path, PathBuf};
+
+use serenity::model::id::ChannelId;
+use serenity::prelude::*;
+
+use crate::status::{backup_time, dir_size};
+use crate::usage::format_bytes;
+use crate::{backup, response, say_or_log};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DimensionInfo {
+    pub name: String,
+    pub size: u64,
+    pub regions: usize,
+}
+
+fn region_count(dimension: &Path) -> usize {
+    std::fs::read_dir(dimension.join("region")).map(|entries| {
+        entries.filter_map(|x| x.ok()).filter(|x| x.file_name().to_string_lossy().ends_with(".mca")).count()
+    }).unwrap_or(0)
+}
+
+// The directories holding each dimension. Vanilla keeps the nether and end in DIM-1 and DIM1,
+// Bukkit servers in <world>_nether and <world>_the_end, and modded dimensions are under
+// dimensions/<namespace>/<name>.
+fn dimension_dirs(world: &Path) -> Vec<(String, PathBuf)> {
+    let mut dirs = vec![
+        ("the_nether".to_string(), world.join("DIM-1")),
+        ("the_end".to_string(), world.join("DIM1")),
+    ];
+    if let (Some(parent), Some(name)) = (world.parent(), world.file_name()) {
+        let name = name.to_string_lossy();
+        dirs.push(("the_nether".to_string(), parent.join(format!("{}_nether", name)).join("DIM-1")));
+        dirs.push(("the_end".to_string(), parent.join(format!("{}_the_end", name)).join("DIM1")));
+    }
+    let namespaces = std::fs::read_dir(world.join("dimensions")).into_iter().flatten().filter_map(|x| x.ok());
+    for namespace in namespaces {
+        for dimension in std::fs::read_dir(namespace.path()).into_iter().flatten().filter_map(|x| x.ok()) {
+            let name = format!("{}:{}", namespace.file_name().to_string_lossy(), dimension.file_name().to_string_lossy());
+            dirs.push((name, dimension.path()));
+        }
+    }
+    dirs.retain(|(_, dir)| dir.is_dir());
+    dirs
+}
+
+// The overworld is what's left of the world directory once the other dimensions are taken out
+pub fn dimensions(world: &Path) -> Vec<DimensionInfo> {
+    let mut nested = 0;
+    let mut dimensions = vec![DimensionInfo { name: "overworld".to_string(), size: 0, regions: region_count(world) }];
+    for (name, dir) in dimension_dirs(world) {
+        let size = dir_size(&dir);
+        if dir.starts_with(world) {
+            nested += size;
+        }
+        dimensions.push(DimensionInfo { name, size, regions: region_count(&dir) });
+    }
+    dimensions[0].size = dir_size(world).saturating_sub(nested);
+    dimensions
+}
+
+// The world's size when a backup was made, from the uncompressed sizes in the archive
+fn backup_world_size(path: &Path) -> Option<u64> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut archive = zip::ZipArchive::new(file).ok()?;
+    let mut size = 0;
+    for i in 0..archive.len() {
+        size += archive.by_index_raw(i).ok()?.size();
+    }
+    Some(size)
+}
+
+fn format_growth(now: u64, before: u64) -> String {
+    match now >= before {
+        true => format!("+{}", format_bytes(now - before)),
+        false => format!("-{}", format_bytes(before - now)),
+    }
+}
+
+// backup is the last backup's id and the world's size in it
+pub fn describe(dimensions: &[DimensionInfo], backup: Option<(&str, u64)>) -> String {
+    let width = dimensions.iter().map(|x| x.name.len()).max().unwrap_or(0);
+    let mut lines: Vec<String> = dimensions.iter()
+        .map(|x| format!("{:width$}  {:>10}  {} region files", x.name, format_bytes(x.size), x.regions, width = width))
+        .collect();
+
+    let total: u64 = dimensions.iter().map(|x| x.size).sum();
+    let regions: usize = dimensions.iter().map(|x| x.regions).sum();
+    lines.push(format!("{:width$}  {:>10}  {} region files", "total", format_bytes(total), regions, width = width));
+
+    let last_backup = match backup {
+        Some((id, size)) => {
+            let when = backup_time(id).map(|x| x.format("%Y-%m-%d %H:%M").to_string()).unwrap_or(id.to_string());
+            format!("{} since the last backup on {}", format_growth(total, size), when)
+        },
+        None => "No backups yet".to_string(),
+    };
+    lines.push(String::new());
+    lines.push(last_backup);
+    lines.join("\n")
+}
+
+// Works out the world's statistics in the background and replies with them
+pub fn send_world_info(ctx: &Context, channel_id: ChannelId, attachment_threshold: usize) {
+    let ctx = ctx.clone();
+    tokio::task::spawn(async move {
+        let world_dir = backup::world_dir();
+        let result = tokio::task::spawn_blocking(move || {
+            let world = Path::new(&world_dir);
+            if !world.is_dir() {
+                return Err(format!("World directory {} not found", world_dir));
+            }
+            let backup = backup::list_backups().into_iter().next()
+                .and_then(|id| backup::backup_path(&id).and_then(|x| backup_world_size(&x)).map(|size| (id, size)));
+            Ok(describe(&dimensions(world), backup.as_ref().map(|(id, size)| (id.as_str(), *size))))
+        }).await.unwrap_or_else(|e| Err(format!("Error reading the world: {}", e)));
+
+        match result {
+            Ok(text) => response::send_code_block(channel_id, &ctx, "World", &text, attachment_threshold).await,
+            Err(e) => say_or_log(channel_id, &ctx, &e).await,
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world_info::{describe, DimensionInfo};
+
+    #[test]
+    fn test_describe_world() {
+        let gib = 1024 * 1024 * 1024;
+        let dimensions = vec![
+            DimensionInfo { name: "overworld".to_string(), size: 2 * gib, regions: 40 },
+            DimensionInfo { name: "the_nether".to_string(), size: gib / 2, regions: 8 },
+        ];
+        assert_eq!(describe(&dimensions, Some(("world-20240102-030405", 2 * gib))), [
+            "overworld     2.00 GiB  40 region files",
+            "the_nether    0.50 GiB  8 region files",
+            "total         2.50 GiB  48 region files",
+            "",
+            "+0.50 GiB since the last backup on 2024-01-02 03:04",
+        ].join("\n"));
+        assert!(describe(&dimensions, None).ends_with("No backups yet"));
+        assert!(describe(&dimensions, Some(("world-20240102-030405", 3 * gib))).ends_with("-0.50 GiB since the last backup on 2024-01-02 03:04"));
+    }
+}